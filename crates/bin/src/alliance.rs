@@ -0,0 +1,628 @@
+use axum::{
+    extract::{Extension, Form, Path},
+    response::{Html, Redirect, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use galactic_war::{
+    alliance::{Alliance, AllianceRole, AllianceService},
+    app::AppState,
+    diplomacy::{DiplomacyService, Relation},
+    UserGalaxyAccount, UserService,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::auth::{create_error_response, current_galaxy_account};
+use crate::{escape_html, seconds_to_readable};
+
+/// Diplomacy news shown on the dashboard
+const DASHBOARD_LOG_LENGTH: usize = 5;
+
+/// Entries on the public diplomacy page
+const PAGE_LOG_LENGTH: usize = 50;
+
+/// Alliance form data, each action only uses some of the fields
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct AllianceForm {
+    pub name: String,
+    pub tag: String,
+    pub account_name: String,
+    pub alliance_id: Option<i64>,
+    pub role: String,
+}
+
+/// Handle POST requests to /galaxy/:galaxy/alliance/:action
+///
+/// Runs an alliance action for the logged in player's account in the galaxy, then returns to
+/// the galaxy dashboard.
+pub async fn handle_alliance_action(
+    Path((galaxy_name, action)): Path<(String, String)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+    Form(form): Form<AllianceForm>,
+) -> Result<Redirect, Response> {
+    let account = current_galaxy_account(jar, &app_state, &galaxy_name).await?;
+    let db = app_state
+        .database()
+        .ok_or_else(|| create_error_response("Galaxy service not available"))?;
+
+    let alliances = AllianceService::new(db.clone());
+    let alliance_id = || form.alliance_id.ok_or("No alliance given");
+    let result = match action.as_str() {
+        "create" => alliances
+            .create(account.id, &form.name, &form.tag)
+            .await
+            .map(|_| ()),
+        "invite" => alliances.invite(account.id, &form.account_name).await,
+        "accept" => {
+            let alliance_id = alliance_id().map_err(create_error_response)?;
+            alliances.accept(account.id, alliance_id).await
+        }
+        "decline" => {
+            let alliance_id = alliance_id().map_err(create_error_response)?;
+            alliances.decline(account.id, alliance_id).await
+        }
+        "leave" => alliances.leave(account.id).await,
+        "kick" => alliances.kick(account.id, &form.account_name).await,
+        "role" => {
+            let role = form
+                .role
+                .parse::<AllianceRole>()
+                .map_err(|e| create_error_response(&e))?;
+            alliances
+                .set_role(account.id, &form.account_name, role)
+                .await
+        }
+        _ => return Err(create_error_response("Unknown alliance action")),
+    };
+    // Errors can repeat the names players typed in
+    result.map_err(|e| create_error_response(&escape_html(&e.to_string())))?;
+    // Alliances win together when the galaxy ends
+    app_state
+        .refresh_players(&galaxy_name)
+        .await
+        .map_err(|e| create_error_response(&e))?;
+
+    Ok(Redirect::to(&format!("/galaxy/{}/dashboard", galaxy_name)))
+}
+
+/// Diplomacy form data, each action only uses some of the fields
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct DiplomacyForm {
+    pub alliance_id: Option<i64>,
+    pub proposal_id: Option<i64>,
+    pub relation: String,
+}
+
+/// Handle POST requests to /galaxy/:galaxy/diplomacy/:action
+///
+/// Conducts diplomacy for the alliance of the logged in player, then returns to the galaxy
+/// dashboard.
+pub async fn handle_diplomacy_action(
+    Path((galaxy_name, action)): Path<(String, String)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+    Form(form): Form<DiplomacyForm>,
+) -> Result<Redirect, Response> {
+    let account = current_galaxy_account(jar, &app_state, &galaxy_name).await?;
+    let db = app_state
+        .database()
+        .ok_or_else(|| create_error_response("Galaxy service not available"))?;
+
+    let diplomacy = DiplomacyService::new(db.clone());
+    let tick = app_state.tick();
+    let alliance_id = form.alliance_id.ok_or("No alliance given");
+    let proposal_id = form.proposal_id.ok_or("No proposal given");
+    let result = match action.as_str() {
+        "propose" => {
+            let relation = form
+                .relation
+                .parse::<Relation>()
+                .map_err(|e| create_error_response(&e))?;
+            diplomacy
+                .propose(
+                    account.id,
+                    alliance_id.map_err(create_error_response)?,
+                    relation,
+                    tick,
+                )
+                .await
+                .map(|_| ())
+        }
+        "accept" => diplomacy
+            .accept(
+                account.id,
+                proposal_id.map_err(create_error_response)?,
+                tick,
+            )
+            .await
+            .map(|_| ()),
+        "reject" => {
+            diplomacy
+                .reject(
+                    account.id,
+                    proposal_id.map_err(create_error_response)?,
+                    tick,
+                )
+                .await
+        }
+        "war" => {
+            diplomacy
+                .declare_war(
+                    account.id,
+                    alliance_id.map_err(create_error_response)?,
+                    tick,
+                )
+                .await
+        }
+        "cancel" => {
+            diplomacy
+                .cancel(
+                    account.id,
+                    alliance_id.map_err(create_error_response)?,
+                    tick,
+                )
+                .await
+        }
+        _ => return Err(create_error_response("Unknown diplomacy action")),
+    };
+    result.map_err(|e| create_error_response(&escape_html(&e.to_string())))?;
+
+    Ok(Redirect::to(&format!("/galaxy/{}/dashboard", galaxy_name)))
+}
+
+/// Handler for GET requests to /:galaxy/diplomacy
+///
+/// The public relations between alliances and the diplomacy log of a galaxy
+pub async fn diplomacy_get(
+    Path(galaxy): Path<String>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Html<String>, String> {
+    let db = app_state.database().ok_or("Galaxy service not available")?;
+    let tick = app_state.tick();
+    let alliances = AllianceService::new(db.clone())
+        .alliances(&galaxy)
+        .await
+        .map_err(|e| e.to_string())?;
+    let diplomacy = DiplomacyService::new(db.clone());
+    let relations = diplomacy
+        .galaxy_relations(&galaxy, tick)
+        .await
+        .map_err(|e| e.to_string())?;
+    let name = |alliance_id: i64| {
+        alliances
+            .iter()
+            .find(|alliance| alliance.id == alliance_id)
+            .map(|alliance| {
+                format!(
+                    "[{}] {}",
+                    escape_html(&alliance.tag),
+                    escape_html(&alliance.name)
+                )
+            })
+            .unwrap_or_default()
+    };
+
+    let mut page = format!(
+        "<h2>{} Diplomacy</h2>
+    <p><a href=/{}/leaderboard>Leaderboard</a></p>
+    <table width=600 border=0 cellspacing=1 cellpadding=3>
+    <tr><td bgcolor=dddddd><b>Alliances</b></td><td bgcolor=dddddd width=25%><b>Relation</b></td>
+    <td bgcolor=dddddd width=20%><b>Since</b></td></tr>",
+        galaxy, galaxy
+    );
+    if relations.is_empty() {
+        page.push_str("<tr><td bgcolor=#ffffff colspan=3>Every alliance is neutral</td></tr>");
+    }
+    for (a, b, status) in &relations {
+        let mut relation = status.relation.describe().to_string();
+        if let Some(expires_at) = status.expires_at {
+            relation.push_str(&format!(
+                ", ends in {}",
+                seconds_to_readable(expires_at.saturating_sub(tick))
+            ));
+        }
+        page.push_str(&format!(
+            "<tr><td bgcolor=#ffffff>{} &middot; {}</td><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>{} ago</td></tr>",
+            name(*a),
+            name(*b),
+            relation,
+            seconds_to_readable(tick.saturating_sub(status.since))
+        ));
+    }
+    page.push_str("</table>\n<h3>Log</h3>\n");
+
+    let log = diplomacy
+        .log(&galaxy, None, PAGE_LOG_LENGTH)
+        .await
+        .map_err(|e| e.to_string())?;
+    if log.is_empty() {
+        page.push_str("<p>Nothing has happened yet.</p>");
+    }
+    for entry in log {
+        page.push_str(&format!(
+            "<p>{} ago: {}</p>\n",
+            seconds_to_readable(tick.saturating_sub(entry.tick as usize)),
+            escape_html(&entry.message)
+        ));
+    }
+    Ok(Html::from(page))
+}
+
+/// The alliance part of the galaxy dashboard
+///
+/// Members see their alliance with every member's systems, and the forms their role allows.
+/// Players outside an alliance see their invites and can found one.
+pub async fn alliance_section(
+    app_state: &Arc<AppState>,
+    galaxy_name: &str,
+    account: &UserGalaxyAccount,
+    csrf: &str,
+) -> Result<String, String> {
+    let db = app_state.database().ok_or("Galaxy service not available")?;
+    let alliances = AllianceService::new(db.clone());
+    let action = |name: &str| format!("/galaxy/{}/alliance/{}", galaxy_name, name);
+
+    let mut section = String::from("<div class=\"alliance\">\n<h2>Alliance</h2>\n");
+    let Some(alliance) = alliances
+        .alliance_of(account.id)
+        .await
+        .map_err(|e| e.to_string())?
+    else {
+        let invites = alliances
+            .invites(account.id)
+            .await
+            .map_err(|e| e.to_string())?;
+        if invites.is_empty() {
+            section.push_str("<p>You aren't in an alliance.</p>\n");
+        }
+        for invite in invites {
+            section.push_str(&format!(
+                r#"<div class="system-item"><div>Invited to <strong>[{}] {}</strong></div>
+<div><form method="post" action="{}" style="display:inline">{}<input type="hidden" name="alliance_id" value="{}"><button>Accept</button></form>
+<form method="post" action="{}" style="display:inline">{}<input type="hidden" name="alliance_id" value="{}"><button>Decline</button></form></div></div>
+"#,
+                escape_html(&invite.tag),
+                escape_html(&invite.name),
+                action("accept"),
+                csrf,
+                invite.id,
+                action("decline"),
+                csrf,
+                invite.id
+            ));
+        }
+        section.push_str(&format!(
+            r#"<form method="post" action="{}">{}
+<input name="name" placeholder="Alliance name" required> <input name="tag" placeholder="Tag" size="5" required>
+<button>Found alliance</button></form>
+</div>
+"#,
+            action("create"),
+            csrf
+        ));
+        return Ok(section);
+    };
+
+    let role = alliance.role(account.id).unwrap_or(AllianceRole::Member);
+    section.push_str(&format!(
+        "<p><strong>[{}] {}</strong> &middot; You are {}</p>\n",
+        escape_html(&alliance.tag),
+        escape_html(&alliance.name),
+        article(role)
+    ));
+
+    // Allies share their systems with each other
+    let user_service = UserService::new(db.clone());
+    for member in &alliance.members {
+        let mut systems = Vec::new();
+        for coords in user_service
+            .get_user_systems_coords(member.account_id)
+            .await
+            .map_err(|e| e.to_string())?
+        {
+            let score = app_state
+                .system_info(galaxy_name, coords)
+                .await
+                .map(|info| info.score.to_string())
+                .unwrap_or_else(|_| "?".to_string());
+            systems.push(format!(
+                "<a href=\"/{}/{}/{}\">({}, {})</a> score {}",
+                galaxy_name, coords.x, coords.y, coords.x, coords.y, score
+            ));
+        }
+        section.push_str(&format!(
+            "<div class=\"system-item\"><div><strong>{}</strong> ({})<br>{}</div></div>\n",
+            escape_html(&member.account_name),
+            member.role,
+            systems.join(" | ")
+        ));
+    }
+
+    if role.can_invite() {
+        let pending = alliances
+            .pending_invites(alliance.id)
+            .await
+            .map_err(|e| e.to_string())?;
+        if !pending.is_empty() {
+            let names: Vec<String> = pending
+                .iter()
+                .map(|account| escape_html(&account.account_name))
+                .collect();
+            section.push_str(&format!("<p>Invited: {}</p>\n", names.join(", ")));
+        }
+        section.push_str(&format!(
+            r#"<form method="post" action="{0}">{1}<input name="account_name" placeholder="Player" required> <button>Invite</button></form>
+<form method="post" action="{2}">{1}<input name="account_name" placeholder="Member" required> <button>Kick</button></form>
+"#,
+            action("invite"),
+            csrf,
+            action("kick")
+        ));
+    }
+    if role.can_set_roles() {
+        let options: Vec<String> = AllianceRole::ALL
+            .iter()
+            .map(|role| format!("<option value=\"{}\">{}</option>", role, role))
+            .collect();
+        section.push_str(&format!(
+            r#"<form method="post" action="{}">{}<input name="account_name" placeholder="Member" required> <select name="role">{}</select> <button>Set role</button></form>
+"#,
+            action("role"),
+            csrf,
+            options.join("")
+        ));
+    }
+    section.push_str(&diplomacy_section(app_state, galaxy_name, &alliance, role, csrf).await?);
+    section.push_str(&format!(
+        r#"<form method="post" action="{}">{}<button class="logout">Leave alliance</button></form>
+</div>
+"#,
+        action("leave"),
+        csrf
+    ));
+    Ok(section)
+}
+
+/// Relations of an alliance with the others in the galaxy, its open proposals and latest news
+///
+/// Leaders and officers get the forms to change relations.
+async fn diplomacy_section(
+    app_state: &Arc<AppState>,
+    galaxy_name: &str,
+    alliance: &Alliance,
+    role: AllianceRole,
+    csrf: &str,
+) -> Result<String, String> {
+    let db = app_state.database().ok_or("Galaxy service not available")?;
+    let tick = app_state.tick();
+    let others: Vec<Alliance> = AllianceService::new(db.clone())
+        .alliances(galaxy_name)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|other| other.id != alliance.id)
+        .collect();
+    let diplomacy = DiplomacyService::new(db.clone());
+    let relations = diplomacy
+        .relations(alliance.id, tick)
+        .await
+        .map_err(|e| e.to_string())?;
+    let proposals = diplomacy
+        .proposals(alliance.id, tick)
+        .await
+        .map_err(|e| e.to_string())?;
+    let action = |name: &str| format!("/galaxy/{}/diplomacy/{}", galaxy_name, name);
+    let label = |alliance_id: i64| {
+        others
+            .iter()
+            .find(|other| other.id == alliance_id)
+            .map(|other| format!("[{}] {}", escape_html(&other.tag), escape_html(&other.name)))
+            .unwrap_or_default()
+    };
+    let diplomat = role.can_conduct_diplomacy();
+
+    let mut section = format!(
+        "<h3>Diplomacy</h3>\n<p><a href=\"/{}/diplomacy\">Galaxy diplomacy log</a></p>\n",
+        galaxy_name
+    );
+    for proposal in &proposals {
+        let expires = seconds_to_readable(proposal.expires_at.saturating_sub(tick));
+        if proposal.from_alliance == alliance.id {
+            section.push_str(&format!(
+                "<p>You proposed {} to {}, open for {}</p>\n",
+                proposal.relation.describe(),
+                label(proposal.to_alliance),
+                expires
+            ));
+            continue;
+        }
+        section.push_str(&format!(
+            "<div class=\"system-item\"><div><strong>{}</strong> proposes {}, open for {}</div>\n",
+            label(proposal.from_alliance),
+            proposal.relation.describe(),
+            expires
+        ));
+        if diplomat {
+            section.push_str(&format!(
+                r#"<div><form method="post" action="{}" style="display:inline">{}<input type="hidden" name="proposal_id" value="{}"><button>Accept</button></form>
+<form method="post" action="{}" style="display:inline">{}<input type="hidden" name="proposal_id" value="{}"><button>Reject</button></form></div>
+"#,
+                action("accept"),
+                csrf,
+                proposal.id,
+                action("reject"),
+                csrf,
+                proposal.id
+            ));
+        }
+        section.push_str("</div>\n");
+    }
+
+    for other in &others {
+        let status = relations.get(&other.id);
+        let relation = status.map_or(Relation::Neutral, |status| status.relation);
+        let mut description = relation.describe().to_string();
+        if let Some(expires_at) = status.and_then(|status| status.expires_at) {
+            description.push_str(&format!(
+                ", ends in {}",
+                seconds_to_readable(expires_at.saturating_sub(tick))
+            ));
+        }
+        section.push_str(&format!(
+            "<div class=\"system-item\"><div><strong>[{}] {}</strong> &middot; {}</div>\n",
+            escape_html(&other.tag),
+            escape_html(&other.name),
+            description
+        ));
+        if diplomat {
+            let hidden = format!(
+                "{}<input type=\"hidden\" name=\"alliance_id\" value=\"{}\">",
+                csrf, other.id
+            );
+            let mut forms = Vec::new();
+            let options: Vec<String> = Relation::PROPOSABLE
+                .iter()
+                .filter(|proposal| relation.can_propose(**proposal))
+                .map(|proposal| {
+                    format!(
+                        "<option value=\"{}\">{}</option>",
+                        proposal,
+                        proposal.describe()
+                    )
+                })
+                .collect();
+            if !options.is_empty() {
+                forms.push(format!(
+                    r#"<form method="post" action="{}" style="display:inline">{}<select name="relation">{}</select> <button>Propose</button></form>"#,
+                    action("propose"),
+                    hidden,
+                    options.join("")
+                ));
+            }
+            if relation.can_declare_war() {
+                forms.push(format!(
+                    r#"<form method="post" action="{}" style="display:inline">{}<button>Declare war</button></form>"#,
+                    action("war"),
+                    hidden
+                ));
+            }
+            if relation.can_cancel() {
+                forms.push(format!(
+                    r#"<form method="post" action="{}" style="display:inline">{}<button>Cancel {}</button></form>"#,
+                    action("cancel"),
+                    hidden,
+                    relation.describe()
+                ));
+            }
+            section.push_str(&format!("<div>{}</div>\n", forms.join("\n")));
+        }
+        section.push_str("</div>\n");
+    }
+
+    // Relation changes involving the alliance double as its notifications
+    for entry in diplomacy
+        .log(galaxy_name, Some(alliance.id), DASHBOARD_LOG_LENGTH)
+        .await
+        .map_err(|e| e.to_string())?
+    {
+        section.push_str(&format!(
+            "<p>{} ago: {}</p>\n",
+            seconds_to_readable(tick.saturating_sub(entry.tick as usize)),
+            escape_html(&entry.message)
+        ));
+    }
+    Ok(section)
+}
+
+fn article(role: AllianceRole) -> String {
+    match role {
+        AllianceRole::Officer => format!("an {}", role),
+        _ => format!("a {}", role),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use galactic_war::{config::GalaxyConfig, Database, ManualClock};
+
+    /// A server with a galaxy named andromeda
+    async fn setup() -> (Arc<AppState>, Database) {
+        let db = Database::new_test().await.unwrap();
+        let app_state = Arc::new(
+            AppState::new_with_database(db.clone(), Arc::new(ManualClock::new(1_700_000_000)))
+                .await
+                .unwrap(),
+        );
+        let config: GalaxyConfig =
+            serde_yaml::from_str(include_str!("../galaxies/blitz.yaml")).unwrap();
+        app_state
+            .create_galaxy("andromeda", &config, app_state.tick())
+            .await
+            .unwrap();
+        (app_state, db)
+    }
+
+    /// Join andromeda as a new user
+    async fn join(app_state: &AppState, db: &Database, name: &str) -> UserGalaxyAccount {
+        let user_id = db
+            .create_user(name, &format!("{}@example.com", name), "hash")
+            .await
+            .unwrap();
+        let (account, _) = UserService::new(db.clone())
+            .join_galaxy(user_id, "andromeda", name, app_state)
+            .await
+            .unwrap();
+        account
+    }
+
+    #[tokio::test]
+    async fn test_alliance_section_escapes_names() {
+        let (app_state, db) = setup().await;
+        let account = join(&app_state, &db, "<i>Ada</i>").await;
+        AllianceService::new(db.clone())
+            .create(account.id, "<script>alert(1)</script>", "EVIL")
+            .await
+            .unwrap();
+
+        let section = alliance_section(&app_state, "andromeda", &account, "")
+            .await
+            .unwrap();
+        assert!(!section.contains("<script>"));
+        assert!(section.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(!section.contains("<i>Ada</i>"));
+        assert!(section.contains("&lt;i&gt;Ada&lt;/i&gt;"));
+    }
+
+    #[tokio::test]
+    async fn test_diplomacy_escapes_names() {
+        let (app_state, db) = setup().await;
+        let ada = join(&app_state, &db, "ada").await;
+        let bob = join(&app_state, &db, "bob").await;
+        let alliances = AllianceService::new(db.clone());
+        alliances
+            .create(ada.id, "<script>alert(1)</script>", "EVIL")
+            .await
+            .unwrap();
+        let good = alliances.create(bob.id, "Good", "GOOD").await.unwrap();
+        DiplomacyService::new(db.clone())
+            .declare_war(ada.id, good.id, app_state.tick())
+            .await
+            .unwrap();
+
+        let Html(page) = diplomacy_get(Path("andromeda".to_string()), Extension(app_state.clone()))
+            .await
+            .unwrap();
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("&lt;script&gt;alert(1)&lt;/script&gt; &middot; [GOOD] Good"));
+        assert!(page.contains("[EVIL] &lt;script&gt;alert(1)&lt;/script&gt; declared war"));
+
+        let section = alliance_section(&app_state, "andromeda", &bob, "")
+            .await
+            .unwrap();
+        assert!(!section.contains("<script>"));
+    }
+}
@@ -0,0 +1,1541 @@
+use axum::{
+    extract::{
+        rejection::{JsonRejection, PathRejection},
+        Extension, FromRequest, FromRequestParts,
+    },
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware,
+    response::{
+        sse::{Event as SseEvent, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{any, get, post},
+    Json, Router,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, Stream};
+use galactic_war::{
+    api_tokens::{ApiTokenError, ApiTokenService, TokenScope},
+    app::AppState,
+    config::{GalaxyConfig, GalaxySize},
+    lifecycle::GalaxyPhase,
+    live::LiveUpdate,
+    AuthService, Coords, Cost, Details, Event, StructureInfo, StructureType, SystemInfo, User,
+    UserGalaxyAccount, UserService, UserServiceError,
+};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{IntoParams, Modify, OpenApi, ToSchema};
+
+use crate::auth::{csrf_token, get_current_user, CSRF_HEADER, SESSION_COOKIE};
+
+/// Where the current version of the API is served
+pub const PREFIX: &str = "/api/v1";
+
+/// Routes of the JSON API, to be nested under [`PREFIX`]
+///
+/// Reading galaxies and systems is public like their pages, acting needs a logged in user or an
+/// API token with a scope allowing it.
+pub fn routes() -> Router {
+    Router::new()
+        .route("/galaxies", get(galaxies_get).post(galaxies_post))
+        .route("/galaxies/:galaxy", get(galaxy_get))
+        .route("/galaxies/:galaxy/join", post(join_post))
+        .route("/galaxies/:galaxy/account", get(galaxy_account_get))
+        .route("/galaxies/:galaxy/live", get(live_get))
+        .route("/galaxies/:galaxy/systems", get(systems_get))
+        .route("/galaxies/:galaxy/systems/:x/:y", get(system_get))
+        .route("/galaxies/:galaxy/systems/:x/:y/events", get(events_get))
+        .route("/galaxies/:galaxy/systems/:x/:y/costs", get(costs_get))
+        .route(
+            "/galaxies/:galaxy/systems/:x/:y/structures/:structure",
+            get(structure_get),
+        )
+        .route(
+            "/galaxies/:galaxy/systems/:x/:y/structures/:structure/build",
+            post(build_post),
+        )
+        .route("/account", get(account_get))
+        .route("/openapi.json", get(openapi_get))
+        .route("/*path", any(not_found))
+        .layer(middleware::map_response(error_body))
+}
+
+/// OpenAPI document of the API, generated from the handlers and types
+///
+/// Served at /api/v1/openapi.json, and kept in the docs as `technical/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Galactic War API",
+        version = "1",
+        description = "JSON API of Galactic War, for scripts and other clients"
+    ),
+    servers((url = "/api/v1")),
+    paths(
+        galaxies_get,
+        galaxies_post,
+        galaxy_get,
+        join_post,
+        galaxy_account_get,
+        live_get,
+        systems_get,
+        system_get,
+        events_get,
+        costs_get,
+        structure_get,
+        build_post,
+        account_get,
+        openapi_get,
+    ),
+    modifiers(&Authentication)
+)]
+pub struct ApiDoc;
+
+/// Adds the ways to authenticate, the session cookie and API tokens
+struct Authentication;
+
+impl Modify for Authentication {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                SESSION_COOKIE,
+                "Session cookie set by logging in at /login, allowed to do everything. Requests \
+                 changing anything also need the X-CSRF-Token header, from GET /account",
+            ))),
+        );
+        components.add_security_scheme(
+            "token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(
+                        "Personal API token from the dashboard, its scope is read_only, play or admin",
+                    ))
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// An error of the API, sent as `{"error": {"status", "code", "message"}}`
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, message)
+    }
+
+    fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, message)
+    }
+
+    fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, message)
+    }
+
+    /// The game refused an action, like a build without enough resources
+    fn conflict(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, message)
+    }
+
+    fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+}
+
+/// Body of every error response
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+/// What went wrong
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ErrorDetail {
+    /// HTTP status code
+    pub status: u16,
+    /// The status in snake case, like `not_found`
+    pub code: String,
+    /// What went wrong, for people
+    pub message: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let code = self
+            .status
+            .canonical_reason()
+            .unwrap_or("Error")
+            .to_lowercase()
+            .replace(' ', "_");
+        let body = ErrorResponse {
+            error: ErrorDetail {
+                status: self.status.as_u16(),
+                code,
+                message: self.message,
+            },
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::new(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::new(rejection.status(), rejection.body_text())
+    }
+}
+
+/// Path parameters, rejected with an [`ApiError`]
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+struct ApiPath<T>(T);
+
+/// A JSON body, rejected with an [`ApiError`]
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+struct ApiJson<T>(T);
+
+/// Handler for requests to unknown API routes
+async fn not_found() -> ApiError {
+    ApiError::not_found("No such API route")
+}
+
+/// Give the errors axum answers by itself, like 405 for a wrong method, the API's error body
+async fn error_body(response: Response) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type.as_bytes().starts_with(b"application/json"));
+    let status = response.status();
+    if status.is_success() || status.is_redirection() || is_json {
+        return response;
+    }
+    let message = status.canonical_reason().unwrap_or("Error");
+    ApiError::new(status, message).into_response()
+}
+
+/// A galaxy on the API
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct GalaxyResponse {
+    pub name: String,
+    pub phase: GalaxyPhase,
+    /// Game time of the galaxy, which stops while it's paused or after it ended
+    pub tick: usize,
+    pub size: GalaxySize,
+    pub systems: usize,
+}
+
+/// Response listing the galaxies
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct GalaxiesResponse {
+    pub galaxies: Vec<GalaxyResponse>,
+}
+
+/// Request to create a galaxy
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CreateGalaxyRequest {
+    pub name: String,
+}
+
+/// A system on the API, with its coordinates
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SystemResponse {
+    #[serde(flatten)]
+    pub coords: Coords,
+    #[serde(flatten)]
+    pub info: SystemInfo,
+}
+
+/// Response listing the systems of a galaxy
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SystemsResponse {
+    pub tick: usize,
+    pub systems: Vec<SystemResponse>,
+}
+
+/// Response listing the events in flight in a system
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct EventsResponse {
+    pub events: Vec<Event>,
+}
+
+/// Response with the cost of the next level of every structure of a system
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CostsResponse {
+    pub costs: IndexMap<StructureType, Cost>,
+}
+
+/// A structure on the API
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct StructureResponse {
+    pub structure: StructureType,
+    #[serde(flatten)]
+    pub info: StructureInfo,
+}
+
+/// Request to join a galaxy
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct JoinRequest {
+    pub account_name: String,
+}
+
+/// A galaxy account on the API
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AccountResponse {
+    pub id: i64,
+    pub galaxy: String,
+    pub account_name: String,
+    pub joined_at: DateTime<Utc>,
+    pub last_active: DateTime<Utc>,
+    pub alliance_id: Option<i64>,
+    /// Systems the account owns
+    pub systems: Vec<Coords>,
+}
+
+/// The logged in user and their galaxy accounts
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UserResponse {
+    pub id: i64,
+    pub username: String,
+    pub email: String,
+    pub accounts: Vec<AccountResponse>,
+    /// CSRF token to send in the X-CSRF-Token header, when logged in with the session cookie
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub csrf_token: Option<String>,
+}
+
+/// Load a galaxy, returning its phase and game tick
+async fn load_galaxy(app_state: &Arc<AppState>, galaxy: &str) -> Result<GalaxyResponse, ApiError> {
+    let tick = app_state.tick();
+    app_state
+        .game_tick(galaxy, tick)
+        .await
+        .map_err(|_| ApiError::not_found(format!("Galaxy '{}' not found", galaxy)))?;
+    let galaxies = app_state.galaxies().lock().await;
+    let loaded = galaxies
+        .get(galaxy)
+        .ok_or_else(|| ApiError::not_found(format!("Galaxy '{}' not found", galaxy)))?;
+    Ok(GalaxyResponse {
+        name: galaxy.to_string(),
+        phase: loaded.phase(tick),
+        tick: loaded.game_tick(tick),
+        size: loaded.get_config().size.clone(),
+        systems: loaded.systems().len(),
+    })
+}
+
+/// Load a galaxy, checking it has a system at some coordinates
+async fn load_system(
+    app_state: &Arc<AppState>,
+    galaxy: &str,
+    x: usize,
+    y: usize,
+) -> Result<Coords, ApiError> {
+    load_galaxy(app_state, galaxy).await?;
+    let coords = Coords { x, y };
+    let galaxies = app_state.galaxies().lock().await;
+    if galaxies
+        .get(galaxy)
+        .is_some_and(|galaxy| galaxy.systems().contains_key(&coords))
+    {
+        Ok(coords)
+    } else {
+        Err(ApiError::not_found(format!(
+            "System ({}, {}) not found in galaxy '{}'",
+            x, y, galaxy
+        )))
+    }
+}
+
+fn structure_type(structure: &str) -> Result<StructureType, ApiError> {
+    StructureType::from_str(structure)
+        .map_err(|_| ApiError::not_found(format!("Structure '{}' not found", structure)))
+}
+
+/// Get the details of a structure in a system
+async fn structure_details(
+    app_state: &Arc<AppState>,
+    galaxy: &str,
+    coords: Coords,
+    structure: StructureType,
+) -> Result<StructureInfo, ApiError> {
+    match app_state
+        .get_galaxy_details(galaxy, app_state.tick(), coords, Some(structure))
+        .await
+        .map_err(ApiError::internal)?
+    {
+        Details::Structure(info) => Ok(info),
+        Details::System(_) => Err(ApiError::internal("Unexpected Details type")),
+    }
+}
+
+/// How a request authenticates, with an API token or else the session cookie
+#[derive(FromRequestParts)]
+struct Credentials {
+    jar: CookieJar,
+    headers: HeaderMap,
+    method: Method,
+}
+
+impl Credentials {
+    /// Get the user, checking an API token has a scope allowing what it's used for
+    ///
+    /// Logged in browsers can do everything their user can, but need the CSRF token of their
+    /// session in a header to change anything.
+    async fn user(&self, app_state: &Arc<AppState>, needed: TokenScope) -> Result<User, ApiError> {
+        let Some(authorization) = self.headers.get(header::AUTHORIZATION) else {
+            let user = get_current_user(self.jar.clone(), Extension(app_state.clone()))
+                .await
+                .ok_or_else(|| ApiError::unauthorized("Not logged in"))?;
+            if !self.method.is_safe() && !self.has_csrf_token() {
+                return Err(ApiError::forbidden("Missing or invalid CSRF token"));
+            }
+            return Ok(user);
+        };
+        let token = authorization
+            .to_str()
+            .ok()
+            .and_then(|value| value.split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+            .map(|(_, token)| token.trim())
+            .ok_or_else(|| ApiError::unauthorized("Authorization must be a bearer token"))?;
+        let db = app_state
+            .database()
+            .ok_or_else(|| ApiError::internal("Galaxy service not available"))?;
+        let (user, scope) = ApiTokenService::new(db.clone())
+            .authenticate(token)
+            .await
+            .map_err(|e| match e {
+                ApiTokenError::Database(e) => ApiError::internal(e.to_string()),
+                e => ApiError::unauthorized(e.to_string()),
+            })?;
+        if !scope.allows(needed) {
+            return Err(ApiError::forbidden(format!(
+                "This token needs the {} scope",
+                needed
+            )));
+        }
+        Ok(user)
+    }
+
+    /// Whether the request has the CSRF token of its session in the header
+    fn has_csrf_token(&self) -> bool {
+        let (Some(session), Some(token)) =
+            (self.jar.get(SESSION_COOKIE), self.headers.get(CSRF_HEADER))
+        else {
+            return false;
+        };
+        token
+            .to_str()
+            .is_ok_and(|token| AuthService::verify_csrf_token(session.value(), token))
+    }
+
+    /// The CSRF token of the session, when the request is authenticated by one
+    fn csrf_token(&self) -> Option<String> {
+        if self.headers.contains_key(header::AUTHORIZATION) {
+            return None;
+        }
+        csrf_token(&self.jar)
+    }
+}
+
+fn user_service(app_state: &Arc<AppState>) -> Result<UserService, ApiError> {
+    app_state
+        .database()
+        .map(|db| UserService::new(db.clone()))
+        .ok_or_else(|| ApiError::internal("Galaxy service not available"))
+}
+
+/// Get the galaxy account of the logged in user
+async fn current_account(
+    credentials: &Credentials,
+    app_state: &Arc<AppState>,
+    galaxy: &str,
+    needed: TokenScope,
+) -> Result<UserGalaxyAccount, ApiError> {
+    let user = credentials.user(app_state, needed).await?;
+    user_service(app_state)?
+        .get_user_galaxy_account(user.id, galaxy)
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?
+        .ok_or_else(|| ApiError::forbidden("You don't have an account in this galaxy"))
+}
+
+async fn account_response(
+    app_state: &Arc<AppState>,
+    account: UserGalaxyAccount,
+) -> Result<AccountResponse, ApiError> {
+    let systems = user_service(app_state)?
+        .get_user_systems_coords(account.id)
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    Ok(AccountResponse {
+        id: account.id,
+        galaxy: account.galaxy_name,
+        account_name: account.account_name,
+        joined_at: account.joined_at,
+        last_active: account.last_active,
+        alliance_id: account.alliance_id,
+        systems,
+    })
+}
+
+/// Path of a galaxy
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+struct GalaxyPath {
+    /// Name of the galaxy
+    galaxy: String,
+}
+
+/// Path of a system
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+struct SystemPath {
+    /// Name of the galaxy
+    galaxy: String,
+    /// X coordinate of the system
+    x: usize,
+    /// Y coordinate of the system
+    y: usize,
+}
+
+/// Path of a structure of a system
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+struct StructurePath {
+    /// Name of the galaxy
+    galaxy: String,
+    /// X coordinate of the system
+    x: usize,
+    /// Y coordinate of the system
+    y: usize,
+    /// Lowercase name of the structure, like `asteroidmine`
+    structure: String,
+}
+
+/// List the galaxies
+#[utoipa::path(
+    get,
+    path = "/galaxies",
+    tag = "galaxies",
+    responses((status = 200, description = "All galaxies", body = GalaxiesResponse))
+)]
+async fn galaxies_get(
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Json<GalaxiesResponse>, ApiError> {
+    let mut names = app_state.list_galaxies().await;
+    names.sort();
+    names.dedup();
+    let mut galaxies = Vec::new();
+    for name in names {
+        galaxies.push(load_galaxy(&app_state, &name).await?);
+    }
+    Ok(Json(GalaxiesResponse { galaxies }))
+}
+
+/// Create a galaxy
+///
+/// The galaxy has the same config as those created on the web interface. Tokens need the admin
+/// scope to create galaxies.
+#[utoipa::path(
+    post,
+    path = "/galaxies",
+    tag = "galaxies",
+    request_body = CreateGalaxyRequest,
+    security(("session" = []), ("token" = ["admin"])),
+    responses(
+        (status = 201, description = "The new galaxy", body = GalaxyResponse),
+        (status = 400, description = "Invalid galaxy name", body = ErrorResponse),
+        (status = 401, description = "Not logged in", body = ErrorResponse),
+        (status = 403, description = "The token isn't an admin token, or the CSRF token is missing", body = ErrorResponse),
+        (status = 409, description = "The galaxy already exists", body = ErrorResponse),
+    )
+)]
+async fn galaxies_post(
+    credentials: Credentials,
+    Extension(app_state): Extension<Arc<AppState>>,
+    ApiJson(request): ApiJson<CreateGalaxyRequest>,
+) -> Result<(StatusCode, Json<GalaxyResponse>), ApiError> {
+    credentials.user(&app_state, TokenScope::Admin).await?;
+    if request.name.is_empty()
+        || !request
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(ApiError::bad_request(
+            "Galaxy names are letters, digits, '-' and '_'",
+        ));
+    }
+    if app_state.list_galaxies().await.contains(&request.name) {
+        return Err(ApiError::conflict(format!(
+            "Galaxy '{}' already exists",
+            request.name
+        )));
+    }
+
+    // FIXME: Hardcoded galaxy config, like the web interface
+    let config: GalaxyConfig = serde_yaml::from_str(include_str!("../galaxies/blitz.yaml"))
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    app_state
+        .create_galaxy(&request.name, &config, app_state.tick())
+        .await
+        .map_err(ApiError::internal)?;
+    Ok((
+        StatusCode::CREATED,
+        Json(load_galaxy(&app_state, &request.name).await?),
+    ))
+}
+
+/// Get a galaxy
+#[utoipa::path(
+    get,
+    path = "/galaxies/{galaxy}",
+    tag = "galaxies",
+    params(GalaxyPath),
+    responses(
+        (status = 200, description = "The galaxy", body = GalaxyResponse),
+        (status = 404, description = "Unknown galaxy", body = ErrorResponse),
+    )
+)]
+async fn galaxy_get(
+    ApiPath(path): ApiPath<GalaxyPath>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Json<GalaxyResponse>, ApiError> {
+    Ok(Json(load_galaxy(&app_state, &path.galaxy).await?))
+}
+
+/// Join a galaxy
+///
+/// Creates an account for the user in the galaxy, with a system of its own.
+#[utoipa::path(
+    post,
+    path = "/galaxies/{galaxy}/join",
+    tag = "accounts",
+    params(GalaxyPath),
+    request_body = JoinRequest,
+    security(("session" = []), ("token" = ["play"])),
+    responses(
+        (status = 201, description = "The new account", body = AccountResponse),
+        (status = 400, description = "Invalid account name", body = ErrorResponse),
+        (status = 401, description = "Not logged in", body = ErrorResponse),
+        (status = 403, description = "The token can't play, or the CSRF token is missing", body = ErrorResponse),
+        (status = 404, description = "Unknown galaxy", body = ErrorResponse),
+        (status = 409, description = "The name is taken, the user already joined, or the galaxy is closed or full", body = ErrorResponse),
+    )
+)]
+async fn join_post(
+    ApiPath(path): ApiPath<GalaxyPath>,
+    credentials: Credentials,
+    Extension(app_state): Extension<Arc<AppState>>,
+    ApiJson(request): ApiJson<JoinRequest>,
+) -> Result<(StatusCode, Json<AccountResponse>), ApiError> {
+    let user = credentials.user(&app_state, TokenScope::Play).await?;
+    load_galaxy(&app_state, &path.galaxy).await?;
+    if request.account_name.trim().is_empty() {
+        return Err(ApiError::bad_request("Account name can't be empty"));
+    }
+
+    let (account, _) = user_service(&app_state)?
+        .join_galaxy(user.id, &path.galaxy, &request.account_name, &app_state)
+        .await
+        .map_err(|e| match e {
+            UserServiceError::AccountNameTaken
+            | UserServiceError::UserAlreadyInGalaxy
+            | UserServiceError::GalaxyFull
+            | UserServiceError::RegistrationClosed(_) => ApiError::conflict(e.to_string()),
+            e => ApiError::internal(e.to_string()),
+        })?;
+    Ok((
+        StatusCode::CREATED,
+        Json(account_response(&app_state, account).await?),
+    ))
+}
+
+/// Get the user's account in a galaxy
+#[utoipa::path(
+    get,
+    path = "/galaxies/{galaxy}/account",
+    tag = "accounts",
+    params(GalaxyPath),
+    security(("session" = []), ("token" = ["read_only"])),
+    responses(
+        (status = 200, description = "The account", body = AccountResponse),
+        (status = 401, description = "Not logged in", body = ErrorResponse),
+        (status = 403, description = "No account in the galaxy", body = ErrorResponse),
+        (status = 404, description = "Unknown galaxy", body = ErrorResponse),
+    )
+)]
+async fn galaxy_account_get(
+    ApiPath(path): ApiPath<GalaxyPath>,
+    credentials: Credentials,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Json<AccountResponse>, ApiError> {
+    load_galaxy(&app_state, &path.galaxy).await?;
+    let account =
+        current_account(&credentials, &app_state, &path.galaxy, TokenScope::ReadOnly).await?;
+    Ok(Json(account_response(&app_state, account).await?))
+}
+
+/// Follow the user's systems in a galaxy
+///
+/// Streams server-sent events for every change to the user's systems, named after the `type`
+/// of the update: `system` with the whole system when it changed, and every 15 seconds so its
+/// resources keep moving, `build_complete` when an upgrade finished and `raid` when an NPC
+/// system raided it.
+#[utoipa::path(
+    get,
+    path = "/galaxies/{galaxy}/live",
+    tag = "accounts",
+    params(GalaxyPath),
+    security(("session" = []), ("token" = ["read_only"])),
+    responses(
+        (status = 200, description = "The updates, as server-sent events", content_type = "text/event-stream", body = LiveUpdate),
+        (status = 401, description = "Not logged in", body = ErrorResponse),
+        (status = 403, description = "No account in the galaxy", body = ErrorResponse),
+        (status = 404, description = "Unknown galaxy", body = ErrorResponse),
+    )
+)]
+async fn live_get(
+    ApiPath(path): ApiPath<GalaxyPath>,
+    credentials: Credentials,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Sse<impl Stream<Item = Result<SseEvent, Infallible>>>, ApiError> {
+    load_galaxy(&app_state, &path.galaxy).await?;
+    let account =
+        current_account(&credentials, &app_state, &path.galaxy, TokenScope::ReadOnly).await?;
+    let users = user_service(&app_state)?;
+    let owned = users
+        .get_user_systems_coords(account.id)
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    let receiver = app_state.live_hub().subscribe();
+
+    let events = stream::unfold(
+        (users, receiver, account.id, path.galaxy, owned),
+        |(users, mut receiver, account_id, galaxy, mut owned)| async move {
+            loop {
+                let update = match receiver.recv().await {
+                    Ok(update) => update,
+                    // The systems are sent whole, so the next update catches up
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                };
+                if update.galaxy != galaxy || !owned.contains(&update.coords) {
+                    continue;
+                }
+                // Stop following systems the user lost
+                owned = users.get_user_systems_coords(account_id).await.ok()?;
+                if !owned.contains(&update.coords) {
+                    continue;
+                }
+                let event = SseEvent::default()
+                    .event(update.event.name())
+                    .json_data(&update)
+                    .unwrap_or_default();
+                return Some((Ok(event), (users, receiver, account_id, galaxy, owned)));
+            }
+        },
+    );
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// List the systems of a galaxy
+#[utoipa::path(
+    get,
+    path = "/galaxies/{galaxy}/systems",
+    tag = "systems",
+    params(GalaxyPath),
+    responses(
+        (status = 200, description = "Every system of the galaxy", body = SystemsResponse),
+        (status = 404, description = "Unknown galaxy", body = ErrorResponse),
+    )
+)]
+async fn systems_get(
+    ApiPath(path): ApiPath<GalaxyPath>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Json<SystemsResponse>, ApiError> {
+    let galaxy = path.galaxy;
+    let tick = load_galaxy(&app_state, &galaxy).await?.tick;
+    let mut coords = {
+        let galaxies = app_state.galaxies().lock().await;
+        galaxies
+            .get(&galaxy)
+            .map(|galaxy| galaxy.systems().keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+    coords.sort_by_key(|coords| (coords.x, coords.y));
+
+    let mut systems = Vec::new();
+    for coords in coords {
+        let info = app_state
+            .system_info(&galaxy, coords)
+            .await
+            .map_err(ApiError::internal)?;
+        systems.push(SystemResponse { coords, info });
+    }
+    Ok(Json(SystemsResponse { tick, systems }))
+}
+
+/// Get a system
+#[utoipa::path(
+    get,
+    path = "/galaxies/{galaxy}/systems/{x}/{y}",
+    tag = "systems",
+    params(SystemPath),
+    responses(
+        (status = 200, description = "The system", body = SystemResponse),
+        (status = 400, description = "Invalid coordinates", body = ErrorResponse),
+        (status = 404, description = "Unknown galaxy or system", body = ErrorResponse),
+    )
+)]
+async fn system_get(
+    ApiPath(path): ApiPath<SystemPath>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Json<SystemResponse>, ApiError> {
+    let coords = load_system(&app_state, &path.galaxy, path.x, path.y).await?;
+    let info = app_state
+        .system_info(&path.galaxy, coords)
+        .await
+        .map_err(ApiError::internal)?;
+    Ok(Json(SystemResponse { coords, info }))
+}
+
+/// List the events in flight in a system
+#[utoipa::path(
+    get,
+    path = "/galaxies/{galaxy}/systems/{x}/{y}/events",
+    tag = "systems",
+    params(SystemPath),
+    responses(
+        (status = 200, description = "The events of the system", body = EventsResponse),
+        (status = 400, description = "Invalid coordinates", body = ErrorResponse),
+        (status = 404, description = "Unknown galaxy or system", body = ErrorResponse),
+    )
+)]
+async fn events_get(
+    ApiPath(path): ApiPath<SystemPath>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Json<EventsResponse>, ApiError> {
+    let coords = load_system(&app_state, &path.galaxy, path.x, path.y).await?;
+    let info = app_state
+        .system_info(&path.galaxy, coords)
+        .await
+        .map_err(ApiError::internal)?;
+    Ok(Json(EventsResponse {
+        events: info.events,
+    }))
+}
+
+/// Get the cost of the next level of every structure of a system
+#[utoipa::path(
+    get,
+    path = "/galaxies/{galaxy}/systems/{x}/{y}/costs",
+    tag = "systems",
+    params(SystemPath),
+    responses(
+        (status = 200, description = "The costs, by structure", body = CostsResponse),
+        (status = 400, description = "Invalid coordinates", body = ErrorResponse),
+        (status = 404, description = "Unknown galaxy or system", body = ErrorResponse),
+    )
+)]
+async fn costs_get(
+    ApiPath(path): ApiPath<SystemPath>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Json<CostsResponse>, ApiError> {
+    let coords = load_system(&app_state, &path.galaxy, path.x, path.y).await?;
+    // The colony builds the other structures, so it knows what they cost
+    let colony = structure_details(&app_state, &path.galaxy, coords, StructureType::Colony).await?;
+    Ok(Json(CostsResponse {
+        costs: colony.builds.unwrap_or_default(),
+    }))
+}
+
+/// Get a structure of a system
+#[utoipa::path(
+    get,
+    path = "/galaxies/{galaxy}/systems/{x}/{y}/structures/{structure}",
+    tag = "systems",
+    params(StructurePath),
+    responses(
+        (status = 200, description = "The structure", body = StructureResponse),
+        (status = 400, description = "Invalid coordinates", body = ErrorResponse),
+        (status = 404, description = "Unknown galaxy, system or structure", body = ErrorResponse),
+    )
+)]
+async fn structure_get(
+    ApiPath(path): ApiPath<StructurePath>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Json<StructureResponse>, ApiError> {
+    let coords = load_system(&app_state, &path.galaxy, path.x, path.y).await?;
+    let structure = structure_type(&path.structure)?;
+    let info = structure_details(&app_state, &path.galaxy, coords, structure).await?;
+    Ok(Json(StructureResponse { structure, info }))
+}
+
+/// Upgrade a structure
+///
+/// Starts upgrading a structure of a system the user owns, returning the build event.
+#[utoipa::path(
+    post,
+    path = "/galaxies/{galaxy}/systems/{x}/{y}/structures/{structure}/build",
+    tag = "systems",
+    params(StructurePath),
+    security(("session" = []), ("token" = ["play"])),
+    responses(
+        (status = 201, description = "The build event", body = Event),
+        (status = 400, description = "Invalid coordinates", body = ErrorResponse),
+        (status = 401, description = "Not logged in", body = ErrorResponse),
+        (status = 403, description = "The system isn't the user's, the token can't play, or the CSRF token is missing", body = ErrorResponse),
+        (status = 404, description = "Unknown galaxy, system or structure", body = ErrorResponse),
+        (status = 409, description = "The game refused the build", body = ErrorResponse),
+    )
+)]
+async fn build_post(
+    ApiPath(path): ApiPath<StructurePath>,
+    credentials: Credentials,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<(StatusCode, Json<Event>), ApiError> {
+    let coords = load_system(&app_state, &path.galaxy, path.x, path.y).await?;
+    let structure = structure_type(&path.structure)?;
+    let account = current_account(&credentials, &app_state, &path.galaxy, TokenScope::Play).await?;
+    let owned = user_service(&app_state)?
+        .get_user_systems_coords(account.id)
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    if !owned.contains(&coords) {
+        return Err(ApiError::forbidden("You don't own this system"));
+    }
+
+    let event = app_state
+        .build_structure(&path.galaxy, app_state.tick(), coords, structure)
+        .await
+        .map_err(ApiError::conflict)?;
+    Ok((StatusCode::CREATED, Json(event)))
+}
+
+/// Get the user and their galaxy accounts
+#[utoipa::path(
+    get,
+    path = "/account",
+    tag = "accounts",
+    security(("session" = []), ("token" = ["read_only"])),
+    responses(
+        (status = 200, description = "The user", body = UserResponse),
+        (status = 401, description = "Not logged in", body = ErrorResponse),
+    )
+)]
+async fn account_get(
+    credentials: Credentials,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Json<UserResponse>, ApiError> {
+    let user = credentials.user(&app_state, TokenScope::ReadOnly).await?;
+    let accounts = user_service(&app_state)?
+        .get_user_galaxy_accounts(user.id)
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    let mut response = UserResponse {
+        id: user.id,
+        username: user.username,
+        email: user.email,
+        accounts: Vec::new(),
+        csrf_token: credentials.csrf_token(),
+    };
+    for account in accounts {
+        response
+            .accounts
+            .push(account_response(&app_state, account).await?);
+    }
+    Ok(Json(response))
+}
+
+/// The OpenAPI document of the API
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "meta",
+    responses((status = 200, description = "This document"))
+)]
+async fn openapi_get() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, Method, Request};
+    use galactic_war::{AuthService, Database, ManualClock};
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    const START: usize = 1_700_000_000;
+
+    struct TestServer {
+        app: Router,
+        app_state: Arc<AppState>,
+        db: Database,
+        clock: Arc<ManualClock>,
+    }
+
+    impl TestServer {
+        async fn new() -> Self {
+            let clock = Arc::new(ManualClock::new(START));
+            let db = Database::new_test().await.unwrap();
+            let app_state = AppState::new_with_database(db.clone(), clock.clone())
+                .await
+                .unwrap();
+            let app_state = Arc::new(app_state);
+            Self {
+                app: crate::router(app_state.clone()),
+                app_state,
+                db,
+                clock,
+            }
+        }
+
+        /// Register a user, returning their session cookie
+        async fn login(&self, name: &str) -> String {
+            let user_id = self
+                .db
+                .create_user(name, &format!("{}@example.com", name), "hash")
+                .await
+                .unwrap();
+            let session = AuthService::new(self.db.clone())
+                .create_session(user_id)
+                .await
+                .unwrap();
+            format!("galactic_war_session={}", session.token)
+        }
+
+        /// Create an API token of a user, returning it as an authorization header
+        async fn token(&self, name: &str, scope: TokenScope) -> String {
+            let user = self.db.get_user_by_username(name).await.unwrap().unwrap();
+            let (_, token) = ApiTokenService::new(self.db.clone())
+                .create(user.id, "Bot", scope)
+                .await
+                .unwrap();
+            format!("Bearer {}", token)
+        }
+
+        async fn request(
+            &self,
+            method: Method,
+            uri: &str,
+            auth: Option<&str>,
+            body: Option<Value>,
+        ) -> (StatusCode, Value) {
+            let mut request = Request::builder().method(method).uri(uri);
+            match auth {
+                Some(token) if token.starts_with("Bearer ") => {
+                    request = request.header(header::AUTHORIZATION, token);
+                }
+                Some(cookie) => {
+                    // Logged in browsers send the CSRF token of their session too
+                    let session = cookie.trim_start_matches("galactic_war_session=");
+                    request = request
+                        .header(header::COOKIE, cookie)
+                        .header(CSRF_HEADER, AuthService::csrf_token(session));
+                }
+                None => {}
+            }
+            let request = match body {
+                Some(body) => request
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.to_string())),
+                None => request.body(Body::empty()),
+            }
+            .unwrap();
+            let response = self.app.clone().oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+        }
+
+        async fn get(&self, uri: &str, auth: Option<&str>) -> (StatusCode, Value) {
+            self.request(Method::GET, uri, auth, None).await
+        }
+
+        async fn post(&self, uri: &str, auth: Option<&str>, body: Value) -> (StatusCode, Value) {
+            self.request(Method::POST, uri, auth, Some(body)).await
+        }
+    }
+
+    fn assert_error(response: (StatusCode, Value), status: StatusCode, code: &str) {
+        assert_eq!(response.0, status, "{}", response.1);
+        assert_eq!(response.1["error"]["status"], status.as_u16());
+        assert_eq!(response.1["error"]["code"], code);
+        assert!(response.1["error"]["message"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_galaxies() {
+        let server = TestServer::new().await;
+        assert_eq!(
+            server.get("/api/v1/galaxies", None).await,
+            (StatusCode::OK, json!({"galaxies": []}))
+        );
+
+        assert_error(
+            server
+                .post("/api/v1/galaxies", None, json!({"name": "andromeda"}))
+                .await,
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+        );
+        let ada = server.login("ada").await;
+        let (status, galaxy) = server
+            .post("/api/v1/galaxies", Some(&ada), json!({"name": "andromeda"}))
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(galaxy["name"], "andromeda");
+        assert_eq!(galaxy["phase"], "running");
+        assert_eq!(galaxy["systems"], 200);
+
+        let (status, galaxies) = server.get("/api/v1/galaxies", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(galaxies["galaxies"][0], galaxy);
+        assert_eq!(
+            server.get("/api/v1/galaxies/andromeda", None).await,
+            (StatusCode::OK, galaxy)
+        );
+
+        assert_error(
+            server
+                .post("/api/v1/galaxies", Some(&ada), json!({"name": "andromeda"}))
+                .await,
+            StatusCode::CONFLICT,
+            "conflict",
+        );
+        assert_error(
+            server
+                .post("/api/v1/galaxies", Some(&ada), json!({"name": "../etc"}))
+                .await,
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+        );
+        assert_error(
+            server.post("/api/v1/galaxies", Some(&ada), json!({})).await,
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "unprocessable_entity",
+        );
+        assert_error(
+            server.get("/api/v1/galaxies/nowhere", None).await,
+            StatusCode::NOT_FOUND,
+            "not_found",
+        );
+        assert_error(
+            server.get("/api/v1/nowhere", None).await,
+            StatusCode::NOT_FOUND,
+            "not_found",
+        );
+        assert_error(
+            server.get("/api/v1/nowhere/at/all", None).await,
+            StatusCode::NOT_FOUND,
+            "not_found",
+        );
+        assert_error(
+            server
+                .request(Method::DELETE, "/api/v1/galaxies", None, None)
+                .await,
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method_not_allowed",
+        );
+    }
+
+    #[tokio::test]
+    async fn test_play() {
+        let server = TestServer::new().await;
+        let ada = server.login("ada").await;
+        let bob = server.login("bob").await;
+        server
+            .post("/api/v1/galaxies", Some(&ada), json!({"name": "andromeda"}))
+            .await;
+
+        assert_error(
+            server
+                .post(
+                    "/api/v1/galaxies/andromeda/join",
+                    None,
+                    json!({"account_name": "Ada"}),
+                )
+                .await,
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+        );
+        let (status, account) = server
+            .post(
+                "/api/v1/galaxies/andromeda/join",
+                Some(&ada),
+                json!({"account_name": "Ada"}),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(account["account_name"], "Ada");
+        assert_error(
+            server
+                .post(
+                    "/api/v1/galaxies/andromeda/join",
+                    Some(&bob),
+                    json!({"account_name": "Ada"}),
+                )
+                .await,
+            StatusCode::CONFLICT,
+            "conflict",
+        );
+        let (x, y) = (
+            account["systems"][0]["x"].as_u64().unwrap(),
+            account["systems"][0]["y"].as_u64().unwrap(),
+        );
+        let system = format!("/api/v1/galaxies/andromeda/systems/{}/{}", x, y);
+
+        let (status, user) = server.get("/api/v1/account", Some(&ada)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(user["username"], "ada");
+        assert_eq!(user["accounts"][0], account);
+        assert_eq!(
+            server
+                .get("/api/v1/galaxies/andromeda/account", Some(&ada))
+                .await,
+            (StatusCode::OK, account)
+        );
+        assert_error(
+            server
+                .get("/api/v1/galaxies/andromeda/account", Some(&bob))
+                .await,
+            StatusCode::FORBIDDEN,
+            "forbidden",
+        );
+
+        let (status, info) = server.get(&system, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(info["x"], x);
+        assert_eq!(info["structures"]["colony"], 1);
+        assert_eq!(info["resources"]["metal"], 225);
+        let (status, costs) = server.get(&format!("{}/costs", system), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(costs["costs"]["colony"]["resources"]["metal"], 6);
+        let (status, colony) = server
+            .get(&format!("{}/structures/colony", system), None)
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(colony["structure"], "colony");
+        assert_eq!(colony["level"], 1);
+
+        let build = format!("{}/structures/colony/build", system);
+        assert_error(
+            server.post(&build, Some(&bob), json!(null)).await,
+            StatusCode::FORBIDDEN,
+            "forbidden",
+        );
+        let (status, event) = server.post(&build, Some(&ada), json!(null)).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", event);
+        assert_eq!(event["action"], "build");
+        assert_eq!(event["structure"], "colony");
+        let (status, events) = server.get(&format!("{}/events", system), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(events["events"][0], event);
+        // Only one build at a time
+        assert_error(
+            server.post(&build, Some(&ada), json!(null)).await,
+            StatusCode::CONFLICT,
+            "conflict",
+        );
+
+        server.clock.advance(3600);
+        let (_, info) = server.get(&system, None).await;
+        assert_eq!(info["structures"]["colony"], 2);
+
+        assert_error(
+            server
+                .post(
+                    &format!("{}/structures/spaceport/build", system),
+                    Some(&ada),
+                    json!(null),
+                )
+                .await,
+            StatusCode::NOT_FOUND,
+            "not_found",
+        );
+        assert_error(
+            server
+                .get("/api/v1/galaxies/andromeda/systems/1000/1000", None)
+                .await,
+            StatusCode::NOT_FOUND,
+            "not_found",
+        );
+        assert_error(
+            server
+                .get("/api/v1/galaxies/andromeda/systems/x/1", None)
+                .await,
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tokens() {
+        let server = TestServer::new().await;
+        server.login("ada").await;
+        let read_only = server.token("ada", TokenScope::ReadOnly).await;
+        let play = server.token("ada", TokenScope::Play).await;
+        let admin = server.token("ada", TokenScope::Admin).await;
+
+        let galaxy = json!({"name": "andromeda"});
+        for token in [&read_only, &play] {
+            assert_error(
+                server
+                    .post("/api/v1/galaxies", Some(token), galaxy.clone())
+                    .await,
+                StatusCode::FORBIDDEN,
+                "forbidden",
+            );
+        }
+        let (status, _) = server.post("/api/v1/galaxies", Some(&admin), galaxy).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let join = json!({"account_name": "Ada"});
+        assert_error(
+            server
+                .post(
+                    "/api/v1/galaxies/andromeda/join",
+                    Some(&read_only),
+                    join.clone(),
+                )
+                .await,
+            StatusCode::FORBIDDEN,
+            "forbidden",
+        );
+        let (status, account) = server
+            .post("/api/v1/galaxies/andromeda/join", Some(&play), join)
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, user) = server.get("/api/v1/account", Some(&read_only)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(user["username"], "ada");
+        assert_eq!(
+            server
+                .get("/api/v1/galaxies/andromeda/account", Some(&read_only))
+                .await,
+            (StatusCode::OK, account.clone())
+        );
+
+        let build = format!(
+            "/api/v1/galaxies/andromeda/systems/{}/{}/structures/colony/build",
+            account["systems"][0]["x"], account["systems"][0]["y"]
+        );
+        assert_error(
+            server.post(&build, Some(&read_only), json!(null)).await,
+            StatusCode::FORBIDDEN,
+            "forbidden",
+        );
+        let (status, event) = server.post(&build, Some(&play), json!(null)).await;
+        assert_eq!(status, StatusCode::CREATED, "{}", event);
+
+        for auth in ["Bearer gwt_nope", "Bearer ", "Basic YWRhOmFkYQ=="] {
+            assert_error(
+                server.get("/api/v1/account", Some(auth)).await,
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+            );
+        }
+        let tokens = ApiTokenService::new(server.db.clone());
+        let user_id = user["id"].as_i64().unwrap();
+        let token_id = tokens.tokens(user_id).await.unwrap()[0].id;
+        tokens.revoke(user_id, token_id).await.unwrap();
+        assert_error(
+            server.get("/api/v1/account", Some(&read_only)).await,
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+        );
+    }
+
+    #[tokio::test]
+    async fn test_csrf() {
+        let server = TestServer::new().await;
+        let cookie = server.login("ada").await;
+        let token = server.token("ada", TokenScope::Admin).await;
+
+        // Browsers get the token of their session, API tokens don't need one
+        let (_, user) = server.get("/api/v1/account", Some(&cookie)).await;
+        let session = cookie.trim_start_matches("galactic_war_session=");
+        assert_eq!(user["csrf_token"], AuthService::csrf_token(session));
+        let (_, user) = server.get("/api/v1/account", Some(&token)).await;
+        assert!(user.get("csrf_token").is_none());
+
+        for csrf in [
+            None,
+            Some("0000"),
+            Some(AuthService::csrf_token("other").as_str()),
+        ] {
+            let mut request = Request::builder()
+                .method(Method::POST)
+                .uri("/api/v1/galaxies")
+                .header(header::COOKIE, &cookie)
+                .header(header::CONTENT_TYPE, "application/json");
+            if let Some(csrf) = csrf {
+                request = request.header(CSRF_HEADER, csrf);
+            }
+            let request = request
+                .body(Body::from(json!({"name": "andromeda"}).to_string()))
+                .unwrap();
+            let response = server.app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+        assert!(server.app_state.list_galaxies().await.is_empty());
+
+        let (status, _) = server
+            .post(
+                "/api/v1/galaxies",
+                Some(&cookie),
+                json!({"name": "andromeda"}),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_live() {
+        use futures_util::StreamExt;
+
+        let server = TestServer::new().await;
+        let ada = server.login("ada").await;
+        let bob = server.login("bob").await;
+        server
+            .post("/api/v1/galaxies", Some(&ada), json!({"name": "andromeda"}))
+            .await;
+        let (_, account) = server
+            .post(
+                "/api/v1/galaxies/andromeda/join",
+                Some(&ada),
+                json!({"account_name": "Ada"}),
+            )
+            .await;
+        let coords = account["systems"][0].clone();
+
+        let live = "/api/v1/galaxies/andromeda/live";
+        assert_error(
+            server.get(live, None).await,
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+        );
+        assert_error(
+            server.get(live, Some(&bob)).await,
+            StatusCode::FORBIDDEN,
+            "forbidden",
+        );
+
+        let request = Request::builder()
+            .uri(live)
+            .header(header::COOKIE, &ada)
+            .body(Body::empty())
+            .unwrap();
+        let response = server.app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+        let mut body = response.into_body().into_data_stream();
+
+        let build = format!(
+            "/api/v1/galaxies/andromeda/systems/{}/{}/structures/colony/build",
+            coords["x"], coords["y"]
+        );
+        let (status, event) = server.post(&build, Some(&ada), json!(null)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        server
+            .app_state
+            .publish_live_updates("andromeda", server.app_state.tick(), false)
+            .await
+            .unwrap();
+
+        let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), body.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+        assert!(chunk.starts_with("event: system\n"), "{}", chunk);
+        let data = chunk
+            .lines()
+            .find_map(|line| line.strip_prefix("data: "))
+            .unwrap();
+        let update: Value = serde_json::from_str(data).unwrap();
+        assert_eq!(update["galaxy"], "andromeda");
+        assert_eq!(update["coords"], coords);
+        assert_eq!(update["event"]["type"], "system");
+        assert_eq!(update["event"]["system"]["events"][0], event);
+    }
+
+    /// The checked in document, regenerated with `UPDATE_OPENAPI=1 cargo test`
+    const OPENAPI_PATH: &str = "../../docs/src/technical/openapi.json";
+
+    #[tokio::test]
+    async fn test_openapi() {
+        let spec = ApiDoc::openapi();
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(OPENAPI_PATH);
+        if std::env::var_os("UPDATE_OPENAPI").is_some() {
+            std::fs::write(&path, spec.to_pretty_json().unwrap() + "\n").unwrap();
+        }
+        // Compared as JSON values, so formatting the file doesn't matter
+        let spec = serde_json::to_value(&spec).unwrap();
+        let checked_in = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|file| serde_json::from_str::<Value>(&file).ok());
+        assert!(
+            checked_in.as_ref() == Some(&spec),
+            "The API changed, check the changes are compatible and regenerate {} with UPDATE_OPENAPI=1 cargo test",
+            OPENAPI_PATH
+        );
+
+        let server = TestServer::new().await;
+        let (status, served) = server.get("/api/v1/openapi.json", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(served, spec);
+        for path in [
+            "/galaxies",
+            "/galaxies/{galaxy}/systems/{x}/{y}",
+            "/galaxies/{galaxy}/systems/{x}/{y}/structures/{structure}/build",
+            "/account",
+        ] {
+            assert!(served["paths"][path].is_object(), "{} is missing", path);
+        }
+        assert!(served["components"]["schemas"]["SystemInfo"].is_object());
+        assert!(served["components"]["schemas"]["ErrorResponse"].is_object());
+    }
+}
@@ -1,15 +1,26 @@
 use axum::{
-    extract::{Extension, Form},
-    http::{header, StatusCode},
+    body::Body,
+    extract::{Extension, Form, Request},
+    http::{header, StatusCode, Uri},
+    middleware::Next,
     response::{Html, Redirect, Response},
 };
-use axum_extra::extract::cookie::{Cookie, CookieJar};
-use galactic_war::{app::AppState, User};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use galactic_war::{app::AppState, AuthService, User, UserGalaxyAccount, UserService};
 use serde::Deserialize;
 use std::sync::Arc;
 
 /// Cookie name for storing session tokens
-const SESSION_COOKIE: &str = "galactic_war_session";
+pub(crate) const SESSION_COOKIE: &str = "galactic_war_session";
+
+/// Form field carrying the CSRF token of the session
+pub(crate) const CSRF_FIELD: &str = "csrf_token";
+
+/// Header carrying the CSRF token of the session, for scripts
+pub(crate) const CSRF_HEADER: &str = "x-csrf-token";
+
+/// Largest body read looking for a CSRF token
+const MAX_FORM_SIZE: usize = 1024 * 1024;
 
 /// Login form data
 #[derive(Deserialize)]
@@ -51,6 +62,130 @@ pub async fn get_current_user(
     None
 }
 
+/// CSRF token of the logged in session, None without a session
+pub(crate) fn csrf_token(jar: &CookieJar) -> Option<String> {
+    jar.get(SESSION_COOKIE)
+        .map(|session| AuthService::csrf_token(session.value()))
+}
+
+/// Hidden field with the CSRF token of the session, every form that POSTs needs one
+pub(crate) fn csrf_field(jar: &CookieJar) -> String {
+    csrf_token(jar)
+        .map(|token| {
+            format!(
+                r#"<input type="hidden" name="{}" value="{}">"#,
+                CSRF_FIELD, token
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Reject requests changing state for a logged in user without the CSRF token of the session
+///
+/// Only the pages of this server have the token, so other sites can't make a player's browser
+/// act for them. It comes in the form field or the header. Requests without a session don't
+/// act for anyone, but some routes like creating a galaxy need no login, so browsers can't send
+/// those from another site either.
+pub(crate) async fn check_csrf(request: Request, next: Next) -> Response {
+    if request.method().is_safe() {
+        return next.run(request).await;
+    }
+    let jar = CookieJar::from_headers(request.headers());
+    let Some(session) = jar
+        .get(SESSION_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+    else {
+        if is_cross_site(&request) {
+            let mut response = create_error_response("Requests from other sites aren't allowed");
+            *response.status_mut() = StatusCode::FORBIDDEN;
+            return response;
+        }
+        return next.run(request).await;
+    };
+
+    let (parts, body) = request.into_parts();
+    let Ok(body) = axum::body::to_bytes(body, MAX_FORM_SIZE).await else {
+        return create_error_response("Request too large");
+    };
+    let token = parts
+        .headers
+        .get(CSRF_HEADER)
+        .and_then(|token| token.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| form_field(&body, CSRF_FIELD));
+    if !token.is_some_and(|token| AuthService::verify_csrf_token(&session, &token)) {
+        let mut response =
+            create_error_response("This form has expired, reload the page and try again");
+        *response.status_mut() = StatusCode::FORBIDDEN;
+        return response;
+    }
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
+
+/// Whether a browser sent a request from a page of another site
+///
+/// Browsers name the page in the Origin header, or at least the Referer. Requests without
+/// either don't come from a page, like those of scripts.
+fn is_cross_site(request: &Request) -> bool {
+    let headers = request.headers();
+    let Some(source) = headers
+        .get(header::ORIGIN)
+        .or_else(|| headers.get(header::REFERER))
+    else {
+        return false;
+    };
+    let source = source
+        .to_str()
+        .ok()
+        .and_then(|source| source.parse::<Uri>().ok());
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| {
+            request
+                .uri()
+                .authority()
+                .map(|authority| authority.as_str())
+        });
+    match (source, host) {
+        (Some(source), Some(host)) => source
+            .authority()
+            .is_none_or(|authority| !authority.as_str().eq_ignore_ascii_case(host)),
+        _ => true,
+    }
+}
+
+/// Get a field of a URL encoded form, as it was sent
+///
+/// Values aren't decoded, which is fine for the hex CSRF tokens.
+fn form_field(body: &[u8], name: &str) -> Option<String> {
+    std::str::from_utf8(body)
+        .ok()?
+        .split('&')
+        .filter_map(|field| field.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// Get the galaxy account of the logged in user, for handlers that act for it
+pub(crate) async fn current_galaxy_account(
+    jar: CookieJar,
+    app_state: &Arc<AppState>,
+    galaxy_name: &str,
+) -> Result<UserGalaxyAccount, Response> {
+    let user = get_current_user(jar, Extension(app_state.clone()))
+        .await
+        .ok_or_else(|| create_error_response("Not logged in"))?;
+    let db = app_state
+        .database()
+        .ok_or_else(|| create_error_response("Galaxy service not available"))?;
+    UserService::new(db.clone())
+        .get_user_galaxy_account(user.id, galaxy_name)
+        .await
+        .map_err(|_| create_error_response("Failed to load galaxy account"))?
+        .ok_or_else(|| create_error_response("You don't have an account in this galaxy"))
+}
+
 /// Show login page
 pub async fn login_page() -> Html<String> {
     Html(r#"
@@ -184,6 +319,7 @@ pub async fn handle_login(
                 Ok(session) => {
                     let cookie = Cookie::build((SESSION_COOKIE, session.token))
                         .http_only(true)
+                        .same_site(SameSite::Lax)
                         .path("/")
                         .build();
 
@@ -231,6 +367,7 @@ pub async fn handle_register(
                 Ok(session) => {
                     let cookie = Cookie::build((SESSION_COOKIE, session.token))
                         .http_only(true)
+                        .same_site(SameSite::Lax)
                         .path("/")
                         .build();
 
@@ -252,6 +389,14 @@ pub async fn handle_register(
     Err(create_error_response("Registration not available"))
 }
 
+/// The logout button, a form as logging out changes the session
+fn logout_form(csrf: &str) -> String {
+    format!(
+        r#"<form method="post" action="/logout">{}<button class="logout">Logout</button></form>"#,
+        csrf
+    )
+}
+
 /// Handle logout
 pub async fn handle_logout(
     jar: CookieJar,
@@ -275,6 +420,7 @@ pub async fn user_dashboard(
     jar: CookieJar,
     Extension(app_state): Extension<Arc<AppState>>,
 ) -> Result<Html<String>, Response> {
+    let csrf = csrf_field(&jar);
     let user = get_current_user(jar, Extension(app_state.clone()))
         .await
         .ok_or_else(|| {
@@ -329,13 +475,14 @@ pub async fn user_dashboard(
 <body>
     <div class="header">
         <h1>Welcome, {}</h1>
-        <a href="/logout"><button class="logout">Logout</button></a>
+        {}
     </div>
     
     <div class="galaxy-list">
         <h2>Your Galaxy Accounts</h2>
     "#,
-                    user.username
+                    user.username,
+                    logout_form(&csrf)
                 );
 
                 if accounts.is_empty() {
@@ -364,19 +511,21 @@ pub async fn user_dashboard(
                 // Get list of available galaxies
                 let galaxies = app_state.list_galaxies().await;
 
-                page.push_str(
+                page.push_str(&format!(
                     r#"
     </div>
     
     <div class="join-form">
         <h3>Join a Galaxy</h3>
         <form method="post" action="/join-galaxy">
+            {}
             <div class="form-group">
                 <label for="galaxy_name">Select Galaxy:</label>
                 <select id="galaxy_name" name="galaxy_name" required>
                     <option value="">Choose a galaxy...</option>
     "#,
-                );
+                    csrf
+                ));
 
                 for galaxy in galaxies {
                     // Check if user already has account in this galaxy
@@ -417,10 +566,23 @@ pub async fn user_dashboard(
                             galaxy, galaxy
                         ));
                 }
+                page.push_str("\n    </div>\n");
+
+                match crate::mail::mail_alerts_section(&app_state, user.id, &csrf).await {
+                    Ok(section) => page.push_str(&section),
+                    Err(e) => return Err(create_error_response(&e)),
+                }
+                match crate::webhooks::webhooks_section(&app_state, user.id, &csrf).await {
+                    Ok(section) => page.push_str(&section),
+                    Err(e) => return Err(create_error_response(&e)),
+                }
+                match crate::tokens::api_tokens_section(&app_state, user.id, &csrf).await {
+                    Ok(section) => page.push_str(&section),
+                    Err(e) => return Err(create_error_response(&e)),
+                }
 
                 page.push_str(
                     r#"
-    </div>
 </body>
 </html>
                     "#,
@@ -492,6 +654,9 @@ pub async fn handle_join_galaxy(
                     "Galaxy is full - no space for new systems",
                 ));
             }
+            Err(galactic_war::UserServiceError::RegistrationClosed(reason)) => {
+                return Err(create_error_response(&reason));
+            }
             Err(_) => {
                 return Err(create_error_response("Failed to join galaxy"));
             }
@@ -507,6 +672,7 @@ pub async fn galaxy_dashboard(
     jar: CookieJar,
     Extension(app_state): Extension<Arc<AppState>>,
 ) -> Result<Html<String>, Response> {
+    let csrf = csrf_field(&jar);
     let user = get_current_user(jar, Extension(app_state.clone()))
         .await
         .ok_or_else(|| {
@@ -531,6 +697,24 @@ pub async fn galaxy_dashboard(
                     .update_user_activity(user.id, &galaxy_name)
                     .await;
 
+                let messages =
+                    match crate::messages::unread_counter(&app_state, &galaxy_name, &account).await
+                    {
+                        Ok(counter) => counter,
+                        Err(e) => return Err(create_error_response(&e)),
+                    };
+                let notifications = match crate::notifications::notifications_section(
+                    &app_state,
+                    &galaxy_name,
+                    &account,
+                    &csrf,
+                )
+                .await
+                {
+                    Ok(section) => section,
+                    Err(e) => return Err(create_error_response(&e)),
+                };
+
                 // Get user's systems
                 match user_service.get_user_systems_coords(account.id).await {
                     Ok(systems_coords) => {
@@ -565,26 +749,31 @@ pub async fn galaxy_dashboard(
     <div class="nav-links">
         <a href="/dashboard">← Back to Dashboard</a>
         <a href="/{0}">View Public Galaxy Stats</a>
+        <a href="/galaxy/{0}/chat">Chat</a>
     </div>
     
     <div class="header">
         <h1>{0} Galaxy - {1}</h1>
-        <a href="/logout"><button class="logout">Logout</button></a>
+        {6}
     </div>
     
     <div class="account-info">
         <strong>Account:</strong> {1}<br>
         <strong>Joined:</strong> {2}<br>
-        <strong>Last Active:</strong> {3}
+        <strong>Last Active:</strong> {3}<br>
+        {4}
     </div>
-    
+{5}
     <div class="systems-list">
         <h2>Your Systems</h2>
     "#,
                             galaxy_name,
                             account.account_name,
                             account.joined_at.format("%Y-%m-%d %H:%M UTC"),
-                            account.last_active.format("%Y-%m-%d %H:%M UTC")
+                            account.last_active.format("%Y-%m-%d %H:%M UTC"),
+                            messages,
+                            notifications,
+                            logout_form(&csrf)
                         );
 
                         if systems_coords.is_empty() {
@@ -627,9 +816,20 @@ pub async fn galaxy_dashboard(
                             }
                         }
 
+                        page.push_str("\n    </div>\n");
+                        match crate::alliance::alliance_section(
+                            &app_state,
+                            &galaxy_name,
+                            &account,
+                            &csrf,
+                        )
+                        .await
+                        {
+                            Ok(section) => page.push_str(&section),
+                            Err(e) => return Err(create_error_response(&e)),
+                        }
                         page.push_str(
                             r#"
-    </div>
 </body>
 </html>
                             "#,
@@ -657,7 +857,7 @@ pub async fn galaxy_dashboard(
 }
 
 /// Helper function to create error responses
-fn create_error_response(message: &str) -> Response {
+pub(crate) fn create_error_response(message: &str) -> Response {
     let body = format!(
         r#"
 <!DOCTYPE html>
@@ -0,0 +1,341 @@
+use axum::{
+    extract::{Extension, Form, Path},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, Redirect, Response,
+    },
+};
+use axum_extra::extract::cookie::CookieJar;
+use futures_util::stream::{self, Stream};
+use galactic_war::{
+    app::AppState,
+    chat::{Channel, ChannelKind, ChatError, ChatService},
+    models::ChatMessageRow,
+    UserGalaxyAccount,
+};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::auth::{create_error_response, csrf_field, current_galaxy_account};
+use crate::escape_html;
+
+/// Chat form data, each action only uses some of the fields
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct ChatForm {
+    pub body: String,
+    pub name: String,
+    pub account_name: String,
+}
+
+/// A chat message as pushed to the browser
+#[derive(Serialize)]
+struct ChatEvent {
+    id: i64,
+    account_name: String,
+    body: String,
+    sent_at: String,
+}
+
+impl From<ChatMessageRow> for ChatEvent {
+    fn from(message: ChatMessageRow) -> Self {
+        ChatEvent {
+            id: message.id,
+            account_name: message.account_name,
+            body: message.body,
+            sent_at: message.sent_at.format("%H:%M").to_string(),
+        }
+    }
+}
+
+/// Handler for GET requests to /galaxy/:galaxy/chat
+///
+/// The galaxy channel, with the list of the player's channels
+pub async fn chat_get(
+    Path(galaxy_name): Path<String>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+) -> Result<Html<String>, Response> {
+    let csrf = csrf_field(&jar);
+    let account = current_galaxy_account(jar, &app_state, &galaxy_name).await?;
+    let chat = service(&app_state).map_err(create_error_response)?;
+    let channels = chat.channels(account.id).await.map_err(failed)?;
+    let channel = channels
+        .first()
+        .cloned()
+        .ok_or_else(|| failed(ChatError::ChannelNotFound))?;
+
+    channel_page(&chat, &galaxy_name, &account, &channels, &channel, &csrf).await
+}
+
+/// Handle POST requests to /galaxy/:galaxy/chat
+///
+/// Starts a group for the logged in player's account, then opens it
+pub async fn handle_create_group(
+    Path(galaxy_name): Path<String>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+    Form(form): Form<ChatForm>,
+) -> Result<Redirect, Response> {
+    let account = current_galaxy_account(jar, &app_state, &galaxy_name).await?;
+    let channel = service(&app_state)
+        .map_err(create_error_response)?
+        .create_group(account.id, &form.name)
+        .await
+        .map_err(failed)?;
+
+    Ok(Redirect::to(&format!(
+        "/galaxy/{}/chat/{}",
+        galaxy_name, channel.id
+    )))
+}
+
+/// Handler for GET requests to /galaxy/:galaxy/chat/:channel
+pub async fn channel_get(
+    Path((galaxy_name, channel_id)): Path<(String, i64)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+) -> Result<Html<String>, Response> {
+    let csrf = csrf_field(&jar);
+    let account = current_galaxy_account(jar, &app_state, &galaxy_name).await?;
+    let chat = service(&app_state).map_err(create_error_response)?;
+    let channel = chat.channel(account.id, channel_id).await.map_err(failed)?;
+    let channels = chat.channels(account.id).await.map_err(failed)?;
+
+    channel_page(&chat, &galaxy_name, &account, &channels, &channel, &csrf).await
+}
+
+/// Handle POST requests to /galaxy/:galaxy/chat/:channel/:action
+///
+/// Posts in a channel, adds someone to a group or leaves it, then returns to the chat.
+pub async fn handle_chat_action(
+    Path((galaxy_name, channel_id, action)): Path<(String, i64, String)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+    Form(form): Form<ChatForm>,
+) -> Result<Redirect, Response> {
+    let account = current_galaxy_account(jar, &app_state, &galaxy_name).await?;
+    let chat = service(&app_state).map_err(create_error_response)?;
+
+    let result = match action.as_str() {
+        "post" => chat
+            .post(account.id, channel_id, &form.body)
+            .await
+            .map(|_| ()),
+        "add" => chat.add(account.id, channel_id, &form.account_name).await,
+        "leave" => {
+            chat.leave(account.id, channel_id).await.map_err(failed)?;
+            return Ok(Redirect::to(&format!("/galaxy/{}/chat", galaxy_name)));
+        }
+        _ => return Err(create_error_response("Unknown chat action")),
+    };
+    result.map_err(failed)?;
+
+    Ok(Redirect::to(&format!(
+        "/galaxy/{}/chat/{}",
+        galaxy_name, channel_id
+    )))
+}
+
+/// Handler for GET requests to /galaxy/:galaxy/chat/:channel/events
+///
+/// Pushes the new messages of a channel to the browser as server-sent events. The stream ends
+/// as soon as the player can no longer read the channel, e.g. after leaving its alliance.
+pub async fn channel_events(
+    Path((galaxy_name, channel_id)): Path<(String, i64)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Response> {
+    let account = current_galaxy_account(jar, &app_state, &galaxy_name).await?;
+    let chat = service(&app_state).map_err(create_error_response)?;
+    let channel = chat.channel(account.id, channel_id).await.map_err(failed)?;
+    let receiver = chat.subscribe();
+
+    let events = stream::unfold(
+        (chat, receiver, account.id, channel.id),
+        |(chat, mut receiver, account_id, channel_id)| async move {
+            loop {
+                let message = match receiver.recv().await {
+                    Ok(message) => message,
+                    // Messages missed by a slow browser are in the history
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                };
+                if message.channel_id != channel_id {
+                    continue;
+                }
+                chat.channel(account_id, channel_id).await.ok()?;
+                let event = Event::default()
+                    .event("message")
+                    .json_data(ChatEvent::from(message))
+                    .unwrap_or_default();
+                return Some((Ok(event), (chat, receiver, account_id, channel_id)));
+            }
+        },
+    );
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+fn service(app_state: &Arc<AppState>) -> Result<ChatService, &'static str> {
+    let db = app_state.database().ok_or("Galaxy service not available")?;
+    Ok(ChatService::new(db.clone(), app_state.chat_hub().clone()))
+}
+
+/// Error messages can repeat the names players typed in
+fn failed(e: ChatError) -> Response {
+    create_error_response(&escape_html(&e.to_string()))
+}
+
+/// Show a channel with its history, kept up to date by the events stream
+async fn channel_page(
+    chat: &ChatService,
+    galaxy_name: &str,
+    account: &UserGalaxyAccount,
+    channels: &[Channel],
+    channel: &Channel,
+    csrf: &str,
+) -> Result<Html<String>, Response> {
+    let history = chat.history(account.id, channel.id).await.map_err(failed)?;
+
+    let mut list = String::new();
+    for other in channels {
+        let name = escape_html(&other.name);
+        if other.id == channel.id {
+            list.push_str(&format!("<strong>{}</strong>\n", name));
+        } else {
+            list.push_str(&format!(
+                "<a href=\"/galaxy/{}/chat/{}\">{}</a>\n",
+                galaxy_name, other.id, name
+            ));
+        }
+    }
+
+    let mut messages = String::new();
+    for message in history {
+        messages.push_str(&format!(
+            "<div><span class=\"time\">{}</span> <strong>{}</strong>: {}</div>\n",
+            message.sent_at.format("%H:%M"),
+            escape_html(&message.account_name),
+            escape_html(&message.body)
+        ));
+    }
+
+    let mut group = String::new();
+    if channel.kind == ChannelKind::Group {
+        let members = chat
+            .members(account.id, channel.id)
+            .await
+            .map_err(failed)?
+            .iter()
+            .map(|member| escape_html(&member.account_name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        group = format!(
+            r#"<h2>Members</h2>
+<p>{2}</p>
+<form method="post" action="/galaxy/{0}/chat/{1}/add" style="display:inline">{3}<input name="account_name" placeholder="Player" required> <button>Add</button></form>
+<form method="post" action="/galaxy/{0}/chat/{1}/leave" style="display:inline">{3}<button class="logout">Leave group</button></form>
+"#,
+            galaxy_name, channel.id, members, csrf
+        );
+    }
+
+    Ok(Html(format!(
+        r#"
+<!DOCTYPE html>
+<html>
+<head>
+    <title>Galactic War - {0} Chat</title>
+    <style>
+        body {{ font-family: Arial, sans-serif; max-width: 1000px; margin: 20px auto; }}
+        .nav-links {{ margin-bottom: 20px; }}
+        .nav-links a {{ margin-right: 15px; text-decoration: none; color: #007cba; }}
+        .channels {{ background: #f0f8ff; padding: 15px; border-radius: 5px; margin-bottom: 20px; }}
+        .channels a, .channels strong {{ margin-right: 15px; }}
+        .channels a {{ text-decoration: none; color: #007cba; }}
+        #messages {{
+            border: 1px solid #ddd; padding: 10px; margin-bottom: 10px; border-radius: 5px;
+            background: #f9f9f9; height: 400px; overflow-y: auto;
+        }}
+        #messages div {{ white-space: pre-wrap; }}
+        .time {{ color: #888; }}
+        button {{
+            padding: 8px 16px; background: #007cba; color: white;
+            border: none; border-radius: 4px; cursor: pointer;
+        }}
+        button:hover {{ background: #005a8a; }}
+        .logout {{ background: #dc3545; }}
+        .logout:hover {{ background: #c82333; }}
+    </style>
+</head>
+<body>
+    <div class="nav-links">
+        <a href="/galaxy/{0}/dashboard">← Back to Galaxy Dashboard</a>
+    </div>
+    <h1>{0} Galaxy - {1}</h1>
+    <div class="channels">
+{2}    </div>
+    <h2>{3}</h2>
+    <div id="messages">
+{4}    </div>
+    <form id="post" method="post" action="/galaxy/{0}/chat/{5}/post">
+        {9}
+        <input name="body" size="80" maxlength="{6}" autocomplete="off" required autofocus> <button>Send</button>
+    </form>
+{7}
+    <h2>Start a group</h2>
+    <form method="post" action="/galaxy/{0}/chat">
+        {9}
+        <input name="name" placeholder="Group name" maxlength="{8}" required> <button>Create</button>
+    </form>
+    <script>
+        const messages = document.getElementById('messages');
+        messages.scrollTop = messages.scrollHeight;
+
+        const events = new EventSource('/galaxy/{0}/chat/{5}/events');
+        events.addEventListener('message', (e) => {{
+            const message = JSON.parse(e.data);
+            const line = document.createElement('div');
+            const time = document.createElement('span');
+            time.className = 'time';
+            time.textContent = message.sent_at;
+            const name = document.createElement('strong');
+            name.textContent = message.account_name;
+            line.append(time, ' ', name, ': ' + message.body);
+            messages.appendChild(line);
+            messages.scrollTop = messages.scrollHeight;
+        }});
+
+        // Post without reloading, the message comes back through the events
+        const form = document.getElementById('post');
+        form.addEventListener('submit', async (e) => {{
+            e.preventDefault();
+            const response = await fetch(form.action, {{
+                method: 'POST',
+                body: new URLSearchParams(new FormData(form)),
+            }});
+            if (response.ok) {{
+                form.reset();
+            }} else {{
+                alert('Your message could not be sent');
+            }}
+        }});
+    </script>
+</body>
+</html>
+"#,
+        galaxy_name,
+        escape_html(&account.account_name),
+        list,
+        escape_html(&channel.name),
+        messages,
+        channel.id,
+        galactic_war::chat::MAX_MESSAGE_LENGTH,
+        group,
+        galactic_war::chat::MAX_GROUP_NAME_LENGTH,
+        csrf
+    )))
+}
@@ -0,0 +1,120 @@
+use axum::{
+    extract::{Extension, Form},
+    response::{Redirect, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use galactic_war::{app::AppState, mail::MailService, notifications::NotificationKind};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::auth::{create_error_response, get_current_user};
+
+/// Notification kinds that can be mailed, in the order they are listed
+const ALERT_KINDS: [NotificationKind; 5] = [
+    NotificationKind::Build,
+    NotificationKind::IncomingAttack,
+    NotificationKind::ScoutDetected,
+    NotificationKind::BattleReport,
+    NotificationKind::Diplomacy,
+];
+
+/// Email alert form data, a checkbox for every notification kind
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct MailAlertForm {
+    pub build: Option<String>,
+    pub incoming_attack: Option<String>,
+    pub scout_detected: Option<String>,
+    pub battle_report: Option<String>,
+    pub diplomacy: Option<String>,
+}
+
+impl MailAlertForm {
+    /// The notification kinds that were checked
+    fn kinds(&self) -> Vec<NotificationKind> {
+        [
+            &self.build,
+            &self.incoming_attack,
+            &self.scout_detected,
+            &self.battle_report,
+            &self.diplomacy,
+        ]
+        .into_iter()
+        .zip(ALERT_KINDS)
+        .filter(|(checked, _)| checked.is_some())
+        .map(|(_, kind)| kind)
+        .collect()
+    }
+}
+
+/// Handle POST requests to /mail-alerts
+///
+/// Sets which notifications the logged in user gets by email, then returns to the dashboard.
+pub async fn handle_mail_alerts(
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+    Form(form): Form<MailAlertForm>,
+) -> Result<Redirect, Response> {
+    let user = get_current_user(jar, Extension(app_state.clone()))
+        .await
+        .ok_or_else(|| create_error_response("Not logged in"))?;
+    service(&app_state)
+        .map_err(create_error_response)?
+        .set_alerts(user.id, &form.kinds())
+        .await
+        .map_err(|e| create_error_response(&e.to_string()))?;
+
+    Ok(Redirect::to("/dashboard"))
+}
+
+/// The email alerts section of the user dashboard
+pub async fn mail_alerts_section(
+    app_state: &Arc<AppState>,
+    user_id: i64,
+    csrf: &str,
+) -> Result<String, String> {
+    let mail = service(app_state)?;
+    let alerts = mail.alerts(user_id).await.map_err(|e| e.to_string())?;
+
+    let mut section = String::from(
+        r#"
+    <div class="galaxy-list">
+        <h2>Email Alerts</h2>
+"#,
+    );
+    if !mail.is_enabled() {
+        section.push_str("<p>Email isn't set up on this server, so none will be sent.</p>\n");
+    }
+    section.push_str(&format!(
+        r#"<p>Get an email for the unread notifications of your galaxy accounts:</p>
+<form method="post" action="/mail-alerts">
+    {}
+    <div class="form-group">
+"#,
+        csrf
+    ));
+    for kind in ALERT_KINDS {
+        section.push_str(&format!(
+            r#"        <label><input type="checkbox" name="{0}"{1}> {0}</label>
+"#,
+            kind,
+            if alerts.contains(&kind) {
+                " checked"
+            } else {
+                ""
+            }
+        ));
+    }
+    section.push_str(
+        r#"    </div>
+    <button>Save</button>
+</form>
+    </div>
+"#,
+    );
+    Ok(section)
+}
+
+fn service(app_state: &Arc<AppState>) -> Result<MailService, &'static str> {
+    app_state.mail().ok_or("Galaxy service not available")
+}
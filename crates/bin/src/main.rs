@@ -1,22 +1,74 @@
 use axum::response::Html;
 use galactic_war::{
-    app::AppState, config::GalaxyConfig, tick, Coords, Details, EventCallback, StructureType,
+    ai,
+    app::AppState,
+    config::{GalaxyConfig, ScoreCategory},
+    leaderboard::{AllianceEntry, LeaderboardEntry, ScorePoint, ScoreSeries, DELTA_PERIOD},
+    lifecycle::GalaxyPhase,
+    planner::{BuildPlan, Goal, MAX_HOURS, MAX_LEVEL},
+    webhooks::WebhookService,
+    Coords, Details, EventCallback, StructureType,
 };
 
 use std::sync::Arc;
 
+mod alliance;
+mod api;
 mod auth;
+mod chat;
+mod mail;
+mod messages;
+mod notifications;
+mod tokens;
 mod web;
+mod webhooks;
 
 use crate::web::GalacticWeb;
 
 use axum::{
-    extract::Path,
+    extract::{Path, Query},
+    middleware,
+    response::Redirect,
     routing::{get, post},
-    Extension, Router,
+    Extension, Json, Router,
 };
+use serde::{Deserialize, Serialize};
 use std::cmp::max;
 use std::str::FromStr;
+use std::time::Duration;
+
+/// Seconds between two turns of the AI players
+const AI_TURN_INTERVAL: u64 = 30;
+
+/// Seconds between two snapshots of the scores
+const SCORE_SNAPSHOT_INTERVAL: u64 = 3600;
+
+/// Seconds between two checks for galaxies that ended without anyone looking at them
+const GALAXY_END_INTERVAL: u64 = 10;
+
+/// Seconds between two checks for finished builds and raids to notify players of
+const NOTIFICATION_INTERVAL: u64 = 10;
+
+/// Seconds between two runs of the webhook deliveries that are due
+const WEBHOOK_INTERVAL: u64 = 5;
+
+/// Seconds between two runs mailing the notifications users opted in to
+const MAIL_ALERT_INTERVAL: u64 = 30;
+
+/// Seconds between two live updates of the player systems that changed
+const LIVE_INTERVAL: u64 = 1;
+
+/// Seconds between two live updates of every player system, so their resources keep moving
+const LIVE_REFRESH_INTERVAL: u64 = 15;
+
+/// Days of score history charted on the leaderboard
+const LEADERBOARD_DAYS: usize = 7;
+
+/// Hours the build advisor looks ahead when no goal is given
+const ADVISOR_HOURS: usize = 24;
+
+/// Builds of a plan listed on the build page
+const ADVISOR_STEPS: usize = 5;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -28,46 +80,227 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize application state with persistence
     let app_state = Arc::new(AppState::new().await?);
 
+    tokio::spawn(run_ai_players(app_state.clone()));
+    tokio::spawn(run_score_snapshots(app_state.clone()));
+    tokio::spawn(run_galaxy_ends(app_state.clone()));
+    tokio::spawn(run_notifications(app_state.clone()));
+    tokio::spawn(run_webhooks(app_state.clone()));
+    tokio::spawn(run_mail_alerts(app_state.clone()));
+    tokio::spawn(run_live_updates(app_state.clone()));
+
     serve(app_state).await
 }
 
+/// Add the configured AI players to every galaxy and play their turns
+async fn run_ai_players(app_state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(AI_TURN_INTERVAL));
+    loop {
+        interval.tick().await;
+        let galaxies: Vec<String> = app_state.galaxies().lock().await.keys().cloned().collect();
+        for galaxy in galaxies {
+            if let Err(e) = ai::add_configured_players(&app_state, &galaxy).await {
+                log::warn!("Failed to add AI players to galaxy {}: {}", galaxy, e);
+            }
+            if let Err(e) = ai::run_turns(&app_state, &galaxy).await {
+                log::warn!("Failed to run AI turns in galaxy {}: {}", galaxy, e);
+            }
+        }
+    }
+}
+
+/// Keep a history of the scores in every galaxy for the leaderboards
+async fn run_score_snapshots(app_state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(SCORE_SNAPSHOT_INTERVAL));
+    loop {
+        interval.tick().await;
+        let galaxies: Vec<String> = app_state.galaxies().lock().await.keys().cloned().collect();
+        for galaxy in galaxies {
+            if let Err(e) = app_state.snapshot_scores(&galaxy, app_state.tick()).await {
+                log::warn!("Failed to snapshot scores in galaxy {}: {}", galaxy, e);
+            }
+        }
+    }
+}
+
+/// Report the galaxies that ended, storing their standings
+///
+/// Galaxies end themselves on the first request past their end, this catches the quiet ones.
+async fn run_galaxy_ends(app_state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(GALAXY_END_INTERVAL));
+    loop {
+        interval.tick().await;
+        let galaxies: Vec<String> = app_state.galaxies().lock().await.keys().cloned().collect();
+        for galaxy in galaxies {
+            if let Err(e) = app_state.check_galaxy_end(&galaxy, app_state.tick()).await {
+                log::warn!("Failed to check the end of galaxy {}: {}", galaxy, e);
+            }
+        }
+    }
+}
+
+/// Tell players about their finished builds and the raids on their systems
+async fn run_notifications(app_state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(NOTIFICATION_INTERVAL));
+    loop {
+        interval.tick().await;
+        let galaxies: Vec<String> = app_state.galaxies().lock().await.keys().cloned().collect();
+        for galaxy in galaxies {
+            if let Err(e) = app_state
+                .collect_notifications(&galaxy, app_state.tick())
+                .await
+            {
+                log::warn!(
+                    "Failed to collect notifications in galaxy {}: {}",
+                    galaxy,
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// POST the queued webhook payloads, and retry the failed ones once their delay passed
+async fn run_webhooks(app_state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(WEBHOOK_INTERVAL));
+    loop {
+        interval.tick().await;
+        let Some(db) = app_state.database() else {
+            continue;
+        };
+        if let Err(e) = WebhookService::new(db.clone())
+            .deliver_due(app_state.tick())
+            .await
+        {
+            log::warn!("Failed to deliver webhooks: {}", e);
+        }
+    }
+}
+
+/// Mail the unread notifications of the users who opted in to their kind
+async fn run_mail_alerts(app_state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(MAIL_ALERT_INTERVAL));
+    loop {
+        interval.tick().await;
+        let Some(mail) = app_state.mail() else {
+            continue;
+        };
+        if let Err(e) = mail.send_alerts().await {
+            log::warn!("Failed to mail alerts: {}", e);
+        }
+    }
+}
+
+/// Push the changes of player systems to the players following them
+async fn run_live_updates(app_state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(LIVE_INTERVAL));
+    for round in 0u64.. {
+        interval.tick().await;
+        let all = round % (LIVE_REFRESH_INTERVAL / LIVE_INTERVAL) == 0;
+        let galaxies: Vec<String> = app_state.galaxies().lock().await.keys().cloned().collect();
+        for galaxy in galaxies {
+            if let Err(e) = app_state
+                .publish_live_updates(&galaxy, app_state.tick(), all)
+                .await
+            {
+                log::warn!("Failed to publish live updates in galaxy {}: {}", galaxy, e);
+            }
+        }
+    }
+}
+
 /// Serve the Galaxy(s) over HTTP
 async fn serve(app_state: Arc<AppState>) -> Result<(), Box<dyn std::error::Error>> {
-    // Only use GET requests
-    // We will eventually use an API to allow other ways of interacting with
-    // the game, likely with POST requests, but for now we will only
-    // expose the game over the web interface for simplicity
-    let app = Router::new()
+    let app = router(app_state);
+
+    // Hardcode serve on port 3050
+    let listener = tokio::net::TcpListener::bind("0.0.0.0:3050").await.unwrap();
+    log::info!("Server listening on http://0.0.0.0:3050");
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+/// All the routes of the server
+///
+/// GET requests of the web interface never change anything, everything that does is a POST
+/// carrying the CSRF token of the session. Scripts and other clients use the JSON API under
+/// /api/v1.
+fn router(app_state: Arc<AppState>) -> Router {
+    Router::new()
         .route("/favicon.ico", get(favicon_get))
         .route("/robots.txt", get(robots_get))
         // Auth routes
-        .route("/login", get(auth::login_page))
-        .route("/login", post(auth::handle_login))
-        .route("/register", get(auth::register_page))
-        .route("/register", post(auth::handle_register))
-        .route("/logout", get(auth::handle_logout))
+        .route("/logout", post(auth::handle_logout))
         .route("/dashboard", get(auth::user_dashboard))
         .route("/join-galaxy", post(auth::handle_join_galaxy))
+        .route("/webhooks/:action", post(webhooks::handle_webhook_action))
+        .route("/api-tokens/:action", post(tokens::handle_token_action))
+        .route("/mail-alerts", post(mail::handle_mail_alerts))
         .route("/galaxy/:galaxy/dashboard", get(auth::galaxy_dashboard))
+        .route(
+            "/galaxy/:galaxy/alliance/:action",
+            post(alliance::handle_alliance_action),
+        )
+        .route(
+            "/galaxy/:galaxy/diplomacy/:action",
+            post(alliance::handle_diplomacy_action),
+        )
+        .route("/galaxy/:galaxy/messages", get(messages::mailbox_get))
+        .route(
+            "/galaxy/:galaxy/messages/:action",
+            post(messages::handle_message_action),
+        )
+        .route("/galaxy/:galaxy/message/:id", get(messages::message_get))
+        .route(
+            "/galaxy/:galaxy/notifications",
+            get(notifications::notifications_get),
+        )
+        .route(
+            "/galaxy/:galaxy/notifications/:action",
+            post(notifications::handle_notification_action),
+        )
+        .route(
+            "/galaxy/:galaxy/chat",
+            get(chat::chat_get).post(chat::handle_create_group),
+        )
+        .route("/galaxy/:galaxy/chat/:channel", get(chat::channel_get))
+        .route(
+            "/galaxy/:galaxy/chat/:channel/events",
+            get(chat::channel_events),
+        )
+        .route(
+            "/galaxy/:galaxy/chat/:channel/:action",
+            post(chat::handle_chat_action),
+        )
         // Galaxy routes
         .route("/:galaxy", get(galaxy_get))
         .route("/:galaxy/", get(galaxy_get))
         .route("/:galaxy/stats", get(galaxy_stats_get))
-        .route("/:galaxy/create", get(galaxy_create_get))
+        .route("/:galaxy/leaderboard", get(leaderboard_get))
+        .route("/:galaxy/leaderboard/scores", get(leaderboard_scores_get))
+        .route("/:galaxy/leaderboard/history", get(leaderboard_history_get))
+        .route(
+            "/:galaxy/leaderboard/alliances",
+            get(leaderboard_alliances_get),
+        )
+        .route("/:galaxy/diplomacy", get(alliance::diplomacy_get))
+        .route("/:galaxy/create", post(galaxy_create_post))
         .route("/:galaxy/:x/:y", get(system_get))
         .route("/:galaxy/:x/:y/", get(system_get))
         .route("/:galaxy/:x/:y/build", get(system_build))
         .route("/:galaxy/:x/:y/build/", get(system_build))
-        .route("/:galaxy/:x/:y/build/:structure", get(system_build_struct))
+        .route("/:galaxy/:x/:y/build/:structure", post(system_build_struct))
+        .route("/:galaxy/:x/:y/advisor", get(system_advisor))
         .route("/:galaxy/:x/:y/:structure", get(structure_get))
         .route("/", get(base_get))
-        .layer(Extension(app_state));
-
-    // Hardcode serve on port 3050
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3050").await.unwrap();
-    log::info!("Server listening on http://0.0.0.0:3050");
-    axum::serve(listener, app).await?;
-    Ok(())
+        .layer(middleware::from_fn(auth::check_csrf))
+        // Logging in and registering start a new session, and the API checks tokens itself
+        .route("/login", get(auth::login_page).post(auth::handle_login))
+        .route(
+            "/register",
+            get(auth::register_page).post(auth::handle_register),
+        )
+        .nest(api::PREFIX, api::routes())
+        .layer(Extension(app_state))
 }
 
 /// Handler for GET requests to /favicon.ico
@@ -81,7 +314,10 @@ async fn robots_get() -> Html<String> {
 }
 
 /// Handler for GET requests to /
-async fn base_get(Extension(app_state): Extension<Arc<AppState>>) -> Html<String> {
+async fn base_get(
+    jar: axum_extra::extract::CookieJar,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Html<String> {
     let mut page = r#"
 <!DOCTYPE html>
 <html>
@@ -152,14 +388,15 @@ async fn base_get(Extension(app_state): Extension<Arc<AppState>>) -> Html<String
         page.push_str(&format!("<option value=\"{}\">{}</option>", galaxy, galaxy));
     }
 
-    page.push_str(
+    page.push_str(&format!(
         r#"</select>
         <button onclick="navigate()">View Galaxy</button>
     </div>
     
     <div class="galaxy-section">
         <h2>Create a New Galaxy</h2>
-        <form id="createGalaxy" method="get">
+        <form id="createGalaxy" method="post">
+            {}
             <div class="form-group">
                 <label for="newGalaxy">Enter New Galaxy Name:</label>
                 <input type="text" id="newGalaxy" name="newGalaxy" required placeholder="Enter galaxy name">
@@ -170,13 +407,14 @@ async fn base_get(Extension(app_state): Extension<Arc<AppState>>) -> Html<String
 </body>
 </html>
 "#,
-    );
+        auth::csrf_field(&jar)
+    ));
     Html::from(page)
 }
 
-/// Handler for GET requests to /:galaxy/create
+/// Handler for POST requests to /:galaxy/create
 #[axum::debug_handler]
-async fn galaxy_create_get(
+async fn galaxy_create_post(
     Path(galaxy): Path<String>,
     Extension(app_state): Extension<Arc<AppState>>,
 ) -> Html<String> {
@@ -185,7 +423,7 @@ async fn galaxy_create_get(
     let galaxy_config: GalaxyConfig = serde_yaml::from_str(contents).unwrap();
 
     let result = match app_state
-        .create_galaxy(&galaxy, &galaxy_config, tick())
+        .create_galaxy(&galaxy, &galaxy_config, app_state.tick())
         .await
     {
         Ok(msg) => msg,
@@ -195,12 +433,14 @@ async fn galaxy_create_get(
     Html::from(result)
 }
 
-/// Handler for GET requests to /:galaxy/:x/:y/build/:structure
+/// Handler for POST requests to /:galaxy/:x/:y/build/:structure
+///
+/// Starts the build and goes back to the build page of the system.
 async fn system_build_struct(
     Path((galaxy, x, y, structure)): Path<(String, usize, usize, String)>,
     jar: axum_extra::extract::CookieJar,
     Extension(app_state): Extension<Arc<AppState>>,
-) -> Result<String, String> {
+) -> Result<Redirect, String> {
     if structure.is_empty() {
         return Err("No structure specified".to_string());
     }
@@ -220,10 +460,10 @@ async fn system_build_struct(
                         // User owns this system, allow the build
                         let structure_type = StructureType::from_str(&structure)
                             .map_err(|_| format!("Invalid structure type: {}", structure))?;
-                        let event = app_state
-                            .build_structure(&galaxy, tick(), coords, structure_type)
+                        app_state
+                            .build_structure(&galaxy, app_state.tick(), coords, structure_type)
                             .await?;
-                        return Ok(format!("{:?}", event));
+                        return Ok(Redirect::to(&format!("/{}/{}/{}/build", galaxy, x, y)));
                     }
                 }
             }
@@ -234,23 +474,21 @@ async fn system_build_struct(
     }
 }
 
-/// Handler for GET requests to /:galaxy/:x/:y/build
-async fn system_build(
-    Path((galaxy, x, y)): Path<(String, usize, usize)>,
+/// Check the logged in user owns a system
+async fn check_system_owner(
+    galaxy: &str,
+    coords: Coords,
     jar: axum_extra::extract::CookieJar,
-    Extension(app_state): Extension<Arc<AppState>>,
-) -> Result<Html<String>, String> {
-    // Check if user is authenticated and owns this system
+    app_state: &Arc<AppState>,
+) -> Result<(), String> {
     if let Some(user) = auth::get_current_user(jar, Extension(app_state.clone())).await {
         if let Some(db) = app_state.database() {
             let user_service = galactic_war::UserService::new(db.clone());
 
             // Check if user has an account in this galaxy
-            if let Ok(Some(account)) = user_service.get_user_galaxy_account(user.id, &galaxy).await
-            {
+            if let Ok(Some(account)) = user_service.get_user_galaxy_account(user.id, galaxy).await {
                 // Check if user owns this system
                 if let Ok(user_systems) = user_service.get_user_systems_coords(account.id).await {
-                    let coords = (x, y).into();
                     if !user_systems.contains(&coords) {
                         return Err("You don't own this system".to_string());
                     }
@@ -266,6 +504,18 @@ async fn system_build(
     } else {
         return Err("You must be logged in to build structures".to_string());
     }
+    Ok(())
+}
+
+/// Handler for GET requests to /:galaxy/:x/:y/build
+async fn system_build(
+    Path((galaxy, x, y)): Path<(String, usize, usize)>,
+    Query(advisor): Query<AdvisorQuery>,
+    jar: axum_extra::extract::CookieJar,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Html<String>, String> {
+    let csrf = auth::csrf_field(&jar);
+    check_system_owner(&galaxy, (x, y).into(), jar, &app_state).await?;
     let dets = structure_info(&galaxy, (x, y).into(), "Colony", &app_state).await;
 
     let system_info = app_state.system_info(&galaxy, (x, y).into()).await?;
@@ -305,8 +555,8 @@ async fn system_build(
 
         if system_info.resources >= cost.resources {
             page.push_str(&format!(
-                "<td bgcolor=dddddd width=200><a href=/{}/{}/{}/build/{}>Upgrade to level {}</a></td></tr>",
-                galaxy, x, y, structure.to_string().to_lowercase(), level + 1));
+                "<td bgcolor=dddddd width=200><form method=post action=/{}/{}/{}/build/{}>{}<button>Upgrade to level {}</button></form></td></tr>",
+                galaxy, x, y, structure.to_string().to_lowercase(), csrf, level + 1));
         } else {
             // Figure out how long it will take to produce the missing resources at the current rate
             let metal_time = {
@@ -352,9 +602,211 @@ async fn system_build(
     }
 
     page.add("</table>");
+
+    page.add(&advisor_section(&galaxy, (x, y).into(), &advisor, &app_state).await);
     page.get().await
 }
 
+/// Goal asked of the build advisor
+///
+/// Either a structure and the level to reach, or the hours to maximise the score over.
+#[derive(Deserialize, Default)]
+struct AdvisorQuery {
+    structure: Option<String>,
+    level: Option<String>,
+    hours: Option<String>,
+}
+
+impl AdvisorQuery {
+    /// Whether no goal was given, the build page only plans once the player asks
+    fn is_empty(&self) -> bool {
+        [&self.structure, &self.level, &self.hours]
+            .iter()
+            .all(|value| value.as_deref().unwrap_or_default().is_empty())
+    }
+
+    /// The goal asked for, levels and hours beyond what the planner handles are capped
+    async fn goal(&self, galaxy: &str, app_state: &AppState) -> Result<Goal, String> {
+        // Fields left empty in the form count as missing
+        let number = |value: &Option<String>, name: &str| match value.as_deref() {
+            None | Some("") => Ok(None),
+            Some(value) => value
+                .parse::<usize>()
+                .map(Some)
+                .map_err(|_| format!("Invalid {}: {}", name, value)),
+        };
+        let structure = self.structure.as_deref().filter(|s| !s.is_empty());
+        match (structure, number(&self.level, "level")?) {
+            (Some(structure), Some(level)) => Ok(Goal::Level {
+                structure: StructureType::from_str(structure)
+                    .map_err(|_| format!("Invalid structure type: {}", structure))?,
+                level: level.min(MAX_LEVEL),
+            }),
+            (None, None) => {
+                let tick = app_state.game_tick(galaxy, app_state.tick()).await?;
+                let hours = number(&self.hours, "hours")?
+                    .unwrap_or(ADVISOR_HOURS)
+                    .min(MAX_HOURS);
+                let deadline = hours
+                    .checked_mul(3600)
+                    .and_then(|ticks| tick.checked_add(ticks))
+                    .ok_or("Too many hours")?;
+                Ok(Goal::Score { deadline })
+            }
+            _ => Err("A structure goal needs both a structure and a level".to_string()),
+        }
+    }
+}
+
+/// A build suggested by the advisor, times are game ticks
+#[derive(Serialize)]
+struct AdvisorStep {
+    structure: String,
+    level: usize,
+    start: usize,
+    completion: usize,
+}
+
+/// Response of the advisor API
+#[derive(Serialize)]
+struct AdvisorResponse {
+    /// Game tick the plan was made at
+    tick: usize,
+    /// Tick the goal is reached, or the deadline of a score goal
+    finish: usize,
+    score: usize,
+    steps: Vec<AdvisorStep>,
+}
+
+/// Plan the builds for the advisor's goal
+async fn advise(
+    galaxy: &str,
+    coords: Coords,
+    query: &AdvisorQuery,
+    app_state: &AppState,
+) -> Result<(usize, BuildPlan), String> {
+    let goal = query.goal(galaxy, app_state).await?;
+    let tick = app_state.game_tick(galaxy, app_state.tick()).await?;
+    let plan = app_state
+        .plan_builds(galaxy, app_state.tick(), coords, goal)
+        .await?;
+    Ok((tick, plan))
+}
+
+/// Advisor section of the build page
+async fn advisor_section(
+    galaxy: &str,
+    coords: Coords,
+    query: &AdvisorQuery,
+    app_state: &Arc<AppState>,
+) -> String {
+    let mut html = String::from("<h3>Advisor</h3>");
+    // Planning takes a while, so only when the player asks for a plan
+    let advice = if query.is_empty() {
+        None
+    } else {
+        Some(advise(galaxy, coords, query, app_state).await)
+    };
+    match advice {
+        None => {}
+        Some(Ok((tick, plan))) => {
+            match plan.goal {
+                Goal::Level { structure, level } => html.push_str(&format!(
+                    "<p>Fastest way to {} level {}, reached in {}:",
+                    structure,
+                    level,
+                    seconds_to_readable(plan.finish.saturating_sub(tick))
+                )),
+                Goal::Score { deadline } => html.push_str(&format!(
+                    "<p>Best score in the next {}, reaching {}:",
+                    seconds_to_readable(deadline.saturating_sub(tick)),
+                    plan.score
+                )),
+            }
+            if plan.steps.is_empty() {
+                html.push_str("<br>Nothing to build");
+            }
+            html.push_str("<ol>");
+            for step in plan.steps.iter().take(ADVISOR_STEPS) {
+                let wait = step.start.saturating_sub(tick);
+                html.push_str(&format!(
+                    "<li>{} to level {}, {} (done in {})</li>",
+                    step.structure,
+                    step.level,
+                    if wait == 0 {
+                        "now".to_string()
+                    } else {
+                        format!("in {}", seconds_to_readable(wait))
+                    },
+                    seconds_to_readable(step.completion.saturating_sub(tick))
+                ));
+            }
+            html.push_str("</ol>");
+            if plan.steps.len() > ADVISOR_STEPS {
+                html.push_str(&format!(
+                    "<p>and {} more builds.",
+                    plan.steps.len() - ADVISOR_STEPS
+                ));
+            }
+        }
+        // Errors can repeat what was in the query string
+        Some(Err(e)) => html.push_str(&format!("<p>No plan: {}", escape_html(&e))),
+    }
+
+    let (x, y) = (coords.x, coords.y);
+    html.push_str(&format!(
+        "<form method=get action=/{galaxy}/{x}/{y}/build>Reach <select name=structure>"
+    ));
+    for structure in [
+        StructureType::Colony,
+        StructureType::AsteroidMine,
+        StructureType::WaterHarvester,
+        StructureType::Hatchery,
+        StructureType::StorageDepot,
+    ] {
+        html.push_str(&format!(
+            "<option value={}>{}</option>",
+            structure.to_string().to_lowercase(),
+            structure
+        ));
+    }
+    html.push_str(" level <input name=level size=3> <input type=submit value=Plan></form>");
+    html.push_str(&format!(
+        "<form method=get action=/{galaxy}/{x}/{y}/build>Best score in \
+        <input name=hours size=3 value={ADVISOR_HOURS}> hours <input type=submit value=Plan></form>"
+    ));
+    html
+}
+
+/// Handler for GET requests to /:galaxy/:x/:y/advisor
+///
+/// The build advisor as JSON, taking the same goals as the build page.
+async fn system_advisor(
+    Path((galaxy, x, y)): Path<(String, usize, usize)>,
+    Query(query): Query<AdvisorQuery>,
+    jar: axum_extra::extract::CookieJar,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Json<AdvisorResponse>, String> {
+    let coords = (x, y).into();
+    check_system_owner(&galaxy, coords, jar, &app_state).await?;
+    let (tick, plan) = advise(&galaxy, coords, &query, &app_state).await?;
+    Ok(Json(AdvisorResponse {
+        tick,
+        finish: plan.finish,
+        score: plan.score,
+        steps: plan
+            .steps
+            .into_iter()
+            .map(|step| AdvisorStep {
+                structure: step.structure.to_string().to_lowercase(),
+                level: step.level,
+                start: step.start,
+                completion: step.completion,
+            })
+            .collect(),
+    }))
+}
+
 /// Handler for GET requests to /:galaxy/:x/:y/:structure
 ///
 /// This displays very basic info about the structure
@@ -383,13 +835,10 @@ async fn structure_get(
                     } else {
                         page.add(&format!("<h2>{}</h2>", structure));
                     }
-                    if structure_config.description.is_some() {
-                        page.add(&format!(
-                            "<p>{}</p>",
-                            structure_config.description.as_ref().unwrap()
-                        ));
+                    if let Some(description) = &structure_config.description {
+                        page.add(&format!("<p>{}</p>", description));
                     }
-                    let production = structure_config.get_production(dets.level);
+                    let production = structure_config.get_production(dets.level, config.speed());
                     if production.metal > 0 || production.crew > 0 || production.water > 0 {
                         page.add("<h3>Produces:</h3><b>");
                         if production.metal > 0 {
@@ -423,7 +872,7 @@ async fn structure_info(
     let structure_type = StructureType::from_str(structure)
         .map_err(|_| format!("Structure '{}' not found", structure))?;
     app_state
-        .get_galaxy_details(galaxy, tick(), coords, Some(structure_type))
+        .get_galaxy_details(galaxy, app_state.tick(), coords, Some(structure_type))
         .await
 }
 
@@ -468,14 +917,17 @@ async fn galaxy_stats_get(
     Path(galaxy): Path<String>,
     Extension(app_state): Extension<Arc<AppState>>,
 ) -> Result<Html<String>, String> {
-    let mut page = "
+    let mut page = galaxy_status(&galaxy, &app_state).await?;
+    page.push_str("
     <table width=600 border=0 cellspacing=1 cellpadding=3>
     <tr><td align=center><b>
     <table width=600 border=0 cellspacing=1 cellpadding=3>
     <tr><td bgcolor=dddddd><b>Isle</b></td><td bgcolor=dddddd width=15%><b>💰 Metal</b></td>
-    <td bgcolor=dddddd width=15%><b>🧑 Crew</b></td><td bgcolor=dddddd width=15%><b>💧 Water</b></td><td bgcolor=dddddd width=15%><b>Activity</b></td><td width=2%></td></tr>
-".to_string();
+    <td bgcolor=dddddd width=15%><b>🧑 Crew</b></td><td bgcolor=dddddd width=15%><b>💧 Water</b></td><td bgcolor=dddddd width=10%><b>Score</b></td><td bgcolor=dddddd width=15%><b>Activity</b></td><td width=2%></td></tr>
+");
 
+    // Event completions are in game ticks, which exclude the time the galaxy was paused
+    let game_tick = app_state.game_tick(&galaxy, app_state.tick()).await?;
     for (addr, dets) in galaxy_info(&galaxy, &app_state).await? {
         match dets {
             Details::System(info) => {
@@ -489,7 +941,7 @@ async fn galaxy_stats_get(
                     match event.action {
                         EventCallback::Build => {
                             activity.push_str("🏗️");
-                            let eta = event.completion - tick();
+                            let eta = event.completion.saturating_sub(game_tick);
 
                             activity_hover.push_str(&format!(
                                 "Structure {}: {} remaining",
@@ -503,9 +955,14 @@ async fn galaxy_stats_get(
                         }
                     }
                 }
+                let score_hover = ScoreCategory::ALL
+                    .iter()
+                    .map(|category| format!("{}: {}", category, info.scores.get(Some(*category))))
+                    .collect::<Vec<_>>()
+                    .join(", ");
                 page.push_str(&format!(
-                "<tr><td bgcolor=#ffffff><a href=/{}/{}/{}>{} ({}:{})</a></td><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff title=\"{}\">{}</td></tr>",
-                galaxy, addr.x, addr.y, "System", addr.x, addr.y, info.resources.metal, info.resources.crew, info.resources.water, activity_hover, activity
+                "<tr><td bgcolor=#ffffff><a href=/{}/{}/{}>{} ({}:{})</a></td><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff title=\"{}\">{}</td><td bgcolor=#ffffff title=\"{}\">{}</td></tr>",
+                galaxy, addr.x, addr.y, "System", addr.x, addr.y, info.resources.metal, info.resources.crew, info.resources.water, score_hover, info.score, activity_hover, activity
             ));
             }
             _ => {
@@ -516,6 +973,370 @@ async fn galaxy_stats_get(
     Ok(Html::from(page.to_string()))
 }
 
+/// Describe the lifecycle of the galaxy, with the final standings once it has ended
+async fn galaxy_status(galaxy: &str, app_state: &Arc<AppState>) -> Result<String, String> {
+    let phase = app_state.galaxy_phase(galaxy, app_state.tick()).await?;
+
+    let mut status = format!(
+        "<p><b>Status:</b> {} &middot; <a href=/{}/leaderboard>Leaderboard</a> &middot; <a href=/{}/diplomacy>Diplomacy</a></p>",
+        phase, galaxy, galaxy
+    );
+    if phase != GalaxyPhase::Ended {
+        return Ok(status);
+    }
+
+    status.push_str(
+        "<h3>Final Standings</h3>
+    <table width=600 border=0 cellspacing=1 cellpadding=3>
+    <tr><td bgcolor=dddddd><b>Rank</b></td><td bgcolor=dddddd><b>Player</b></td>
+    <td bgcolor=dddddd width=15%><b>Score</b></td><td bgcolor=dddddd width=15%><b>Systems</b></td></tr>",
+    );
+    if let Some(db) = app_state.database() {
+        let standings = db
+            .get_final_standings(galaxy)
+            .await
+            .map_err(|e| format!("Failed to load standings: {}", e))?;
+        for standing in standings {
+            status.push_str(&format!(
+                "<tr><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>{}</td></tr>",
+                standing.rank,
+                escape_html(&standing.account_name),
+                standing.score,
+                standing.systems
+            ));
+        }
+    }
+    status.push_str("</table>");
+    Ok(status)
+}
+
+/// Query string of the leaderboards
+#[derive(Deserialize)]
+struct CategoryQuery {
+    /// Score category to rank by, the total score if missing
+    category: Option<String>,
+}
+
+impl CategoryQuery {
+    fn category(&self) -> Result<Option<ScoreCategory>, String> {
+        match self.category.as_deref() {
+            None | Some("") => Ok(None),
+            Some(category) => category.parse().map(Some),
+        }
+    }
+}
+
+/// Handler for GET requests to /:galaxy/leaderboard
+///
+/// Ranks the players with their progress over the last day and a chart of the last week
+async fn leaderboard_get(
+    Path(galaxy): Path<String>,
+    Query(query): Query<CategoryQuery>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Html<String>, String> {
+    let category = query.category()?;
+    let tick = app_state.tick();
+    let board = app_state.leaderboard(&galaxy, tick, category).await?;
+    let history = app_state
+        .score_history(
+            &galaxy,
+            None,
+            tick.saturating_sub(LEADERBOARD_DAYS * DELTA_PERIOD),
+        )
+        .await?;
+
+    let mut tabs = vec![match category {
+        None => "<b>Total</b>".to_string(),
+        Some(_) => format!("<a href=\"/{}/leaderboard\">Total</a>", galaxy),
+    }];
+    for tab in ScoreCategory::ALL {
+        tabs.push(if category == Some(tab) {
+            format!("<b>{}</b>", tab)
+        } else {
+            format!(
+                "<a href=\"/{}/leaderboard?category={}\">{}</a>",
+                galaxy, tab, tab
+            )
+        });
+    }
+    let mut page = format!(
+        "<h2>{} Leaderboard</h2>
+    <p>{}</p>
+    <table width=600 border=0 cellspacing=1 cellpadding=3>
+    <tr><td bgcolor=dddddd><b>Rank</b></td><td bgcolor=dddddd><b>Player</b></td>
+    <td bgcolor=dddddd width=15%><b>Score</b></td><td bgcolor=dddddd width=15%><b>Systems</b></td>
+    <td bgcolor=dddddd width=15%><b>Last day</b></td><td bgcolor=dddddd><b>Last {} days</b></td></tr>",
+        galaxy,
+        tabs.join(" | "),
+        LEADERBOARD_DAYS
+    );
+    for entry in &board {
+        let change = match (entry.score_delta, entry.rank_delta) {
+            (Some(score), Some(rank)) => {
+                let arrow = match rank {
+                    r if r > 0 => format!(" ▲{}", r),
+                    r if r < 0 => format!(" ▼{}", -r),
+                    _ => String::new(),
+                };
+                format!("{:+}{}", score, arrow)
+            }
+            _ => "new".to_string(),
+        };
+        let chart = history
+            .iter()
+            .find(|series| series.account_id == entry.account_id)
+            .map(|series| sparkline(series, category))
+            .unwrap_or_default();
+        page.push_str(&format!(
+            "<tr><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>{}</td></tr>",
+            entry.rank,
+            escape_html(&entry.account_name),
+            entry.score,
+            entry.systems,
+            change,
+            chart
+        ));
+    }
+    page.push_str("</table>");
+
+    let alliances = app_state
+        .alliance_leaderboard(&galaxy, tick, category)
+        .await?;
+    if !alliances.is_empty() {
+        page.push_str(
+            "<h3>Alliances</h3>
+    <table width=600 border=0 cellspacing=1 cellpadding=3>
+    <tr><td bgcolor=dddddd><b>Rank</b></td><td bgcolor=dddddd><b>Alliance</b></td>
+    <td bgcolor=dddddd width=15%><b>Members</b></td><td bgcolor=dddddd width=15%><b>Score</b></td>
+    <td bgcolor=dddddd width=15%><b>Systems</b></td></tr>",
+        );
+        for entry in &alliances {
+            page.push_str(&format!(
+                "<tr><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>[{}] {}</td><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>{}</td><td bgcolor=#ffffff>{}</td></tr>",
+                entry.rank,
+                escape_html(&entry.tag),
+                escape_html(&entry.name),
+                entry.members,
+                entry.score,
+                entry.systems
+            ));
+        }
+        page.push_str("</table>");
+    }
+    Ok(Html::from(page))
+}
+
+/// Draw the score of a player over time as a small SVG line
+fn sparkline(series: &ScoreSeries, category: Option<ScoreCategory>) -> String {
+    const WIDTH: usize = 120;
+    const HEIGHT: usize = 24;
+    let (Some(first), Some(last)) = (series.points.first(), series.points.last()) else {
+        return String::new();
+    };
+    let score = |point: &ScorePoint| match category {
+        Some(_) => point.scores.get(category),
+        None => point.score,
+    };
+    let span = (last.tick - first.tick).max(1);
+    let top = series.points.iter().map(score).max().unwrap_or(0).max(1);
+    let points: Vec<String> = series
+        .points
+        .iter()
+        .map(|point| {
+            format!(
+                "{},{}",
+                (point.tick - first.tick) * WIDTH / span,
+                HEIGHT - score(point) * HEIGHT / top
+            )
+        })
+        .collect();
+    format!(
+        "<svg width={WIDTH} height={HEIGHT}><polyline fill=none stroke=black points=\"{}\"/></svg>",
+        points.join(" ")
+    )
+}
+
+/// A player on the leaderboard API
+#[derive(Serialize)]
+struct LeaderboardEntryResponse {
+    rank: usize,
+    account_id: i64,
+    account_name: String,
+    /// Score the leaderboard is ranked by
+    score: usize,
+    economy: usize,
+    military: usize,
+    defense: usize,
+    systems: usize,
+    /// Score gained over the last day, null for players who joined since
+    score_delta: Option<i64>,
+    /// Places gained over the last day, null for players who joined since
+    rank_delta: Option<i64>,
+}
+
+impl From<LeaderboardEntry> for LeaderboardEntryResponse {
+    fn from(entry: LeaderboardEntry) -> Self {
+        Self {
+            rank: entry.rank,
+            account_id: entry.account_id,
+            account_name: entry.account_name,
+            score: entry.score,
+            economy: entry.scores.economy,
+            military: entry.scores.military,
+            defense: entry.scores.defense,
+            systems: entry.systems,
+            score_delta: entry.score_delta,
+            rank_delta: entry.rank_delta,
+        }
+    }
+}
+
+/// Response of the leaderboard API
+#[derive(Serialize)]
+struct LeaderboardResponse {
+    tick: usize,
+    players: Vec<LeaderboardEntryResponse>,
+}
+
+/// Handler for GET requests to /:galaxy/leaderboard/scores
+async fn leaderboard_scores_get(
+    Path(galaxy): Path<String>,
+    Query(query): Query<CategoryQuery>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Json<LeaderboardResponse>, String> {
+    let category = query.category()?;
+    let tick = app_state.tick();
+    let board = app_state.leaderboard(&galaxy, tick, category).await?;
+    Ok(Json(LeaderboardResponse {
+        tick,
+        players: board.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// An alliance on the leaderboard API
+#[derive(Serialize)]
+struct AllianceEntryResponse {
+    rank: usize,
+    alliance_id: i64,
+    name: String,
+    tag: String,
+    members: usize,
+    /// Score the leaderboard is ranked by
+    score: usize,
+    economy: usize,
+    military: usize,
+    defense: usize,
+    systems: usize,
+}
+
+impl From<AllianceEntry> for AllianceEntryResponse {
+    fn from(entry: AllianceEntry) -> Self {
+        Self {
+            rank: entry.rank,
+            alliance_id: entry.alliance_id,
+            name: entry.name,
+            tag: entry.tag,
+            members: entry.members,
+            score: entry.score,
+            economy: entry.scores.economy,
+            military: entry.scores.military,
+            defense: entry.scores.defense,
+            systems: entry.systems,
+        }
+    }
+}
+
+/// Response of the alliance leaderboard API
+#[derive(Serialize)]
+struct AllianceLeaderboardResponse {
+    tick: usize,
+    alliances: Vec<AllianceEntryResponse>,
+}
+
+/// Handler for GET requests to /:galaxy/leaderboard/alliances
+async fn leaderboard_alliances_get(
+    Path(galaxy): Path<String>,
+    Query(query): Query<CategoryQuery>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Json<AllianceLeaderboardResponse>, String> {
+    let category = query.category()?;
+    let tick = app_state.tick();
+    let board = app_state
+        .alliance_leaderboard(&galaxy, tick, category)
+        .await?;
+    Ok(Json(AllianceLeaderboardResponse {
+        tick,
+        alliances: board.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// Query string of the score history API
+#[derive(Deserialize)]
+struct HistoryQuery {
+    /// Only the history of this account
+    account: Option<i64>,
+    /// Days of history, defaults to the leaderboard chart
+    days: Option<usize>,
+}
+
+/// A snapshot of a player's score
+#[derive(Serialize)]
+struct HistoryPoint {
+    tick: usize,
+    score: usize,
+    /// Rank by the total score
+    rank: usize,
+    economy: usize,
+    military: usize,
+    defense: usize,
+}
+
+/// Scores of a player over time
+#[derive(Serialize)]
+struct HistoryResponse {
+    account_id: i64,
+    account_name: String,
+    points: Vec<HistoryPoint>,
+}
+
+/// Handler for GET requests to /:galaxy/leaderboard/history
+///
+/// Score snapshots for charts, one series per player
+async fn leaderboard_history_get(
+    Path(galaxy): Path<String>,
+    Query(query): Query<HistoryQuery>,
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<Json<Vec<HistoryResponse>>, String> {
+    let days = query.days.unwrap_or(LEADERBOARD_DAYS);
+    let since = app_state
+        .tick()
+        .saturating_sub(days.saturating_mul(DELTA_PERIOD));
+    let history = app_state
+        .score_history(&galaxy, query.account, since)
+        .await?;
+    Ok(Json(
+        history
+            .into_iter()
+            .map(|series| HistoryResponse {
+                account_id: series.account_id,
+                account_name: series.account_name,
+                points: series
+                    .points
+                    .into_iter()
+                    .map(|point| HistoryPoint {
+                        tick: point.tick,
+                        score: point.score,
+                        rank: point.rank,
+                        economy: point.scores.economy,
+                        military: point.scores.military,
+                        defense: point.scores.defense,
+                    })
+                    .collect(),
+            })
+            .collect(),
+    ))
+}
+
 /// Handler for GET requests to /:galaxy
 ///
 /// Serves the Galaxy Dashboard page
@@ -546,7 +1367,7 @@ async fn galaxy_info(
     // Now get details for each address
     for addr in addresses {
         match app_state
-            .get_galaxy_details(galaxy_name, tick(), addr, None)
+            .get_galaxy_details(galaxy_name, app_state.tick(), addr, None)
             .await
         {
             Ok(details) => system_info.push((addr, details)),
@@ -557,6 +1378,22 @@ async fn galaxy_info(
     Ok(system_info)
 }
 
+/// Escape text written by players before putting it in a page
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 /// Convert seconds into a human readable format
 fn seconds_to_readable(seconds: usize) -> String {
     let hours = seconds / 3600;
@@ -568,3 +1405,273 @@ fn seconds_to_readable(seconds: usize) -> String {
         format!("{:2}:{:02}", minutes, seconds)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, Method, Request, StatusCode};
+    use galactic_war::{AuthService, Database, ManualClock, UserService};
+    use tower::ServiceExt;
+
+    /// A server with a galaxy named andromeda
+    async fn setup() -> (Arc<AppState>, Database) {
+        let db = Database::new_test().await.unwrap();
+        let app_state = Arc::new(
+            AppState::new_with_database(db.clone(), Arc::new(ManualClock::new(1_700_000_000)))
+                .await
+                .unwrap(),
+        );
+        let config: GalaxyConfig =
+            serde_yaml::from_str(include_str!("../galaxies/blitz.yaml")).unwrap();
+        app_state
+            .create_galaxy("andromeda", &config, app_state.tick())
+            .await
+            .unwrap();
+        (app_state, db)
+    }
+
+    /// Join andromeda as a new user
+    async fn join(
+        app_state: &AppState,
+        db: &Database,
+        name: &str,
+    ) -> galactic_war::UserGalaxyAccount {
+        let user_id = db
+            .create_user(name, &format!("{}@example.com", name), "hash")
+            .await
+            .unwrap();
+        UserService::new(db.clone())
+            .join_galaxy(user_id, "andromeda", name, app_state)
+            .await
+            .unwrap()
+            .0
+    }
+
+    /// Send a request to the web interface as a logged in browser, returning the status
+    async fn send(app: &Router, method: Method, uri: &str, cookie: &str, form: &str) -> StatusCode {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::COOKIE, cookie)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(form.to_string()))
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    /// Send a request without a session from a page, returning the status
+    async fn send_from(app: &Router, uri: &str, origin: &str) -> StatusCode {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header(header::HOST, "localhost:3050")
+            .header(header::ORIGIN, origin)
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_mutating_routes() {
+        let (app_state, db) = setup().await;
+        let app = router(app_state.clone());
+        let account = join(&app_state, &db, "ada").await;
+        let session = AuthService::new(db.clone())
+            .create_session(account.user_id)
+            .await
+            .unwrap()
+            .token;
+        let cookie = format!("galactic_war_session={}", session);
+        let csrf = format!("csrf_token={}", AuthService::csrf_token(&session));
+        let forged = format!("csrf_token={}", AuthService::csrf_token("other"));
+
+        // Links and forms from other sites can't create galaxies
+        assert_eq!(
+            send(&app, Method::GET, "/triangulum/create", &cookie, "").await,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        for form in ["", &forged] {
+            assert_eq!(
+                send(&app, Method::POST, "/triangulum/create", &cookie, form).await,
+                StatusCode::FORBIDDEN
+            );
+        }
+        for origin in ["https://example.com", "null"] {
+            assert_eq!(
+                send_from(&app, "/triangulum/create", origin).await,
+                StatusCode::FORBIDDEN
+            );
+        }
+        assert_eq!(app_state.list_galaxies().await, vec!["andromeda"]);
+        assert_eq!(
+            send(&app, Method::POST, "/triangulum/create", &cookie, &csrf).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send_from(&app, "/pinwheel/create", "http://localhost:3050").await,
+            StatusCode::OK
+        );
+        let mut galaxies = app_state.list_galaxies().await;
+        galaxies.sort();
+        assert_eq!(galaxies, vec!["andromeda", "pinwheel", "triangulum"]);
+
+        let join = format!("galaxy_name=triangulum&account_name=Ada&{}", csrf);
+        assert_eq!(
+            send(&app, Method::POST, "/join-galaxy", &cookie, &join).await,
+            StatusCode::SEE_OTHER
+        );
+        let coords = UserService::new(db.clone())
+            .get_user_systems_coords(account.id)
+            .await
+            .unwrap()[0];
+        let events = app_state
+            .system_info("andromeda", coords)
+            .await
+            .unwrap()
+            .events
+            .len();
+
+        // Nor spend the player's resources
+        let build = format!("/andromeda/{}/{}/build/colony", coords.x, coords.y);
+        assert_eq!(
+            send(&app, Method::GET, &build, &cookie, "").await,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        for form in ["", &forged] {
+            assert_eq!(
+                send(&app, Method::POST, &build, &cookie, form).await,
+                StatusCode::FORBIDDEN
+            );
+        }
+        let system = app_state.system_info("andromeda", coords).await.unwrap();
+        assert_eq!(system.events.len(), events);
+        assert_eq!(
+            send(&app, Method::POST, &build, &cookie, &csrf).await,
+            StatusCode::SEE_OTHER
+        );
+        let system = app_state.system_info("andromeda", coords).await.unwrap();
+        assert_eq!(system.events.len(), events + 1);
+
+        // Nor log the player out
+        assert_eq!(
+            send(&app, Method::GET, "/logout", &cookie, "").await,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert_eq!(
+            send(&app, Method::POST, "/logout", &cookie, "").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send(&app, Method::GET, "/dashboard", &cookie, "").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(&app, Method::POST, "/logout", &cookie, &csrf).await,
+            StatusCode::SEE_OTHER
+        );
+        assert_eq!(
+            send(&app, Method::GET, "/dashboard", &cookie, "").await,
+            StatusCode::FOUND
+        );
+
+        // Logging in again works with the stale cookie
+        assert_eq!(
+            send(
+                &app,
+                Method::POST,
+                "/login",
+                &cookie,
+                "login=ada&password=x"
+            )
+            .await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn test_leaderboard_escapes_names() {
+        let (app_state, db) = setup().await;
+        let account = join(&app_state, &db, "<script>ada</script>").await;
+        galactic_war::alliance::AllianceService::new(db.clone())
+            .create(account.id, "<b>Evil</b>", "EVIL")
+            .await
+            .unwrap();
+
+        let Html(page) = leaderboard_get(
+            Path("andromeda".to_string()),
+            Query(CategoryQuery { category: None }),
+            Extension(app_state.clone()),
+        )
+        .await
+        .unwrap();
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("&lt;script&gt;ada&lt;/script&gt;"));
+        assert!(!page.contains("<b>Evil</b>"));
+        assert!(page.contains("[EVIL] &lt;b&gt;Evil&lt;/b&gt;"));
+    }
+
+    #[tokio::test]
+    async fn test_final_standings_escape_names() {
+        let (app_state, db) = setup().await;
+        join(&app_state, &db, "<script>ada</script>").await;
+        app_state
+            .freeze_galaxy("andromeda", app_state.tick())
+            .await
+            .unwrap();
+
+        let status = galaxy_status("andromeda", &app_state).await.unwrap();
+        assert!(status.contains("Final Standings"));
+        assert!(!status.contains("<script>"));
+        assert!(status.contains("&lt;script&gt;ada&lt;/script&gt;"));
+    }
+
+    #[tokio::test]
+    async fn test_advisor() {
+        let (app_state, db) = setup().await;
+        let account = join(&app_state, &db, "ada").await;
+        let coords = UserService::new(db.clone())
+            .get_user_systems_coords(account.id)
+            .await
+            .unwrap()[0];
+        let query = |structure: &str, level: &str, hours: &str| AdvisorQuery {
+            structure: Some(structure.to_string()),
+            level: Some(level.to_string()),
+            hours: Some(hours.to_string()),
+        };
+
+        // Nothing is planned until the player asks
+        let html = advisor_section("andromeda", coords, &query("", "", ""), &app_state).await;
+        assert!(!html.contains("<p>"));
+        let html =
+            advisor_section("andromeda", coords, &query("colony", "2", ""), &app_state).await;
+        assert!(html.contains("Fastest way to Colony level 2"));
+
+        let html =
+            advisor_section("andromeda", coords, &query("<script>", "1", ""), &app_state).await;
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("Invalid structure type: &lt;script&gt;"));
+
+        // Goals beyond what the planner handles are capped
+        let tick = app_state
+            .game_tick("andromeda", app_state.tick())
+            .await
+            .unwrap();
+        let hours = usize::MAX.to_string();
+        assert_eq!(
+            query("", "", &hours).goal("andromeda", &app_state).await,
+            Ok(Goal::Score {
+                deadline: tick + MAX_HOURS * 3600
+            })
+        );
+        assert_eq!(
+            query("colony", &hours, "")
+                .goal("andromeda", &app_state)
+                .await,
+            Ok(Goal::Level {
+                structure: StructureType::Colony,
+                level: MAX_LEVEL
+            })
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use axum::{
+    extract::{Extension, Form, Path, Query},
+    response::{Html, Redirect, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use galactic_war::{
+    app::AppState,
+    messages::{MessageError, MessageService},
+    models::MessageRow,
+    UserGalaxyAccount,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::auth::{create_error_response, csrf_field, current_galaxy_account};
+use crate::escape_html;
+
+/// Message form data, each action only uses some of the fields
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct MessageForm {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+    pub message_id: Option<i64>,
+    pub account_name: String,
+}
+
+/// Query string of the mailbox, to start a reply
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct ComposeQuery {
+    pub to: String,
+    pub subject: String,
+}
+
+/// Handle POST requests to /galaxy/:galaxy/messages/:action
+///
+/// Sends, deletes or marks messages for the logged in player's account in the galaxy, or
+/// changes who it blocks, then returns to the mailbox.
+pub async fn handle_message_action(
+    Path((galaxy_name, action)): Path<(String, String)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+    Form(form): Form<MessageForm>,
+) -> Result<Redirect, Response> {
+    let account = current_galaxy_account(jar, &app_state, &galaxy_name).await?;
+    let messages = service(&app_state).map_err(create_error_response)?;
+
+    let message_id = form.message_id.ok_or("No message given");
+    let result = match action.as_str() {
+        "send" => messages
+            .send(account.id, &form.to, &form.subject, &form.body)
+            .await
+            .map(|_| ()),
+        "delete" => {
+            messages
+                .delete(account.id, message_id.map_err(create_error_response)?)
+                .await
+        }
+        "unread" => {
+            messages
+                .mark_unread(account.id, message_id.map_err(create_error_response)?)
+                .await
+        }
+        "block" => messages.block(account.id, &form.account_name).await,
+        "unblock" => messages.unblock(account.id, &form.account_name).await,
+        _ => return Err(create_error_response("Unknown message action")),
+    };
+    result.map_err(failed)?;
+
+    Ok(Redirect::to(&format!("/galaxy/{}/messages", galaxy_name)))
+}
+
+/// Handler for GET requests to /galaxy/:galaxy/messages
+///
+/// The mailbox of the logged in player's account, with a form to write a message
+pub async fn mailbox_get(
+    Path(galaxy_name): Path<String>,
+    Query(compose): Query<ComposeQuery>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+) -> Result<Html<String>, Response> {
+    let csrf = csrf_field(&jar);
+    let account = current_galaxy_account(jar, &app_state, &galaxy_name).await?;
+    let messages = service(&app_state).map_err(create_error_response)?;
+    let inbox = messages.inbox(account.id).await.map_err(failed)?;
+    let outbox = messages.outbox(account.id).await.map_err(failed)?;
+    let blocked = messages.blocked(account.id).await.map_err(failed)?;
+
+    let mut content = format!(
+        r#"<h2>Write a message</h2>
+<form method="post" action="/galaxy/{}/messages/send">{}
+<input name="to" placeholder="Player" value="{}" required> <input name="subject" placeholder="Subject" size="40" value="{}" required><br>
+<textarea name="body" rows="5" cols="70" required></textarea><br>
+<button>Send</button></form>
+<h2>Inbox</h2>
+"#,
+        galaxy_name,
+        csrf,
+        escape_html(&compose.to),
+        escape_html(&compose.subject)
+    );
+    content.push_str(&folder(&galaxy_name, &inbox, true));
+    content.push_str("<h2>Sent</h2>\n");
+    content.push_str(&folder(&galaxy_name, &outbox, false));
+
+    content.push_str("<h2>Blocked players</h2>\n");
+    if blocked.is_empty() {
+        content.push_str("<p>You haven't blocked anyone.</p>\n");
+    }
+    for other in &blocked {
+        content.push_str(&format!(
+            r#"<div class="system-item"><div>{}</div><form method="post" action="/galaxy/{}/messages/unblock">{}<input type="hidden" name="account_name" value="{}"><button>Unblock</button></form></div>
+"#,
+            escape_html(&other.account_name),
+            galaxy_name,
+            csrf,
+            escape_html(&other.account_name)
+        ));
+    }
+    content.push_str(&format!(
+        r#"<form method="post" action="/galaxy/{}/messages/block">{}<input name="account_name" placeholder="Player" required> <button class="logout">Block</button></form>
+"#,
+        galaxy_name, csrf
+    ));
+
+    Ok(Html(page(&galaxy_name, &account, &content)))
+}
+
+/// Handler for GET requests to /galaxy/:galaxy/message/:id
+///
+/// Shows a message of the logged in player's mailbox, marking it read if it was received
+pub async fn message_get(
+    Path((galaxy_name, message_id)): Path<(String, i64)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+) -> Result<Html<String>, Response> {
+    let csrf = csrf_field(&jar);
+    let account = current_galaxy_account(jar, &app_state, &galaxy_name).await?;
+    let message = service(&app_state)
+        .map_err(create_error_response)?
+        .read(account.id, message_id)
+        .await
+        .map_err(failed)?;
+
+    let received = message.recipient_id == account.id;
+    let mut content = format!(
+        r#"<h2>{}</h2>
+<p>From <strong>{}</strong> to <strong>{}</strong>, {}</p>
+<div class="account-info" style="white-space: pre-wrap">{}</div>
+"#,
+        escape_html(&message.subject),
+        escape_html(&message.sender_name),
+        escape_html(&message.recipient_name),
+        message.sent_at.format("%Y-%m-%d %H:%M UTC"),
+        escape_html(&message.body)
+    );
+    if received {
+        let subject = if message.subject.starts_with("Re: ") {
+            message.subject.clone()
+        } else {
+            format!("Re: {}", message.subject)
+        };
+        content.push_str(&format!(
+            r#"<form method="get" action="/galaxy/{0}/messages" style="display:inline"><input type="hidden" name="to" value="{1}"><input type="hidden" name="subject" value="{2}"><button>Reply</button></form>
+<form method="post" action="/galaxy/{0}/messages/unread" style="display:inline">{4}<input type="hidden" name="message_id" value="{3}"><button>Mark unread</button></form>
+"#,
+            galaxy_name,
+            escape_html(&message.sender_name),
+            escape_html(&subject),
+            message.id,
+            csrf
+        ));
+    }
+    content.push_str(&format!(
+        r#"<form method="post" action="/galaxy/{}/messages/delete" style="display:inline">{}<input type="hidden" name="message_id" value="{}"><button class="logout">Delete</button></form>
+"#,
+        galaxy_name, csrf, message.id
+    ));
+
+    Ok(Html(page(&galaxy_name, &account, &content)))
+}
+
+/// The unread counter on the galaxy dashboard, linking to the mailbox
+pub async fn unread_counter(
+    app_state: &Arc<AppState>,
+    galaxy_name: &str,
+    account: &UserGalaxyAccount,
+) -> Result<String, String> {
+    let db = app_state.database().ok_or("Galaxy service not available")?;
+    let unread = MessageService::new(db.clone())
+        .unread_count(account.id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let label = match unread {
+        0 => "no unread messages".to_string(),
+        1 => "<strong>1 unread message</strong>".to_string(),
+        n => format!("<strong>{} unread messages</strong>", n),
+    };
+    Ok(format!(
+        "<a href=\"/galaxy/{}/messages\">Messages</a> ({})",
+        galaxy_name, label
+    ))
+}
+
+fn service(app_state: &Arc<AppState>) -> Result<MessageService, &'static str> {
+    let db = app_state.database().ok_or("Galaxy service not available")?;
+    Ok(MessageService::new(db.clone()))
+}
+
+/// Error messages can repeat the names players typed in
+fn failed(e: MessageError) -> Response {
+    create_error_response(&escape_html(&e.to_string()))
+}
+
+/// List the messages of a mailbox folder, unread ones in bold
+fn folder(galaxy_name: &str, messages: &[MessageRow], received: bool) -> String {
+    if messages.is_empty() {
+        return "<p>No messages.</p>\n".to_string();
+    }
+    let mut list = String::new();
+    for message in messages {
+        let (direction, other) = if received {
+            ("From", &message.sender_name)
+        } else {
+            ("To", &message.recipient_name)
+        };
+        let mut subject = format!(
+            "<a href=\"/galaxy/{}/message/{}\">{}</a>",
+            galaxy_name,
+            message.id,
+            escape_html(&message.subject)
+        );
+        if received && !message.is_read() {
+            subject = format!("<strong>{}</strong>", subject);
+        }
+        list.push_str(&format!(
+            "<div class=\"system-item\"><div>{}<br>{} {}</div><div>{}</div></div>\n",
+            subject,
+            direction,
+            escape_html(other),
+            message.sent_at.format("%Y-%m-%d %H:%M UTC")
+        ));
+    }
+    list
+}
+
+fn page(galaxy_name: &str, account: &UserGalaxyAccount, content: &str) -> String {
+    format!(
+        r#"
+<!DOCTYPE html>
+<html>
+<head>
+    <title>Galactic War - {0} Messages</title>
+    <style>
+        body {{ font-family: Arial, sans-serif; max-width: 1000px; margin: 20px auto; }}
+        .nav-links {{ margin-bottom: 20px; }}
+        .nav-links a {{ margin-right: 15px; text-decoration: none; color: #007cba; }}
+        .account-info {{ background: #f0f8ff; padding: 15px; border-radius: 5px; margin-bottom: 20px; }}
+        .system-item {{
+            border: 1px solid #ddd; padding: 10px; margin-bottom: 10px;
+            border-radius: 5px; background: #f9f9f9; display: flex;
+            justify-content: space-between; align-items: center;
+        }}
+        button {{
+            padding: 8px 16px; background: #007cba; color: white;
+            border: none; border-radius: 4px; cursor: pointer;
+        }}
+        button:hover {{ background: #005a8a; }}
+        .logout {{ background: #dc3545; }}
+        .logout:hover {{ background: #c82333; }}
+    </style>
+</head>
+<body>
+    <div class="nav-links">
+        <a href="/galaxy/{0}/dashboard">← Back to Galaxy Dashboard</a>
+        <a href="/galaxy/{0}/messages">Mailbox</a>
+    </div>
+    <h1>{0} Galaxy - {1}</h1>
+{2}
+</body>
+</html>
+"#,
+        galaxy_name,
+        escape_html(&account.account_name),
+        content
+    )
+}
@@ -0,0 +1,215 @@
+use axum::{
+    extract::{Extension, Form, Path, Query},
+    response::{Redirect, Response},
+    Json,
+};
+use axum_extra::extract::cookie::CookieJar;
+use galactic_war::{
+    app::AppState,
+    models::NotificationRow,
+    notifications::{NotificationError, NotificationService},
+    UserGalaxyAccount,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::auth::{create_error_response, current_galaxy_account};
+use crate::escape_html;
+
+/// Notifications shown on the dashboard
+const DASHBOARD_LENGTH: usize = 10;
+
+/// Notification form data
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct NotificationForm {
+    pub notification_id: Option<i64>,
+}
+
+/// Query string of the notifications API
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct NotificationQuery {
+    /// Only list the unread notifications
+    pub unread: bool,
+}
+
+/// A system on the notifications API
+#[derive(Serialize)]
+struct SystemResponse {
+    x: i64,
+    y: i64,
+}
+
+/// A notification on the notifications API
+#[derive(Serialize)]
+struct NotificationResponse {
+    id: i64,
+    kind: String,
+    tick: i64,
+    /// System the notification is about, if any
+    system: Option<SystemResponse>,
+    message: String,
+    created_at: String,
+    read: bool,
+}
+
+impl From<NotificationRow> for NotificationResponse {
+    fn from(row: NotificationRow) -> Self {
+        let read = row.is_read();
+        NotificationResponse {
+            id: row.id,
+            kind: row.kind,
+            tick: row.tick,
+            system: row
+                .system_x
+                .zip(row.system_y)
+                .map(|(x, y)| SystemResponse { x, y }),
+            message: row.message,
+            created_at: row.created_at.to_rfc3339(),
+            read,
+        }
+    }
+}
+
+/// Response of the notifications API
+#[derive(Serialize)]
+pub struct NotificationsResponse {
+    unread: usize,
+    notifications: Vec<NotificationResponse>,
+}
+
+/// Handler for GET requests to /galaxy/:galaxy/notifications
+///
+/// The latest notifications of the logged in player's account in the galaxy, newest first.
+/// `?unread=true` only lists the unread ones.
+pub async fn notifications_get(
+    Path(galaxy_name): Path<String>,
+    Query(query): Query<NotificationQuery>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+) -> Result<Json<NotificationsResponse>, Response> {
+    let account = current_galaxy_account(jar, &app_state, &galaxy_name).await?;
+    let notifications = service(&app_state, &galaxy_name)
+        .await
+        .map_err(|e| create_error_response(&e))?;
+    let unread = notifications
+        .unread_count(account.id)
+        .await
+        .map_err(failed)?;
+    let list = notifications
+        .list(account.id, query.unread)
+        .await
+        .map_err(failed)?;
+
+    Ok(Json(NotificationsResponse {
+        unread,
+        notifications: list.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// Handle POST requests to /galaxy/:galaxy/notifications/:action
+///
+/// Marks one or all notifications of the logged in player's account as read, then returns to
+/// the galaxy dashboard.
+pub async fn handle_notification_action(
+    Path((galaxy_name, action)): Path<(String, String)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+    Form(form): Form<NotificationForm>,
+) -> Result<Redirect, Response> {
+    let account = current_galaxy_account(jar, &app_state, &galaxy_name).await?;
+    let db = app_state
+        .database()
+        .ok_or_else(|| create_error_response("Galaxy service not available"))?;
+    let notifications = NotificationService::new(db.clone());
+
+    match action.as_str() {
+        "read" => {
+            let notification_id = form
+                .notification_id
+                .ok_or_else(|| create_error_response("No notification given"))?;
+            notifications
+                .read(account.id, notification_id)
+                .await
+                .map_err(failed)?;
+        }
+        "read_all" => {
+            notifications.read_all(account.id).await.map_err(failed)?;
+        }
+        _ => return Err(create_error_response("Unknown notification action")),
+    }
+
+    Ok(Redirect::to(&format!("/galaxy/{}/dashboard", galaxy_name)))
+}
+
+/// The notifications section of the galaxy dashboard, unread ones in bold
+pub async fn notifications_section(
+    app_state: &Arc<AppState>,
+    galaxy_name: &str,
+    account: &UserGalaxyAccount,
+    csrf: &str,
+) -> Result<String, String> {
+    let notifications = service(app_state, galaxy_name).await?;
+    let unread = notifications
+        .unread_count(account.id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let latest = notifications
+        .list(account.id, false)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut section = format!("<h2>Notifications ({} unread)</h2>\n", unread);
+    if latest.is_empty() {
+        section.push_str("<p>Nothing has happened yet.</p>\n");
+    }
+    for notification in latest.iter().take(DASHBOARD_LENGTH) {
+        let mut message = escape_html(&notification.message);
+        if let (Some(x), Some(y)) = (notification.system_x, notification.system_y) {
+            message = format!("<a href=\"/{}/{}/{}\">{}</a>", galaxy_name, x, y, message);
+        }
+        let action = if notification.is_read() {
+            String::new()
+        } else {
+            message = format!("<strong>{}</strong>", message);
+            format!(
+                r#"<form method="post" action="/galaxy/{}/notifications/read">{}<input type="hidden" name="notification_id" value="{}"><button>Mark read</button></form>"#,
+                galaxy_name, csrf, notification.id
+            )
+        };
+        section.push_str(&format!(
+            "<div class=\"system-item\"><div>{}<br>{}</div>{}</div>\n",
+            message,
+            notification.created_at.format("%Y-%m-%d %H:%M UTC"),
+            action
+        ));
+    }
+    if unread > 0 {
+        section.push_str(&format!(
+            r#"<form method="post" action="/galaxy/{}/notifications/read_all">{}<button>Mark all read</button></form>
+"#,
+            galaxy_name, csrf
+        ));
+    }
+    Ok(section)
+}
+
+/// A notification service for a galaxy, after reporting what happened in it
+async fn service(
+    app_state: &Arc<AppState>,
+    galaxy_name: &str,
+) -> Result<NotificationService, String> {
+    let db = app_state
+        .database()
+        .ok_or("Galaxy service not available")?
+        .clone();
+    app_state
+        .collect_notifications(galaxy_name, app_state.tick())
+        .await?;
+    Ok(NotificationService::new(db))
+}
+
+fn failed(e: NotificationError) -> Response {
+    create_error_response(&e.to_string())
+}
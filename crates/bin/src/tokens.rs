@@ -0,0 +1,168 @@
+use axum::{
+    extract::{Extension, Form, Path},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use galactic_war::{
+    api_tokens::{token_scope, ApiTokenError, ApiTokenService, TokenScope, MAX_NAME_LENGTH},
+    app::AppState,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::auth::{create_error_response, get_current_user};
+use crate::escape_html;
+
+/// API token form data
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct TokenForm {
+    pub name: String,
+    pub scope: String,
+    pub token_id: Option<i64>,
+}
+
+/// Handle POST requests to /api-tokens/:action
+///
+/// Creating a token shows it once on a page of its own, revoking one returns to the dashboard.
+pub async fn handle_token_action(
+    Path(action): Path<String>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+    Form(form): Form<TokenForm>,
+) -> Result<Response, Response> {
+    let user = get_current_user(jar, Extension(app_state.clone()))
+        .await
+        .ok_or_else(|| create_error_response("Not logged in"))?;
+    let tokens = service(&app_state).map_err(create_error_response)?;
+
+    match action.as_str() {
+        "create" => {
+            let scope: TokenScope = form
+                .scope
+                .parse()
+                .map_err(|e: String| create_error_response(&e))?;
+            let (row, token) = tokens
+                .create(user.id, &form.name, scope)
+                .await
+                .map_err(failed)?;
+            Ok(Html(created_page(&row.name, scope, &token)).into_response())
+        }
+        "delete" => {
+            let token_id = form
+                .token_id
+                .ok_or_else(|| create_error_response("No API token given"))?;
+            tokens.revoke(user.id, token_id).await.map_err(failed)?;
+            Ok(Redirect::to("/dashboard").into_response())
+        }
+        _ => Err(create_error_response("Unknown API token action")),
+    }
+}
+
+/// The page showing a new token, the only time it can be seen
+fn created_page(name: &str, scope: TokenScope, token: &str) -> String {
+    format!(
+        r#"
+<!DOCTYPE html>
+<html>
+<head>
+    <title>API Token - Galactic War</title>
+    <style>
+        body {{ font-family: Arial, sans-serif; max-width: 600px; margin: 100px auto; text-align: center; }}
+        code {{ display: block; padding: 10px; background: #f4f4f4; word-break: break-all; }}
+        a {{ color: #007cba; text-decoration: none; }}
+    </style>
+</head>
+<body>
+    <h1>API token {}</h1>
+    <p>This token has the {} scope. Copy it now, it won't be shown again.</p>
+    <code>{}</code>
+    <p>Send it in an <code style="display: inline; padding: 0;">Authorization: Bearer</code> header to the API.</p>
+    <a href="/dashboard">← Back to Dashboard</a>
+</body>
+</html>
+    "#,
+        escape_html(name),
+        scope,
+        token
+    )
+}
+
+/// The API tokens section of the user dashboard
+pub async fn api_tokens_section(
+    app_state: &Arc<AppState>,
+    user_id: i64,
+    csrf: &str,
+) -> Result<String, String> {
+    let tokens = service(app_state)?
+        .tokens(user_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut section = String::from(
+        r#"
+    <div class="galaxy-list">
+        <h2>API Tokens</h2>
+        <p>Bots and scripts use the <a href="/api/v1/openapi.json">JSON API</a> with a token as an <code>Authorization: Bearer</code> header. Read only tokens can see your accounts, play tokens can also play them, and admin tokens can create galaxies too.</p>
+"#,
+    );
+    if tokens.is_empty() {
+        section.push_str("<p>You haven't created any API tokens.</p>\n");
+    }
+    for token in &tokens {
+        let scope = token_scope(token)
+            .map(|scope| scope.to_string())
+            .unwrap_or_default();
+        let last_used = token
+            .last_used_at
+            .map(|used| used.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_else(|| "never".to_string());
+        section.push_str(&format!(
+            r#"<div class="galaxy-item">
+    <div class="galaxy-name">{}</div>
+    <div>Scope: {}</div>
+    <div>Created {}, last used {}</div>
+    <form method="post" action="/api-tokens/delete">{}<input type="hidden" name="token_id" value="{}"><button class="logout">Revoke</button></form>
+</div>
+"#,
+            escape_html(&token.name),
+            scope,
+            token.created_at.format("%Y-%m-%d %H:%M UTC"),
+            last_used,
+            csrf,
+            token.id
+        ));
+    }
+
+    section.push_str(&format!(
+        r#"<form method="post" action="/api-tokens/create">
+    {}
+    <div class="form-group"><label for="token-name">Name:</label><input type="text" id="token-name" name="name" required maxlength="{}" placeholder="My bot"></div>
+    <div class="form-group"><label for="token-scope">Scope:</label><select id="token-scope" name="scope">
+"#,
+        csrf, MAX_NAME_LENGTH
+    ));
+    for scope in TokenScope::ALL {
+        section.push_str(&format!(
+            "        <option value=\"{0}\">{0}</option>\n",
+            scope
+        ));
+    }
+    section.push_str(
+        r#"    </select></div>
+    <button>Create token</button>
+</form>
+    </div>
+"#,
+    );
+    Ok(section)
+}
+
+fn service(app_state: &Arc<AppState>) -> Result<ApiTokenService, &'static str> {
+    let db = app_state.database().ok_or("Galaxy service not available")?;
+    Ok(ApiTokenService::new(db.clone()))
+}
+
+fn failed(e: ApiTokenError) -> Response {
+    create_error_response(&e.to_string())
+}
@@ -8,12 +8,45 @@ use galactic_war::{app::AppState, Coords, Resources, SystemProduction};
 
 use std::sync::Arc;
 
+use crate::escape_html;
+
 /// Return a standardized HTML table for displaying resources
+///
+/// The cells have ids so [`LIVE_SCRIPT`] can update them.
 pub fn resource_table(resources: &Resources, production: &SystemProduction) -> String {
-    format!("<table width=600 border=1 cellspacing=0 cellpadding=3><tr><td>💰 {}</td><td>🧑 {}</td><td>💧 {}</td><td>🏃 {}/{}/{}</td></tr></table>",
+    format!("<table width=600 border=1 cellspacing=0 cellpadding=3><tr><td id=metal>💰 {}</td><td id=crew>🧑 {}</td><td id=water>💧 {}</td><td id=production>🏃 {}/{}/{}</td></tr></table>",
 resources.metal, resources.crew, resources.water, production.metal, production.crew, production.water)
 }
 
+/// Follows the live updates of the system of the page, for its owner
+///
+/// The resources are updated in place, and the page is reloaded when a build finishes to show
+/// the new levels. Visitors who don't own the system get no updates.
+const LIVE_SCRIPT: &str = r#"
+<script>
+    (function() {
+        const page = document.getElementById("system");
+        const x = Number(page.dataset.x), y = Number(page.dataset.y);
+        const live = new EventSource("/api/v1/galaxies/" + encodeURIComponent(page.dataset.galaxy) + "/live");
+        const here = (update) => update.coords.x === x && update.coords.y === y;
+        live.addEventListener("system", (e) => {
+            const update = JSON.parse(e.data);
+            if (!here(update)) return;
+            const resources = update.event.system.resources;
+            const production = update.event.system.production;
+            document.getElementById("metal").textContent = "💰 " + resources.metal;
+            document.getElementById("crew").textContent = "🧑 " + resources.crew;
+            document.getElementById("water").textContent = "💧 " + resources.water;
+            document.getElementById("production").textContent =
+                "🏃 " + production.metal + "/" + production.crew + "/" + production.water;
+        });
+        live.addEventListener("build_complete", (e) => {
+            if (here(JSON.parse(e.data))) location.reload();
+        });
+    })();
+</script>
+"#;
+
 #[derive(Debug)]
 pub struct GalacticWeb {
     pub galaxy: String,
@@ -99,6 +132,13 @@ impl GalacticWeb {
             &system_info.production,
         ));
         page.push_str(self.body.as_str());
+        page.push_str(&format!(
+            "<div id=system data-galaxy=\"{}\" data-x={} data-y={}></div>",
+            escape_html(&self.galaxy),
+            self.coords.x,
+            self.coords.y
+        ));
+        page.push_str(LIVE_SCRIPT);
         page.push_str("</body>");
         Ok(Html::from(page))
     }
@@ -0,0 +1,176 @@
+use axum::{
+    extract::{Extension, Form, Path},
+    response::{Redirect, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use galactic_war::{
+    app::AppState,
+    webhooks::{webhook_events, WebhookError, WebhookEvent, WebhookService, SIGNATURE_HEADER},
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::auth::{create_error_response, get_current_user};
+use crate::escape_html;
+
+/// Deliveries of a webhook shown on the dashboard
+const DASHBOARD_DELIVERIES: usize = 5;
+
+/// Webhook form data, a checkbox for every event
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct WebhookForm {
+    pub url: String,
+    pub build_complete: Option<String>,
+    pub under_attack: Option<String>,
+    pub message_received: Option<String>,
+    pub webhook_id: Option<i64>,
+}
+
+impl WebhookForm {
+    /// The events that were checked
+    fn events(&self) -> Vec<WebhookEvent> {
+        [
+            (WebhookEvent::BuildComplete, &self.build_complete),
+            (WebhookEvent::UnderAttack, &self.under_attack),
+            (WebhookEvent::MessageReceived, &self.message_received),
+        ]
+        .into_iter()
+        .filter(|(_, checked)| checked.is_some())
+        .map(|(event, _)| event)
+        .collect()
+    }
+}
+
+/// Handle POST requests to /webhooks/:action
+///
+/// Registers or deletes a webhook of the logged in user, then returns to the dashboard.
+pub async fn handle_webhook_action(
+    Path(action): Path<String>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    jar: CookieJar,
+    Form(form): Form<WebhookForm>,
+) -> Result<Redirect, Response> {
+    let user = get_current_user(jar, Extension(app_state.clone()))
+        .await
+        .ok_or_else(|| create_error_response("Not logged in"))?;
+    let webhooks = service(&app_state).map_err(create_error_response)?;
+
+    match action.as_str() {
+        "create" => {
+            webhooks
+                .register(user.id, &form.url, &form.events())
+                .await
+                .map_err(failed)?;
+        }
+        "delete" => {
+            let webhook_id = form
+                .webhook_id
+                .ok_or_else(|| create_error_response("No webhook given"))?;
+            webhooks.delete(user.id, webhook_id).await.map_err(failed)?;
+        }
+        _ => return Err(create_error_response("Unknown webhook action")),
+    }
+
+    Ok(Redirect::to("/dashboard"))
+}
+
+/// The webhooks section of the user dashboard, with the latest deliveries of each webhook
+pub async fn webhooks_section(
+    app_state: &Arc<AppState>,
+    user_id: i64,
+    csrf: &str,
+) -> Result<String, String> {
+    let webhooks = service(app_state)?;
+    let registered = webhooks
+        .webhooks(user_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut section = format!(
+        r#"
+    <div class="galaxy-list">
+        <h2>Webhooks</h2>
+        <p>Events are POSTed as JSON, signed in the <code>{}</code> header with the HMAC-SHA256 of the body keyed by the webhook's secret.</p>
+"#,
+        SIGNATURE_HEADER
+    );
+    if registered.is_empty() {
+        section.push_str("<p>You haven't registered any webhooks.</p>\n");
+    }
+    for webhook in &registered {
+        let events = webhook_events(webhook)
+            .iter()
+            .map(|event| event.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        section.push_str(&format!(
+            r#"<div class="galaxy-item">
+    <div class="galaxy-name">{}</div>
+    <div>Events: {}</div>
+    <div>Secret: <code>{}</code></div>
+"#,
+            escape_html(&webhook.url),
+            events,
+            webhook.secret
+        ));
+        let deliveries = webhooks
+            .deliveries(user_id, webhook.id)
+            .await
+            .map_err(|e| e.to_string())?;
+        for delivery in deliveries.iter().take(DASHBOARD_DELIVERIES) {
+            let outcome = match (&delivery.error, delivery.response_status) {
+                (Some(error), _) => escape_html(error),
+                (None, Some(status)) => format!("HTTP {}", status),
+                (None, None) => String::new(),
+            };
+            section.push_str(&format!(
+                "    <div>{} {} {} after {} attempts {}</div>\n",
+                delivery.created_at.format("%Y-%m-%d %H:%M UTC"),
+                delivery.event,
+                delivery.status,
+                delivery.attempts,
+                outcome
+            ));
+        }
+        section.push_str(&format!(
+            r#"    <form method="post" action="/webhooks/delete">{}<input type="hidden" name="webhook_id" value="{}"><button class="logout">Delete</button></form>
+</div>
+"#,
+            csrf, webhook.id
+        ));
+    }
+
+    section.push_str(&format!(
+        r#"<form method="post" action="/webhooks/create">
+    {}
+    <div class="form-group"><label for="url">URL:</label><input type="text" id="url" name="url" required placeholder="https://example.com/hook"></div>
+    <div class="form-group">
+"#,
+        csrf
+    ));
+    for event in WebhookEvent::ALL {
+        section.push_str(&format!(
+            r#"        <label><input type="checkbox" name="{0}" checked> {0}</label>
+"#,
+            event
+        ));
+    }
+    section.push_str(
+        r#"    </div>
+    <button>Add webhook</button>
+</form>
+    </div>
+"#,
+    );
+    Ok(section)
+}
+
+fn service(app_state: &Arc<AppState>) -> Result<WebhookService, &'static str> {
+    let db = app_state.database().ok_or("Galaxy service not available")?;
+    Ok(WebhookService::new(db.clone()))
+}
+
+fn failed(e: WebhookError) -> Response {
+    create_error_response(&e.to_string())
+}
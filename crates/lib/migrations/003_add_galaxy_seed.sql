@@ -0,0 +1,2 @@
+-- Store the generation seed for each galaxy so its map can be regenerated identically
+ALTER TABLE galaxies ADD COLUMN seed INTEGER;
@@ -0,0 +1,17 @@
+-- Track the end of a galaxy and its final standings
+
+-- Tick at which the galaxy ended, NULL while it is still running
+ALTER TABLE galaxies ADD COLUMN ended_at INTEGER;
+
+-- Final standings, written once when the galaxy ends
+CREATE TABLE galaxy_standings (
+    galaxy_name TEXT NOT NULL,
+    rank INTEGER NOT NULL,
+    user_galaxy_account_id INTEGER NOT NULL,
+    score INTEGER NOT NULL DEFAULT 0,
+    systems INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (galaxy_name) REFERENCES galaxies(name) ON DELETE CASCADE,
+    FOREIGN KEY (user_galaxy_account_id) REFERENCES user_galaxy_accounts(id) ON DELETE CASCADE,
+    PRIMARY KEY (galaxy_name, rank)
+);
@@ -0,0 +1,7 @@
+-- Allow galaxies to be paused, time spent paused doesn't count for the systems
+
+-- Tick at which the galaxy was paused, NULL while it is running
+ALTER TABLE galaxies ADD COLUMN paused_at INTEGER;
+
+-- Total ticks spent paused before the current pause
+ALTER TABLE galaxies ADD COLUMN paused_offset INTEGER NOT NULL DEFAULT 0;
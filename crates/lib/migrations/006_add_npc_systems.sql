@@ -0,0 +1,11 @@
+-- NPC barbarian systems and their state
+
+CREATE TABLE npc_systems (
+    system_id INTEGER PRIMARY KEY,
+    defenders INTEGER NOT NULL DEFAULT 0,
+    updated_tick INTEGER NOT NULL,
+    next_build INTEGER NOT NULL,
+    -- NULL when raids are disabled
+    next_attack INTEGER,
+    FOREIGN KEY (system_id) REFERENCES systems(id) ON DELETE CASCADE
+);
@@ -0,0 +1,4 @@
+-- Mark galaxy accounts played by the AI
+
+-- Name of the strategy playing the account, NULL for human players
+ALTER TABLE user_galaxy_accounts ADD COLUMN ai_strategy TEXT;
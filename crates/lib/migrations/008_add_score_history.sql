@@ -0,0 +1,16 @@
+-- Periodic snapshots of every player's score, for leaderboards and charts
+
+CREATE TABLE score_history (
+    galaxy_name TEXT NOT NULL,
+    -- Clock tick the snapshot was taken at
+    tick INTEGER NOT NULL,
+    user_galaxy_account_id INTEGER NOT NULL,
+    rank INTEGER NOT NULL,
+    score INTEGER NOT NULL DEFAULT 0,
+    systems INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (galaxy_name) REFERENCES galaxies(name) ON DELETE CASCADE,
+    FOREIGN KEY (user_galaxy_account_id) REFERENCES user_galaxy_accounts(id) ON DELETE CASCADE,
+    PRIMARY KEY (galaxy_name, tick, user_galaxy_account_id)
+);
+
+CREATE INDEX idx_score_history_account ON score_history(user_galaxy_account_id, tick);
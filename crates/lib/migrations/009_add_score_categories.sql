@@ -0,0 +1,5 @@
+-- Score of every category in the snapshots, see the scoring section of the galaxy config
+
+ALTER TABLE score_history ADD COLUMN economy INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE score_history ADD COLUMN military INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE score_history ADD COLUMN defense INTEGER NOT NULL DEFAULT 0;
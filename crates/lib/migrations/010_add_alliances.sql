@@ -0,0 +1,33 @@
+-- Alliances of galaxy accounts, with their members' roles and pending invites
+
+CREATE TABLE alliances (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    galaxy_name TEXT NOT NULL,
+    name TEXT NOT NULL,
+    -- Short tag shown next to the members' names
+    tag TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (galaxy_name) REFERENCES galaxies(name) ON DELETE CASCADE,
+    UNIQUE(galaxy_name, name),
+    UNIQUE(galaxy_name, tag)
+);
+
+-- Alliance of the account and its role in it (leader, officer or member), NULL for neither
+-- (SQLite doesn't support adding foreign keys via ALTER TABLE)
+ALTER TABLE user_galaxy_accounts ADD COLUMN alliance_id INTEGER;
+ALTER TABLE user_galaxy_accounts ADD COLUMN alliance_role TEXT;
+
+CREATE TABLE alliance_invites (
+    alliance_id INTEGER NOT NULL,
+    user_galaxy_account_id INTEGER NOT NULL,
+    invited_by INTEGER NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (alliance_id) REFERENCES alliances(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_galaxy_account_id) REFERENCES user_galaxy_accounts(id) ON DELETE CASCADE,
+    FOREIGN KEY (invited_by) REFERENCES user_galaxy_accounts(id) ON DELETE CASCADE,
+    PRIMARY KEY (alliance_id, user_galaxy_account_id)
+);
+
+CREATE INDEX idx_alliances_galaxy ON alliances(galaxy_name);
+CREATE INDEX idx_user_galaxy_accounts_alliance ON user_galaxy_accounts(alliance_id);
+CREATE INDEX idx_alliance_invites_account ON alliance_invites(user_galaxy_account_id);
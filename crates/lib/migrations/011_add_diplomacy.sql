@@ -0,0 +1,53 @@
+-- Diplomatic relations between alliances, pending proposals and the public diplomacy log
+
+-- Relation between two alliances, stored once with alliance_a < alliance_b. Alliances without
+-- a row are neutral.
+CREATE TABLE alliance_relations (
+    galaxy_name TEXT NOT NULL,
+    alliance_a INTEGER NOT NULL,
+    alliance_b INTEGER NOT NULL,
+    -- war, ceasefire, non_aggression_pact or peace
+    relation TEXT NOT NULL,
+    -- Clock tick the relation started
+    since INTEGER NOT NULL,
+    -- Clock tick a ceasefire runs out and the war resumes
+    expires_at INTEGER,
+    FOREIGN KEY (galaxy_name) REFERENCES galaxies(name) ON DELETE CASCADE,
+    FOREIGN KEY (alliance_a) REFERENCES alliances(id) ON DELETE CASCADE,
+    FOREIGN KEY (alliance_b) REFERENCES alliances(id) ON DELETE CASCADE,
+    PRIMARY KEY (alliance_a, alliance_b)
+);
+
+CREATE TABLE diplomacy_proposals (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    galaxy_name TEXT NOT NULL,
+    from_alliance INTEGER NOT NULL,
+    to_alliance INTEGER NOT NULL,
+    relation TEXT NOT NULL,
+    proposed_by INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    FOREIGN KEY (galaxy_name) REFERENCES galaxies(name) ON DELETE CASCADE,
+    FOREIGN KEY (from_alliance) REFERENCES alliances(id) ON DELETE CASCADE,
+    FOREIGN KEY (to_alliance) REFERENCES alliances(id) ON DELETE CASCADE,
+    FOREIGN KEY (proposed_by) REFERENCES user_galaxy_accounts(id) ON DELETE CASCADE
+);
+
+-- Alliance names are copied into the log so it outlives disbanded alliances
+CREATE TABLE diplomacy_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    galaxy_name TEXT NOT NULL,
+    tick INTEGER NOT NULL,
+    alliance_a INTEGER NOT NULL,
+    alliance_b INTEGER NOT NULL,
+    -- declared_war, accepted, cancelled or expired
+    action TEXT NOT NULL,
+    relation TEXT NOT NULL,
+    message TEXT NOT NULL,
+    FOREIGN KEY (galaxy_name) REFERENCES galaxies(name) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_alliance_relations_galaxy ON alliance_relations(galaxy_name);
+CREATE INDEX idx_diplomacy_proposals_to ON diplomacy_proposals(to_alliance);
+CREATE INDEX idx_diplomacy_proposals_from ON diplomacy_proposals(from_alliance);
+CREATE INDEX idx_diplomacy_log_galaxy ON diplomacy_log(galaxy_name, tick);
@@ -0,0 +1,31 @@
+-- Private messages between galaxy accounts, and the accounts each one has blocked
+
+CREATE TABLE messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    galaxy_name TEXT NOT NULL,
+    sender_id INTEGER NOT NULL,
+    recipient_id INTEGER NOT NULL,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    sent_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    -- NULL until the recipient reads the message
+    read_at TIMESTAMP,
+    -- Both sides delete their own copy, the row goes once neither has it
+    sender_deleted BOOLEAN NOT NULL DEFAULT FALSE,
+    recipient_deleted BOOLEAN NOT NULL DEFAULT FALSE,
+    FOREIGN KEY (galaxy_name) REFERENCES galaxies(name) ON DELETE CASCADE,
+    FOREIGN KEY (sender_id) REFERENCES user_galaxy_accounts(id) ON DELETE CASCADE,
+    FOREIGN KEY (recipient_id) REFERENCES user_galaxy_accounts(id) ON DELETE CASCADE
+);
+
+CREATE TABLE account_blocks (
+    user_galaxy_account_id INTEGER NOT NULL,
+    blocked_account_id INTEGER NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_galaxy_account_id) REFERENCES user_galaxy_accounts(id) ON DELETE CASCADE,
+    FOREIGN KEY (blocked_account_id) REFERENCES user_galaxy_accounts(id) ON DELETE CASCADE,
+    PRIMARY KEY (user_galaxy_account_id, blocked_account_id)
+);
+
+CREATE INDEX idx_messages_recipient ON messages(recipient_id, recipient_deleted);
+CREATE INDEX idx_messages_sender ON messages(sender_id, sender_deleted);
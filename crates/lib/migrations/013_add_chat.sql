@@ -0,0 +1,42 @@
+-- Chat channels with their message history
+--
+-- Every galaxy has one galaxy-wide channel and every alliance one channel for its members.
+-- Groups are ad-hoc channels with an explicit member list.
+
+CREATE TABLE chat_channels (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    galaxy_name TEXT NOT NULL,
+    -- galaxy, alliance or group
+    kind TEXT NOT NULL,
+    -- Alliance of an alliance channel
+    alliance_id INTEGER,
+    name TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (galaxy_name) REFERENCES galaxies(name) ON DELETE CASCADE,
+    FOREIGN KEY (alliance_id) REFERENCES alliances(id) ON DELETE CASCADE
+);
+
+CREATE TABLE chat_channel_members (
+    channel_id INTEGER NOT NULL,
+    user_galaxy_account_id INTEGER NOT NULL,
+    joined_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (channel_id) REFERENCES chat_channels(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_galaxy_account_id) REFERENCES user_galaxy_accounts(id) ON DELETE CASCADE,
+    PRIMARY KEY (channel_id, user_galaxy_account_id)
+);
+
+CREATE TABLE chat_messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    channel_id INTEGER NOT NULL,
+    user_galaxy_account_id INTEGER NOT NULL,
+    body TEXT NOT NULL,
+    sent_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (channel_id) REFERENCES chat_channels(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_galaxy_account_id) REFERENCES user_galaxy_accounts(id) ON DELETE CASCADE
+);
+
+-- Only one galaxy channel per galaxy and one channel per alliance
+CREATE UNIQUE INDEX idx_chat_channels_galaxy ON chat_channels(galaxy_name) WHERE kind = 'galaxy';
+CREATE UNIQUE INDEX idx_chat_channels_alliance ON chat_channels(alliance_id) WHERE kind = 'alliance';
+CREATE INDEX idx_chat_channel_members_account ON chat_channel_members(user_galaxy_account_id);
+CREATE INDEX idx_chat_messages_channel ON chat_messages(channel_id, id);
@@ -0,0 +1,20 @@
+-- Notifications telling galaxy accounts about what happened to them, with their read state
+
+CREATE TABLE notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_galaxy_account_id INTEGER NOT NULL,
+    -- build, incoming_attack, scout_detected, battle_report or diplomacy
+    kind TEXT NOT NULL,
+    -- Tick the event happened at
+    tick INTEGER NOT NULL,
+    -- System the notification is about, NULL for account wide ones
+    system_x INTEGER,
+    system_y INTEGER,
+    message TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    -- NULL until the account reads the notification
+    read_at TIMESTAMP,
+    FOREIGN KEY (user_galaxy_account_id) REFERENCES user_galaxy_accounts(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_notifications_account ON notifications(user_galaxy_account_id, read_at);
@@ -0,0 +1,14 @@
+-- Game alerts users chose to get by email, and which notifications were mailed
+
+CREATE TABLE mail_alerts (
+    user_id INTEGER NOT NULL,
+    -- Notification kind the user gets by email
+    kind TEXT NOT NULL,
+    -- Only notifications from this time on are mailed
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, kind),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+-- NULL until the notification was mailed to the account's user
+ALTER TABLE notifications ADD COLUMN mailed_at TIMESTAMP;
@@ -0,0 +1,16 @@
+-- Personal API tokens of users, for bots and scripts
+
+CREATE TABLE api_tokens (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    -- Hex SHA-256 of the token, which is only shown once when it's created
+    token_hash TEXT NOT NULL UNIQUE,
+    -- read_only, play or admin
+    scope TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    last_used_at TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_api_tokens_user ON api_tokens(user_id);
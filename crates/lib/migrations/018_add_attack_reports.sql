@@ -0,0 +1,21 @@
+-- NPC raids on player systems, saved with the galaxy so none are lost on a restart
+
+CREATE TABLE attack_reports (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    galaxy_name TEXT NOT NULL,
+    tick INTEGER NOT NULL,
+    attacker_x INTEGER NOT NULL,
+    attacker_y INTEGER NOT NULL,
+    target_x INTEGER NOT NULL,
+    target_y INTEGER NOT NULL,
+    raiders INTEGER NOT NULL,
+    loot_metal INTEGER NOT NULL DEFAULT 0,
+    loot_crew INTEGER NOT NULL DEFAULT 0,
+    loot_water INTEGER NOT NULL DEFAULT 0,
+    -- 1 for raids the owner of the target hasn't been told about yet, 0 for the recent raids
+    -- listed on the galaxy page, a raid can be in both
+    unreported INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (galaxy_name) REFERENCES galaxies(name) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_attack_reports_galaxy ON attack_reports(galaxy_name);
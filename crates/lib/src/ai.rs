@@ -0,0 +1,457 @@
+/// AI opponent players
+///
+/// An AI player owns systems like any other account and plays through the same `AppState` API
+/// as the web interface. What it builds is decided by a pluggable [`Strategy`].
+use indexmap::IndexMap;
+use std::collections::HashSet;
+
+use crate::app::AppState;
+use crate::user_service::{UserService, UserServiceError};
+use crate::{Coords, Cost, Details, Event, StructureType, SystemInfo};
+
+/// Names of the built-in strategies
+pub const STRATEGIES: [&str; 3] = ["economic", "raider", "turtle"];
+
+/// Structures that produce resources
+const PRODUCERS: [StructureType; 3] = [
+    StructureType::AsteroidMine,
+    StructureType::WaterHarvester,
+    StructureType::Hatchery,
+];
+
+/// What an AI player knows about one of its systems
+#[derive(Debug, Clone)]
+pub struct SystemView {
+    pub coords: Coords,
+    pub info: SystemInfo,
+    /// Cost of the next level of every structure in the system
+    pub costs: IndexMap<StructureType, Cost>,
+}
+
+impl SystemView {
+    /// Check if the next level of a structure can be paid for now
+    pub fn can_afford(&self, structure: StructureType) -> bool {
+        self.costs
+            .get(&structure)
+            .is_some_and(|cost| self.info.resources >= cost.resources)
+    }
+
+    /// Order structures by the total resources their next level costs, cheapest first
+    pub fn cheapest(&self, structures: &[StructureType]) -> Vec<StructureType> {
+        let mut structures: Vec<StructureType> = structures
+            .iter()
+            .filter(|structure| self.costs.contains_key(*structure))
+            .cloned()
+            .collect();
+        structures.sort_by_key(|structure| {
+            let cost = &self.costs[structure].resources;
+            cost.metal + cost.crew + cost.water
+        });
+        structures
+    }
+}
+
+/// Decides what an AI player builds
+pub trait Strategy: Send + Sync + std::fmt::Debug {
+    /// Name stored with the account
+    fn name(&self) -> &'static str;
+
+    /// Structures to upgrade in a system, most wanted first
+    ///
+    /// The first one that can be afforded is built.
+    fn priorities(&self, view: &SystemView) -> Vec<StructureType>;
+}
+
+/// Grows production as fast as it can, always upgrading the cheapest producer
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Economic;
+
+impl Strategy for Economic {
+    fn name(&self) -> &'static str {
+        "economic"
+    }
+
+    fn priorities(&self, view: &SystemView) -> Vec<StructureType> {
+        let mut priorities = view.cheapest(&PRODUCERS);
+        priorities.extend([StructureType::Colony, StructureType::StorageDepot]);
+        priorities
+    }
+}
+
+/// Puts crew first, as the crew will man its fleets once combat is added
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Raider;
+
+impl Strategy for Raider {
+    fn name(&self) -> &'static str {
+        "raider"
+    }
+
+    fn priorities(&self, view: &SystemView) -> Vec<StructureType> {
+        let mut priorities = vec![StructureType::Hatchery, StructureType::Colony];
+        priorities
+            .extend(view.cheapest(&[StructureType::AsteroidMine, StructureType::WaterHarvester]));
+        priorities.push(StructureType::StorageDepot);
+        priorities
+    }
+}
+
+/// Protects what it has, storage first with a slow economy behind it
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Turtle;
+
+impl Strategy for Turtle {
+    fn name(&self) -> &'static str {
+        "turtle"
+    }
+
+    fn priorities(&self, view: &SystemView) -> Vec<StructureType> {
+        let mut priorities = vec![StructureType::StorageDepot, StructureType::Colony];
+        priorities.extend(view.cheapest(&PRODUCERS));
+        priorities
+    }
+}
+
+/// Get a built-in strategy by name
+pub fn strategy(name: &str) -> Option<Box<dyn Strategy>> {
+    match name {
+        "economic" => Some(Box::new(Economic)),
+        "raider" => Some(Box::new(Raider)),
+        "turtle" => Some(Box::new(Turtle)),
+        _ => None,
+    }
+}
+
+/// Take a turn for an AI player, building at most one structure in each of its systems
+pub async fn take_turn(
+    app_state: &AppState,
+    galaxy_name: &str,
+    strategy: &dyn Strategy,
+    systems: &[Coords],
+) -> Result<Vec<Event>, String> {
+    let mut events = Vec::new();
+    for &coords in systems {
+        let tick = app_state.tick();
+        let info = match app_state
+            .get_galaxy_details(galaxy_name, tick, coords, None)
+            .await?
+        {
+            Details::System(info) => info,
+            _ => return Err("Unexpected Details type".to_string()),
+        };
+        // Systems only build one structure at a time
+        if info.events.iter().any(|event| event.structure.is_some()) {
+            continue;
+        }
+        let costs = match app_state
+            .get_galaxy_details(galaxy_name, tick, coords, Some(StructureType::Colony))
+            .await?
+        {
+            Details::Structure(colony) => colony.builds.unwrap_or_default(),
+            _ => return Err("Unexpected Details type".to_string()),
+        };
+
+        let view = SystemView {
+            coords,
+            info,
+            costs,
+        };
+        let Some(structure) = strategy
+            .priorities(&view)
+            .into_iter()
+            .find(|structure| view.can_afford(*structure))
+        else {
+            continue;
+        };
+        match app_state
+            .build_structure(galaxy_name, tick, coords, structure)
+            .await
+        {
+            Ok(event) => events.push(event),
+            // The galaxy may be paused or over, try again next turn
+            Err(e) => log::debug!("AI build of {} at {:?} failed: {}", structure, coords, e),
+        }
+    }
+    Ok(events)
+}
+
+/// Add the AI players from the galaxy config that haven't joined yet
+///
+/// Returns the number of players added. AI accounts live in the database, so nothing is added
+/// without one.
+pub async fn add_configured_players(
+    app_state: &AppState,
+    galaxy_name: &str,
+) -> Result<usize, UserServiceError> {
+    let Some(db) = app_state.database() else {
+        return Ok(0);
+    };
+    let players = {
+        let galaxies = app_state.galaxies().lock().await;
+        match galaxies.get(galaxy_name) {
+            Some(galaxy) => galaxy.get_config().ai.players.clone(),
+            None => return Ok(0),
+        }
+    };
+    if players.is_empty() {
+        return Ok(0);
+    }
+
+    let joined: HashSet<String> = db
+        .get_galaxy_accounts(galaxy_name)
+        .await?
+        .into_iter()
+        .map(|account| account.account_name)
+        .collect();
+    let user_service = UserService::new(db.clone());
+    let mut added = 0;
+    for player in players.iter().filter(|p| !joined.contains(&p.name)) {
+        match user_service
+            .add_ai_player(galaxy_name, &player.name, &player.strategy, app_state)
+            .await
+        {
+            Ok(_) => {}
+            // AI players follow the same registration window as everyone else
+            Err(UserServiceError::RegistrationClosed(_)) => break,
+            Err(e) => return Err(e),
+        }
+        log::info!(
+            "AI player {} joined galaxy {} with the {} strategy",
+            player.name,
+            galaxy_name,
+            player.strategy
+        );
+        added += 1;
+    }
+    Ok(added)
+}
+
+/// Take a turn for every AI player in a galaxy
+///
+/// Returns the number of structures built.
+pub async fn run_turns(app_state: &AppState, galaxy_name: &str) -> Result<usize, String> {
+    let Some(db) = app_state.database() else {
+        return Ok(0);
+    };
+    let accounts = db
+        .get_galaxy_accounts(galaxy_name)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut built = 0;
+    for account in accounts {
+        let Some(name) = account.ai_strategy.as_deref() else {
+            continue;
+        };
+        let Some(strategy) = strategy(name) else {
+            log::warn!("Unknown AI strategy {} for {}", name, account.account_name);
+            continue;
+        };
+        let systems: Vec<Coords> = db
+            .get_user_systems(account.id)
+            .await
+            .map_err(|e| e.to_string())?
+            .iter()
+            .map(|system| system.coords().into())
+            .collect();
+        built += take_turn(app_state, galaxy_name, strategy.as_ref(), &systems)
+            .await?
+            .len();
+    }
+    Ok(built)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{GalaxyConfig, VictoryCondition};
+    use crate::test_utils::{join, setup, setup_with, HOUR};
+    use crate::Resources;
+
+    const CONFIG: &str = r#"
+seed: 1
+system_count: 10
+size:
+  x: 20
+  y: 20
+ai:
+  players:
+    - name: Builder
+      strategy: economic
+    - name: Shell
+      strategy: turtle
+systems:
+  resources:
+    metal: 1000
+    crew: 1000
+    water: 1000
+  structures:
+    colony:
+      starting_level: 1
+      storage:
+        metal: 1000000
+        crew: 1000000
+        water: 1000000
+      cost:
+        time: 600
+        metal: 100
+        crew: 100
+        water: 100
+    asteroidmine:
+      starting_level: 1
+      production:
+        metal: 100
+      cost:
+        time: 600
+        metal: 50
+        crew: 50
+        water: 50
+    storagedepot:
+      starting_level: 1
+      cost:
+        time: 600
+        metal: 80
+        crew: 80
+        water: 80
+"#;
+
+    fn create_test_view() -> SystemView {
+        let cost = |metal| Cost {
+            resources: Resources {
+                metal,
+                crew: 1,
+                water: 1,
+            },
+            ticks: 60,
+        };
+        SystemView {
+            coords: (0, 0).into(),
+            info: SystemInfo {
+                resources: Resources {
+                    metal: 100,
+                    crew: 10,
+                    water: 10,
+                },
+                ..Default::default()
+            },
+            costs: [
+                (StructureType::Colony, cost(50)),
+                (StructureType::AsteroidMine, cost(30)),
+                (StructureType::WaterHarvester, cost(20)),
+                (StructureType::Hatchery, cost(200)),
+                (StructureType::StorageDepot, cost(40)),
+            ]
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    #[test]
+    fn test_strategies() {
+        let view = create_test_view();
+        let first_affordable = |strategy: &dyn Strategy| {
+            strategy
+                .priorities(&view)
+                .into_iter()
+                .find(|structure| view.can_afford(*structure))
+        };
+
+        assert_eq!(
+            first_affordable(&Economic),
+            Some(StructureType::WaterHarvester)
+        );
+        // The hatchery is too expensive, the colony comes next
+        assert_eq!(first_affordable(&Raider), Some(StructureType::Colony));
+        assert_eq!(first_affordable(&Turtle), Some(StructureType::StorageDepot));
+
+        for name in STRATEGIES {
+            assert_eq!(strategy(name).unwrap().name(), name);
+        }
+        assert!(strategy("cheater").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ai_players_join_once() {
+        let (_clock, db, app_state) = setup(CONFIG, &["ai"]).await;
+
+        assert_eq!(add_configured_players(&app_state, "ai").await.unwrap(), 2);
+        assert_eq!(add_configured_players(&app_state, "ai").await.unwrap(), 0);
+
+        let accounts = db.get_galaxy_accounts("ai").await.unwrap();
+        assert_eq!(accounts.len(), 2);
+        assert!(accounts.iter().all(|account| account.is_ai()));
+        assert_eq!(accounts[0].ai_strategy.as_deref(), Some("economic"));
+
+        // Nobody can log in as an AI player
+        let user = db
+            .get_user_by_id(accounts[0].user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let service = UserService::new(db.clone());
+        assert!(service
+            .auth()
+            .authenticate_user(&user.username, "!")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ai_players_build() {
+        let (clock, db, app_state) = setup(CONFIG, &["ai"]).await;
+        add_configured_players(&app_state, "ai").await.unwrap();
+
+        // Both players build right away, then wait for the build to finish
+        assert_eq!(run_turns(&app_state, "ai").await.unwrap(), 2);
+        assert_eq!(run_turns(&app_state, "ai").await.unwrap(), 0);
+        clock.advance(HOUR);
+        assert_eq!(run_turns(&app_state, "ai").await.unwrap(), 2);
+
+        let accounts = db.get_galaxy_accounts("ai").await.unwrap();
+        let system = db.get_user_systems(accounts[1].id).await.unwrap()[0].clone();
+        let info = app_state
+            .system_info("ai", system.coords().into())
+            .await
+            .unwrap();
+        // The turtle went for storage first
+        assert_eq!(info.structures.get(&StructureType::StorageDepot), Some(&2));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_strategy() {
+        let (_clock, db, app_state) = setup(CONFIG, &["ai"]).await;
+        let result = UserService::new(db)
+            .add_ai_player("ai", "Cheater", "cheater", &app_state)
+            .await;
+        assert!(matches!(result, Err(UserServiceError::UnknownStrategy(_))));
+    }
+
+    #[tokio::test]
+    async fn test_ai_players_dont_rank() {
+        let mut config: GalaxyConfig = serde_yaml::from_str(CONFIG).unwrap();
+        config.lifecycle.victory = vec![VictoryCondition::Score { score: 4 }];
+        let (clock, db, app_state) = setup_with(&config, &["ai"]).await;
+        add_configured_players(&app_state, "ai").await.unwrap();
+        let (ada, _) = join(&db, &app_state, "ai", "Ada").await;
+
+        // The AI players build past the winning score while Ada doesn't
+        run_turns(&app_state, "ai").await.unwrap();
+        clock.advance(HOUR);
+        assert_eq!(
+            app_state
+                .check_galaxy_end("ai", app_state.tick())
+                .await
+                .unwrap(),
+            None
+        );
+
+        // Ada wins when the galaxy ends anyway
+        app_state
+            .freeze_galaxy("ai", app_state.tick())
+            .await
+            .unwrap();
+        let standings = app_state.final_standings("ai").await.unwrap();
+        assert_eq!(standings.len(), 1);
+        assert_eq!((standings[0].rank, standings[0].account_id), (1, ada.id));
+    }
+}
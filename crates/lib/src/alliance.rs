@@ -0,0 +1,642 @@
+/// Alliances of galaxy accounts
+///
+/// An alliance belongs to a galaxy and is led by one of its members. Leaders and officers
+/// invite other accounts, which join by accepting. Membership is stored against the galaxy
+/// accounts so the rest of the game can tell allies apart, e.g. for the last alliance standing
+/// victory condition.
+use std::collections::HashMap;
+
+use crate::db::{Database, PersistenceError};
+use crate::models::{AllianceRow, UserGalaxyAccountRow};
+
+/// Longest alliance name
+pub const MAX_NAME_LENGTH: usize = 32;
+
+/// Longest alliance tag
+pub const MAX_TAG_LENGTH: usize = 5;
+
+/// Role of a member in their alliance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AllianceRole {
+    Member,
+    Officer,
+    Leader,
+}
+
+impl AllianceRole {
+    pub const ALL: [AllianceRole; 3] = [
+        AllianceRole::Leader,
+        AllianceRole::Officer,
+        AllianceRole::Member,
+    ];
+
+    /// Leaders and officers can invite new members
+    pub fn can_invite(self) -> bool {
+        self >= AllianceRole::Officer
+    }
+
+    /// Leaders and officers speak for the alliance in diplomacy
+    pub fn can_conduct_diplomacy(self) -> bool {
+        self >= AllianceRole::Officer
+    }
+
+    /// Members can only be kicked by someone with a higher role
+    pub fn can_kick(self, other: AllianceRole) -> bool {
+        self.can_invite() && self > other
+    }
+
+    /// Only the leader can promote and demote members
+    pub fn can_set_roles(self) -> bool {
+        self == AllianceRole::Leader
+    }
+}
+
+impl std::fmt::Display for AllianceRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AllianceRole::Leader => write!(f, "leader"),
+            AllianceRole::Officer => write!(f, "officer"),
+            AllianceRole::Member => write!(f, "member"),
+        }
+    }
+}
+
+impl std::str::FromStr for AllianceRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "leader" => Ok(AllianceRole::Leader),
+            "officer" => Ok(AllianceRole::Officer),
+            "member" => Ok(AllianceRole::Member),
+            _ => Err(format!("Unknown alliance role {}", s)),
+        }
+    }
+}
+
+/// A member of an alliance
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllianceMember {
+    pub account_id: i64,
+    pub account_name: String,
+    pub role: AllianceRole,
+}
+
+/// An alliance and its members
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alliance {
+    pub id: i64,
+    pub galaxy_name: String,
+    pub name: String,
+    pub tag: String,
+    /// Leader first, then officers and members in the order they joined the galaxy
+    pub members: Vec<AllianceMember>,
+}
+
+impl Alliance {
+    /// Role of an account, None if it isn't a member
+    pub fn role(&self, account_id: i64) -> Option<AllianceRole> {
+        self.members
+            .iter()
+            .find(|member| member.account_id == account_id)
+            .map(|member| member.role)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AllianceError {
+    #[error("Database error: {0}")]
+    Database(#[from] PersistenceError),
+
+    #[error("Galaxy account not found")]
+    AccountNotFound,
+
+    #[error("No account named {0} in this galaxy")]
+    UnknownAccount(String),
+
+    #[error("Alliance not found")]
+    AllianceNotFound,
+
+    #[error("Invalid alliance: {0}")]
+    InvalidAlliance(String),
+
+    #[error("Alliance name or tag already taken in this galaxy")]
+    NameTaken,
+
+    #[error("Already a member of an alliance")]
+    AlreadyInAlliance,
+
+    #[error("Not a member of this alliance")]
+    NotInAlliance,
+
+    #[error("Already invited to this alliance")]
+    AlreadyInvited,
+
+    #[error("No invite from this alliance")]
+    NotInvited,
+
+    #[error("Your role doesn't allow this")]
+    NotPermitted,
+
+    #[error("The leader must hand over the alliance before leaving")]
+    LeaderCannotLeave,
+}
+
+/// Service for managing alliances
+pub struct AllianceService {
+    db: Database,
+}
+
+impl AllianceService {
+    /// Create a new alliance service
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// Found an alliance, led by the account creating it
+    pub async fn create(
+        &self,
+        account_id: i64,
+        name: &str,
+        tag: &str,
+    ) -> Result<Alliance, AllianceError> {
+        let account = self.account(account_id).await?;
+        if account.alliance_id.is_some() {
+            return Err(AllianceError::AlreadyInAlliance);
+        }
+        let (name, tag) = (name.trim(), tag.trim());
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            return Err(AllianceError::InvalidAlliance(format!(
+                "the name must be 1 to {} characters",
+                MAX_NAME_LENGTH
+            )));
+        }
+        if tag.is_empty()
+            || tag.len() > MAX_TAG_LENGTH
+            || !tag.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return Err(AllianceError::InvalidAlliance(format!(
+                "the tag must be 1 to {} letters or digits",
+                MAX_TAG_LENGTH
+            )));
+        }
+        if !self
+            .db
+            .is_alliance_name_available(&account.galaxy_name, name, tag)
+            .await?
+        {
+            return Err(AllianceError::NameTaken);
+        }
+
+        let alliance_id = self
+            .db
+            .create_alliance(&account.galaxy_name, name, tag, account.id)
+            .await?;
+        self.get(alliance_id)
+            .await?
+            .ok_or(AllianceError::AllianceNotFound)
+    }
+
+    /// Get an alliance with its members
+    pub async fn get(&self, alliance_id: i64) -> Result<Option<Alliance>, AllianceError> {
+        match self.db.get_alliance(alliance_id).await? {
+            Some(row) => Ok(Some(self.with_members(row).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the alliance of an account, if it has one
+    pub async fn alliance_of(&self, account_id: i64) -> Result<Option<Alliance>, AllianceError> {
+        match self.account(account_id).await?.alliance_id {
+            Some(alliance_id) => self.get(alliance_id).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Get every alliance in a galaxy, oldest first
+    pub async fn alliances(&self, galaxy_name: &str) -> Result<Vec<Alliance>, AllianceError> {
+        let mut alliances = Vec::new();
+        for row in self.db.get_galaxy_alliances(galaxy_name).await? {
+            alliances.push(self.with_members(row).await?);
+        }
+        Ok(alliances)
+    }
+
+    /// Map every account in an alliance to its alliance id
+    pub async fn memberships(&self, galaxy_name: &str) -> Result<HashMap<i64, i64>, AllianceError> {
+        Ok(memberships(
+            &self.db.get_galaxy_accounts(galaxy_name).await?,
+        ))
+    }
+
+    /// Check if two accounts are in the same alliance
+    ///
+    /// An account is always allied with itself.
+    pub async fn are_allies(&self, account_a: i64, account_b: i64) -> Result<bool, AllianceError> {
+        if account_a == account_b {
+            return Ok(true);
+        }
+        let a = self.account(account_a).await?.alliance_id;
+        let b = self.account(account_b).await?.alliance_id;
+        Ok(a.is_some() && a == b)
+    }
+
+    /// Invite an account to the alliance of the one inviting, by its name in the galaxy
+    pub async fn invite(&self, account_id: i64, account_name: &str) -> Result<(), AllianceError> {
+        let (account, alliance) = self.membership(account_id).await?;
+        if !alliance
+            .role(account.id)
+            .is_some_and(AllianceRole::can_invite)
+        {
+            return Err(AllianceError::NotPermitted);
+        }
+        let invited = self
+            .db
+            .get_galaxy_account_by_name(&account.galaxy_name, account_name)
+            .await?
+            .ok_or_else(|| AllianceError::UnknownAccount(account_name.to_string()))?;
+        if invited.alliance_id.is_some() {
+            return Err(AllianceError::AlreadyInAlliance);
+        }
+        if !self
+            .db
+            .create_alliance_invite(alliance.id, invited.id, account.id)
+            .await?
+        {
+            return Err(AllianceError::AlreadyInvited);
+        }
+        Ok(())
+    }
+
+    /// Get the alliances that have invited an account
+    pub async fn invites(&self, account_id: i64) -> Result<Vec<AllianceRow>, AllianceError> {
+        Ok(self.db.get_account_invites(account_id).await?)
+    }
+
+    /// Get the accounts an alliance has invited
+    pub async fn pending_invites(
+        &self,
+        alliance_id: i64,
+    ) -> Result<Vec<UserGalaxyAccountRow>, AllianceError> {
+        Ok(self.db.get_alliance_invites(alliance_id).await?)
+    }
+
+    /// Accept an invite, joining the alliance as a member
+    ///
+    /// Every other invite of the account is dropped.
+    pub async fn accept(&self, account_id: i64, alliance_id: i64) -> Result<(), AllianceError> {
+        let account = self.account(account_id).await?;
+        if account.alliance_id.is_some() {
+            return Err(AllianceError::AlreadyInAlliance);
+        }
+        if !self
+            .invites(account.id)
+            .await?
+            .iter()
+            .any(|alliance| alliance.id == alliance_id)
+        {
+            return Err(AllianceError::NotInvited);
+        }
+        // Checked again as it joins, in case another invite was accepted meanwhile
+        if !self
+            .db
+            .join_alliance(account.id, alliance_id, &AllianceRole::Member.to_string())
+            .await?
+        {
+            return Err(AllianceError::AlreadyInAlliance);
+        }
+        Ok(())
+    }
+
+    /// Decline an invite
+    pub async fn decline(&self, account_id: i64, alliance_id: i64) -> Result<(), AllianceError> {
+        if !self
+            .db
+            .delete_alliance_invite(alliance_id, account_id)
+            .await?
+        {
+            return Err(AllianceError::NotInvited);
+        }
+        Ok(())
+    }
+
+    /// Leave the alliance of an account
+    ///
+    /// The leader can only leave as the last member, which disbands the alliance.
+    pub async fn leave(&self, account_id: i64) -> Result<(), AllianceError> {
+        let (account, alliance) = self.membership(account_id).await?;
+        if alliance.role(account.id) == Some(AllianceRole::Leader) {
+            if alliance.members.len() > 1 {
+                return Err(AllianceError::LeaderCannotLeave);
+            }
+            self.db.delete_alliance(alliance.id).await?;
+        } else {
+            self.db.leave_alliance(account.id).await?;
+        }
+        Ok(())
+    }
+
+    /// Remove a member from the alliance of the account kicking them
+    pub async fn kick(&self, account_id: i64, account_name: &str) -> Result<(), AllianceError> {
+        let (account, alliance) = self.membership(account_id).await?;
+        let target = Self::member(&alliance, account_name)?;
+        match alliance.role(account.id) {
+            Some(role) if role.can_kick(target.role) => {}
+            _ => return Err(AllianceError::NotPermitted),
+        }
+        self.db.leave_alliance(target.account_id).await?;
+        Ok(())
+    }
+
+    /// Change the role of a member
+    ///
+    /// Making someone else the leader hands the alliance over, the old leader becomes an
+    /// officer.
+    pub async fn set_role(
+        &self,
+        account_id: i64,
+        account_name: &str,
+        role: AllianceRole,
+    ) -> Result<(), AllianceError> {
+        let (account, alliance) = self.membership(account_id).await?;
+        if !alliance
+            .role(account.id)
+            .is_some_and(AllianceRole::can_set_roles)
+        {
+            return Err(AllianceError::NotPermitted);
+        }
+        let target = Self::member(&alliance, account_name)?;
+        if target.account_id == account.id {
+            return Err(AllianceError::NotPermitted);
+        }
+        if role == AllianceRole::Leader {
+            if !self
+                .db
+                .hand_over_alliance(account.id, target.account_id)
+                .await?
+            {
+                return Err(AllianceError::NotPermitted);
+            }
+        } else {
+            self.db
+                .set_alliance_role(target.account_id, &role.to_string())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn account(&self, account_id: i64) -> Result<UserGalaxyAccountRow, AllianceError> {
+        self.db
+            .get_galaxy_account(account_id)
+            .await?
+            .ok_or(AllianceError::AccountNotFound)
+    }
+
+    /// An account and the alliance it's a member of
+    async fn membership(
+        &self,
+        account_id: i64,
+    ) -> Result<(UserGalaxyAccountRow, Alliance), AllianceError> {
+        let account = self.account(account_id).await?;
+        let alliance_id = account.alliance_id.ok_or(AllianceError::NotInAlliance)?;
+        let alliance = self
+            .get(alliance_id)
+            .await?
+            .ok_or(AllianceError::AllianceNotFound)?;
+        Ok((account, alliance))
+    }
+
+    fn member<'a>(
+        alliance: &'a Alliance,
+        account_name: &str,
+    ) -> Result<&'a AllianceMember, AllianceError> {
+        alliance
+            .members
+            .iter()
+            .find(|member| member.account_name == account_name)
+            .ok_or(AllianceError::NotInAlliance)
+    }
+
+    async fn with_members(&self, row: AllianceRow) -> Result<Alliance, AllianceError> {
+        let mut members: Vec<AllianceMember> = self
+            .db
+            .get_alliance_members(row.id)
+            .await?
+            .into_iter()
+            .map(|account| AllianceMember {
+                role: account
+                    .alliance_role
+                    .as_deref()
+                    .and_then(|role| role.parse().ok())
+                    .unwrap_or(AllianceRole::Member),
+                account_id: account.id,
+                account_name: account.account_name,
+            })
+            .collect();
+        // Sorting is stable, so members with the same role stay in the order they joined
+        members.sort_by_key(|member| std::cmp::Reverse(member.role));
+        Ok(Alliance {
+            id: row.id,
+            galaxy_name: row.galaxy_name,
+            name: row.name,
+            tag: row.tag,
+            members,
+        })
+    }
+}
+
+/// Map every account in an alliance to its alliance id
+pub fn memberships(accounts: &[UserGalaxyAccountRow]) -> HashMap<i64, i64> {
+    accounts
+        .iter()
+        .filter_map(|account| Some((account.id, account.alliance_id?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{self, join, CONFIG};
+    use crate::{AppState, UserGalaxyAccount};
+
+    #[test]
+    fn test_role_permissions() {
+        use AllianceRole::*;
+        assert!(Leader.can_invite());
+        assert!(Officer.can_invite());
+        assert!(!Member.can_invite());
+        assert!(Officer.can_conduct_diplomacy());
+        assert!(!Member.can_conduct_diplomacy());
+
+        assert!(Leader.can_kick(Officer));
+        assert!(Officer.can_kick(Member));
+        assert!(!Officer.can_kick(Officer));
+        assert!(!Officer.can_kick(Leader));
+        assert!(!Member.can_kick(Member));
+
+        assert!(Leader.can_set_roles());
+        assert!(!Officer.can_set_roles());
+
+        for role in AllianceRole::ALL {
+            assert_eq!(role.to_string().parse::<AllianceRole>(), Ok(role));
+        }
+        assert!("admiral".parse::<AllianceRole>().is_err());
+    }
+
+    /// Ada, Bo and Cy in a galaxy, with Ada leading the Star League
+    async fn setup() -> (AppState, AllianceService, Alliance, [UserGalaxyAccount; 3]) {
+        let (_, db, app_state) = test_utils::setup(CONFIG, &["pact"]).await;
+        let mut accounts = Vec::new();
+        for name in ["Ada", "Bo", "Cy"] {
+            accounts.push(join(&db, &app_state, "pact", name).await.0);
+        }
+        let alliances = AllianceService::new(db);
+        let alliance = alliances
+            .create(accounts[0].id, "Star League", "STAR")
+            .await
+            .unwrap();
+        (app_state, alliances, alliance, accounts.try_into().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_create() {
+        let (_app_state, alliances, alliance, [ada, bo, _cy]) = setup().await;
+
+        assert_eq!(alliance.role(ada.id), Some(AllianceRole::Leader));
+        assert!(matches!(
+            alliances.create(bo.id, "Star League", "SL").await,
+            Err(AllianceError::NameTaken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invites() {
+        let (_app_state, alliances, alliance, [ada, bo, cy]) = setup().await;
+
+        // Bo joins by accepting an invite, and members can't invite
+        alliances.invite(ada.id, "Bo").await.unwrap();
+        assert!(matches!(
+            alliances.invite(ada.id, "Bo").await,
+            Err(AllianceError::AlreadyInvited)
+        ));
+        assert_eq!(alliances.invites(bo.id).await.unwrap()[0].id, alliance.id);
+        alliances.accept(bo.id, alliance.id).await.unwrap();
+        assert!(alliances.invites(bo.id).await.unwrap().is_empty());
+        assert!(matches!(
+            alliances.invite(bo.id, "Cy").await,
+            Err(AllianceError::NotPermitted)
+        ));
+        assert!(alliances.are_allies(ada.id, bo.id).await.unwrap());
+        assert!(!alliances.are_allies(ada.id, cy.id).await.unwrap());
+
+        // A declined invite can't be accepted
+        alliances.invite(ada.id, "Cy").await.unwrap();
+        alliances.decline(cy.id, alliance.id).await.unwrap();
+        assert!(matches!(
+            alliances.accept(cy.id, alliance.id).await,
+            Err(AllianceError::NotInvited)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_roles() {
+        let (_app_state, alliances, alliance, [ada, bo, cy]) = setup().await;
+        alliances.invite(ada.id, "Bo").await.unwrap();
+        alliances.accept(bo.id, alliance.id).await.unwrap();
+
+        // Officers can invite, but not kick each other
+        alliances
+            .set_role(ada.id, "Bo", AllianceRole::Officer)
+            .await
+            .unwrap();
+        alliances.invite(bo.id, "Cy").await.unwrap();
+        assert_eq!(alliances.invites(cy.id).await.unwrap()[0].id, alliance.id);
+        assert!(matches!(
+            alliances.kick(bo.id, "Ada").await,
+            Err(AllianceError::NotPermitted)
+        ));
+
+        // The leader hands over before leaving
+        assert!(matches!(
+            alliances.leave(ada.id).await,
+            Err(AllianceError::LeaderCannotLeave)
+        ));
+        alliances
+            .set_role(ada.id, "Bo", AllianceRole::Leader)
+            .await
+            .unwrap();
+        let alliance = alliances.get(alliance.id).await.unwrap().unwrap();
+        assert_eq!(alliance.members[0].account_name, "Bo");
+        assert_eq!(alliance.role(ada.id), Some(AllianceRole::Officer));
+
+        // A handover that lost a race with another changes nothing
+        assert!(!alliances
+            .db
+            .hand_over_alliance(ada.id, cy.id)
+            .await
+            .unwrap());
+        let alliance = alliances.get(alliance.id).await.unwrap().unwrap();
+        assert_eq!(alliance.role(bo.id), Some(AllianceRole::Leader));
+        assert_eq!(alliance.role(ada.id), Some(AllianceRole::Officer));
+    }
+
+    #[tokio::test]
+    async fn test_accept_race() {
+        let (_app_state, alliances, alliance, [ada, bo, cy]) = setup().await;
+        alliances.invite(ada.id, "Cy").await.unwrap();
+        let other = alliances.create(bo.id, "Dark Pact", "DARK").await.unwrap();
+        alliances.invite(bo.id, "Cy").await.unwrap();
+
+        // Cy accepted both invites at once, only the first gets in
+        assert!(alliances
+            .db
+            .join_alliance(cy.id, other.id, "member")
+            .await
+            .unwrap());
+        assert!(!alliances
+            .db
+            .join_alliance(cy.id, alliance.id, "member")
+            .await
+            .unwrap());
+        assert_eq!(
+            alliances.alliance_of(cy.id).await.unwrap().unwrap().id,
+            other.id
+        );
+    }
+
+    #[tokio::test]
+    async fn test_leaderboard() {
+        let (app_state, alliances, alliance, [ada, bo, _cy]) = setup().await;
+        alliances.invite(ada.id, "Bo").await.unwrap();
+        alliances.accept(bo.id, alliance.id).await.unwrap();
+
+        let board = app_state
+            .alliance_leaderboard("pact", app_state.tick(), None)
+            .await
+            .unwrap();
+        assert_eq!(board.len(), 1);
+        assert_eq!(board[0].tag, "STAR");
+        assert_eq!(board[0].members, 2);
+        assert_eq!(board[0].systems, 2);
+        // A colony and a mine at level 1 each
+        assert_eq!(board[0].score, 4);
+    }
+
+    #[tokio::test]
+    async fn test_disband() {
+        let (_app_state, alliances, alliance, [ada, bo, _cy]) = setup().await;
+        alliances.invite(ada.id, "Bo").await.unwrap();
+        alliances.accept(bo.id, alliance.id).await.unwrap();
+        alliances
+            .set_role(ada.id, "Bo", AllianceRole::Leader)
+            .await
+            .unwrap();
+
+        // The last member leaving disbands the alliance
+        alliances.kick(bo.id, "Ada").await.unwrap();
+        assert!(alliances.alliance_of(ada.id).await.unwrap().is_none());
+        alliances.leave(bo.id).await.unwrap();
+        assert!(alliances.get(alliance.id).await.unwrap().is_none());
+        assert!(alliances.alliances("pact").await.unwrap().is_empty());
+    }
+}
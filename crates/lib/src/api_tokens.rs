@@ -0,0 +1,291 @@
+/// Personal API tokens of users
+///
+/// Bots and scripts authenticate to the JSON API with a token instead of a browser session. A
+/// user creates tokens on their dashboard, each with a scope limiting what it can do for them,
+/// and revokes them there. Only the SHA-256 of a token is stored, so it's shown once when it's
+/// created and can't be recovered afterwards.
+use sha2::{Digest, Sha256};
+
+use crate::db::{Database, PersistenceError};
+use crate::models::{ApiTokenRow, User};
+
+/// API tokens a user can have
+pub const MAX_TOKENS: usize = 10;
+
+/// Longest token name
+pub const MAX_NAME_LENGTH: usize = 50;
+
+/// Start of every token, so they are easy to recognise in configs and logs
+pub const TOKEN_PREFIX: &str = "gwt_";
+
+/// What a token may do, each scope allowing everything the previous ones do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TokenScope {
+    /// Read the user's accounts, on top of what is public
+    ReadOnly,
+    /// Play the user's galaxy accounts, like joining galaxies and building
+    Play,
+    /// Manage the server, like creating galaxies
+    Admin,
+}
+
+impl TokenScope {
+    /// Every scope, in the order they are listed
+    pub const ALL: [TokenScope; 3] = [TokenScope::ReadOnly, TokenScope::Play, TokenScope::Admin];
+
+    /// Whether a token with this scope may do what needs another scope
+    pub fn allows(&self, needed: TokenScope) -> bool {
+        *self >= needed
+    }
+}
+
+impl std::fmt::Display for TokenScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenScope::ReadOnly => write!(f, "read_only"),
+            TokenScope::Play => write!(f, "play"),
+            TokenScope::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl std::str::FromStr for TokenScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "read_only" => Ok(TokenScope::ReadOnly),
+            "play" => Ok(TokenScope::Play),
+            "admin" => Ok(TokenScope::Admin),
+            _ => Err(format!("Unknown token scope {}", s)),
+        }
+    }
+}
+
+/// The scope of a stored token
+pub fn token_scope(token: &ApiTokenRow) -> Option<TokenScope> {
+    token.scope.parse().ok()
+}
+
+/// Hash a token the way it's stored, as the hex SHA-256
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiTokenError {
+    #[error("Database error: {0}")]
+    Database(#[from] PersistenceError),
+
+    #[error("Token names must be 1 to {MAX_NAME_LENGTH} characters")]
+    InvalidName,
+
+    #[error("You can't have more than {MAX_TOKENS} API tokens")]
+    TooManyTokens,
+
+    #[error("API token not found")]
+    TokenNotFound,
+
+    #[error("Invalid API token")]
+    InvalidToken,
+}
+
+/// Service for API tokens
+pub struct ApiTokenService {
+    db: Database,
+}
+
+impl ApiTokenService {
+    /// Create a new API token service
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// Create a token of a user, returning it with the token itself
+    ///
+    /// The token can't be looked up again, so it has to be shown to the user now.
+    pub async fn create(
+        &self,
+        user_id: i64,
+        name: &str,
+        scope: TokenScope,
+    ) -> Result<(ApiTokenRow, String), ApiTokenError> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            return Err(ApiTokenError::InvalidName);
+        }
+        if self.db.get_api_tokens(user_id).await?.len() >= MAX_TOKENS {
+            return Err(ApiTokenError::TooManyTokens);
+        }
+
+        let token = format!(
+            "{}{}",
+            TOKEN_PREFIX,
+            hex::encode(rand::random::<[u8; 32]>())
+        );
+        let token_id = self
+            .db
+            .create_api_token(user_id, name, &hash_token(&token), &scope.to_string())
+            .await?;
+        let row = self
+            .db
+            .get_api_token(token_id)
+            .await?
+            .ok_or(ApiTokenError::TokenNotFound)?;
+        Ok((row, token))
+    }
+
+    /// Get the tokens of a user
+    pub async fn tokens(&self, user_id: i64) -> Result<Vec<ApiTokenRow>, ApiTokenError> {
+        Ok(self.db.get_api_tokens(user_id).await?)
+    }
+
+    /// Revoke a token of a user, it stops working straight away
+    pub async fn revoke(&self, user_id: i64, token_id: i64) -> Result<(), ApiTokenError> {
+        if self.db.delete_api_token(token_id, user_id).await? {
+            Ok(())
+        } else {
+            Err(ApiTokenError::TokenNotFound)
+        }
+    }
+
+    /// Find the user of a token and what it may do, recording that it was used
+    pub async fn authenticate(&self, token: &str) -> Result<(User, TokenScope), ApiTokenError> {
+        if !token.starts_with(TOKEN_PREFIX) {
+            return Err(ApiTokenError::InvalidToken);
+        }
+        let row = self
+            .db
+            .get_api_token_by_hash(&hash_token(token))
+            .await?
+            .ok_or(ApiTokenError::InvalidToken)?;
+        let scope = token_scope(&row).ok_or(ApiTokenError::InvalidToken)?;
+        let user = self
+            .db
+            .get_user_by_id(row.user_id)
+            .await?
+            .ok_or(ApiTokenError::InvalidToken)?;
+        self.db.touch_api_token(row.id).await?;
+        Ok((user.into(), scope))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::Row;
+
+    /// Ada and Bob with a token service, and a Play token of Ada's
+    async fn setup() -> (Database, ApiTokenService, i64, i64, ApiTokenRow, String) {
+        let db = Database::new_test().await.unwrap();
+        let ada = db
+            .create_user("ada", "ada@example.com", "hash")
+            .await
+            .unwrap();
+        let bob = db
+            .create_user("bob", "bob@example.com", "hash")
+            .await
+            .unwrap();
+        let tokens = ApiTokenService::new(db.clone());
+        let (row, token) = tokens.create(ada, "Bot", TokenScope::Play).await.unwrap();
+        (db, tokens, ada, bob, row, token)
+    }
+
+    #[test]
+    fn test_scopes() {
+        assert!(TokenScope::Admin.allows(TokenScope::Play));
+        assert!(TokenScope::Play.allows(TokenScope::ReadOnly));
+        assert!(TokenScope::Play.allows(TokenScope::Play));
+        assert!(!TokenScope::ReadOnly.allows(TokenScope::Play));
+        assert!(!TokenScope::Play.allows(TokenScope::Admin));
+        for scope in TokenScope::ALL {
+            assert_eq!(scope.to_string().parse(), Ok(scope));
+        }
+    }
+
+    #[test]
+    fn test_hash_token() {
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create() {
+        let (db, tokens, ada, _bob, row, token) = setup().await;
+
+        assert!(matches!(
+            tokens.create(ada, "  ", TokenScope::Play).await,
+            Err(ApiTokenError::InvalidName)
+        ));
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(row.name, "Bot");
+        assert_eq!(token_scope(&row), Some(TokenScope::Play));
+        assert_eq!(row.last_used_at, None);
+
+        // Only the hash is stored
+        let stored: String = sqlx::query("SELECT token_hash FROM api_tokens WHERE id = ?")
+            .bind(row.id)
+            .fetch_one(db.pool())
+            .await
+            .unwrap()
+            .get("token_hash");
+        assert_eq!(stored, hash_token(&token));
+        assert!(!stored.contains(&token[TOKEN_PREFIX.len()..]));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate() {
+        let (_db, tokens, ada, _bob, _row, token) = setup().await;
+
+        let (user, scope) = tokens.authenticate(&token).await.unwrap();
+        assert_eq!(user.username, "ada");
+        assert_eq!(scope, TokenScope::Play);
+        assert!(tokens.tokens(ada).await.unwrap()[0].last_used_at.is_some());
+        for invalid in [
+            "",
+            "gwt_",
+            &token[TOKEN_PREFIX.len()..],
+            &format!("{}0", token),
+        ] {
+            assert!(matches!(
+                tokens.authenticate(invalid).await,
+                Err(ApiTokenError::InvalidToken)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_revoke() {
+        let (_db, tokens, ada, bob, row, token) = setup().await;
+
+        // Only the owner can revoke a token, and it stops working
+        assert!(matches!(
+            tokens.revoke(bob, row.id).await,
+            Err(ApiTokenError::TokenNotFound)
+        ));
+        tokens.revoke(ada, row.id).await.unwrap();
+        assert!(tokens.tokens(ada).await.unwrap().is_empty());
+        assert!(matches!(
+            tokens.authenticate(&token).await,
+            Err(ApiTokenError::InvalidToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_too_many_tokens() {
+        let (_db, tokens, _ada, bob, _row, _token) = setup().await;
+
+        for i in 0..MAX_TOKENS {
+            tokens
+                .create(bob, &format!("Token {}", i), TokenScope::ReadOnly)
+                .await
+                .unwrap();
+        }
+        assert!(matches!(
+            tokens.create(bob, "One more", TokenScope::ReadOnly).await,
+            Err(ApiTokenError::TooManyTokens)
+        ));
+    }
+}
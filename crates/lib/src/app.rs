@@ -1,7 +1,21 @@
-use crate::{config::GalaxyConfig, Coords, Details, Event, Galaxy, SystemInfo};
+use crate::{
+    alliance::{self, AllianceService},
+    chat::ChatHub,
+    config::{GalaxyConfig, ScoreCategory},
+    diplomacy::DiplomacyService,
+    leaderboard::{self, AllianceEntry, LeaderboardEntry, ScoreSeries, DELTA_PERIOD},
+    lifecycle::{self, EndReason, GalaxyPhase, Standing},
+    live::{LiveEvent, LiveHub, LiveUpdate},
+    mail::{self, MailService, Mailer},
+    notifications::NotificationService,
+    planner::{self, BuildPlan, Goal},
+    scoring::Scores,
+    Coords, Details, Event, Galaxy, SystemInfo,
+};
 
 use crate::{
     app_config::AppConfig,
+    clock::{AcceleratedClock, Clock, RealClock},
     db::Database,
     persistence::{PersistenceConfig, PersistenceManager},
 };
@@ -17,6 +31,26 @@ pub struct AppState {
     persistence_manager: Option<PersistenceManager>,
     /// Galaxy storage
     galaxies: Arc<Mutex<HashMap<String, Galaxy>>>,
+    /// Clock used for every game action
+    clock: Arc<dyn Clock>,
+    /// Delivers new chat messages to connected players
+    chat: ChatHub,
+    /// Delivers changes of their systems to connected players
+    live: LiveHub,
+    /// Sends email, None if it's disabled
+    mailer: Option<Arc<dyn Mailer>>,
+    /// Public URL of the server, for links in emails
+    base_url: String,
+}
+
+/// Ranked human players of a galaxy
+#[derive(Debug, Default)]
+struct PlayerStandings {
+    standings: Vec<Standing>,
+    /// Name of every ranked account
+    names: HashMap<i64, String>,
+    /// Score of every category per account
+    scores: HashMap<i64, Scores>,
 }
 
 impl AppState {
@@ -29,8 +63,24 @@ impl AppState {
     pub async fn new_with_config(
         config_path: Option<&str>,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let galaxies = Arc::new(Mutex::new(HashMap::new()));
         let app_config = AppConfig::load_from_file_and_env(config_path)?;
+        let mailer = mail::mailer(&app_config.mail)?;
+        if mailer.is_some() {
+            log::info!(
+                "Sending email with the {:?} backend",
+                app_config.mail.backend
+            );
+        }
+
+        let clock: Arc<dyn Clock> = if app_config.clock.speed == 1.0 {
+            Arc::new(RealClock)
+        } else {
+            log::info!(
+                "Running the game clock at {}x speed",
+                app_config.clock.speed
+            );
+            Arc::new(AcceleratedClock::new(app_config.clock.speed))
+        };
 
         if app_config.persistence.enabled {
             log::info!("Initializing with database persistence");
@@ -50,38 +100,110 @@ impl AppState {
             log::info!("Persistence config: auto_save_interval={}s, write_coalescing={}, coalescing_delay={}ms", 
                 config.auto_save_interval, config.write_coalescing, config.coalescing_delay_ms);
 
-            // Create persistence manager with weak reference to galaxies
-            let galaxies_weak = Arc::downgrade(&galaxies);
-            let persistence_manager =
-                PersistenceManager::new(database, config, galaxies_weak).await?;
+            let app_state = Self::with_persistence(database, config, clock).await?;
+            Ok(match mailer {
+                Some(mailer) => app_state.with_mailer(mailer, &app_config.mail.base_url),
+                None => app_state,
+            })
+        } else {
+            log::info!("Persistence disabled via configuration");
+            Ok(Self::new_in_memory(clock))
+        }
+    }
+
+    /// Initialize with an existing database and clock
+    ///
+    /// Uses the default persistence settings. This is mostly useful for tests that need
+    /// accounts, which are only stored in the database.
+    pub async fn new_with_database(
+        database: Database,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_persistence(database, PersistenceConfig::default(), clock).await
+    }
 
-            let app_state = Self {
-                persistence_manager: Some(persistence_manager),
-                galaxies,
-            };
+    /// Initialize without persistence, all galaxies only live in memory
+    pub fn new_in_memory(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            persistence_manager: None,
+            galaxies: Arc::new(Mutex::new(HashMap::new())),
+            clock,
+            chat: ChatHub::new(),
+            live: LiveHub::new(),
+            mailer: None,
+            base_url: String::new(),
+        }
+    }
 
-            // Load all existing galaxies at startup
-            if let Err(e) = app_state.load_all_galaxies().await {
-                log::error!("Failed to load galaxies at startup: {}", e);
-            }
+    /// Start the persistence manager and load every galaxy in the database
+    async fn with_persistence(
+        database: Database,
+        config: PersistenceConfig,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let galaxies = Arc::new(Mutex::new(HashMap::new()));
 
-            Ok(app_state)
-        } else {
-            log::info!("Persistence disabled via configuration");
-            Ok(Self {
-                persistence_manager: None,
-                galaxies,
-            })
+        // Create persistence manager with weak reference to galaxies
+        let galaxies_weak = Arc::downgrade(&galaxies);
+        let persistence_manager = PersistenceManager::new(database, config, galaxies_weak).await?;
+
+        let app_state = Self {
+            persistence_manager: Some(persistence_manager),
+            galaxies,
+            clock,
+            chat: ChatHub::new(),
+            live: LiveHub::new(),
+            mailer: None,
+            base_url: String::new(),
+        };
+
+        // Load all existing galaxies at startup
+        if let Err(e) = app_state.load_all_galaxies().await {
+            log::error!("Failed to load galaxies at startup: {}", e);
         }
+
+        Ok(app_state)
     }
 
     /// Create a test instance without persistence (for testing)
     #[cfg(test)]
     pub async fn new_test() -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(Self {
-            persistence_manager: None,
-            galaxies: Arc::new(Mutex::new(HashMap::new())),
-        })
+        Ok(Self::new_in_memory(Arc::new(RealClock)))
+    }
+
+    /// Get the current tick from the game clock
+    pub fn tick(&self) -> usize {
+        self.clock.now()
+    }
+
+    /// Get the game clock
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// Get the hub delivering new chat messages
+    pub fn chat_hub(&self) -> &ChatHub {
+        &self.chat
+    }
+
+    /// Get the hub delivering live updates of player systems
+    pub fn live_hub(&self) -> &LiveHub {
+        &self.live
+    }
+
+    /// Send email through a mailer, linking to the server at a public URL
+    pub fn with_mailer(mut self, mailer: Arc<dyn Mailer>, base_url: &str) -> Self {
+        self.mailer = Some(mailer);
+        self.base_url = base_url.to_string();
+        self
+    }
+
+    /// Get a mail service, None without a database
+    ///
+    /// The service only sends email if a mailer was set up.
+    pub fn mail(&self) -> Option<MailService> {
+        self.database()
+            .map(|db| MailService::new(db.clone(), self.mailer.clone(), &self.base_url))
     }
 
     /// Create a new galaxy
@@ -128,31 +250,38 @@ impl AppState {
                         }
                     }
                 }
-                Ok(false) => {
-                    // Galaxy doesn't exist, create it
-                    let config_yaml = serde_yaml::to_string(config)
-                        .map_err(|e| format!("Failed to serialize config: {}", e))?;
+                Ok(false) => {} // Galaxy doesn't exist, it is created below
+                Err(e) => log::error!("Database error checking galaxy existence: {}", e),
+            }
+        }
+
+        // Create galaxy in memory, this also picks the seed if the config doesn't set one
+        let galaxy = Galaxy::new(config.clone(), initial_tick)?;
 
-                    match pm
+        if let Some(ref pm) = self.persistence_manager {
+            // Store the config with the resolved seed, so the galaxy can be regenerated
+            let config_yaml = serde_yaml::to_string(galaxy.get_config())
+                .map_err(|e| format!("Failed to serialize config: {}", e))?;
+
+            match pm
+                .database()
+                .create_galaxy(galaxy_name, &config_yaml, initial_tick)
+                .await
+            {
+                Ok(_) => {
+                    log::info!("Created galaxy {} in database", galaxy_name);
+                    if let Err(e) = pm
                         .database()
-                        .create_galaxy(galaxy_name, &config_yaml, initial_tick)
+                        .update_galaxy_seed(galaxy_name, galaxy.seed())
                         .await
                     {
-                        Ok(_) => log::info!("Created galaxy {} in database", galaxy_name),
-                        Err(e) => log::error!(
-                            "Failed to create galaxy {} in database: {}",
-                            galaxy_name,
-                            e
-                        ),
+                        log::error!("Failed to store seed for galaxy {}: {}", galaxy_name, e);
                     }
                 }
-                Err(e) => log::error!("Database error checking galaxy existence: {}", e),
+                Err(e) => log::error!("Failed to create galaxy {} in database: {}", galaxy_name, e),
             }
         }
 
-        // Create galaxy in memory
-        let galaxy = Galaxy::new(config.clone(), initial_tick);
-
         {
             let mut galaxies = self.galaxies.lock().await;
             galaxies.insert(galaxy_name.to_string(), galaxy);
@@ -223,7 +352,7 @@ impl AppState {
         coords: Coords,
         structure: crate::StructureType,
     ) -> Result<Event, String> {
-        // Ensure galaxy is loaded
+        // Ensure galaxy is loaded, builds past its end are refused by the galaxy itself
         self.ensure_galaxy_loaded(galaxy_name).await?;
 
         let result = {
@@ -252,6 +381,489 @@ impl AppState {
         }
     }
 
+    /// Report the end of a galaxy, once
+    ///
+    /// Galaxies end themselves as soon as they are brought to a tick past their end or a
+    /// victory condition, and store their final standings with the next save. This brings the
+    /// galaxy up to the tick and returns why it ended if that wasn't reported yet, storing the
+    /// final standings straight away.
+    pub async fn check_galaxy_end(
+        &self,
+        galaxy_name: &str,
+        tick: usize,
+    ) -> Result<Option<EndReason>, String> {
+        self.ensure_galaxy_loaded(galaxy_name).await?;
+        let (reason, ended_at, standings) = {
+            let mut galaxies = self.galaxies.lock().await;
+            let galaxy = galaxies
+                .get_mut(galaxy_name)
+                .ok_or_else(|| format!("Galaxy '{}' not found", galaxy_name))?;
+            match galaxy.take_end(tick)? {
+                Some(reason) => (
+                    reason,
+                    galaxy.ended_at().unwrap_or(tick),
+                    galaxy.standings().to_vec(),
+                ),
+                None => return Ok(None),
+            }
+        };
+
+        log::info!(
+            "Galaxy {} ended at tick {}: {:?}",
+            galaxy_name,
+            ended_at,
+            reason
+        );
+        self.store_final_standings(galaxy_name, ended_at, &standings)
+            .await;
+
+        Ok(Some(reason))
+    }
+
+    /// Get the phase of a galaxy at a tick, bringing it up to the tick so it ends on time
+    pub async fn galaxy_phase(
+        &self,
+        galaxy_name: &str,
+        tick: usize,
+    ) -> Result<GalaxyPhase, String> {
+        self.ensure_galaxy_loaded(galaxy_name).await?;
+        let mut galaxies = self.galaxies.lock().await;
+        let galaxy = galaxies
+            .get_mut(galaxy_name)
+            .ok_or_else(|| format!("Galaxy '{}' not found", galaxy_name))?;
+        galaxy.update_tick(tick)?;
+        Ok(galaxy.phase(tick))
+    }
+
+    /// End a galaxy immediately, freezing it with the current standings
+    pub async fn freeze_galaxy(&self, galaxy_name: &str, tick: usize) -> Result<(), String> {
+        self.ensure_galaxy_loaded(galaxy_name).await?;
+
+        let (ended_at, standings) = {
+            let mut galaxies = self.galaxies.lock().await;
+            let galaxy = galaxies
+                .get_mut(galaxy_name)
+                .ok_or_else(|| format!("Galaxy '{}' not found", galaxy_name))?;
+            galaxy.freeze(tick)?;
+            (
+                galaxy.ended_at().unwrap_or(tick),
+                galaxy.standings().to_vec(),
+            )
+        };
+
+        log::info!("Galaxy {} frozen at tick {}", galaxy_name, ended_at);
+        self.store_final_standings(galaxy_name, ended_at, &standings)
+            .await;
+        Ok(())
+    }
+
+    /// Reload the human players of a galaxy and their alliances, which its standings rank
+    ///
+    /// Players and alliances are only stored in the database, so this is needed whenever
+    /// someone joins the galaxy or an alliance changes members.
+    pub async fn refresh_players(&self, galaxy_name: &str) -> Result<(), String> {
+        self.ensure_galaxy_loaded(galaxy_name).await?;
+        let (owners, accounts) = self.galaxy_players(galaxy_name).await?;
+        let alliances = self.galaxy_alliances(galaxy_name).await?;
+        let mut galaxies = self.galaxies.lock().await;
+        let galaxy = galaxies
+            .get_mut(galaxy_name)
+            .ok_or_else(|| format!("Galaxy '{}' not found", galaxy_name))?;
+        galaxy.set_players(owners, accounts, alliances);
+        Ok(())
+    }
+
+    /// Pause a galaxy, so no production accrues and no events complete until it is resumed
+    pub async fn pause_galaxy(&self, galaxy_name: &str, tick: usize) -> Result<(), String> {
+        self.update_pause(galaxy_name, |galaxy| galaxy.pause(tick))
+            .await?;
+        log::info!("Galaxy {} paused at tick {}", galaxy_name, tick);
+        Ok(())
+    }
+
+    /// Resume a paused galaxy
+    pub async fn resume_galaxy(&self, galaxy_name: &str, tick: usize) -> Result<(), String> {
+        self.update_pause(galaxy_name, |galaxy| galaxy.resume(tick))
+            .await?;
+        log::info!("Galaxy {} resumed at tick {}", galaxy_name, tick);
+        Ok(())
+    }
+
+    /// Get the tick seen by the systems of a galaxy, excluding the time it spent paused
+    pub async fn game_tick(&self, galaxy_name: &str, tick: usize) -> Result<usize, String> {
+        self.ensure_galaxy_loaded(galaxy_name).await?;
+        let galaxies = self.galaxies.lock().await;
+        galaxies
+            .get(galaxy_name)
+            .map(|galaxy| galaxy.game_tick(tick))
+            .ok_or_else(|| format!("Galaxy '{}' not found", galaxy_name))
+    }
+
+    /// Change the pause state of a galaxy, and store it straight away
+    async fn update_pause<F>(&self, galaxy_name: &str, update: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut Galaxy) -> Result<(), String>,
+    {
+        self.ensure_galaxy_loaded(galaxy_name).await?;
+        let (paused_at, paused_offset) = {
+            let mut galaxies = self.galaxies.lock().await;
+            let galaxy = galaxies
+                .get_mut(galaxy_name)
+                .ok_or_else(|| format!("Galaxy '{}' not found", galaxy_name))?;
+            update(galaxy)?;
+            (galaxy.paused_at(), galaxy.paused_offset())
+        };
+
+        if let Some(db) = self.database() {
+            db.update_galaxy_pause(galaxy_name, paused_at, paused_offset)
+                .await
+                .map_err(|e| format!("Failed to store pause state: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Get the owner of every player owned system, and every account in a galaxy
+    ///
+    /// Ownership is only stored in the database, so both are empty without persistence.
+    async fn galaxy_owners(
+        &self,
+        galaxy_name: &str,
+    ) -> Result<(HashMap<Coords, i64>, Vec<i64>), String> {
+        let mut owners = HashMap::new();
+        let mut accounts = Vec::new();
+        if let Some(db) = self.database() {
+            for system in db
+                .get_owned_systems(galaxy_name)
+                .await
+                .map_err(|e| format!("Failed to load system owners: {}", e))?
+            {
+                if let Some(account) = system.user_galaxy_account_id {
+                    owners.insert((system.x as usize, system.y as usize).into(), account);
+                }
+            }
+            accounts = db
+                .get_galaxy_accounts(galaxy_name)
+                .await
+                .map_err(|e| format!("Failed to load galaxy accounts: {}", e))?
+                .iter()
+                .map(|account| account.id)
+                .collect();
+        }
+        Ok((owners, accounts))
+    }
+
+    /// Get the owner of every system of a human player, and every human account in a galaxy
+    ///
+    /// AI players never rank or win, they only stand in for missing opponents.
+    async fn galaxy_players(
+        &self,
+        galaxy_name: &str,
+    ) -> Result<(HashMap<Coords, i64>, Vec<i64>), String> {
+        let Some(db) = self.database() else {
+            return Ok((HashMap::new(), Vec::new()));
+        };
+        let accounts: Vec<i64> = db
+            .get_galaxy_accounts(galaxy_name)
+            .await
+            .map_err(|e| format!("Failed to load galaxy accounts: {}", e))?
+            .iter()
+            .filter(|account| !account.is_ai())
+            .map(|account| account.id)
+            .collect();
+        let (owners, _) = self.galaxy_owners(galaxy_name).await?;
+        let owners = owners
+            .into_iter()
+            .filter(|(_, account)| accounts.contains(account))
+            .collect();
+        Ok((owners, accounts))
+    }
+
+    /// Map every account in an alliance to its alliance id
+    ///
+    /// Empty without persistence, as alliances are only stored in the database.
+    async fn galaxy_alliances(&self, galaxy_name: &str) -> Result<HashMap<i64, i64>, String> {
+        let Some(db) = self.database() else {
+            return Ok(HashMap::new());
+        };
+        let accounts = db
+            .get_galaxy_accounts(galaxy_name)
+            .await
+            .map_err(|e| format!("Failed to load galaxy accounts: {}", e))?;
+        Ok(alliance::memberships(&accounts))
+    }
+
+    /// Store the final standings of a galaxy that has just ended
+    async fn store_final_standings(
+        &self,
+        galaxy_name: &str,
+        ended_at: usize,
+        standings: &[Standing],
+    ) {
+        if let Some(db) = self.database() {
+            if let Err(e) = db
+                .save_final_standings(galaxy_name, ended_at, standings)
+                .await
+            {
+                log::error!(
+                    "Failed to store final standings for galaxy {}: {}",
+                    galaxy_name,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Get the final standings of a galaxy, empty if it hasn't ended
+    pub async fn final_standings(&self, galaxy_name: &str) -> Result<Vec<Standing>, String> {
+        self.ensure_galaxy_loaded(galaxy_name).await?;
+        let galaxies = self.galaxies.lock().await;
+        galaxies
+            .get(galaxy_name)
+            .map(|galaxy| galaxy.standings().to_vec())
+            .ok_or_else(|| format!("Galaxy '{}' not found", galaxy_name))
+    }
+
+    /// Rank the human players of a galaxy by the score of their systems
+    ///
+    /// AI players aren't ranked, they only stand in for missing opponents. Players are ranked
+    /// by a score category, or by their total score for None.
+    async fn player_standings(
+        &self,
+        galaxy_name: &str,
+        tick: usize,
+        category: Option<ScoreCategory>,
+    ) -> Result<PlayerStandings, String> {
+        self.ensure_galaxy_loaded(galaxy_name).await?;
+        let Some(db) = self.database() else {
+            return Ok(PlayerStandings::default());
+        };
+        let names: HashMap<i64, String> = db
+            .get_galaxy_accounts(galaxy_name)
+            .await
+            .map_err(|e| format!("Failed to load galaxy accounts: {}", e))?
+            .into_iter()
+            .filter(|account| !account.is_ai())
+            .map(|account| (account.id, account.account_name))
+            .collect();
+        let (owners, _) = self.galaxy_owners(galaxy_name).await?;
+        let owners: HashMap<Coords, i64> = owners
+            .into_iter()
+            .filter(|(_, account)| names.contains_key(account))
+            .collect();
+        let accounts: Vec<i64> = names.keys().copied().collect();
+
+        let system_scores = {
+            let mut galaxies = self.galaxies.lock().await;
+            let galaxy = galaxies
+                .get_mut(galaxy_name)
+                .ok_or_else(|| format!("Galaxy '{}' not found", galaxy_name))?;
+            galaxy.system_scores(tick, owners.keys().copied())
+        };
+        let mut scores: HashMap<i64, Scores> = HashMap::new();
+        for (coords, account) in &owners {
+            let system = system_scores.get(coords).copied().unwrap_or_default();
+            let total = scores.entry(*account).or_default();
+            *total = *total + system;
+        }
+        let ranked_by = system_scores
+            .iter()
+            .map(|(coords, scores)| (*coords, scores.get(category)))
+            .collect();
+        let standings = lifecycle::compute_standings(&ranked_by, &owners, &accounts);
+        Ok(PlayerStandings {
+            standings,
+            names,
+            scores,
+        })
+    }
+
+    /// Get the leaderboard of a galaxy, with every player's progress over the last day
+    ///
+    /// Players are ranked by a score category, or by their total score for None.
+    pub async fn leaderboard(
+        &self,
+        galaxy_name: &str,
+        tick: usize,
+        category: Option<ScoreCategory>,
+    ) -> Result<Vec<LeaderboardEntry>, String> {
+        let players = self.player_standings(galaxy_name, tick, category).await?;
+        let previous = match self.database() {
+            Some(db) => {
+                let rows = db
+                    .get_score_snapshot(galaxy_name, tick.saturating_sub(DELTA_PERIOD))
+                    .await
+                    .map_err(|e| format!("Failed to load score history: {}", e))?;
+                leaderboard::snapshot_standings(&rows, category)
+            }
+            None => Vec::new(),
+        };
+        Ok(leaderboard::leaderboard(
+            &players.standings,
+            &players.names,
+            &players.scores,
+            &previous,
+        ))
+    }
+
+    /// Get the alliance leaderboard of a galaxy
+    ///
+    /// Alliances are ranked by the sum of their human members, by a score category or by the
+    /// total score for None.
+    pub async fn alliance_leaderboard(
+        &self,
+        galaxy_name: &str,
+        tick: usize,
+        category: Option<ScoreCategory>,
+    ) -> Result<Vec<AllianceEntry>, String> {
+        let Some(db) = self.database() else {
+            return Ok(Vec::new());
+        };
+        let players = self.player_standings(galaxy_name, tick, category).await?;
+        let alliances = AllianceService::new(db.clone())
+            .alliances(galaxy_name)
+            .await
+            .map_err(|e| format!("Failed to load alliances: {}", e))?;
+        Ok(leaderboard::alliance_leaderboard(
+            &alliances,
+            &players.standings,
+            &players.scores,
+        ))
+    }
+
+    /// Store the current standings of a galaxy in the score history
+    ///
+    /// Ended galaxies are skipped as their scores no longer change. Returns whether a
+    /// snapshot was taken.
+    pub async fn snapshot_scores(&self, galaxy_name: &str, tick: usize) -> Result<bool, String> {
+        let Some(db) = self.database() else {
+            return Ok(false);
+        };
+        {
+            self.ensure_galaxy_loaded(galaxy_name).await?;
+            let galaxies = self.galaxies.lock().await;
+            if galaxies
+                .get(galaxy_name)
+                .is_some_and(|galaxy| galaxy.ended_at().is_some())
+            {
+                return Ok(false);
+            }
+        }
+        let players = self.player_standings(galaxy_name, tick, None).await?;
+        db.save_score_snapshot(galaxy_name, tick, &players.standings, &players.scores)
+            .await
+            .map_err(|e| format!("Failed to store score snapshot: {}", e))?;
+        Ok(true)
+    }
+
+    /// Get the score history of a galaxy since a tick, one series per player
+    pub async fn score_history(
+        &self,
+        galaxy_name: &str,
+        account_id: Option<i64>,
+        since: usize,
+    ) -> Result<Vec<ScoreSeries>, String> {
+        let Some(db) = self.database() else {
+            return Ok(Vec::new());
+        };
+        let rows = db
+            .get_score_history(galaxy_name, account_id, since)
+            .await
+            .map_err(|e| format!("Failed to load score history: {}", e))?;
+        Ok(leaderboard::score_series(&rows))
+    }
+
+    /// Notify the owners of player systems of what happened to them since the last call
+    ///
+    /// Builds due by the tick are completed first so they're all reported. The notices are also
+    /// sent as live updates, and without persistence that's all. Returns the number of
+    /// notifications sent.
+    pub async fn collect_notifications(
+        &self,
+        galaxy_name: &str,
+        tick: usize,
+    ) -> Result<usize, String> {
+        self.ensure_galaxy_loaded(galaxy_name).await?;
+        let notices = {
+            let mut galaxies = self.galaxies.lock().await;
+            galaxies
+                .get_mut(galaxy_name)
+                .ok_or_else(|| format!("Galaxy '{}' not found", galaxy_name))?
+                .take_notices(tick)?
+        };
+        for notice in &notices {
+            self.live
+                .publish(LiveUpdate::from_notice(galaxy_name, notice));
+        }
+        let Some(db) = self.database() else {
+            return Ok(0);
+        };
+        if notices.is_empty() {
+            return Ok(0);
+        }
+
+        let (owners, _) = self.galaxy_owners(galaxy_name).await?;
+        let notifications = NotificationService::new(db.clone());
+        let mut sent = 0;
+        for notice in &notices {
+            let Some(&account_id) = owners.get(&notice.coords()) else {
+                continue;
+            };
+            notifications
+                .notify_notice(account_id, notice)
+                .await
+                .map_err(|e| format!("Failed to store notification: {}", e))?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    /// Send the player systems of a galaxy that changed since the last call as live updates
+    ///
+    /// With `all`, every player system is sent, so their resources keep moving for the players
+    /// watching them. Nothing is sent while nobody is subscribed. Returns the number of updates
+    /// sent.
+    pub async fn publish_live_updates(
+        &self,
+        galaxy_name: &str,
+        tick: usize,
+        all: bool,
+    ) -> Result<usize, String> {
+        self.ensure_galaxy_loaded(galaxy_name).await?;
+        let mut galaxies = self.galaxies.lock().await;
+        let galaxy = galaxies
+            .get_mut(galaxy_name)
+            .ok_or_else(|| format!("Galaxy '{}' not found", galaxy_name))?;
+        let changed = galaxy.take_changes(tick, all)?;
+        if !self.live.has_subscribers() {
+            return Ok(0);
+        }
+
+        let game_tick = galaxy.game_tick(tick);
+        for &coords in &changed {
+            let Details::System(system) = galaxy.get_details(tick, coords, None)? else {
+                return Err("Unexpected Details type".to_string());
+            };
+            self.live.publish(LiveUpdate {
+                galaxy: galaxy_name.to_string(),
+                coords,
+                tick: game_tick,
+                event: LiveEvent::System { system },
+            });
+        }
+        Ok(changed.len())
+    }
+
+    /// Check if new players can join a galaxy
+    pub async fn check_registration(&self, galaxy_name: &str, tick: usize) -> Result<(), String> {
+        self.ensure_galaxy_loaded(galaxy_name).await?;
+        let mut galaxies = self.galaxies.lock().await;
+        let galaxy = galaxies
+            .get_mut(galaxy_name)
+            .ok_or_else(|| format!("Galaxy '{}' not found", galaxy_name))?;
+        galaxy.update_tick(tick)?;
+        galaxy.check_registration(tick)
+    }
+
     /// Manually save all dirty galaxies
     pub async fn save_all(&self) -> Result<usize, String> {
         if let Some(ref pm) = self.persistence_manager {
@@ -385,7 +997,19 @@ impl AppState {
 
     /// Gracefully shutdown without consuming self (for signal handlers)
     pub async fn shutdown_gracefully(&self) -> Result<(), String> {
-        // Save all dirty galaxies first
+        // Finished builds are only kept in memory until they're reported
+        let galaxies: Vec<String> = self.galaxies.lock().await.keys().cloned().collect();
+        for galaxy in galaxies {
+            if let Err(e) = self.collect_notifications(&galaxy, self.tick()).await {
+                log::warn!(
+                    "Failed to collect notifications in galaxy {}: {}",
+                    galaxy,
+                    e
+                );
+            }
+        }
+
+        // Save all dirty galaxies
         self.save_all().await?;
 
         if let Some(ref pm) = self.persistence_manager {
@@ -402,7 +1026,7 @@ impl AppState {
     /// Retrieve the details of a system
     pub async fn system_info(&self, galaxy: &str, coords: Coords) -> Result<SystemInfo, String> {
         let dets = self
-            .get_galaxy_details(galaxy, crate::tick(), coords, None)
+            .get_galaxy_details(galaxy, self.tick(), coords, None)
             .await?;
         match dets {
             Details::System(info) => Ok(info),
@@ -410,6 +1034,66 @@ impl AppState {
         }
     }
 
+    /// Check if the owner of one system may attack the owner of another
+    ///
+    /// Missions between systems go through this, so allies and alliances that agreed not to
+    /// fight can't attack each other. Systems without a player owner can always be attacked.
+    pub async fn check_attack(
+        &self,
+        galaxy_name: &str,
+        from: Coords,
+        to: Coords,
+        tick: usize,
+    ) -> Result<(), String> {
+        let Some(db) = self.database() else {
+            return Ok(());
+        };
+        let owner = |coords: Coords| async move {
+            db.get_system(galaxy_name, coords.x, coords.y)
+                .await
+                .map(|system| system.and_then(|system| system.user_galaxy_account_id))
+                .map_err(|e| format!("Failed to load system: {}", e))
+        };
+        let (Some(attacker), Some(defender)) = (owner(from).await?, owner(to).await?) else {
+            return Ok(());
+        };
+        DiplomacyService::new(db.clone())
+            .check_attack(attacker, defender, tick)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// Plan the build order that reaches a goal soonest in a system
+    ///
+    /// Plans start from the galaxy's game tick, which is also the tick a score deadline is
+    /// given in.
+    pub async fn plan_builds(
+        &self,
+        galaxy_name: &str,
+        tick: usize,
+        coords: Coords,
+        goal: Goal,
+    ) -> Result<BuildPlan, String> {
+        self.ensure_galaxy_loaded(galaxy_name).await?;
+        // Copy the system so the search runs without holding the lock
+        let (system, config, game_tick) = {
+            let galaxies = self.galaxies.lock().await;
+            let galaxy = galaxies
+                .get(galaxy_name)
+                .ok_or_else(|| format!("Galaxy '{}' not found", galaxy_name))?;
+            let system = galaxy
+                .systems()
+                .get(&coords)
+                .cloned()
+                .ok_or_else(|| format!("No system at {:?}", coords))?;
+            (system, galaxy.get_config().clone(), galaxy.game_tick(tick))
+        };
+        // Long plans take a while to search, keep them off the async workers
+        tokio::task::spawn_blocking(move || planner::plan(&system, game_tick, &config, goal))
+            .await
+            .map_err(|e| format!("Planner failed: {}", e))?
+    }
+
     /// Get direct access to galaxy storage for binary use (legacy compatibility)
     pub fn galaxies(&self) -> &Arc<Mutex<HashMap<String, Galaxy>>> {
         &self.galaxies
@@ -433,11 +1117,14 @@ impl AppState {
                 if let Some(coords) = galaxy.create_user_system(tick) {
                     // Get galaxy config first (immutable borrow)
                     let galaxy_config = galaxy.get_config().clone();
+                    let tick = galaxy.game_tick(tick);
 
                     // Now get mutable system reference
                     let system = galaxy.systems_mut().get_mut(&coords).unwrap();
+                    let scores = system.scores(tick, &galaxy_config);
                     let system_info = SystemInfo {
-                        score: system.score(tick, &galaxy_config),
+                        score: scores.total(),
+                        scores,
                         resources: system.get_resources(),
                         production: system.get_production(tick, &galaxy_config),
                         structures: {
@@ -466,12 +1153,14 @@ impl AppState {
 mod tests {
     use super::*;
     use crate::config::GalaxyConfig;
+    use crate::test_utils::{self, HOUR, START};
 
     fn create_test_config() -> GalaxyConfig {
         GalaxyConfig {
             system_count: 5,
             size: crate::config::GalaxySize { x: 10, y: 10 },
             systems: crate::config::SystemConfig::default(),
+            ..Default::default()
         }
     }
 
@@ -542,6 +1231,177 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_galaxy_end() {
+        let app_state = AppState::new_test().await.unwrap();
+        let mut config = create_test_config();
+        config.lifecycle.start = Some(100);
+        config.lifecycle.end = Some(1000);
+        app_state
+            .create_galaxy("lifecycle_test", &config, 0)
+            .await
+            .unwrap();
+        let coords = {
+            let galaxies = app_state.galaxies.lock().await;
+            let galaxy = galaxies.get("lifecycle_test").unwrap();
+            *galaxy.systems().keys().next().unwrap()
+        };
+
+        // Nothing can be built before the start, or after the end
+        let result = app_state
+            .build_structure("lifecycle_test", 50, coords, crate::StructureType::Colony)
+            .await;
+        assert_eq!(result.unwrap_err(), "Galaxy has not started yet");
+        assert_eq!(
+            app_state.check_galaxy_end("lifecycle_test", 500).await,
+            Ok(None)
+        );
+        assert_eq!(
+            app_state.check_galaxy_end("lifecycle_test", 1500).await,
+            Ok(Some(EndReason::Timeout))
+        );
+        let result = app_state
+            .build_structure("lifecycle_test", 1600, coords, crate::StructureType::Colony)
+            .await;
+        assert_eq!(result.unwrap_err(), "Galaxy has ended");
+        assert!(app_state
+            .check_registration("lifecycle_test", 1700)
+            .await
+            .is_err());
+
+        // Nobody joined, so there is nobody to rank
+        assert!(app_state
+            .final_standings("lifecycle_test")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_galaxy_ends_itself() {
+        let mut config: GalaxyConfig = serde_yaml::from_str(test_utils::CONFIG).unwrap();
+        config.lifecycle.end = Some(START + HOUR);
+        let (clock, db, app_state) = test_utils::setup_with(&config, &["ending"]).await;
+        let (ada, coords) = test_utils::join(&db, &app_state, "ending", "Ada").await;
+
+        // A build past the end ends the galaxy before any background check
+        clock.advance(2 * HOUR);
+        let result = app_state
+            .build_structure(
+                "ending",
+                app_state.tick(),
+                coords,
+                crate::StructureType::AsteroidMine,
+            )
+            .await;
+        assert_eq!(result.unwrap_err(), "Galaxy has ended");
+
+        // Its standings are saved along with it
+        app_state.save_all().await.unwrap();
+        let standings = db.get_final_standings("ending").await.unwrap();
+        assert_eq!(standings.len(), 1);
+        assert_eq!(standings[0].user_galaxy_account_id, ada.id);
+
+        // The end is reported once
+        assert_eq!(
+            app_state.check_galaxy_end("ending", app_state.tick()).await,
+            Ok(Some(EndReason::Timeout))
+        );
+        assert_eq!(
+            app_state.check_galaxy_end("ending", app_state.tick()).await,
+            Ok(None)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_galaxy_phase_records_the_end() {
+        let app_state = AppState::new_test().await.unwrap();
+        let mut config = create_test_config();
+        config.lifecycle.end = Some(1000);
+        app_state
+            .create_galaxy("phase_test", &config, 0)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            app_state.galaxy_phase("phase_test", 500).await,
+            Ok(GalaxyPhase::Running)
+        );
+        assert_eq!(
+            app_state.galaxy_phase("phase_test", 1500).await,
+            Ok(GalaxyPhase::Ended)
+        );
+        let galaxies = app_state.galaxies.lock().await;
+        assert_eq!(galaxies["phase_test"].ended_at(), Some(1000));
+    }
+
+    #[tokio::test]
+    async fn test_galaxy_pause_and_resume() {
+        let app_state = AppState::new_test().await.unwrap();
+        let config = create_test_config();
+        app_state
+            .create_galaxy("pause_test", &config, 0)
+            .await
+            .unwrap();
+        let coords = {
+            let galaxies = app_state.galaxies.lock().await;
+            let galaxy = galaxies.get("pause_test").unwrap();
+            *galaxy.systems().keys().next().unwrap()
+        };
+
+        app_state.pause_galaxy("pause_test", 100).await.unwrap();
+        assert!(app_state.pause_galaxy("pause_test", 150).await.is_err());
+        let result = app_state
+            .build_structure("pause_test", 200, coords, crate::StructureType::Colony)
+            .await;
+        assert_eq!(result.unwrap_err(), "Galaxy is paused");
+        assert_eq!(app_state.game_tick("pause_test", 400).await, Ok(100));
+
+        // The 400 ticks spent paused don't count
+        app_state.resume_galaxy("pause_test", 500).await.unwrap();
+        assert!(app_state.resume_galaxy("pause_test", 550).await.is_err());
+        assert_eq!(app_state.game_tick("pause_test", 600).await, Ok(200));
+
+        // Freezing ends the galaxy for good
+        app_state.freeze_galaxy("pause_test", 700).await.unwrap();
+        assert_eq!(app_state.game_tick("pause_test", 5000).await, Ok(300));
+        assert!(app_state.freeze_galaxy("pause_test", 800).await.is_err());
+        assert!(app_state.pause_galaxy("pause_test", 900).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_joins_adopt_different_systems() {
+        let db = crate::Database::new_test().await.unwrap();
+        let clock = Arc::new(crate::ManualClock::new(1_700_000_000));
+        let app_state = AppState::new_with_database(db.clone(), clock)
+            .await
+            .unwrap();
+        let mut config = create_test_config();
+        config.spawn = crate::config::SpawnConfig::Adopt;
+        app_state
+            .create_galaxy("adopt", &config, app_state.tick())
+            .await
+            .unwrap();
+
+        let mut users = Vec::new();
+        for name in ["ada", "bo", "cy"] {
+            let user_id = db
+                .create_user(name, &format!("{}@example.com", name), "hash")
+                .await
+                .unwrap();
+            users.push((user_id, name));
+        }
+        let service = crate::UserService::new(db.clone());
+        let join = |(user_id, name): (i64, &'static str)| {
+            service.join_galaxy(user_id, "adopt", name, &app_state)
+        };
+        let (ada, bo, cy) = tokio::join!(join(users[0]), join(users[1]), join(users[2]));
+
+        let coords: std::collections::HashSet<_> =
+            [ada.unwrap().1, bo.unwrap().1, cy.unwrap().1].into();
+        assert_eq!(coords.len(), 3);
+    }
+
     #[tokio::test]
     async fn test_galaxy_preloading() {
         let app_state = AppState::new_test().await.unwrap();
@@ -5,6 +5,89 @@ use std::env;
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct AppConfig {
     pub persistence: PersistenceSettings,
+
+    #[serde(default)]
+    pub clock: ClockSettings,
+
+    #[serde(default)]
+    pub mail: MailSettings,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ClockSettings {
+    /// How fast game time runs compared to real time, 1.0 uses the real clock
+    ///
+    /// Other speeds need persistence disabled, see [`AppConfig::validate`].
+    #[serde(default = "default_clock_speed")]
+    pub speed: f64,
+}
+
+/// Where email goes
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MailBackend {
+    /// No email is sent
+    #[default]
+    None,
+    /// Every email is written to a maildir, for development
+    Maildir,
+    /// Email is sent through an SMTP server
+    Smtp,
+}
+
+impl std::str::FromStr for MailBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(MailBackend::None),
+            "maildir" => Ok(MailBackend::Maildir),
+            "smtp" => Ok(MailBackend::Smtp),
+            _ => Err(format!("Unknown mail backend {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MailSettings {
+    /// Where email goes, none disables it
+    #[serde(default)]
+    pub backend: MailBackend,
+
+    /// Sender of every email
+    #[serde(default = "default_mail_from")]
+    pub from: String,
+
+    /// Public URL of the server, for links in emails
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+
+    /// Directory of the maildir backend
+    #[serde(default = "default_maildir")]
+    pub maildir: String,
+
+    #[serde(default)]
+    pub smtp: SmtpSettings,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SmtpSettings {
+    #[serde(default = "default_smtp_host")]
+    pub host: String,
+
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+
+    /// Login of the server, if it needs one
+    #[serde(default)]
+    pub username: Option<String>,
+
+    #[serde(default)]
+    pub password: Option<String>,
+
+    /// Require STARTTLS, otherwise mail is sent in plain text
+    #[serde(default)]
+    pub starttls: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -42,6 +125,56 @@ fn default_shutdown_timeout() -> u64 {
 fn default_coalescing_delay() -> u64 {
     1000
 }
+fn default_clock_speed() -> f64 {
+    1.0
+}
+fn default_mail_from() -> String {
+    "Galactic War <noreply@localhost>".to_string()
+}
+fn default_base_url() -> String {
+    "http://localhost:3050".to_string()
+}
+fn default_maildir() -> String {
+    "mail".to_string()
+}
+fn default_smtp_host() -> String {
+    "localhost".to_string()
+}
+fn default_smtp_port() -> u16 {
+    25
+}
+
+impl Default for ClockSettings {
+    fn default() -> Self {
+        Self {
+            speed: default_clock_speed(),
+        }
+    }
+}
+
+impl Default for MailSettings {
+    fn default() -> Self {
+        Self {
+            backend: MailBackend::default(),
+            from: default_mail_from(),
+            base_url: default_base_url(),
+            maildir: default_maildir(),
+            smtp: SmtpSettings::default(),
+        }
+    }
+}
+
+impl Default for SmtpSettings {
+    fn default() -> Self {
+        Self {
+            host: default_smtp_host(),
+            port: default_smtp_port(),
+            username: None,
+            password: None,
+            starttls: false,
+        }
+    }
+}
 
 impl Default for PersistenceSettings {
     fn default() -> Self {
@@ -75,10 +208,25 @@ impl AppConfig {
 
         // Override with environment variables using GWAR prefix
         config.apply_env_overrides();
+        config.validate()?;
 
         Ok(config)
     }
 
+    /// Check that the settings can be used together
+    ///
+    /// An accelerated clock starts from the real time whenever the server starts, so galaxies
+    /// saved while it ran would be ahead of it after a restart.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.clock.speed != 1.0 && self.persistence.enabled {
+            return Err(format!(
+                "Clock speed {} needs persistence to be disabled",
+                self.clock.speed
+            ));
+        }
+        Ok(())
+    }
+
     /// Load configuration only from environment variables
     pub fn load_from_env() -> Self {
         let mut config = AppConfig::default();
@@ -117,6 +265,54 @@ impl AppConfig {
                 self.persistence.coalescing_delay_ms = delay;
             }
         }
+
+        if let Ok(val) = env::var("GWAR_CLOCK_SPEED") {
+            if let Ok(speed) = val.parse::<f64>() {
+                self.clock.speed = speed;
+            }
+        }
+
+        if let Ok(val) = env::var("GWAR_MAIL_BACKEND") {
+            if let Ok(backend) = val.parse::<MailBackend>() {
+                self.mail.backend = backend;
+            }
+        }
+
+        if let Ok(val) = env::var("GWAR_MAIL_FROM") {
+            self.mail.from = val;
+        }
+
+        if let Ok(val) = env::var("GWAR_MAIL_BASE_URL") {
+            self.mail.base_url = val;
+        }
+
+        if let Ok(val) = env::var("GWAR_MAIL_MAILDIR") {
+            self.mail.maildir = val;
+        }
+
+        if let Ok(val) = env::var("GWAR_MAIL_SMTP_HOST") {
+            self.mail.smtp.host = val;
+        }
+
+        if let Ok(val) = env::var("GWAR_MAIL_SMTP_PORT") {
+            if let Ok(port) = val.parse::<u16>() {
+                self.mail.smtp.port = port;
+            }
+        }
+
+        if let Ok(val) = env::var("GWAR_MAIL_SMTP_USERNAME") {
+            self.mail.smtp.username = Some(val);
+        }
+
+        if let Ok(val) = env::var("GWAR_MAIL_SMTP_PASSWORD") {
+            self.mail.smtp.password = Some(val);
+        }
+
+        if let Ok(val) = env::var("GWAR_MAIL_SMTP_STARTTLS") {
+            if let Ok(starttls) = val.parse::<bool>() {
+                self.mail.smtp.starttls = starttls;
+            }
+        }
     }
 }
 
@@ -133,6 +329,9 @@ mod tests {
         assert_eq!(config.persistence.shutdown_timeout, 10);
         assert!(config.persistence.write_coalescing);
         assert_eq!(config.persistence.coalescing_delay_ms, 1000);
+        assert_eq!(config.clock.speed, 1.0);
+        assert_eq!(config.mail.backend, MailBackend::None);
+        assert_eq!(config.mail.smtp.port, 25);
     }
 
     #[test]
@@ -169,6 +368,15 @@ persistence:
   shutdown_timeout: 15
   write_coalescing: false
   coalescing_delay_ms: 500
+clock:
+  speed: 60
+mail:
+  backend: smtp
+  from: war@example.com
+  smtp:
+    host: mail.example.com
+    port: 587
+    starttls: true
 "#;
 
         let config: AppConfig = serde_yaml::from_str(yaml_content).unwrap();
@@ -177,5 +385,24 @@ persistence:
         assert_eq!(config.persistence.shutdown_timeout, 15);
         assert!(!config.persistence.write_coalescing);
         assert_eq!(config.persistence.coalescing_delay_ms, 500);
+        assert_eq!(config.clock.speed, 60.0);
+        assert_eq!(config.mail.backend, MailBackend::Smtp);
+        assert_eq!(config.mail.from, "war@example.com");
+        assert_eq!(config.mail.smtp.host, "mail.example.com");
+        assert_eq!(config.mail.smtp.port, 587);
+        assert!(config.mail.smtp.starttls);
+        assert_eq!(config.mail.maildir, "mail");
+    }
+
+    #[test]
+    fn test_clock_speed_needs_persistence_disabled() {
+        let mut config = AppConfig::default();
+        assert!(config.validate().is_ok());
+
+        config.clock.speed = 60.0;
+        assert!(config.validate().is_err());
+
+        config.persistence.enabled = false;
+        assert!(config.validate().is_ok());
     }
 }
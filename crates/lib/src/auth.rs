@@ -1,6 +1,8 @@
 use argon2::password_hash::{rand_core::OsRng, SaltString};
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 
 use crate::db::{Database, PersistenceError};
 
@@ -65,6 +67,27 @@ impl AuthService {
             .collect()
     }
 
+    /// Token the forms of a session carry, so requests forged by other sites are rejected
+    ///
+    /// It's the HMAC-SHA256 of a fixed label keyed by the session token, so it belongs to the
+    /// session without being stored, and can't be worked out without the session cookie.
+    pub fn csrf_token(session_token: &str) -> String {
+        hex::encode(Self::csrf_mac(session_token).finalize().into_bytes())
+    }
+
+    /// Check a CSRF token was made for a session, in constant time
+    pub fn verify_csrf_token(session_token: &str, token: &str) -> bool {
+        hex::decode(token)
+            .is_ok_and(|token| Self::csrf_mac(session_token).verify_slice(&token).is_ok())
+    }
+
+    fn csrf_mac(session_token: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(session_token.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(b"galactic-war csrf");
+        mac
+    }
+
     /// Register a new user
     pub async fn register_user(
         &self,
@@ -247,6 +270,20 @@ mod tests {
         assert!(!auth.verify_password("wrong_password", &hash1));
     }
 
+    #[test]
+    fn test_csrf_token() {
+        let session = AuthService::generate_session_token();
+        let token = AuthService::csrf_token(&session);
+        assert_eq!(token, AuthService::csrf_token(&session));
+        assert!(AuthService::verify_csrf_token(&session, &token));
+
+        let other = AuthService::generate_session_token();
+        assert!(!AuthService::verify_csrf_token(&other, &token));
+        assert!(!AuthService::verify_csrf_token(&session, ""));
+        assert!(!AuthService::verify_csrf_token(&session, "not hex"));
+        assert!(!AuthService::verify_csrf_token(&session, &token[..32]));
+    }
+
     #[tokio::test]
     async fn test_user_registration_and_authentication() {
         let db = Database::new_test()
@@ -0,0 +1,458 @@
+/// Chat channels of galaxy accounts
+///
+/// Every galaxy has a channel open to all its accounts, and every alliance one for its members.
+/// Players can also start groups, ad-hoc channels for the accounts they add. Messages are kept
+/// in the database for the history, and every new message goes through the [`ChatHub`] so the
+/// players following a channel see it straight away.
+use tokio::sync::broadcast;
+
+use crate::db::{Database, PersistenceError};
+use crate::models::{ChatChannelRow, ChatMessageRow, UserGalaxyAccountRow};
+
+/// Longest chat message
+pub const MAX_MESSAGE_LENGTH: usize = 1000;
+
+/// Longest group name
+pub const MAX_GROUP_NAME_LENGTH: usize = 32;
+
+/// Messages of a channel's history
+pub const HISTORY_LENGTH: usize = 100;
+
+/// Messages kept for subscribers that fall behind
+const HUB_CAPACITY: usize = 256;
+
+/// Who a channel is open to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChannelKind {
+    /// Every account in the galaxy
+    Galaxy,
+    /// The members of an alliance
+    Alliance,
+    /// The accounts added to the group
+    Group,
+}
+
+impl std::fmt::Display for ChannelKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelKind::Galaxy => write!(f, "galaxy"),
+            ChannelKind::Alliance => write!(f, "alliance"),
+            ChannelKind::Group => write!(f, "group"),
+        }
+    }
+}
+
+impl std::str::FromStr for ChannelKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "galaxy" => Ok(ChannelKind::Galaxy),
+            "alliance" => Ok(ChannelKind::Alliance),
+            "group" => Ok(ChannelKind::Group),
+            _ => Err(format!("Unknown channel kind {}", s)),
+        }
+    }
+}
+
+/// A chat channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub id: i64,
+    pub galaxy_name: String,
+    pub kind: ChannelKind,
+    /// Alliance of an alliance channel
+    pub alliance_id: Option<i64>,
+    pub name: String,
+}
+
+impl From<ChatChannelRow> for Channel {
+    fn from(row: ChatChannelRow) -> Self {
+        Channel {
+            id: row.id,
+            galaxy_name: row.galaxy_name,
+            kind: row.kind.parse().unwrap_or(ChannelKind::Group),
+            alliance_id: row.alliance_id,
+            name: row.name,
+        }
+    }
+}
+
+/// Broadcasts new chat messages to everyone following the chat
+///
+/// Subscribers get the messages of every channel and pick the ones they follow, after checking
+/// they may read that channel.
+#[derive(Debug, Clone)]
+pub struct ChatHub {
+    sender: broadcast::Sender<ChatMessageRow>,
+}
+
+impl ChatHub {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(HUB_CAPACITY);
+        Self { sender }
+    }
+
+    /// Receive every message posted from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<ChatMessageRow> {
+        self.sender.subscribe()
+    }
+
+    fn publish(&self, message: ChatMessageRow) {
+        // Sending only fails when nobody is listening
+        let _ = self.sender.send(message);
+    }
+}
+
+impl Default for ChatHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChatError {
+    #[error("Database error: {0}")]
+    Database(#[from] PersistenceError),
+
+    #[error("Galaxy account not found")]
+    AccountNotFound,
+
+    #[error("No account named {0} in this galaxy")]
+    UnknownAccount(String),
+
+    #[error("Channel not found")]
+    ChannelNotFound,
+
+    #[error("Invalid message: {0}")]
+    InvalidMessage(String),
+
+    #[error("Invalid group: {0}")]
+    InvalidGroup(String),
+
+    #[error("{0} is already in this group")]
+    AlreadyMember(String),
+
+    #[error("Only groups can be joined and left")]
+    NotAGroup,
+}
+
+/// Service for chat channels
+pub struct ChatService {
+    db: Database,
+    hub: ChatHub,
+}
+
+impl ChatService {
+    /// Create a new chat service publishing new messages to a hub
+    pub fn new(db: Database, hub: ChatHub) -> Self {
+        Self { db, hub }
+    }
+
+    /// Get the channels an account can read
+    ///
+    /// The galaxy channel comes first, then the alliance channel and the groups.
+    pub async fn channels(&self, account_id: i64) -> Result<Vec<Channel>, ChatError> {
+        let account = self.account(account_id).await?;
+        let mut channels = vec![self
+            .db
+            .ensure_galaxy_channel(&account.galaxy_name)
+            .await?
+            .into()];
+        if let Some(alliance_id) = account.alliance_id {
+            if let Some(alliance) = self.db.get_alliance(alliance_id).await? {
+                channels.push(
+                    self.db
+                        .ensure_alliance_channel(
+                            &account.galaxy_name,
+                            alliance.id,
+                            &format!("[{}] {}", alliance.tag, alliance.name),
+                        )
+                        .await?
+                        .into(),
+                );
+            }
+        }
+        for row in self.db.get_account_chat_groups(account.id).await? {
+            channels.push(row.into());
+        }
+        Ok(channels)
+    }
+
+    /// Get a channel an account can read
+    pub async fn channel(&self, account_id: i64, channel_id: i64) -> Result<Channel, ChatError> {
+        let account = self.account(account_id).await?;
+        self.readable(&account, channel_id).await
+    }
+
+    /// Get the latest messages of a channel, oldest first
+    pub async fn history(
+        &self,
+        account_id: i64,
+        channel_id: i64,
+    ) -> Result<Vec<ChatMessageRow>, ChatError> {
+        let channel = self.channel(account_id, channel_id).await?;
+        Ok(self.db.get_chat_history(channel.id, HISTORY_LENGTH).await?)
+    }
+
+    /// Post a message in a channel, delivering it to everyone following the chat
+    pub async fn post(
+        &self,
+        account_id: i64,
+        channel_id: i64,
+        body: &str,
+    ) -> Result<ChatMessageRow, ChatError> {
+        let channel = self.channel(account_id, channel_id).await?;
+        let body = body.trim();
+        if body.is_empty() || body.chars().count() > MAX_MESSAGE_LENGTH {
+            return Err(ChatError::InvalidMessage(format!(
+                "messages must be 1 to {} characters",
+                MAX_MESSAGE_LENGTH
+            )));
+        }
+        let message = self
+            .db
+            .add_chat_message(channel.id, account_id, body)
+            .await?;
+        self.hub.publish(message.clone());
+        Ok(message)
+    }
+
+    /// Get the accounts that can read a channel
+    pub async fn members(
+        &self,
+        account_id: i64,
+        channel_id: i64,
+    ) -> Result<Vec<UserGalaxyAccountRow>, ChatError> {
+        let channel = self.channel(account_id, channel_id).await?;
+        Ok(match (channel.kind, channel.alliance_id) {
+            (ChannelKind::Alliance, Some(alliance_id)) => {
+                self.db.get_alliance_members(alliance_id).await?
+            }
+            (ChannelKind::Group, _) => self.db.get_chat_members(channel.id).await?,
+            _ => self.db.get_galaxy_accounts(&channel.galaxy_name).await?,
+        })
+    }
+
+    /// Start a group with the account creating it as its only member
+    pub async fn create_group(&self, account_id: i64, name: &str) -> Result<Channel, ChatError> {
+        let account = self.account(account_id).await?;
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_GROUP_NAME_LENGTH {
+            return Err(ChatError::InvalidGroup(format!(
+                "the name must be 1 to {} characters",
+                MAX_GROUP_NAME_LENGTH
+            )));
+        }
+        let channel_id = self
+            .db
+            .create_chat_group(&account.galaxy_name, name, account.id)
+            .await?;
+        self.channel(account.id, channel_id).await
+    }
+
+    /// Add an account to a group, by its name in the galaxy
+    ///
+    /// Any member of the group can add others.
+    pub async fn add(
+        &self,
+        account_id: i64,
+        channel_id: i64,
+        account_name: &str,
+    ) -> Result<(), ChatError> {
+        let channel = self.group(account_id, channel_id).await?;
+        let added = self
+            .db
+            .get_galaxy_account_by_name(&channel.galaxy_name, account_name.trim())
+            .await?
+            .ok_or_else(|| ChatError::UnknownAccount(account_name.trim().to_string()))?;
+        if !self.db.add_chat_member(channel.id, added.id).await? {
+            return Err(ChatError::AlreadyMember(added.account_name));
+        }
+        Ok(())
+    }
+
+    /// Leave a group, which is deleted with its history once nobody is left
+    pub async fn leave(&self, account_id: i64, channel_id: i64) -> Result<(), ChatError> {
+        let channel = self.group(account_id, channel_id).await?;
+        self.db.remove_chat_member(channel.id, account_id).await?;
+        if self.db.get_chat_members(channel.id).await?.is_empty() {
+            self.db.delete_chat_channel(channel.id).await?;
+        }
+        Ok(())
+    }
+
+    /// Receive every message posted from now on, in every channel
+    pub fn subscribe(&self) -> broadcast::Receiver<ChatMessageRow> {
+        self.hub.subscribe()
+    }
+
+    /// A group channel the account is a member of
+    async fn group(&self, account_id: i64, channel_id: i64) -> Result<Channel, ChatError> {
+        let channel = self.channel(account_id, channel_id).await?;
+        if channel.kind != ChannelKind::Group {
+            return Err(ChatError::NotAGroup);
+        }
+        Ok(channel)
+    }
+
+    /// A channel the account can read
+    ///
+    /// Channels the account can't read are reported as missing, so their existence isn't given
+    /// away.
+    async fn readable(
+        &self,
+        account: &UserGalaxyAccountRow,
+        channel_id: i64,
+    ) -> Result<Channel, ChatError> {
+        let channel: Channel = self
+            .db
+            .get_chat_channel(channel_id)
+            .await?
+            .ok_or(ChatError::ChannelNotFound)?
+            .into();
+        let readable = channel.galaxy_name == account.galaxy_name
+            && match channel.kind {
+                ChannelKind::Galaxy => true,
+                ChannelKind::Alliance => {
+                    channel.alliance_id.is_some() && channel.alliance_id == account.alliance_id
+                }
+                ChannelKind::Group => self
+                    .db
+                    .get_chat_members(channel.id)
+                    .await?
+                    .iter()
+                    .any(|member| member.id == account.id),
+            };
+        if !readable {
+            return Err(ChatError::ChannelNotFound);
+        }
+        Ok(channel)
+    }
+
+    async fn account(&self, account_id: i64) -> Result<UserGalaxyAccountRow, ChatError> {
+        self.db
+            .get_galaxy_account(account_id)
+            .await?
+            .ok_or(ChatError::AccountNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alliance::AllianceService;
+    use crate::test_utils::{self, join, CONFIG};
+    use crate::{AppState, UserGalaxyAccount};
+
+    /// Ada, Bo and Cy in one galaxy, Dee in another
+    async fn setup() -> (Database, AppState, ChatService, [UserGalaxyAccount; 4]) {
+        let (_, db, app_state) = test_utils::setup(CONFIG, &["chatter", "elsewhere"]).await;
+        let mut accounts = Vec::new();
+        for (galaxy, name) in [
+            ("chatter", "Ada"),
+            ("chatter", "Bo"),
+            ("chatter", "Cy"),
+            ("elsewhere", "Dee"),
+        ] {
+            accounts.push(join(&db, &app_state, galaxy, name).await.0);
+        }
+        let chat = ChatService::new(db.clone(), app_state.chat_hub().clone());
+        (db, app_state, chat, accounts.try_into().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_galaxy_channel() {
+        let (_db, _app_state, chat, [ada, bo, cy, dee]) = setup().await;
+
+        // Everyone shares the galaxy channel, and only that galaxy's
+        let galaxy = chat.channels(ada.id).await.unwrap()[0].clone();
+        assert_eq!(galaxy.kind, ChannelKind::Galaxy);
+        assert_eq!(chat.channels(bo.id).await.unwrap()[0].id, galaxy.id);
+        assert_ne!(chat.channels(dee.id).await.unwrap()[0].id, galaxy.id);
+        assert!(matches!(
+            chat.post(dee.id, galaxy.id, "Hello?").await,
+            Err(ChatError::ChannelNotFound)
+        ));
+
+        // New messages reach subscribers, and stay in the history
+        let mut receiver = chat.subscribe();
+        let posted = chat.post(ada.id, galaxy.id, "  Hello all  ").await.unwrap();
+        assert_eq!(posted.body, "Hello all");
+        assert_eq!(receiver.recv().await.unwrap(), posted);
+        chat.post(bo.id, galaxy.id, "Hi Ada").await.unwrap();
+        let history = chat.history(cy.id, galaxy.id).await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].account_name, "Bo");
+        assert!(matches!(
+            chat.post(ada.id, galaxy.id, " ").await,
+            Err(ChatError::InvalidMessage(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_alliance_channel() {
+        let (db, _app_state, chat, [ada, bo, _cy, _dee]) = setup().await;
+
+        // Alliance channels are kept to the members
+        let alliances = AllianceService::new(db.clone());
+        let star = alliances
+            .create(ada.id, "Star League", "STAR")
+            .await
+            .unwrap();
+        let channels = chat.channels(ada.id).await.unwrap();
+        assert_eq!(channels[1].kind, ChannelKind::Alliance);
+        assert_eq!(channels[1].alliance_id, Some(star.id));
+        let alliance = channels[1].id;
+        chat.post(ada.id, alliance, "Plans").await.unwrap();
+        assert!(matches!(
+            chat.history(bo.id, alliance).await,
+            Err(ChatError::ChannelNotFound)
+        ));
+
+        alliances.invite(ada.id, "Bo").await.unwrap();
+        alliances.accept(bo.id, star.id).await.unwrap();
+        assert_eq!(chat.history(bo.id, alliance).await.unwrap().len(), 1);
+        assert_eq!(chat.members(bo.id, alliance).await.unwrap().len(), 2);
+        alliances.leave(bo.id).await.unwrap();
+        assert!(matches!(
+            chat.post(bo.id, alliance, "Bye").await,
+            Err(ChatError::ChannelNotFound)
+        ));
+
+        // Only groups can be left
+        assert!(matches!(
+            chat.leave(ada.id, alliance).await,
+            Err(ChatError::NotAGroup)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_group_channel() {
+        let (db, _app_state, chat, [_ada, bo, cy, _dee]) = setup().await;
+
+        // Groups are joined by being added
+        let group = chat.create_group(bo.id, "Traders").await.unwrap();
+        assert_eq!(group.kind, ChannelKind::Group);
+        assert!(matches!(
+            chat.history(cy.id, group.id).await,
+            Err(ChatError::ChannelNotFound)
+        ));
+        chat.add(bo.id, group.id, "Cy").await.unwrap();
+        assert!(matches!(
+            chat.add(cy.id, group.id, "Bo").await,
+            Err(ChatError::AlreadyMember(_))
+        ));
+        assert!(matches!(
+            chat.add(bo.id, group.id, "Dee").await,
+            Err(ChatError::UnknownAccount(_))
+        ));
+        chat.post(cy.id, group.id, "Water for sale").await.unwrap();
+        assert_eq!(chat.channels(cy.id).await.unwrap()[1].id, group.id);
+
+        // And deleted once everyone left
+        chat.leave(bo.id, group.id).await.unwrap();
+        chat.leave(cy.id, group.id).await.unwrap();
+        assert!(db.get_chat_channel(group.id).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,287 @@
+/// Clocks that drive the game
+///
+/// All game paths read the current tick from the clock owned by `AppState`, so tests and
+/// development servers can control how time passes.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// Source of the current tick
+pub trait Clock: Send + Sync + std::fmt::Debug {
+    /// Get the current tick
+    fn now(&self) -> usize;
+}
+
+/// The real clock, one tick per second since the Unix epoch
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> usize {
+        crate::tick()
+    }
+}
+
+/// A clock that only moves when it is told to
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    tick: AtomicUsize,
+}
+
+impl ManualClock {
+    /// Create a clock stopped at the given tick
+    pub fn new(tick: usize) -> Self {
+        Self {
+            tick: AtomicUsize::new(tick),
+        }
+    }
+
+    /// Set the current tick
+    pub fn set(&self, tick: usize) {
+        self.tick.store(tick, Ordering::SeqCst);
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, ticks: usize) {
+        self.tick.fetch_add(ticks, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> usize {
+        self.tick.load(Ordering::SeqCst)
+    }
+}
+
+/// A clock that runs `speed` times faster than real time
+///
+/// It starts from the real time when created. Galaxies saved while it was running would be
+/// ahead of it after a restart, so the app config only allows it with persistence disabled.
+#[derive(Debug)]
+pub struct AcceleratedClock {
+    origin: usize,
+    started: Instant,
+    speed: f64,
+}
+
+impl AcceleratedClock {
+    /// Create a clock starting now
+    pub fn new(speed: f64) -> Self {
+        Self::starting_at(crate::tick(), speed)
+    }
+
+    /// Create a clock starting at the given tick
+    pub fn starting_at(origin: usize, speed: f64) -> Self {
+        Self {
+            origin,
+            started: Instant::now(),
+            speed,
+        }
+    }
+}
+
+impl Clock for AcceleratedClock {
+    fn now(&self) -> usize {
+        self.origin + (self.started.elapsed().as_secs_f64() * self.speed) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GalaxyConfig;
+    use crate::lifecycle::EndReason;
+    use crate::planner::Goal;
+    use crate::test_utils::{join, setup_with, DAY, HOUR, START};
+    use crate::{AppState, Coords, Database, StructureType};
+    use std::sync::Arc;
+
+    const CONFIG: &str = r#"
+seed: 1
+system_count: 10
+size:
+  x: 20
+  y: 20
+systems:
+  resources:
+    metal: 1000
+    crew: 100
+    water: 100
+  structures:
+    colony:
+      starting_level: 1
+      storage:
+        metal: 1000000
+        crew: 1000000
+        water: 1000000
+    asteroidmine:
+      starting_level: 1
+      production:
+        multiplier: 2
+        metal: 100
+      cost:
+        multiplier: 2
+        time: 3600
+        metal: 250
+"#;
+
+    /// A galaxy with one player, on a manual clock
+    async fn setup(config: GalaxyConfig) -> (Arc<ManualClock>, Database, AppState, Coords) {
+        let (clock, db, app_state) = setup_with(&config, &["fast"]).await;
+        let (_, coords) = join(&db, &app_state, "fast", "Pilot").await;
+        (clock, db, app_state, coords)
+    }
+
+    fn create_config() -> GalaxyConfig {
+        serde_yaml::from_str(CONFIG).unwrap()
+    }
+
+    async fn metal(app_state: &AppState, coords: Coords) -> usize {
+        app_state
+            .system_info("fast", coords)
+            .await
+            .unwrap()
+            .resources
+            .metal
+    }
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::new(100);
+        assert_eq!(clock.now(), 100);
+        clock.advance(50);
+        assert_eq!(clock.now(), 150);
+        clock.set(10);
+        assert_eq!(clock.now(), 10);
+    }
+
+    #[test]
+    fn test_accelerated_clock() {
+        let clock = AcceleratedClock::starting_at(1000, 1000.0);
+        assert!(clock.now() >= 1000);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        // 20ms at 1000x is at least 20 ticks
+        assert!(clock.now() >= 1020);
+    }
+
+    #[tokio::test]
+    async fn test_fast_forward_days() {
+        let (clock, _db, app_state, coords) = setup(create_config()).await;
+
+        // Level 2 costs 500 metal and takes 2 hours
+        let event = app_state
+            .build_structure(
+                "fast",
+                app_state.tick(),
+                coords,
+                StructureType::AsteroidMine,
+            )
+            .await
+            .unwrap();
+        assert_eq!(event.completion, START + 2 * HOUR);
+        assert_eq!(metal(&app_state, coords).await, 500);
+
+        clock.advance(3 * DAY);
+        let info = app_state.system_info("fast", coords).await.unwrap();
+        assert_eq!(info.structures.get(&StructureType::AsteroidMine), Some(&2));
+        // Finished builds are applied before producing, so 72 hours at 200 per hour
+        assert_eq!(info.resources.metal, 500 + 72 * 200);
+    }
+
+    #[tokio::test]
+    async fn test_no_production_while_paused() {
+        let (clock, _db, app_state, coords) = setup(create_config()).await;
+
+        clock.advance(DAY);
+        assert_eq!(metal(&app_state, coords).await, 1000 + 24 * 100);
+
+        // Nothing happens over a paused weekend
+        app_state
+            .pause_galaxy("fast", app_state.tick())
+            .await
+            .unwrap();
+        clock.advance(2 * DAY);
+        assert_eq!(metal(&app_state, coords).await, 1000 + 24 * 100);
+        let result = app_state
+            .build_structure(
+                "fast",
+                app_state.tick(),
+                coords,
+                StructureType::AsteroidMine,
+            )
+            .await;
+        assert!(result.is_err());
+
+        app_state
+            .resume_galaxy("fast", app_state.tick())
+            .await
+            .unwrap();
+        clock.advance(DAY);
+        assert_eq!(metal(&app_state, coords).await, 1000 + 48 * 100);
+    }
+
+    #[tokio::test]
+    async fn test_galaxy_ends_after_a_week() {
+        let mut config = create_config();
+        config.lifecycle.end = Some(START + 7 * DAY);
+        let (clock, db, app_state, coords) = setup(config).await;
+
+        clock.advance(6 * DAY);
+        assert_eq!(
+            app_state.check_galaxy_end("fast", app_state.tick()).await,
+            Ok(None)
+        );
+
+        clock.advance(2 * DAY);
+        assert_eq!(
+            app_state.check_galaxy_end("fast", app_state.tick()).await,
+            Ok(Some(EndReason::Timeout))
+        );
+        let result = app_state
+            .build_structure(
+                "fast",
+                app_state.tick(),
+                coords,
+                StructureType::AsteroidMine,
+            )
+            .await;
+        assert_eq!(result.unwrap_err(), "Galaxy has ended");
+
+        // Production stopped at the end of the week
+        assert_eq!(metal(&app_state, coords).await, 1000 + 7 * 24 * 100);
+
+        let standings = db.get_final_standings("fast").await.unwrap();
+        assert_eq!(standings.len(), 1);
+        assert_eq!(standings[0].account_name, "Pilot");
+        assert_eq!(standings[0].rank, 1);
+    }
+
+    #[tokio::test]
+    async fn test_following_the_advisor() {
+        let (clock, _db, app_state, coords) = setup(create_config()).await;
+
+        let goal = Goal::Level {
+            structure: StructureType::AsteroidMine,
+            level: 4,
+        };
+        let plan = app_state
+            .plan_builds("fast", app_state.tick(), coords, goal)
+            .await
+            .unwrap();
+        assert_eq!(plan.steps.len(), 3);
+
+        // Every build can be made the moment the plan says
+        for step in &plan.steps {
+            clock.advance(step.start - app_state.tick());
+            let event = app_state
+                .build_structure("fast", app_state.tick(), coords, step.structure)
+                .await
+                .unwrap();
+            assert_eq!(event.completion, step.completion);
+        }
+
+        clock.advance(plan.finish - app_state.tick());
+        let info = app_state.system_info("fast", coords).await.unwrap();
+        assert_eq!(info.structures.get(&StructureType::AsteroidMine), Some(&4));
+        assert_eq!(info.score, plan.score);
+    }
+}
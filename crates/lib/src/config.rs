@@ -4,28 +4,315 @@ use indexmap::IndexMap;
 /// This is everything used externally to configure the galaxy
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use utoipa::ToSchema;
 
 use crate::{Cost, Resources};
 
 /// Configuration for the Galaxy
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct GalaxyConfig {
+    /// Seed for map generation
+    ///
+    /// Galaxies created with the same seed and config have an identical layout.
+    /// A random seed is chosen when this is not set.
+    pub seed: Option<u64>,
+
     /// Static System Count
     pub system_count: usize,
 
+    /// Speed of the galaxy
+    ///
+    /// Scales production and build times together, so a speed of 2 plays twice as fast.
+    /// Defaults to 1.
+    pub speed: Option<f64>,
+
     /// Galaxy size
     pub size: GalaxySize,
 
+    /// How systems are laid out on the map
+    #[serde(default)]
+    pub layout: LayoutConfig,
+
+    /// Where new players are placed when they join
+    #[serde(default)]
+    pub spawn: SpawnConfig,
+
+    /// When the galaxy starts and ends
+    #[serde(default)]
+    pub lifecycle: LifecycleConfig,
+
+    /// NPC barbarian systems
+    #[serde(default)]
+    pub npc: NpcConfig,
+
+    /// AI opponents that join the galaxy
+    #[serde(default)]
+    pub ai: AiConfig,
+
+    /// How systems are scored
+    #[serde(default)]
+    pub scoring: ScoringConfig,
+
     /// System Config
     pub systems: SystemConfig,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default, ToSchema)]
 pub struct GalaxySize {
     pub x: usize,
     pub y: usize,
 }
 
+/// Layout strategy for placing systems when the galaxy is generated.
+///
+/// All distances are in coordinate units.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum LayoutConfig {
+    /// Systems are spread uniformly over the whole galaxy
+    #[default]
+    Uniform,
+
+    /// Uniform, but no two systems are closer than `min_distance`
+    Poisson { min_distance: f64 },
+
+    /// Systems are grouped into `clusters` sectors of the given `radius`
+    Clustered { clusters: usize, radius: f64 },
+
+    /// Systems lie along `arms` spiral arms, winding `turns` times around the center
+    Spiral {
+        arms: usize,
+        turns: f64,
+        spread: f64,
+    },
+
+    /// Systems lie on `rings` concentric rings of the given `width`
+    Rings { rings: usize, width: f64 },
+}
+
+/// Placement policy for the systems of new players.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum SpawnConfig {
+    /// Any empty location
+    #[default]
+    Random,
+
+    /// An empty location at least `distance` away from every player owned system
+    MinDistance { distance: f64 },
+
+    /// The emptiest of `candidates` random locations, furthest from other players
+    Sparse { candidates: usize },
+
+    /// Players join in waves of `wave_size`, each wave placed `depth` further in from the edge
+    Waves { wave_size: usize, depth: f64 },
+
+    /// Take over an existing unowned system instead of creating a new one
+    Adopt,
+}
+
+/// Lifecycle of a galaxy.
+///
+/// All ticks are absolute (seconds since the Unix epoch). Anything left unset is unrestricted,
+/// so the default is a galaxy that is open forever.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct LifecycleConfig {
+    /// Players can't join before this tick
+    pub registration_start: Option<usize>,
+
+    /// Players can't join after this tick
+    pub registration_end: Option<usize>,
+
+    /// Nothing is produced or built before this tick
+    pub start: Option<usize>,
+
+    /// The galaxy ends at this tick if nobody has won before then
+    pub end: Option<usize>,
+
+    /// Conditions that end the galaxy early, the first one reached wins
+    #[serde(default)]
+    pub victory: Vec<VictoryCondition>,
+}
+
+/// A condition that ends the galaxy when a player or alliance reaches it.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum VictoryCondition {
+    /// A player reaches the total score
+    Score { score: usize },
+
+    /// A player owns at least `percent` of all the systems in the galaxy
+    Control { percent: f64 },
+
+    /// Every system owned by a player belongs to a single alliance
+    ///
+    /// Systems never change owner yet, so every player keeps the system they joined with and
+    /// this can't be reached while two alliances or unallied players are in the galaxy.
+    LastAllianceStanding,
+}
+
+/// NPC barbarian systems.
+///
+/// A share of the generated systems is run by the galaxy itself. They build on a schedule,
+/// gather defenders and raid nearby players, so the early game has targets and threats.
+/// The default has no NPC systems.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct NpcConfig {
+    /// Share of the generated systems run by NPCs, from 0 to 1
+    pub fraction: f64,
+
+    /// Ticks between two builds of an NPC system
+    pub build_interval: usize,
+
+    /// Defenders gained per hour (3600 ticks)
+    pub defenders_per_hour: usize,
+
+    /// Defenders stop growing at this count
+    pub max_defenders: usize,
+
+    /// Average ticks between two raids of an NPC system, 0 disables raids
+    pub attack_interval: usize,
+
+    /// Only players within this distance are raided
+    pub attack_range: f64,
+
+    /// Resources each raider carries back
+    pub loot_per_raider: usize,
+
+    /// A raid takes at most this share of each of the target's resources
+    pub max_loot_fraction: f64,
+}
+
+impl Default for NpcConfig {
+    fn default() -> Self {
+        Self {
+            fraction: 0.0,
+            build_interval: 3600,
+            defenders_per_hour: 1,
+            max_defenders: 100,
+            attack_interval: 6 * 3600,
+            attack_range: 10.0,
+            loot_per_raider: 10,
+            max_loot_fraction: 0.25,
+        }
+    }
+}
+
+/// AI opponents.
+///
+/// AI players own systems and play through the same API as human players, so they can fill
+/// small galaxies or give a single player something to play against.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct AiConfig {
+    /// AI players added to the galaxy
+    #[serde(default)]
+    pub players: Vec<AiPlayerConfig>,
+}
+
+/// A single AI player
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct AiPlayerConfig {
+    /// Account name shown to other players
+    pub name: String,
+
+    /// Strategy playing the account: economic, raider or turtle
+    pub strategy: String,
+}
+
+/// How systems are scored.
+///
+/// Every structure level is worth points, weighted per structure, and upgrades can also earn
+/// points for the resources spent on them. Each structure counts towards a category so
+/// leaderboards can rank economy, military and defense separately. The default is the
+/// triangular sum of the levels, all counted as economy.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ScoringConfig {
+    /// How the levels of a structure add up
+    pub levels: LevelScoring,
+
+    /// Points per unit of metal, crew or water spent on upgrades
+    pub resources_spent: f64,
+
+    /// Weight and category of the structures, the ones not listed have a weight of 1 and
+    /// count as economy
+    pub structures: HashMap<String, StructureScoring>,
+}
+
+/// How the levels of a structure add up to its score
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LevelScoring {
+    /// Level N is worth N points, so a structure at level N is worth 1 + 2 + ... + N
+    #[default]
+    Triangular,
+
+    /// Every level is worth a point
+    Linear,
+}
+
+/// Scoring of a single structure
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct StructureScoring {
+    /// Multiplies the points of the structure's levels and the resources spent on it
+    pub weight: f64,
+
+    /// Category the points count towards
+    pub category: ScoreCategory,
+}
+
+impl Default for StructureScoring {
+    fn default() -> Self {
+        Self {
+            weight: 1.0,
+            category: ScoreCategory::Economy,
+        }
+    }
+}
+
+/// Score categories that can be ranked separately
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreCategory {
+    #[default]
+    Economy,
+    Military,
+    Defense,
+}
+
+impl ScoreCategory {
+    pub const ALL: [ScoreCategory; 3] = [
+        ScoreCategory::Economy,
+        ScoreCategory::Military,
+        ScoreCategory::Defense,
+    ];
+}
+
+impl std::fmt::Display for ScoreCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScoreCategory::Economy => write!(f, "economy"),
+            ScoreCategory::Military => write!(f, "military"),
+            ScoreCategory::Defense => write!(f, "defense"),
+        }
+    }
+}
+
+impl std::str::FromStr for ScoreCategory {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "economy" => Ok(ScoreCategory::Economy),
+            "military" => Ok(ScoreCategory::Military),
+            "defense" => Ok(ScoreCategory::Defense),
+            _ => Err(format!("Unknown score category {}", s)),
+        }
+    }
+}
+
 /// Configuration for the creation of an system
 #[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct SystemConfig {
@@ -110,10 +397,25 @@ pub struct StructureConfig {
 }
 
 impl GalaxyConfig {
+    /// Get the speed multiplier of the galaxy.
+    pub fn speed(&self) -> f64 {
+        self.speed.unwrap_or(1.0)
+    }
+
+    /// Check the speed is usable, production and build times are scaled by it
+    pub fn check_speed(&self) -> Result<(), String> {
+        let speed = self.speed();
+        if speed.is_finite() && speed > 0.0 {
+            Ok(())
+        } else {
+            Err(format!("The galaxy speed must be above 0, not {}", speed))
+        }
+    }
+
     /// Get the production for a single structure at a given level.
     pub fn get_structure_production(&self, structure: &str, level: usize) -> Resources {
         if let Some(structure) = self.systems.structures.get(&structure.to_lowercase()) {
-            structure.get_production(level)
+            structure.get_production(level, self.speed())
         } else {
             Resources::default()
         }
@@ -131,28 +433,31 @@ impl GalaxyConfig {
 
 impl StructureConfig {
     /// Get the cost to build this structure at a given level
-    pub fn get_cost(&self, level: usize) -> Cost {
+    ///
+    /// The galaxy speed shortens the build time, but doesn't change the resource cost.
+    pub fn get_cost(&self, level: usize, speed: f64) -> Cost {
         if level == 0 || self.cost.is_none() {
             return Cost::default();
         }
         let cost_config = self.cost.as_ref().unwrap();
-        let cost = Cost {
-            resources: Resources {
-                metal: cost_config.metal,
-                crew: cost_config.crew,
-                water: cost_config.water,
-            },
-            ticks: cost_config.time,
+        let resources = Resources {
+            metal: cost_config.metal,
+            crew: cost_config.crew,
+            water: cost_config.water,
         };
         let multiplier = cost_config
             .multiplier
             .unwrap_or(self.multiplier.unwrap_or(1.0));
         // The cost is cost * (multiplier ^ (level - 1)))
-        cost * multiplier.powi((level - 1) as i32)
+        let scale = multiplier.powi((level - 1) as i32);
+        Cost {
+            resources: resources * scale,
+            ticks: (cost_config.time as f64 * scale / speed).round() as usize,
+        }
     }
 
-    /// Get the production for this structure at a given level.
-    pub fn get_production(&self, level: usize) -> Resources {
+    /// Get the production for this structure at a given level, scaled by the galaxy speed.
+    pub fn get_production(&self, level: usize, speed: f64) -> Resources {
         if level == 0 || self.production.is_none() {
             return Resources::default();
         }
@@ -166,7 +471,7 @@ impl StructureConfig {
             .multiplier
             .unwrap_or(self.multiplier.unwrap_or(1.0));
         // The production is production * (multiplier ^ (level - 1)))
-        production * multiplier.powi((level - 1) as i32)
+        production * (multiplier.powi((level - 1) as i32) * speed)
     }
 
     /// Get the storage for this structure at a given level.
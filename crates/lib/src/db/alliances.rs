@@ -0,0 +1,337 @@
+use super::users::{account_row, ACCOUNT_COLUMNS};
+use super::{Database, PersistenceError};
+
+use crate::models::{AllianceRow, UserGalaxyAccountRow};
+
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+fn alliance_row(row: SqliteRow) -> AllianceRow {
+    AllianceRow {
+        id: row.get("id"),
+        galaxy_name: row.get("galaxy_name"),
+        name: row.get("name"),
+        tag: row.get("tag"),
+        created_at: row.get("created_at"),
+    }
+}
+
+impl Database {
+    /// Create an alliance led by an account
+    ///
+    /// Any invites the leader had are dropped.
+    pub async fn create_alliance(
+        &self,
+        galaxy_name: &str,
+        name: &str,
+        tag: &str,
+        leader_id: i64,
+    ) -> Result<i64, PersistenceError> {
+        let mut tx = self.pool.begin().await?;
+
+        let alliance_id: i64 = sqlx::query(
+            "INSERT INTO alliances (galaxy_name, name, tag) VALUES (?, ?, ?) RETURNING id",
+        )
+        .bind(galaxy_name)
+        .bind(name)
+        .bind(tag)
+        .fetch_one(&mut *tx)
+        .await?
+        .get("id");
+
+        sqlx::query(
+            "UPDATE user_galaxy_accounts SET alliance_id = ?, alliance_role = 'leader' WHERE id = ?",
+        )
+        .bind(alliance_id)
+        .bind(leader_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM alliance_invites WHERE user_galaxy_account_id = ?")
+            .bind(leader_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(alliance_id)
+    }
+
+    /// Get an alliance by id
+    pub async fn get_alliance(
+        &self,
+        alliance_id: i64,
+    ) -> Result<Option<AllianceRow>, PersistenceError> {
+        let result = sqlx::query(
+            "SELECT id, galaxy_name, name, tag, created_at FROM alliances WHERE id = ?",
+        )
+        .bind(alliance_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result.map(alliance_row))
+    }
+
+    /// Get every alliance in a galaxy, oldest first
+    pub async fn get_galaxy_alliances(
+        &self,
+        galaxy_name: &str,
+    ) -> Result<Vec<AllianceRow>, PersistenceError> {
+        let rows = sqlx::query(
+            "SELECT id, galaxy_name, name, tag, created_at FROM alliances WHERE galaxy_name = ? ORDER BY id",
+        )
+        .bind(galaxy_name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(alliance_row).collect())
+    }
+
+    /// Check if an alliance name or tag is already used in a galaxy
+    pub async fn is_alliance_name_available(
+        &self,
+        galaxy_name: &str,
+        name: &str,
+        tag: &str,
+    ) -> Result<bool, PersistenceError> {
+        let result = sqlx::query(
+            "SELECT COUNT(*) as count FROM alliances WHERE galaxy_name = ? AND (name = ? OR tag = ?)",
+        )
+        .bind(galaxy_name)
+        .bind(name)
+        .bind(tag)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let count: i64 = result.get("count");
+        Ok(count == 0)
+    }
+
+    /// Get the members of an alliance, in the order they joined the galaxy
+    pub async fn get_alliance_members(
+        &self,
+        alliance_id: i64,
+    ) -> Result<Vec<UserGalaxyAccountRow>, PersistenceError> {
+        let query = format!(
+            "SELECT {} FROM user_galaxy_accounts WHERE alliance_id = ? ORDER BY id",
+            ACCOUNT_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(alliance_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(account_row).collect())
+    }
+
+    /// Add an account to an alliance with a role, dropping the invites it had
+    ///
+    /// Returns false if the account is already in an alliance.
+    pub async fn join_alliance(
+        &self,
+        account_id: i64,
+        alliance_id: i64,
+        role: &str,
+    ) -> Result<bool, PersistenceError> {
+        let mut tx = self.pool.begin().await?;
+
+        let joined = sqlx::query(
+            "UPDATE user_galaxy_accounts SET alliance_id = ?, alliance_role = ? WHERE id = ? AND alliance_id IS NULL",
+        )
+        .bind(alliance_id)
+        .bind(role)
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+        if joined.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query("DELETE FROM alliance_invites WHERE user_galaxy_account_id = ?")
+            .bind(account_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Change the role of an alliance member
+    pub async fn set_alliance_role(
+        &self,
+        account_id: i64,
+        role: &str,
+    ) -> Result<(), PersistenceError> {
+        sqlx::query("UPDATE user_galaxy_accounts SET alliance_role = ? WHERE id = ?")
+            .bind(role)
+            .bind(account_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Make a member the leader of the alliance, the old leader becomes an officer
+    ///
+    /// Returns false if the old leader no longer leads the alliance, or the member left it.
+    pub async fn hand_over_alliance(
+        &self,
+        leader_id: i64,
+        member_id: i64,
+    ) -> Result<bool, PersistenceError> {
+        let mut tx = self.pool.begin().await?;
+
+        let demoted = sqlx::query(
+            "UPDATE user_galaxy_accounts SET alliance_role = 'officer' WHERE id = ? AND alliance_role = 'leader'",
+        )
+        .bind(leader_id)
+        .execute(&mut *tx)
+        .await?;
+        let promoted = sqlx::query(
+            r#"
+            UPDATE user_galaxy_accounts SET alliance_role = 'leader'
+            WHERE id = ? AND alliance_id = (SELECT alliance_id FROM user_galaxy_accounts WHERE id = ?)
+            "#,
+        )
+        .bind(member_id)
+        .bind(leader_id)
+        .execute(&mut *tx)
+        .await?;
+        if demoted.rows_affected() == 0 || promoted.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Remove an account from its alliance
+    pub async fn leave_alliance(&self, account_id: i64) -> Result<(), PersistenceError> {
+        sqlx::query(
+            "UPDATE user_galaxy_accounts SET alliance_id = NULL, alliance_role = NULL WHERE id = ?",
+        )
+        .bind(account_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete an alliance, removing its members, invites, relations, proposals and chat channel
+    pub async fn delete_alliance(&self, alliance_id: i64) -> Result<(), PersistenceError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "UPDATE user_galaxy_accounts SET alliance_id = NULL, alliance_role = NULL WHERE alliance_id = ?",
+        )
+        .bind(alliance_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM alliance_invites WHERE alliance_id = ?")
+            .bind(alliance_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM alliance_relations WHERE alliance_a = ? OR alliance_b = ?")
+            .bind(alliance_id)
+            .bind(alliance_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM diplomacy_proposals WHERE from_alliance = ? OR to_alliance = ?")
+            .bind(alliance_id)
+            .bind(alliance_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "DELETE FROM chat_messages WHERE channel_id IN (SELECT id FROM chat_channels WHERE alliance_id = ?)",
+        )
+        .bind(alliance_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM chat_channels WHERE alliance_id = ?")
+            .bind(alliance_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM alliances WHERE id = ?")
+            .bind(alliance_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Invite an account to an alliance
+    ///
+    /// Returns false if the account was already invited.
+    pub async fn create_alliance_invite(
+        &self,
+        alliance_id: i64,
+        account_id: i64,
+        invited_by: i64,
+    ) -> Result<bool, PersistenceError> {
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO alliance_invites (alliance_id, user_galaxy_account_id, invited_by) VALUES (?, ?, ?)",
+        )
+        .bind(alliance_id)
+        .bind(account_id)
+        .bind(invited_by)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Get the alliances that invited an account
+    pub async fn get_account_invites(
+        &self,
+        account_id: i64,
+    ) -> Result<Vec<AllianceRow>, PersistenceError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT a.id, a.galaxy_name, a.name, a.tag, a.created_at
+            FROM alliances a
+            JOIN alliance_invites i ON i.alliance_id = a.id
+            WHERE i.user_galaxy_account_id = ?
+            ORDER BY i.created_at, a.id
+            "#,
+        )
+        .bind(account_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(alliance_row).collect())
+    }
+
+    /// Get the accounts an alliance has invited
+    pub async fn get_alliance_invites(
+        &self,
+        alliance_id: i64,
+    ) -> Result<Vec<UserGalaxyAccountRow>, PersistenceError> {
+        let query = format!(
+            "SELECT {} FROM user_galaxy_accounts WHERE id IN (SELECT user_galaxy_account_id FROM alliance_invites WHERE alliance_id = ?) ORDER BY id",
+            ACCOUNT_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(alliance_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(account_row).collect())
+    }
+
+    /// Withdraw or decline an invite
+    ///
+    /// Returns false if there was no such invite.
+    pub async fn delete_alliance_invite(
+        &self,
+        alliance_id: i64,
+        account_id: i64,
+    ) -> Result<bool, PersistenceError> {
+        let result = sqlx::query(
+            "DELETE FROM alliance_invites WHERE alliance_id = ? AND user_galaxy_account_id = ?",
+        )
+        .bind(alliance_id)
+        .bind(account_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,116 @@
+use super::{Database, PersistenceError};
+
+use crate::models::ApiTokenRow;
+
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+const API_TOKEN_COLUMNS: &str = "id, user_id, name, scope, created_at, last_used_at";
+
+fn api_token_row(row: SqliteRow) -> ApiTokenRow {
+    ApiTokenRow {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        name: row.get("name"),
+        scope: row.get("scope"),
+        created_at: row.get("created_at"),
+        last_used_at: row.get("last_used_at"),
+    }
+}
+
+impl Database {
+    /// Store an API token of a user by its hash
+    pub async fn create_api_token(
+        &self,
+        user_id: i64,
+        name: &str,
+        token_hash: &str,
+        scope: &str,
+    ) -> Result<i64, PersistenceError> {
+        let id = sqlx::query(
+            "INSERT INTO api_tokens (user_id, name, token_hash, scope) VALUES (?, ?, ?, ?) RETURNING id",
+        )
+        .bind(user_id)
+        .bind(name)
+        .bind(token_hash)
+        .bind(scope)
+        .fetch_one(&self.pool)
+        .await?
+        .get("id");
+
+        Ok(id)
+    }
+
+    /// Get the API tokens of a user, oldest first
+    pub async fn get_api_tokens(&self, user_id: i64) -> Result<Vec<ApiTokenRow>, PersistenceError> {
+        let query = format!(
+            "SELECT {} FROM api_tokens WHERE user_id = ? ORDER BY id",
+            API_TOKEN_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(api_token_row).collect())
+    }
+
+    /// Get an API token by id
+    pub async fn get_api_token(
+        &self,
+        token_id: i64,
+    ) -> Result<Option<ApiTokenRow>, PersistenceError> {
+        let query = format!("SELECT {} FROM api_tokens WHERE id = ?", API_TOKEN_COLUMNS);
+        let row = sqlx::query(&query)
+            .bind(token_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(api_token_row))
+    }
+
+    /// Get an API token by the hash of the token
+    pub async fn get_api_token_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<ApiTokenRow>, PersistenceError> {
+        let query = format!(
+            "SELECT {} FROM api_tokens WHERE token_hash = ?",
+            API_TOKEN_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(api_token_row))
+    }
+
+    /// Record that an API token was used just now
+    pub async fn touch_api_token(&self, token_id: i64) -> Result<(), PersistenceError> {
+        sqlx::query("UPDATE api_tokens SET last_used_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(token_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Delete an API token of a user
+    ///
+    /// Returns false if the user has no such token.
+    pub async fn delete_api_token(
+        &self,
+        token_id: i64,
+        user_id: i64,
+    ) -> Result<bool, PersistenceError> {
+        let deleted = sqlx::query("DELETE FROM api_tokens WHERE id = ? AND user_id = ?")
+            .bind(token_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        Ok(deleted > 0)
+    }
+}
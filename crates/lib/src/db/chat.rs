@@ -0,0 +1,269 @@
+use super::users::{account_row, ACCOUNT_COLUMNS};
+use super::{Database, PersistenceError};
+
+use crate::models::{ChatChannelRow, ChatMessageRow, UserGalaxyAccountRow};
+
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+const CHANNEL_COLUMNS: &str = "id, galaxy_name, kind, alliance_id, name, created_at";
+
+const MESSAGE_QUERY: &str = r#"
+    SELECT m.id, m.channel_id, m.user_galaxy_account_id, a.account_name, m.body, m.sent_at
+    FROM chat_messages m
+    JOIN user_galaxy_accounts a ON a.id = m.user_galaxy_account_id
+"#;
+
+fn channel_row(row: SqliteRow) -> ChatChannelRow {
+    ChatChannelRow {
+        id: row.get("id"),
+        galaxy_name: row.get("galaxy_name"),
+        kind: row.get("kind"),
+        alliance_id: row.get("alliance_id"),
+        name: row.get("name"),
+        created_at: row.get("created_at"),
+    }
+}
+
+fn message_row(row: SqliteRow) -> ChatMessageRow {
+    ChatMessageRow {
+        id: row.get("id"),
+        channel_id: row.get("channel_id"),
+        user_galaxy_account_id: row.get("user_galaxy_account_id"),
+        account_name: row.get("account_name"),
+        body: row.get("body"),
+        sent_at: row.get("sent_at"),
+    }
+}
+
+impl Database {
+    /// Get the galaxy-wide channel of a galaxy, creating it the first time
+    pub async fn ensure_galaxy_channel(
+        &self,
+        galaxy_name: &str,
+    ) -> Result<ChatChannelRow, PersistenceError> {
+        sqlx::query(
+            "INSERT OR IGNORE INTO chat_channels (galaxy_name, kind, name) VALUES (?, 'galaxy', 'Galaxy')",
+        )
+        .bind(galaxy_name)
+        .execute(&self.pool)
+        .await?;
+
+        let query = format!(
+            "SELECT {} FROM chat_channels WHERE galaxy_name = ? AND kind = 'galaxy'",
+            CHANNEL_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .bind(galaxy_name)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(channel_row(row))
+    }
+
+    /// Get the channel of an alliance, creating it the first time
+    pub async fn ensure_alliance_channel(
+        &self,
+        galaxy_name: &str,
+        alliance_id: i64,
+        name: &str,
+    ) -> Result<ChatChannelRow, PersistenceError> {
+        sqlx::query(
+            "INSERT OR IGNORE INTO chat_channels (galaxy_name, kind, alliance_id, name) VALUES (?, 'alliance', ?, ?)",
+        )
+        .bind(galaxy_name)
+        .bind(alliance_id)
+        .bind(name)
+        .execute(&self.pool)
+        .await?;
+
+        let query = format!(
+            "SELECT {} FROM chat_channels WHERE alliance_id = ? AND kind = 'alliance'",
+            CHANNEL_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .bind(alliance_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(channel_row(row))
+    }
+
+    /// Create a group channel with its creator as the first member
+    pub async fn create_chat_group(
+        &self,
+        galaxy_name: &str,
+        name: &str,
+        account_id: i64,
+    ) -> Result<i64, PersistenceError> {
+        let mut tx = self.pool.begin().await?;
+
+        let channel_id: i64 = sqlx::query(
+            "INSERT INTO chat_channels (galaxy_name, kind, name) VALUES (?, 'group', ?) RETURNING id",
+        )
+        .bind(galaxy_name)
+        .bind(name)
+        .fetch_one(&mut *tx)
+        .await?
+        .get("id");
+        sqlx::query(
+            "INSERT INTO chat_channel_members (channel_id, user_galaxy_account_id) VALUES (?, ?)",
+        )
+        .bind(channel_id)
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(channel_id)
+    }
+
+    /// Get a channel by id
+    pub async fn get_chat_channel(
+        &self,
+        channel_id: i64,
+    ) -> Result<Option<ChatChannelRow>, PersistenceError> {
+        let query = format!("SELECT {} FROM chat_channels WHERE id = ?", CHANNEL_COLUMNS);
+        let result = sqlx::query(&query)
+            .bind(channel_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result.map(channel_row))
+    }
+
+    /// Get the group channels an account is a member of, oldest first
+    pub async fn get_account_chat_groups(
+        &self,
+        account_id: i64,
+    ) -> Result<Vec<ChatChannelRow>, PersistenceError> {
+        let query = format!(
+            "SELECT {} FROM chat_channels WHERE kind = 'group' AND id IN (SELECT channel_id FROM chat_channel_members WHERE user_galaxy_account_id = ?) ORDER BY id",
+            CHANNEL_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(account_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(channel_row).collect())
+    }
+
+    /// Get the members of a group channel, in the order they joined the galaxy
+    pub async fn get_chat_members(
+        &self,
+        channel_id: i64,
+    ) -> Result<Vec<UserGalaxyAccountRow>, PersistenceError> {
+        let query = format!(
+            "SELECT {} FROM user_galaxy_accounts WHERE id IN (SELECT user_galaxy_account_id FROM chat_channel_members WHERE channel_id = ?) ORDER BY id",
+            ACCOUNT_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(channel_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(account_row).collect())
+    }
+
+    /// Add an account to a group channel
+    ///
+    /// Returns false if it was already a member.
+    pub async fn add_chat_member(
+        &self,
+        channel_id: i64,
+        account_id: i64,
+    ) -> Result<bool, PersistenceError> {
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO chat_channel_members (channel_id, user_galaxy_account_id) VALUES (?, ?)",
+        )
+        .bind(channel_id)
+        .bind(account_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Remove an account from a group channel
+    ///
+    /// Returns false if it wasn't a member.
+    pub async fn remove_chat_member(
+        &self,
+        channel_id: i64,
+        account_id: i64,
+    ) -> Result<bool, PersistenceError> {
+        let result = sqlx::query(
+            "DELETE FROM chat_channel_members WHERE channel_id = ? AND user_galaxy_account_id = ?",
+        )
+        .bind(channel_id)
+        .bind(account_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete a channel with its members and messages
+    pub async fn delete_chat_channel(&self, channel_id: i64) -> Result<(), PersistenceError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM chat_messages WHERE channel_id = ?")
+            .bind(channel_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM chat_channel_members WHERE channel_id = ?")
+            .bind(channel_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM chat_channels WHERE id = ?")
+            .bind(channel_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Post a message in a channel
+    pub async fn add_chat_message(
+        &self,
+        channel_id: i64,
+        account_id: i64,
+        body: &str,
+    ) -> Result<ChatMessageRow, PersistenceError> {
+        let id: i64 = sqlx::query(
+            "INSERT INTO chat_messages (channel_id, user_galaxy_account_id, body) VALUES (?, ?, ?) RETURNING id",
+        )
+        .bind(channel_id)
+        .bind(account_id)
+        .bind(body)
+        .fetch_one(&self.pool)
+        .await?
+        .get("id");
+
+        let query = format!("{} WHERE m.id = ?", MESSAGE_QUERY);
+        let row = sqlx::query(&query).bind(id).fetch_one(&self.pool).await?;
+
+        Ok(message_row(row))
+    }
+
+    /// Get the latest messages of a channel, oldest first
+    pub async fn get_chat_history(
+        &self,
+        channel_id: i64,
+        limit: usize,
+    ) -> Result<Vec<ChatMessageRow>, PersistenceError> {
+        let query = format!(
+            "{} WHERE m.channel_id = ? ORDER BY m.id DESC LIMIT ?",
+            MESSAGE_QUERY
+        );
+        let rows = sqlx::query(&query)
+            .bind(channel_id)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().rev().map(message_row).collect())
+    }
+}
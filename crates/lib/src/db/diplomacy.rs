@@ -0,0 +1,278 @@
+use super::{Database, PersistenceError};
+
+use crate::models::{AllianceRelationRow, DiplomacyLogRow, DiplomacyProposalRow};
+
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+fn relation_row(row: SqliteRow) -> AllianceRelationRow {
+    AllianceRelationRow {
+        galaxy_name: row.get("galaxy_name"),
+        alliance_a: row.get("alliance_a"),
+        alliance_b: row.get("alliance_b"),
+        relation: row.get("relation"),
+        since: row.get("since"),
+        expires_at: row.get("expires_at"),
+    }
+}
+
+fn proposal_row(row: SqliteRow) -> DiplomacyProposalRow {
+    DiplomacyProposalRow {
+        id: row.get("id"),
+        galaxy_name: row.get("galaxy_name"),
+        from_alliance: row.get("from_alliance"),
+        to_alliance: row.get("to_alliance"),
+        relation: row.get("relation"),
+        proposed_by: row.get("proposed_by"),
+        created_at: row.get("created_at"),
+        expires_at: row.get("expires_at"),
+    }
+}
+
+fn log_row(row: SqliteRow) -> DiplomacyLogRow {
+    DiplomacyLogRow {
+        id: row.get("id"),
+        galaxy_name: row.get("galaxy_name"),
+        tick: row.get("tick"),
+        alliance_a: row.get("alliance_a"),
+        alliance_b: row.get("alliance_b"),
+        action: row.get("action"),
+        relation: row.get("relation"),
+        message: row.get("message"),
+    }
+}
+
+/// Relations are stored once per pair, lowest id first
+fn pair(alliance_a: i64, alliance_b: i64) -> (i64, i64) {
+    (alliance_a.min(alliance_b), alliance_a.max(alliance_b))
+}
+
+const PROPOSAL_COLUMNS: &str =
+    "id, galaxy_name, from_alliance, to_alliance, relation, proposed_by, created_at, expires_at";
+
+impl Database {
+    /// Get every relation between alliances of a galaxy
+    pub async fn get_alliance_relations(
+        &self,
+        galaxy_name: &str,
+    ) -> Result<Vec<AllianceRelationRow>, PersistenceError> {
+        let rows = sqlx::query(
+            "SELECT galaxy_name, alliance_a, alliance_b, relation, since, expires_at FROM alliance_relations WHERE galaxy_name = ? ORDER BY alliance_a, alliance_b",
+        )
+        .bind(galaxy_name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(relation_row).collect())
+    }
+
+    /// Get the relation between two alliances, None if they are neutral
+    pub async fn get_alliance_relation(
+        &self,
+        alliance_a: i64,
+        alliance_b: i64,
+    ) -> Result<Option<AllianceRelationRow>, PersistenceError> {
+        let (alliance_a, alliance_b) = pair(alliance_a, alliance_b);
+        let result = sqlx::query(
+            "SELECT galaxy_name, alliance_a, alliance_b, relation, since, expires_at FROM alliance_relations WHERE alliance_a = ? AND alliance_b = ?",
+        )
+        .bind(alliance_a)
+        .bind(alliance_b)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result.map(relation_row))
+    }
+
+    /// Set the relation between two alliances, or make them neutral with None
+    ///
+    /// Pending proposals between the two are dropped, as they were made under the old relation.
+    pub async fn set_alliance_relation(
+        &self,
+        galaxy_name: &str,
+        alliance_a: i64,
+        alliance_b: i64,
+        relation: Option<&str>,
+        since: usize,
+        expires_at: Option<usize>,
+    ) -> Result<(), PersistenceError> {
+        let (alliance_a, alliance_b) = pair(alliance_a, alliance_b);
+        let mut tx = self.pool.begin().await?;
+
+        match relation {
+            Some(relation) => {
+                sqlx::query(
+                    "INSERT OR REPLACE INTO alliance_relations (galaxy_name, alliance_a, alliance_b, relation, since, expires_at) VALUES (?, ?, ?, ?, ?, ?)",
+                )
+                .bind(galaxy_name)
+                .bind(alliance_a)
+                .bind(alliance_b)
+                .bind(relation)
+                .bind(since as i64)
+                .bind(expires_at.map(|tick| tick as i64))
+                .execute(&mut *tx)
+                .await?;
+            }
+            None => {
+                sqlx::query(
+                    "DELETE FROM alliance_relations WHERE alliance_a = ? AND alliance_b = ?",
+                )
+                .bind(alliance_a)
+                .bind(alliance_b)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        sqlx::query(
+            "DELETE FROM diplomacy_proposals WHERE (from_alliance = ? AND to_alliance = ?) OR (from_alliance = ? AND to_alliance = ?)",
+        )
+        .bind(alliance_a)
+        .bind(alliance_b)
+        .bind(alliance_b)
+        .bind(alliance_a)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Store a proposal from one alliance to another, returning its id
+    ///
+    /// The id of the row passed in is ignored.
+    pub async fn create_diplomacy_proposal(
+        &self,
+        proposal: &DiplomacyProposalRow,
+    ) -> Result<i64, PersistenceError> {
+        let id: i64 = sqlx::query(
+            "INSERT INTO diplomacy_proposals (galaxy_name, from_alliance, to_alliance, relation, proposed_by, created_at, expires_at) VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING id",
+        )
+        .bind(&proposal.galaxy_name)
+        .bind(proposal.from_alliance)
+        .bind(proposal.to_alliance)
+        .bind(&proposal.relation)
+        .bind(proposal.proposed_by)
+        .bind(proposal.created_at)
+        .bind(proposal.expires_at)
+        .fetch_one(&self.pool)
+        .await?
+        .get("id");
+
+        Ok(id)
+    }
+
+    /// Get a proposal by id
+    pub async fn get_diplomacy_proposal(
+        &self,
+        proposal_id: i64,
+    ) -> Result<Option<DiplomacyProposalRow>, PersistenceError> {
+        let query = format!(
+            "SELECT {} FROM diplomacy_proposals WHERE id = ?",
+            PROPOSAL_COLUMNS
+        );
+        let result = sqlx::query(&query)
+            .bind(proposal_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result.map(proposal_row))
+    }
+
+    /// Get the proposals an alliance made or received, oldest first
+    pub async fn get_alliance_proposals(
+        &self,
+        alliance_id: i64,
+    ) -> Result<Vec<DiplomacyProposalRow>, PersistenceError> {
+        let query = format!(
+            "SELECT {} FROM diplomacy_proposals WHERE from_alliance = ? OR to_alliance = ? ORDER BY id",
+            PROPOSAL_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(alliance_id)
+            .bind(alliance_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(proposal_row).collect())
+    }
+
+    /// Delete a proposal once it's answered
+    ///
+    /// Returns false if there was no such proposal.
+    pub async fn delete_diplomacy_proposal(
+        &self,
+        proposal_id: i64,
+    ) -> Result<bool, PersistenceError> {
+        let result = sqlx::query("DELETE FROM diplomacy_proposals WHERE id = ?")
+            .bind(proposal_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete the proposals of a galaxy that ran out by a tick
+    pub async fn delete_expired_proposals(
+        &self,
+        galaxy_name: &str,
+        tick: usize,
+    ) -> Result<u64, PersistenceError> {
+        let result = sqlx::query(
+            "DELETE FROM diplomacy_proposals WHERE galaxy_name = ? AND expires_at <= ?",
+        )
+        .bind(galaxy_name)
+        .bind(tick as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Add an entry to the diplomacy log of a galaxy
+    ///
+    /// The id of the row passed in is ignored.
+    pub async fn add_diplomacy_log(&self, entry: &DiplomacyLogRow) -> Result<(), PersistenceError> {
+        sqlx::query(
+            "INSERT INTO diplomacy_log (galaxy_name, tick, alliance_a, alliance_b, action, relation, message) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&entry.galaxy_name)
+        .bind(entry.tick)
+        .bind(entry.alliance_a)
+        .bind(entry.alliance_b)
+        .bind(&entry.action)
+        .bind(&entry.relation)
+        .bind(&entry.message)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get the latest entries of a galaxy's diplomacy log, newest first, optionally only those
+    /// involving an alliance
+    pub async fn get_diplomacy_log(
+        &self,
+        galaxy_name: &str,
+        alliance_id: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<DiplomacyLogRow>, PersistenceError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, galaxy_name, tick, alliance_a, alliance_b, action, relation, message
+            FROM diplomacy_log
+            WHERE galaxy_name = ? AND (? IS NULL OR alliance_a = ? OR alliance_b = ?)
+            ORDER BY tick DESC, id DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(galaxy_name)
+        .bind(alliance_id)
+        .bind(alliance_id)
+        .bind(alliance_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(log_row).collect())
+    }
+}
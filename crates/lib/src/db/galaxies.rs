@@ -1,6 +1,7 @@
 use super::{Database, PersistenceError};
 
-use crate::models::GalaxyRow;
+use crate::lifecycle::Standing;
+use crate::models::{AttackReportRow, GalaxyRow, GalaxyStandingRow, NpcSystemRow};
 
 use crate::{Coords, Event, EventCallback, Galaxy, GalaxyConfig, StructureType, System};
 
@@ -45,16 +46,17 @@ impl Database {
         config: &GalaxyConfig,
         initial_tick: usize,
     ) -> Result<Galaxy, PersistenceError> {
+        // Create galaxy struct first, so the config includes the chosen seed
+        let galaxy =
+            Galaxy::new(config.clone(), initial_tick).map_err(PersistenceError::Generation)?;
+
         // Serialize configuration to YAML
-        let config_yaml = serde_yaml::to_string(config)?;
+        let config_yaml = serde_yaml::to_string(galaxy.get_config())?;
 
         // Create galaxy in database
         self.create_galaxy(galaxy_name, &config_yaml, initial_tick)
             .await?;
 
-        // Create galaxy struct
-        let galaxy = Galaxy::new(config.clone(), initial_tick);
-
         // Save initial state
         self.save_galaxy_state(galaxy_name, &galaxy).await?;
 
@@ -67,7 +69,7 @@ impl Database {
         galaxy_name: &str,
     ) -> Result<Option<GalaxyRow>, PersistenceError> {
         let result = sqlx::query(
-            "SELECT name, config_file, tick, created_at, updated_at FROM galaxies WHERE name = ?",
+            "SELECT name, config_file, tick, seed, ended_at, paused_at, paused_offset, created_at, updated_at FROM galaxies WHERE name = ?",
         )
         .bind(galaxy_name)
         .fetch_optional(&self.pool)
@@ -78,6 +80,10 @@ impl Database {
                 name: row.get("name"),
                 config_file: row.get("config_file"),
                 tick: row.get("tick"),
+                seed: row.get("seed"),
+                ended_at: row.get("ended_at"),
+                paused_at: row.get("paused_at"),
+                paused_offset: row.get("paused_offset"),
                 created_at: row.get("created_at"),
                 updated_at: row.get("updated_at"),
             }))
@@ -101,6 +107,40 @@ impl Database {
         Ok(())
     }
 
+    /// Update the generation seed of a galaxy in the database
+    pub async fn update_galaxy_seed(
+        &self,
+        galaxy_name: &str,
+        seed: u64,
+    ) -> Result<(), PersistenceError> {
+        sqlx::query("UPDATE galaxies SET seed = ?, updated_at = CURRENT_TIMESTAMP WHERE name = ?")
+            .bind(seed as i64)
+            .bind(galaxy_name)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Update the pause state of a galaxy in the database
+    pub async fn update_galaxy_pause(
+        &self,
+        galaxy_name: &str,
+        paused_at: Option<usize>,
+        paused_offset: usize,
+    ) -> Result<(), PersistenceError> {
+        sqlx::query(
+            "UPDATE galaxies SET paused_at = ?, paused_offset = ?, updated_at = CURRENT_TIMESTAMP WHERE name = ?",
+        )
+        .bind(paused_at.map(|tick| tick as i64))
+        .bind(paused_offset as i64)
+        .bind(galaxy_name)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
     /// Delete a galaxy and all its associated data
     pub async fn delete_galaxy(&self, galaxy_name: &str) -> Result<(), PersistenceError> {
         sqlx::query("DELETE FROM galaxies WHERE name = ?")
@@ -134,31 +174,54 @@ impl Database {
         let mut tx = self.pool.begin().await?;
 
         // Ensure galaxy exists in database, create if it doesn't
-        let galaxy_exists = sqlx::query("SELECT 1 FROM galaxies WHERE name = ? LIMIT 1")
+        let stored = sqlx::query("SELECT ended_at FROM galaxies WHERE name = ? LIMIT 1")
             .bind(galaxy_name)
             .fetch_optional(&mut *tx)
-            .await?
-            .is_some();
+            .await?;
+        let galaxy_exists = stored.is_some();
+        let stored_end: Option<i64> = stored.and_then(|row| row.get("ended_at"));
+
+        // The galaxy ends itself, so its standings are stored along with the end
+        if stored_end.is_none() && galaxy.ended_at().is_some() {
+            self.save_standings_with_tx(&mut tx, galaxy_name, galaxy.standings())
+                .await?;
+        }
 
         if !galaxy_exists {
             // Create galaxy record if it doesn't exist
-            sqlx::query("INSERT INTO galaxies (name, config_file, tick) VALUES (?, ?, ?)")
-                .bind(galaxy_name)
-                .bind("") // Empty config file for existing galaxies
-                .bind(galaxy.get_tick() as i64)
-                .execute(&mut *tx)
-                .await?;
+            sqlx::query(
+                "INSERT INTO galaxies (name, config_file, tick, seed, ended_at, paused_at, paused_offset) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(galaxy_name)
+            .bind("") // Empty config file for existing galaxies
+            .bind(galaxy.get_tick() as i64)
+            .bind(galaxy.seed() as i64)
+            .bind(galaxy.ended_at().map(|tick| tick as i64))
+            .bind(galaxy.paused_at().map(|tick| tick as i64))
+            .bind(galaxy.paused_offset() as i64)
+            .execute(&mut *tx)
+            .await?;
         } else {
             // Update galaxy metadata
             sqlx::query(
-                "UPDATE galaxies SET tick = ?, updated_at = CURRENT_TIMESTAMP WHERE name = ?",
+                "UPDATE galaxies SET tick = ?, seed = ?, ended_at = ?, paused_at = ?, paused_offset = ?, updated_at = CURRENT_TIMESTAMP WHERE name = ?",
             )
             .bind(galaxy.get_tick() as i64)
+            .bind(galaxy.seed() as i64)
+            .bind(galaxy.ended_at().map(|tick| tick as i64))
+            .bind(galaxy.paused_at().map(|tick| tick as i64))
+            .bind(galaxy.paused_offset() as i64)
             .bind(galaxy_name)
             .execute(&mut *tx)
             .await?;
         }
 
+        // Raids are few, so they are saved whole whenever anything changed
+        if galaxy.needs_persist() {
+            self.save_attack_reports_with_tx(&mut tx, galaxy_name, galaxy)
+                .await?;
+        }
+
         // Get dirty systems to minimize database writes
         let systems_to_save: Vec<_> = if galaxy.needs_persist() {
             if galaxy.get_dirty_systems().is_empty() {
@@ -213,6 +276,27 @@ impl Database {
                         .execute(&mut *tx)
                         .await?;
                 }
+
+                if let Some(npc) = galaxy.npcs().get(&coords) {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO npc_systems (system_id, defenders, updated_tick, next_build, next_attack)
+                        VALUES (?, ?, ?, ?, ?)
+                        ON CONFLICT(system_id) DO UPDATE SET
+                            defenders = excluded.defenders,
+                            updated_tick = excluded.updated_tick,
+                            next_build = excluded.next_build,
+                            next_attack = excluded.next_attack
+                        "#,
+                    )
+                    .bind(system_id)
+                    .bind(npc.defenders as i64)
+                    .bind(npc.updated as i64)
+                    .bind(npc.next_build as i64)
+                    .bind(npc.next_attack.map(|tick| tick as i64))
+                    .execute(&mut *tx)
+                    .await?;
+                }
             }
         }
 
@@ -267,8 +351,11 @@ impl Database {
             None => return Ok(None),
         };
 
-        // Parse configuration
-        let config: GalaxyConfig = serde_yaml::from_str(&galaxy_row.config_file)?;
+        // Parse configuration, falling back to the stored seed if the config doesn't have one
+        let mut config: GalaxyConfig = serde_yaml::from_str(&galaxy_row.config_file)?;
+        if config.seed.is_none() {
+            config.seed = galaxy_row.seed_as_u64();
+        }
 
         // Load all systems for this galaxy
         let current_tick = galaxy_row.tick_as_usize();
@@ -277,12 +364,252 @@ impl Database {
             .await?;
 
         // Create galaxy with loaded data
-        let mut galaxy = Galaxy::new(config, current_tick);
-        galaxy.replace_systems(systems);
-
+        let mut galaxy = Galaxy::from_database(config, current_tick, systems);
+        let npcs = self
+            .get_npc_systems(galaxy_name)
+            .await?
+            .iter()
+            .map(|row| (row.coords(), row.to_npc_state()))
+            .collect();
+        galaxy.restore_npcs(npcs);
+        let (unreported, recent): (Vec<_>, Vec<_>) = self
+            .get_attack_reports(galaxy_name)
+            .await?
+            .into_iter()
+            .partition(|row| row.unreported);
+        galaxy.restore_attacks(
+            recent
+                .iter()
+                .map(AttackReportRow::to_attack_report)
+                .collect(),
+            unreported
+                .iter()
+                .map(AttackReportRow::to_attack_report)
+                .collect(),
+        );
+        let owned = self.get_owned_systems(galaxy_name).await?;
+        galaxy.set_player_systems(owned.iter().map(|row| row.coords().into()));
+        let accounts = self.get_galaxy_accounts(galaxy_name).await?;
+        let humans: Vec<i64> = accounts
+            .iter()
+            .filter(|account| !account.is_ai())
+            .map(|account| account.id)
+            .collect();
+        let owners = owned
+            .iter()
+            .filter_map(|row| Some((row.coords().into(), row.user_galaxy_account_id?)))
+            .filter(|(_, account)| humans.contains(account))
+            .collect();
+        galaxy.set_players(owners, humans, crate::alliance::memberships(&accounts));
+        galaxy.restore_pause(
+            galaxy_row.paused_at_as_usize(),
+            galaxy_row.paused_offset as usize,
+        );
+        if let Some(ended_at) = galaxy_row.ended_at_as_usize() {
+            let standings = self
+                .get_final_standings(galaxy_name)
+                .await?
+                .iter()
+                .map(GalaxyStandingRow::to_standing)
+                .collect();
+            galaxy.restore_end(ended_at, standings);
+        }
         Ok(Some(galaxy))
     }
 
+    /// Mark a galaxy as ended and store its final standings
+    pub async fn save_final_standings(
+        &self,
+        galaxy_name: &str,
+        ended_at: usize,
+        standings: &[Standing],
+    ) -> Result<(), PersistenceError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "UPDATE galaxies SET ended_at = ?, paused_at = NULL, updated_at = CURRENT_TIMESTAMP WHERE name = ?",
+        )
+        .bind(ended_at as i64)
+        .bind(galaxy_name)
+        .execute(&mut *tx)
+        .await?;
+        self.save_standings_with_tx(&mut tx, galaxy_name, standings)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Replace the final standings of a galaxy within a transaction
+    async fn save_standings_with_tx(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        galaxy_name: &str,
+        standings: &[Standing],
+    ) -> Result<(), PersistenceError> {
+        sqlx::query("DELETE FROM galaxy_standings WHERE galaxy_name = ?")
+            .bind(galaxy_name)
+            .execute(&mut **tx)
+            .await?;
+
+        for standing in standings {
+            sqlx::query(
+                "INSERT INTO galaxy_standings (galaxy_name, rank, user_galaxy_account_id, score, systems) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(galaxy_name)
+            .bind(standing.rank as i64)
+            .bind(standing.account_id)
+            .bind(standing.score as i64)
+            .bind(standing.systems as i64)
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Get the final standings of an ended galaxy, best first
+    pub async fn get_final_standings(
+        &self,
+        galaxy_name: &str,
+    ) -> Result<Vec<GalaxyStandingRow>, PersistenceError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT s.galaxy_name, s.rank, s.user_galaxy_account_id, a.account_name, s.score, s.systems
+            FROM galaxy_standings s
+            JOIN user_galaxy_accounts a ON a.id = s.user_galaxy_account_id
+            WHERE s.galaxy_name = ?
+            ORDER BY s.rank
+            "#,
+        )
+        .bind(galaxy_name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut standings = Vec::new();
+        for row in rows {
+            standings.push(GalaxyStandingRow {
+                galaxy_name: row.get("galaxy_name"),
+                rank: row.get("rank"),
+                user_galaxy_account_id: row.get("user_galaxy_account_id"),
+                account_name: row.get("account_name"),
+                score: row.get("score"),
+                systems: row.get("systems"),
+            });
+        }
+
+        Ok(standings)
+    }
+
+    /// Get the state of every NPC system in a galaxy
+    pub async fn get_npc_systems(
+        &self,
+        galaxy_name: &str,
+    ) -> Result<Vec<NpcSystemRow>, PersistenceError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT n.system_id, s.x, s.y, n.defenders, n.updated_tick, n.next_build, n.next_attack
+            FROM npc_systems n
+            JOIN systems s ON s.id = n.system_id
+            WHERE s.galaxy_name = ?
+            "#,
+        )
+        .bind(galaxy_name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut npcs = Vec::new();
+        for row in rows {
+            npcs.push(NpcSystemRow {
+                system_id: row.get("system_id"),
+                x: row.get("x"),
+                y: row.get("y"),
+                defenders: row.get("defenders"),
+                updated_tick: row.get("updated_tick"),
+                next_build: row.get("next_build"),
+                next_attack: row.get("next_attack"),
+            });
+        }
+
+        Ok(npcs)
+    }
+
+    /// Replace the recent and unreported raids of a galaxy within a transaction
+    async fn save_attack_reports_with_tx(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        galaxy_name: &str,
+        galaxy: &Galaxy,
+    ) -> Result<(), PersistenceError> {
+        sqlx::query("DELETE FROM attack_reports WHERE galaxy_name = ?")
+            .bind(galaxy_name)
+            .execute(&mut **tx)
+            .await?;
+
+        let recent = galaxy.recent_attacks().iter().map(|report| (report, false));
+        let unreported = galaxy
+            .unreported_raids()
+            .iter()
+            .map(|report| (report, true));
+        for (report, unreported) in recent.chain(unreported) {
+            sqlx::query(
+                r#"
+                INSERT INTO attack_reports (galaxy_name, tick, attacker_x, attacker_y, target_x, target_y, raiders, loot_metal, loot_crew, loot_water, unreported)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(galaxy_name)
+            .bind(report.tick as i64)
+            .bind(report.attacker.x as i64)
+            .bind(report.attacker.y as i64)
+            .bind(report.target.x as i64)
+            .bind(report.target.y as i64)
+            .bind(report.raiders as i64)
+            .bind(report.loot.metal as i64)
+            .bind(report.loot.crew as i64)
+            .bind(report.loot.water as i64)
+            .bind(unreported)
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Get the recent and unreported raids of a galaxy, oldest first
+    pub async fn get_attack_reports(
+        &self,
+        galaxy_name: &str,
+    ) -> Result<Vec<AttackReportRow>, PersistenceError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT tick, attacker_x, attacker_y, target_x, target_y, raiders, loot_metal, loot_crew, loot_water, unreported
+            FROM attack_reports
+            WHERE galaxy_name = ?
+            ORDER BY id
+            "#,
+        )
+        .bind(galaxy_name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut reports = Vec::new();
+        for row in rows {
+            reports.push(AttackReportRow {
+                tick: row.get("tick"),
+                attacker_x: row.get("attacker_x"),
+                attacker_y: row.get("attacker_y"),
+                target_x: row.get("target_x"),
+                target_y: row.get("target_y"),
+                raiders: row.get("raiders"),
+                loot_metal: row.get("loot_metal"),
+                loot_crew: row.get("loot_crew"),
+                loot_water: row.get("loot_water"),
+                unreported: row.get("unreported"),
+            });
+        }
+
+        Ok(reports)
+    }
+
     /// Load all systems for a galaxy from database
     async fn load_systems_for_galaxy(
         &self,
@@ -519,6 +846,226 @@ mod tests {
         db.close().await;
     }
 
+    #[tokio::test]
+    async fn test_galaxy_seed_persistence() {
+        let db = Database::new_test()
+            .await
+            .expect("Failed to create test database");
+
+        let galaxy_name = "seed_test";
+
+        let config = GalaxyConfig {
+            seed: Some(1234),
+            system_count: 10,
+            size: crate::config::GalaxySize { x: 20, y: 20 },
+            ..Default::default()
+        };
+        let mut galaxy = db
+            .create_galaxy_with_config(galaxy_name, &config, 0)
+            .await
+            .expect("Failed to create galaxy");
+        galaxy.mark_all_dirty();
+        db.save_galaxy_state(galaxy_name, &galaxy)
+            .await
+            .expect("Failed to save galaxy state");
+
+        let row = db
+            .get_galaxy(galaxy_name)
+            .await
+            .expect("Failed to get galaxy")
+            .expect("Galaxy should exist");
+        assert_eq!(row.seed_as_u64(), Some(1234));
+
+        let loaded_galaxy = db
+            .load_galaxy(galaxy_name)
+            .await
+            .expect("Failed to load galaxy")
+            .expect("Galaxy should exist");
+        assert_eq!(loaded_galaxy.seed(), 1234);
+
+        db.close().await;
+    }
+
+    #[tokio::test]
+    async fn test_final_standings_persistence() {
+        let db = Database::new_test()
+            .await
+            .expect("Failed to create test database");
+
+        let galaxy_name = "standings_test";
+        let config = GalaxyConfig {
+            system_count: 5,
+            size: crate::config::GalaxySize { x: 10, y: 10 },
+            ..Default::default()
+        };
+        db.create_galaxy_with_config(galaxy_name, &config, 0)
+            .await
+            .expect("Failed to create galaxy");
+        let user_id = db
+            .create_user("winner", "winner@example.com", "hash")
+            .await
+            .expect("Failed to create user");
+        let account_id = db
+            .create_user_galaxy_account(user_id, galaxy_name, "Champion", None)
+            .await
+            .expect("Failed to create account");
+
+        let standings = vec![crate::lifecycle::Standing {
+            rank: 1,
+            account_id,
+            score: 42,
+            systems: 3,
+        }];
+        db.save_final_standings(galaxy_name, 500, &standings)
+            .await
+            .expect("Failed to save standings");
+
+        let rows = db
+            .get_final_standings(galaxy_name)
+            .await
+            .expect("Failed to get standings");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].account_name, "Champion");
+        assert_eq!(rows[0].score, 42);
+
+        // The galaxy is still ended after a reload
+        let loaded_galaxy = db
+            .load_galaxy(galaxy_name)
+            .await
+            .expect("Failed to load galaxy")
+            .expect("Galaxy should exist");
+        assert_eq!(loaded_galaxy.ended_at(), Some(500));
+        assert_eq!(loaded_galaxy.standings(), standings.as_slice());
+
+        db.close().await;
+    }
+
+    #[tokio::test]
+    async fn test_galaxy_pause_persistence() {
+        let db = Database::new_test()
+            .await
+            .expect("Failed to create test database");
+
+        let galaxy_name = "pause_test";
+        let config = GalaxyConfig {
+            system_count: 5,
+            size: crate::config::GalaxySize { x: 10, y: 10 },
+            ..Default::default()
+        };
+        db.create_galaxy_with_config(galaxy_name, &config, 0)
+            .await
+            .expect("Failed to create galaxy");
+        db.update_galaxy_pause(galaxy_name, Some(300), 100)
+            .await
+            .expect("Failed to pause galaxy");
+
+        let galaxy = db
+            .load_galaxy(galaxy_name)
+            .await
+            .expect("Failed to load galaxy")
+            .expect("Galaxy should exist");
+        assert_eq!(galaxy.paused_at(), Some(300));
+        assert_eq!(galaxy.paused_offset(), 100);
+        assert_eq!(galaxy.game_tick(1000), 200);
+
+        db.close().await;
+    }
+
+    #[tokio::test]
+    async fn test_npc_systems_persistence() {
+        let db = Database::new_test()
+            .await
+            .expect("Failed to create test database");
+
+        let galaxy_name = "npc_test";
+        let mut config = GalaxyConfig {
+            seed: Some(3),
+            system_count: 10,
+            size: crate::config::GalaxySize { x: 20, y: 20 },
+            ..Default::default()
+        };
+        config.npc.fraction = 0.3;
+        db.create_galaxy_with_config(galaxy_name, &config, 0)
+            .await
+            .expect("Failed to create galaxy");
+
+        let mut galaxy = crate::Galaxy::new(config, 0).expect("Failed to create galaxy");
+        galaxy.mark_all_dirty();
+        db.save_galaxy_state(galaxy_name, &galaxy)
+            .await
+            .expect("Failed to save galaxy");
+
+        let loaded = db
+            .load_galaxy(galaxy_name)
+            .await
+            .expect("Failed to load galaxy")
+            .expect("Galaxy should exist");
+        assert_eq!(loaded.npcs().len(), 3);
+        assert_eq!(loaded.npcs(), galaxy.npcs());
+
+        db.close().await;
+    }
+
+    #[tokio::test]
+    async fn test_attack_reports_persistence() {
+        let db = Database::new_test()
+            .await
+            .expect("Failed to create test database");
+
+        let galaxy_name = "raid_test";
+        let config = GalaxyConfig {
+            seed: Some(4),
+            system_count: 10,
+            size: crate::config::GalaxySize { x: 20, y: 20 },
+            ..Default::default()
+        };
+        db.create_galaxy_with_config(galaxy_name, &config, 0)
+            .await
+            .expect("Failed to create galaxy");
+
+        let mut galaxy = crate::Galaxy::new(config, 0).expect("Failed to create galaxy");
+        let coords: Vec<crate::Coords> = galaxy.systems().keys().copied().collect();
+        let raid = |tick| crate::npc::AttackReport {
+            tick,
+            attacker: coords[0],
+            target: coords[1],
+            raiders: 3,
+            loot: crate::Resources {
+                metal: 10,
+                crew: 0,
+                water: 5,
+            },
+        };
+        galaxy.restore_attacks(vec![raid(10), raid(20)], vec![raid(20)]);
+        galaxy.mark_all_dirty();
+        db.save_galaxy_state(galaxy_name, &galaxy)
+            .await
+            .expect("Failed to save galaxy");
+
+        // Raids survive a restart, and are only reported once
+        let mut loaded = db
+            .load_galaxy(galaxy_name)
+            .await
+            .expect("Failed to load galaxy")
+            .expect("Galaxy should exist");
+        assert_eq!(loaded.recent_attacks(), &[raid(10), raid(20)]);
+        assert_eq!(loaded.unreported_raids(), &[raid(20)]);
+        assert_eq!(loaded.take_notices(30).unwrap().len(), 1);
+        db.save_galaxy_state(galaxy_name, &loaded)
+            .await
+            .expect("Failed to save galaxy");
+
+        let loaded = db
+            .load_galaxy(galaxy_name)
+            .await
+            .expect("Failed to load galaxy")
+            .expect("Galaxy should exist");
+        assert_eq!(loaded.recent_attacks().len(), 2);
+        assert!(loaded.unreported_raids().is_empty());
+
+        db.close().await;
+    }
+
     #[tokio::test]
     async fn test_system_individual_tick_persistence() {
         let db = Database::new_test()
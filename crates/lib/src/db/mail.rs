@@ -0,0 +1,104 @@
+use super::{Database, PersistenceError};
+
+use crate::models::MailAlertRow;
+
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+const MAIL_ALERT_QUERY: &str = r#"
+    SELECT n.id AS notification_id, n.kind, n.message, a.galaxy_name, a.account_name,
+        u.username, u.email
+    FROM notifications n
+    JOIN user_galaxy_accounts a ON a.id = n.user_galaxy_account_id
+    JOIN users u ON u.id = a.user_id
+    JOIN mail_alerts m ON m.user_id = u.id AND m.kind = n.kind
+    WHERE n.mailed_at IS NULL AND n.read_at IS NULL AND n.created_at >= m.created_at
+"#;
+
+fn mail_alert_row(row: SqliteRow) -> MailAlertRow {
+    MailAlertRow {
+        notification_id: row.get("notification_id"),
+        kind: row.get("kind"),
+        message: row.get("message"),
+        galaxy_name: row.get("galaxy_name"),
+        account_name: row.get("account_name"),
+        username: row.get("username"),
+        email: row.get("email"),
+    }
+}
+
+impl Database {
+    /// Get the notification kinds a user gets by email
+    pub async fn get_mail_alerts(&self, user_id: i64) -> Result<Vec<String>, PersistenceError> {
+        let rows = sqlx::query("SELECT kind FROM mail_alerts WHERE user_id = ? ORDER BY kind")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(|row| row.get("kind")).collect())
+    }
+
+    /// Set the notification kinds a user gets by email
+    ///
+    /// Kinds the user already had keep the time they were chosen.
+    pub async fn set_mail_alerts(
+        &self,
+        user_id: i64,
+        kinds: &[String],
+    ) -> Result<(), PersistenceError> {
+        let mut tx = self.pool.begin().await?;
+
+        let current: Vec<String> = sqlx::query("SELECT kind FROM mail_alerts WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|row| row.get("kind"))
+            .collect();
+        for kind in current.iter().filter(|kind| !kinds.contains(kind)) {
+            sqlx::query("DELETE FROM mail_alerts WHERE user_id = ? AND kind = ?")
+                .bind(user_id)
+                .bind(kind)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for kind in kinds {
+            sqlx::query("INSERT OR IGNORE INTO mail_alerts (user_id, kind) VALUES (?, ?)")
+                .bind(user_id)
+                .bind(kind)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Get the unread notifications that weren't mailed yet to users who get their kind by
+    /// email, oldest first
+    pub async fn get_pending_mail_alerts(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<MailAlertRow>, PersistenceError> {
+        let query = format!("{} ORDER BY n.id LIMIT ?", MAIL_ALERT_QUERY);
+        let rows = sqlx::query(&query)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(mail_alert_row).collect())
+    }
+
+    /// Mark a notification as mailed, or as skipped when it can never be
+    pub async fn set_notification_mailed(
+        &self,
+        notification_id: i64,
+    ) -> Result<(), PersistenceError> {
+        sqlx::query("UPDATE notifications SET mailed_at = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(notification_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+use super::users::{account_row, ACCOUNT_COLUMNS};
+use super::{Database, PersistenceError};
+
+use crate::models::{MessageRow, UserGalaxyAccountRow};
+
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+const MESSAGE_QUERY: &str = r#"
+    SELECT m.id, m.galaxy_name, m.sender_id, s.account_name AS sender_name, m.recipient_id,
+        r.account_name AS recipient_name, m.subject, m.body, m.sent_at, m.read_at
+    FROM messages m
+    JOIN user_galaxy_accounts s ON s.id = m.sender_id
+    JOIN user_galaxy_accounts r ON r.id = m.recipient_id
+"#;
+
+fn message_row(row: SqliteRow) -> MessageRow {
+    MessageRow {
+        id: row.get("id"),
+        galaxy_name: row.get("galaxy_name"),
+        sender_id: row.get("sender_id"),
+        sender_name: row.get("sender_name"),
+        recipient_id: row.get("recipient_id"),
+        recipient_name: row.get("recipient_name"),
+        subject: row.get("subject"),
+        body: row.get("body"),
+        sent_at: row.get("sent_at"),
+        read_at: row.get("read_at"),
+    }
+}
+
+impl Database {
+    /// Send a message from one galaxy account to another
+    pub async fn send_message(
+        &self,
+        galaxy_name: &str,
+        sender_id: i64,
+        recipient_id: i64,
+        subject: &str,
+        body: &str,
+    ) -> Result<i64, PersistenceError> {
+        let id: i64 = sqlx::query(
+            "INSERT INTO messages (galaxy_name, sender_id, recipient_id, subject, body) VALUES (?, ?, ?, ?, ?) RETURNING id",
+        )
+        .bind(galaxy_name)
+        .bind(sender_id)
+        .bind(recipient_id)
+        .bind(subject)
+        .bind(body)
+        .fetch_one(&self.pool)
+        .await?
+        .get("id");
+
+        Ok(id)
+    }
+
+    /// Get a message by id, as long as one side still has it
+    pub async fn get_message(
+        &self,
+        message_id: i64,
+    ) -> Result<Option<MessageRow>, PersistenceError> {
+        let query = format!("{} WHERE m.id = ?", MESSAGE_QUERY);
+        let result = sqlx::query(&query)
+            .bind(message_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result.map(message_row))
+    }
+
+    /// Get a message an account sent or received, as long as it hasn't deleted it
+    pub async fn get_account_message(
+        &self,
+        message_id: i64,
+        account_id: i64,
+    ) -> Result<Option<MessageRow>, PersistenceError> {
+        let query = format!(
+            "{} WHERE m.id = ? AND ((m.recipient_id = ? AND NOT m.recipient_deleted) OR (m.sender_id = ? AND NOT m.sender_deleted))",
+            MESSAGE_QUERY
+        );
+        let result = sqlx::query(&query)
+            .bind(message_id)
+            .bind(account_id)
+            .bind(account_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result.map(message_row))
+    }
+
+    /// Get the messages an account received and hasn't deleted, newest first
+    pub async fn get_inbox(
+        &self,
+        account_id: i64,
+        limit: usize,
+    ) -> Result<Vec<MessageRow>, PersistenceError> {
+        let query = format!(
+            "{} WHERE m.recipient_id = ? AND NOT m.recipient_deleted ORDER BY m.id DESC LIMIT ?",
+            MESSAGE_QUERY
+        );
+        let rows = sqlx::query(&query)
+            .bind(account_id)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(message_row).collect())
+    }
+
+    /// Get the messages an account sent and hasn't deleted, newest first
+    pub async fn get_outbox(
+        &self,
+        account_id: i64,
+        limit: usize,
+    ) -> Result<Vec<MessageRow>, PersistenceError> {
+        let query = format!(
+            "{} WHERE m.sender_id = ? AND NOT m.sender_deleted ORDER BY m.id DESC LIMIT ?",
+            MESSAGE_QUERY
+        );
+        let rows = sqlx::query(&query)
+            .bind(account_id)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(message_row).collect())
+    }
+
+    /// Count the messages in an account's inbox it hasn't read
+    pub async fn count_unread_messages(&self, account_id: i64) -> Result<i64, PersistenceError> {
+        let result = sqlx::query(
+            "SELECT COUNT(*) as count FROM messages WHERE recipient_id = ? AND NOT recipient_deleted AND read_at IS NULL",
+        )
+        .bind(account_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(result.get("count"))
+    }
+
+    /// Mark a message as read or unread
+    pub async fn set_message_read(
+        &self,
+        message_id: i64,
+        read: bool,
+    ) -> Result<(), PersistenceError> {
+        let query = if read {
+            "UPDATE messages SET read_at = CURRENT_TIMESTAMP WHERE id = ? AND read_at IS NULL"
+        } else {
+            "UPDATE messages SET read_at = NULL WHERE id = ?"
+        };
+        sqlx::query(query)
+            .bind(message_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Delete an account's copy of a message
+    ///
+    /// The message is removed for good once both sides deleted it. Returns false if the account
+    /// didn't have the message.
+    pub async fn delete_message(
+        &self,
+        message_id: i64,
+        account_id: i64,
+    ) -> Result<bool, PersistenceError> {
+        let mut tx = self.pool.begin().await?;
+
+        let sent = sqlx::query(
+            "UPDATE messages SET sender_deleted = TRUE WHERE id = ? AND sender_id = ? AND NOT sender_deleted",
+        )
+        .bind(message_id)
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        let received = sqlx::query(
+            "UPDATE messages SET recipient_deleted = TRUE WHERE id = ? AND recipient_id = ? AND NOT recipient_deleted",
+        )
+        .bind(message_id)
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        sqlx::query("DELETE FROM messages WHERE id = ? AND sender_deleted AND recipient_deleted")
+            .bind(message_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(sent + received > 0)
+    }
+
+    /// Block an account from messaging another
+    ///
+    /// Returns false if it was already blocked.
+    pub async fn block_account(
+        &self,
+        account_id: i64,
+        blocked_id: i64,
+    ) -> Result<bool, PersistenceError> {
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO account_blocks (user_galaxy_account_id, blocked_account_id) VALUES (?, ?)",
+        )
+        .bind(account_id)
+        .bind(blocked_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Unblock an account
+    ///
+    /// Returns false if it wasn't blocked.
+    pub async fn unblock_account(
+        &self,
+        account_id: i64,
+        blocked_id: i64,
+    ) -> Result<bool, PersistenceError> {
+        let result = sqlx::query(
+            "DELETE FROM account_blocks WHERE user_galaxy_account_id = ? AND blocked_account_id = ?",
+        )
+        .bind(account_id)
+        .bind(blocked_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Check if an account has blocked another
+    pub async fn is_account_blocked(
+        &self,
+        account_id: i64,
+        blocked_id: i64,
+    ) -> Result<bool, PersistenceError> {
+        let result = sqlx::query(
+            "SELECT COUNT(*) as count FROM account_blocks WHERE user_galaxy_account_id = ? AND blocked_account_id = ?",
+        )
+        .bind(account_id)
+        .bind(blocked_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let count: i64 = result.get("count");
+        Ok(count > 0)
+    }
+
+    /// Get the accounts an account has blocked
+    pub async fn get_blocked_accounts(
+        &self,
+        account_id: i64,
+    ) -> Result<Vec<UserGalaxyAccountRow>, PersistenceError> {
+        let query = format!(
+            "SELECT {} FROM user_galaxy_accounts WHERE id IN (SELECT blocked_account_id FROM account_blocks WHERE user_galaxy_account_id = ?) ORDER BY account_name",
+            ACCOUNT_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(account_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(account_row).collect())
+    }
+}
@@ -2,11 +2,20 @@ use std::env;
 
 use sqlx::{Pool, Sqlite, SqlitePool};
 
+pub mod alliances;
+pub mod api_tokens;
+pub mod chat;
+pub mod diplomacy;
 pub mod events;
 pub mod galaxies;
+pub mod mail;
+pub mod messages;
+pub mod notifications;
+pub mod scores;
 pub mod structures;
 pub mod systems;
 pub mod users;
+pub mod webhooks;
 
 // Error types for database operations
 
@@ -26,6 +35,9 @@ pub enum PersistenceError {
 
     #[error("Migration error: {0}")]
     Migration(String),
+
+    #[error("Galaxy generation error: {0}")]
+    Generation(String),
 }
 
 /// Database connection manager
@@ -0,0 +1,134 @@
+use super::{Database, PersistenceError};
+
+use crate::models::NotificationRow;
+
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+const NOTIFICATION_COLUMNS: &str =
+    "id, user_galaxy_account_id, kind, tick, system_x, system_y, message, created_at, read_at";
+
+fn notification_row(row: SqliteRow) -> NotificationRow {
+    NotificationRow {
+        id: row.get("id"),
+        user_galaxy_account_id: row.get("user_galaxy_account_id"),
+        kind: row.get("kind"),
+        tick: row.get("tick"),
+        system_x: row.get("system_x"),
+        system_y: row.get("system_y"),
+        message: row.get("message"),
+        created_at: row.get("created_at"),
+        read_at: row.get("read_at"),
+    }
+}
+
+impl Database {
+    /// Store a notification, ignoring its id, creation and read time
+    pub async fn add_notification(
+        &self,
+        notification: &NotificationRow,
+    ) -> Result<i64, PersistenceError> {
+        let id = sqlx::query(
+            "INSERT INTO notifications (user_galaxy_account_id, kind, tick, system_x, system_y, message) VALUES (?, ?, ?, ?, ?, ?) RETURNING id",
+        )
+        .bind(notification.user_galaxy_account_id)
+        .bind(&notification.kind)
+        .bind(notification.tick)
+        .bind(notification.system_x)
+        .bind(notification.system_y)
+        .bind(&notification.message)
+        .fetch_one(&self.pool)
+        .await?
+        .get("id");
+
+        Ok(id)
+    }
+
+    /// Get the latest notifications of an account, newest first
+    pub async fn get_notifications(
+        &self,
+        account_id: i64,
+        unread_only: bool,
+        limit: usize,
+    ) -> Result<Vec<NotificationRow>, PersistenceError> {
+        let query = format!(
+            "SELECT {} FROM notifications WHERE user_galaxy_account_id = ? AND (NOT ? OR read_at IS NULL) ORDER BY tick DESC, id DESC LIMIT ?",
+            NOTIFICATION_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(account_id)
+            .bind(unread_only)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(notification_row).collect())
+    }
+
+    /// Count the unread notifications of an account
+    pub async fn count_unread_notifications(
+        &self,
+        account_id: i64,
+    ) -> Result<i64, PersistenceError> {
+        let result = sqlx::query(
+            "SELECT COUNT(*) as count FROM notifications WHERE user_galaxy_account_id = ? AND read_at IS NULL",
+        )
+        .bind(account_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(result.get("count"))
+    }
+
+    /// Mark a notification of an account as read
+    ///
+    /// Returns false if the account has no such notification.
+    pub async fn set_notification_read(
+        &self,
+        notification_id: i64,
+        account_id: i64,
+    ) -> Result<bool, PersistenceError> {
+        let result = sqlx::query(
+            "UPDATE notifications SET read_at = COALESCE(read_at, CURRENT_TIMESTAMP) WHERE id = ? AND user_galaxy_account_id = ?",
+        )
+        .bind(notification_id)
+        .bind(account_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Mark every notification of an account as read, returning how many were unread
+    pub async fn set_all_notifications_read(
+        &self,
+        account_id: i64,
+    ) -> Result<u64, PersistenceError> {
+        let result = sqlx::query(
+            "UPDATE notifications SET read_at = CURRENT_TIMESTAMP WHERE user_galaxy_account_id = ? AND read_at IS NULL",
+        )
+        .bind(account_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Delete the notifications of an account beyond the newest ones
+    pub async fn delete_old_notifications(
+        &self,
+        account_id: i64,
+        keep: usize,
+    ) -> Result<(), PersistenceError> {
+        sqlx::query(
+            "DELETE FROM notifications WHERE user_galaxy_account_id = ? AND id NOT IN (SELECT id FROM notifications WHERE user_galaxy_account_id = ? ORDER BY tick DESC, id DESC LIMIT ?)",
+        )
+        .bind(account_id)
+        .bind(account_id)
+        .bind(keep as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
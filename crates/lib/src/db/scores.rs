@@ -0,0 +1,121 @@
+use super::{Database, PersistenceError};
+
+use std::collections::HashMap;
+
+use crate::lifecycle::Standing;
+use crate::models::ScoreHistoryRow;
+use crate::scoring::Scores;
+
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+const SELECT_HISTORY: &str = r#"
+    SELECT h.galaxy_name, h.tick, h.user_galaxy_account_id, a.account_name, h.rank, h.score, h.systems,
+        h.economy, h.military, h.defense
+    FROM score_history h
+    JOIN user_galaxy_accounts a ON a.id = h.user_galaxy_account_id
+"#;
+
+fn history_row(row: SqliteRow) -> ScoreHistoryRow {
+    ScoreHistoryRow {
+        galaxy_name: row.get("galaxy_name"),
+        tick: row.get("tick"),
+        user_galaxy_account_id: row.get("user_galaxy_account_id"),
+        account_name: row.get("account_name"),
+        rank: row.get("rank"),
+        score: row.get("score"),
+        systems: row.get("systems"),
+        economy: row.get("economy"),
+        military: row.get("military"),
+        defense: row.get("defense"),
+    }
+}
+
+impl Database {
+    /// Store the standings of a galaxy at a tick, replacing any snapshot taken at the same tick
+    ///
+    /// `scores` has the score of every category per account.
+    pub async fn save_score_snapshot(
+        &self,
+        galaxy_name: &str,
+        tick: usize,
+        standings: &[Standing],
+        scores: &HashMap<i64, Scores>,
+    ) -> Result<(), PersistenceError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM score_history WHERE galaxy_name = ? AND tick = ?")
+            .bind(galaxy_name)
+            .bind(tick as i64)
+            .execute(&mut *tx)
+            .await?;
+
+        for standing in standings {
+            let categories = scores
+                .get(&standing.account_id)
+                .copied()
+                .unwrap_or_default();
+            sqlx::query(
+                "INSERT INTO score_history (galaxy_name, tick, user_galaxy_account_id, rank, score, systems, economy, military, defense) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(galaxy_name)
+            .bind(tick as i64)
+            .bind(standing.account_id)
+            .bind(standing.rank as i64)
+            .bind(standing.score as i64)
+            .bind(standing.systems as i64)
+            .bind(categories.economy as i64)
+            .bind(categories.military as i64)
+            .bind(categories.defense as i64)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Get the latest snapshot taken at or before a tick, best first
+    ///
+    /// Empty if there is no snapshot that old.
+    pub async fn get_score_snapshot(
+        &self,
+        galaxy_name: &str,
+        tick: usize,
+    ) -> Result<Vec<ScoreHistoryRow>, PersistenceError> {
+        let query = format!(
+            "{} WHERE h.galaxy_name = ? AND h.tick = (SELECT MAX(tick) FROM score_history WHERE galaxy_name = ? AND tick <= ?) ORDER BY h.rank",
+            SELECT_HISTORY
+        );
+        let rows = sqlx::query(&query)
+            .bind(galaxy_name)
+            .bind(galaxy_name)
+            .bind(tick as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(history_row).collect())
+    }
+
+    /// Get every snapshot since a tick, oldest first, optionally for a single account
+    pub async fn get_score_history(
+        &self,
+        galaxy_name: &str,
+        account_id: Option<i64>,
+        since: usize,
+    ) -> Result<Vec<ScoreHistoryRow>, PersistenceError> {
+        let query = format!(
+            "{} WHERE h.galaxy_name = ? AND h.tick >= ? AND (? IS NULL OR h.user_galaxy_account_id = ?) ORDER BY h.tick, h.rank",
+            SELECT_HISTORY
+        );
+        let rows = sqlx::query(&query)
+            .bind(galaxy_name)
+            .bind(since as i64)
+            .bind(account_id)
+            .bind(account_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(history_row).collect())
+    }
+}
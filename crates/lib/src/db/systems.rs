@@ -163,6 +163,36 @@ impl Database {
         Ok(systems)
     }
 
+    /// Get systems owned by any user in a galaxy
+    pub async fn get_owned_systems(
+        &self,
+        galaxy_name: &str,
+    ) -> Result<Vec<SystemRow>, PersistenceError> {
+        let rows = sqlx::query("SELECT id, galaxy_name, x, y, metal, crew, water, current_tick, user_galaxy_account_id, created_at, updated_at FROM systems WHERE galaxy_name = ? AND user_galaxy_account_id IS NOT NULL")
+            .bind(galaxy_name)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut systems = Vec::new();
+        for row in rows {
+            systems.push(SystemRow {
+                id: row.get("id"),
+                galaxy_name: row.get("galaxy_name"),
+                x: row.get("x"),
+                y: row.get("y"),
+                metal: row.get("metal"),
+                crew: row.get("crew"),
+                water: row.get("water"),
+                current_tick: row.get("current_tick"),
+                user_galaxy_account_id: row.get("user_galaxy_account_id"),
+                created_at: row.get("created_at"),
+                updated_at: row.get("updated_at"),
+            });
+        }
+
+        Ok(systems)
+    }
+
     /// Assign a system to a user
     pub async fn assign_system_to_user(
         &self,
@@ -336,6 +366,49 @@ mod tests {
         db.close().await;
     }
 
+    #[tokio::test]
+    async fn test_owned_and_unowned_systems() {
+        let db = Database::new_test()
+            .await
+            .expect("Failed to create test database");
+        let galaxy_name = "ownership_galaxy";
+
+        setup_test_galaxy(&db, galaxy_name).await;
+
+        let user_id = db
+            .create_user("owner", "owner@example.com", "password")
+            .await
+            .expect("Failed to create user");
+        let account_id = db
+            .create_user_galaxy_account(user_id, galaxy_name, "Owner", None)
+            .await
+            .expect("Failed to create galaxy account");
+
+        let resources = crate::Resources::default();
+        db.save_system(galaxy_name, 1, 1, &resources, 0, Some(account_id))
+            .await
+            .expect("Failed to save owned system");
+        db.save_system(galaxy_name, 2, 2, &resources, 0, None)
+            .await
+            .expect("Failed to save unowned system");
+
+        let owned = db
+            .get_owned_systems(galaxy_name)
+            .await
+            .expect("Failed to get owned systems");
+        assert_eq!(owned.len(), 1);
+        assert_eq!(owned[0].coords(), (1, 1));
+
+        let unowned = db
+            .get_unowned_systems(galaxy_name)
+            .await
+            .expect("Failed to get unowned systems");
+        assert_eq!(unowned.len(), 1);
+        assert_eq!(unowned[0].coords(), (2, 2));
+
+        db.close().await;
+    }
+
     #[tokio::test]
     async fn test_system_not_found() {
         let db = Database::new_test()
@@ -4,8 +4,26 @@ use crate::models::{UserGalaxyAccountRow, UserRow, UserSessionRow};
 
 use chrono::{DateTime, Utc};
 
+use sqlx::sqlite::SqliteRow;
 use sqlx::Row;
 
+/// Columns of a galaxy account, in the order read by `account_row`
+pub(super) const ACCOUNT_COLUMNS: &str = "id, user_id, galaxy_name, account_name, joined_at, last_active, ai_strategy, alliance_id, alliance_role";
+
+pub(super) fn account_row(row: SqliteRow) -> UserGalaxyAccountRow {
+    UserGalaxyAccountRow {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        galaxy_name: row.get("galaxy_name"),
+        account_name: row.get("account_name"),
+        joined_at: row.get("joined_at"),
+        last_active: row.get("last_active"),
+        ai_strategy: row.get("ai_strategy"),
+        alliance_id: row.get("alliance_id"),
+        alliance_role: row.get("alliance_role"),
+    }
+}
+
 impl Database {
     /// Create a new user account
     pub async fn create_user(
@@ -99,23 +117,25 @@ impl Database {
         }
     }
 
-    /// Create a galaxy account for a user
+    /// Create a galaxy account for a user, played by the AI if a strategy is given
     pub async fn create_user_galaxy_account(
         &self,
         user_id: i64,
         galaxy_name: &str,
         account_name: &str,
+        ai_strategy: Option<&str>,
     ) -> Result<i64, PersistenceError> {
         let result = sqlx::query(
             r#"
-            INSERT INTO user_galaxy_accounts (user_id, galaxy_name, account_name, last_active)
-            VALUES (?, ?, ?, CURRENT_TIMESTAMP)
+            INSERT INTO user_galaxy_accounts (user_id, galaxy_name, account_name, ai_strategy, last_active)
+            VALUES (?, ?, ?, ?, CURRENT_TIMESTAMP)
             RETURNING id
             "#,
         )
         .bind(user_id)
         .bind(galaxy_name)
         .bind(account_name)
+        .bind(ai_strategy)
         .fetch_one(&self.pool)
         .await?;
 
@@ -129,7 +149,7 @@ impl Database {
         galaxy_name: &str,
     ) -> Result<Option<UserGalaxyAccountRow>, PersistenceError> {
         let result = sqlx::query(
-            "SELECT id, user_id, galaxy_name, account_name, joined_at, last_active FROM user_galaxy_accounts WHERE user_id = ? AND galaxy_name = ?"
+            "SELECT id, user_id, galaxy_name, account_name, joined_at, last_active, ai_strategy, alliance_id, alliance_role FROM user_galaxy_accounts WHERE user_id = ? AND galaxy_name = ?"
         )
         .bind(user_id)
         .bind(galaxy_name)
@@ -137,14 +157,7 @@ impl Database {
         .await?;
 
         if let Some(row) = result {
-            Ok(Some(UserGalaxyAccountRow {
-                id: row.get("id"),
-                user_id: row.get("user_id"),
-                galaxy_name: row.get("galaxy_name"),
-                account_name: row.get("account_name"),
-                joined_at: row.get("joined_at"),
-                last_active: row.get("last_active"),
-            }))
+            Ok(Some(account_row(row)))
         } else {
             Ok(None)
         }
@@ -156,25 +169,64 @@ impl Database {
         user_id: i64,
     ) -> Result<Vec<UserGalaxyAccountRow>, PersistenceError> {
         let rows = sqlx::query(
-            "SELECT id, user_id, galaxy_name, account_name, joined_at, last_active FROM user_galaxy_accounts WHERE user_id = ? ORDER BY last_active DESC"
+            "SELECT id, user_id, galaxy_name, account_name, joined_at, last_active, ai_strategy, alliance_id, alliance_role FROM user_galaxy_accounts WHERE user_id = ? ORDER BY last_active DESC"
         )
         .bind(user_id)
         .fetch_all(&self.pool)
         .await?;
 
-        let mut accounts = Vec::new();
-        for row in rows {
-            accounts.push(UserGalaxyAccountRow {
-                id: row.get("id"),
-                user_id: row.get("user_id"),
-                galaxy_name: row.get("galaxy_name"),
-                account_name: row.get("account_name"),
-                joined_at: row.get("joined_at"),
-                last_active: row.get("last_active"),
-            });
-        }
+        Ok(rows.into_iter().map(account_row).collect())
+    }
+
+    /// Get all accounts in a galaxy
+    pub async fn get_galaxy_accounts(
+        &self,
+        galaxy_name: &str,
+    ) -> Result<Vec<UserGalaxyAccountRow>, PersistenceError> {
+        let rows = sqlx::query(
+            "SELECT id, user_id, galaxy_name, account_name, joined_at, last_active, ai_strategy, alliance_id, alliance_role FROM user_galaxy_accounts WHERE galaxy_name = ? ORDER BY id"
+        )
+        .bind(galaxy_name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(account_row).collect())
+    }
+
+    /// Get a galaxy account by its id
+    pub async fn get_galaxy_account(
+        &self,
+        account_id: i64,
+    ) -> Result<Option<UserGalaxyAccountRow>, PersistenceError> {
+        let query = format!(
+            "SELECT {} FROM user_galaxy_accounts WHERE id = ?",
+            ACCOUNT_COLUMNS
+        );
+        let result = sqlx::query(&query)
+            .bind(account_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result.map(account_row))
+    }
+
+    /// Get a galaxy account by its display name
+    pub async fn get_galaxy_account_by_name(
+        &self,
+        galaxy_name: &str,
+        account_name: &str,
+    ) -> Result<Option<UserGalaxyAccountRow>, PersistenceError> {
+        let query = format!(
+            "SELECT {} FROM user_galaxy_accounts WHERE galaxy_name = ? AND account_name = ?",
+            ACCOUNT_COLUMNS
+        );
+        let result = sqlx::query(&query)
+            .bind(galaxy_name)
+            .bind(account_name)
+            .fetch_optional(&self.pool)
+            .await?;
 
-        Ok(accounts)
+        Ok(result.map(account_row))
     }
 
     /// Update user's last active time in a galaxy
@@ -357,7 +409,7 @@ mod tests {
 
         // Test galaxy account creation
         let account_id = db
-            .create_user_galaxy_account(user_id, galaxy_name, account_name)
+            .create_user_galaxy_account(user_id, galaxy_name, account_name, None)
             .await
             .expect("Failed to create galaxy account");
 
@@ -0,0 +1,215 @@
+use super::{Database, PersistenceError};
+
+use crate::models::{WebhookDeliveryRow, WebhookRow};
+
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+
+const WEBHOOK_COLUMNS: &str = "id, user_id, url, secret, events, created_at";
+
+const DELIVERY_COLUMNS: &str = "id, webhook_id, event, payload, status, attempts, next_attempt_at, response_status, error, created_at, delivered_at";
+
+fn webhook_row(row: SqliteRow) -> WebhookRow {
+    WebhookRow {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        url: row.get("url"),
+        secret: row.get("secret"),
+        events: row.get("events"),
+        created_at: row.get("created_at"),
+    }
+}
+
+fn delivery_row(row: SqliteRow) -> WebhookDeliveryRow {
+    WebhookDeliveryRow {
+        id: row.get("id"),
+        webhook_id: row.get("webhook_id"),
+        event: row.get("event"),
+        payload: row.get("payload"),
+        status: row.get("status"),
+        attempts: row.get("attempts"),
+        next_attempt_at: row.get("next_attempt_at"),
+        response_status: row.get("response_status"),
+        error: row.get("error"),
+        created_at: row.get("created_at"),
+        delivered_at: row.get("delivered_at"),
+    }
+}
+
+impl Database {
+    /// Register a webhook of a user
+    pub async fn create_webhook(
+        &self,
+        user_id: i64,
+        url: &str,
+        secret: &str,
+        events: &str,
+    ) -> Result<i64, PersistenceError> {
+        let id = sqlx::query(
+            "INSERT INTO webhooks (user_id, url, secret, events) VALUES (?, ?, ?, ?) RETURNING id",
+        )
+        .bind(user_id)
+        .bind(url)
+        .bind(secret)
+        .bind(events)
+        .fetch_one(&self.pool)
+        .await?
+        .get("id");
+
+        Ok(id)
+    }
+
+    /// Get the webhooks of a user, oldest first
+    pub async fn get_webhooks(&self, user_id: i64) -> Result<Vec<WebhookRow>, PersistenceError> {
+        let query = format!(
+            "SELECT {} FROM webhooks WHERE user_id = ? ORDER BY id",
+            WEBHOOK_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(webhook_row).collect())
+    }
+
+    /// Get a webhook by id
+    pub async fn get_webhook(
+        &self,
+        webhook_id: i64,
+    ) -> Result<Option<WebhookRow>, PersistenceError> {
+        let query = format!("SELECT {} FROM webhooks WHERE id = ?", WEBHOOK_COLUMNS);
+        let row = sqlx::query(&query)
+            .bind(webhook_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(webhook_row))
+    }
+
+    /// Delete a webhook of a user with its deliveries
+    ///
+    /// Returns false if the user has no such webhook.
+    pub async fn delete_webhook(
+        &self,
+        webhook_id: i64,
+        user_id: i64,
+    ) -> Result<bool, PersistenceError> {
+        let mut tx = self.pool.begin().await?;
+
+        let deleted = sqlx::query("DELETE FROM webhooks WHERE id = ? AND user_id = ?")
+            .bind(webhook_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        if deleted > 0 {
+            sqlx::query("DELETE FROM webhook_deliveries WHERE webhook_id = ?")
+                .bind(webhook_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(deleted > 0)
+    }
+
+    /// Queue a payload for a webhook, due right away
+    pub async fn add_webhook_delivery(
+        &self,
+        webhook_id: i64,
+        event: &str,
+        payload: &str,
+    ) -> Result<i64, PersistenceError> {
+        let id = sqlx::query(
+            "INSERT INTO webhook_deliveries (webhook_id, event, payload) VALUES (?, ?, ?) RETURNING id",
+        )
+        .bind(webhook_id)
+        .bind(event)
+        .bind(payload)
+        .fetch_one(&self.pool)
+        .await?
+        .get("id");
+
+        Ok(id)
+    }
+
+    /// Get the pending deliveries whose next attempt is due at a tick, oldest first
+    pub async fn get_due_webhook_deliveries(
+        &self,
+        tick: usize,
+        limit: usize,
+    ) -> Result<Vec<WebhookDeliveryRow>, PersistenceError> {
+        let query = format!(
+            "SELECT {} FROM webhook_deliveries WHERE status = 'pending' AND next_attempt_at <= ? ORDER BY next_attempt_at, id LIMIT ?",
+            DELIVERY_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(tick as i64)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(delivery_row).collect())
+    }
+
+    /// Record the outcome of an attempt at a delivery
+    ///
+    /// The delivery time is set when its status becomes delivered.
+    pub async fn update_webhook_delivery(
+        &self,
+        delivery: &WebhookDeliveryRow,
+    ) -> Result<(), PersistenceError> {
+        sqlx::query(
+            "UPDATE webhook_deliveries SET status = ?, attempts = ?, next_attempt_at = ?, response_status = ?, error = ?, delivered_at = CASE WHEN ? = 'delivered' THEN CURRENT_TIMESTAMP ELSE delivered_at END WHERE id = ?",
+        )
+        .bind(&delivery.status)
+        .bind(delivery.attempts)
+        .bind(delivery.next_attempt_at)
+        .bind(delivery.response_status)
+        .bind(&delivery.error)
+        .bind(&delivery.status)
+        .bind(delivery.id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get the latest deliveries of a webhook, newest first
+    pub async fn get_webhook_deliveries(
+        &self,
+        webhook_id: i64,
+        limit: usize,
+    ) -> Result<Vec<WebhookDeliveryRow>, PersistenceError> {
+        let query = format!(
+            "SELECT {} FROM webhook_deliveries WHERE webhook_id = ? ORDER BY id DESC LIMIT ?",
+            DELIVERY_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(webhook_id)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(delivery_row).collect())
+    }
+
+    /// Delete the deliveries of a webhook beyond the newest ones
+    pub async fn delete_old_webhook_deliveries(
+        &self,
+        webhook_id: i64,
+        keep: usize,
+    ) -> Result<(), PersistenceError> {
+        sqlx::query(
+            "DELETE FROM webhook_deliveries WHERE webhook_id = ? AND id NOT IN (SELECT id FROM webhook_deliveries WHERE webhook_id = ? ORDER BY id DESC LIMIT ?)",
+        )
+        .bind(webhook_id)
+        .bind(webhook_id)
+        .bind(keep as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,918 @@
+/// Diplomacy between alliances
+///
+/// Alliances without a formal relation are neutral. Leaders and officers can declare war and
+/// cancel treaties on their own, while ceasefires, non-aggression pacts and peace have to be
+/// proposed to the other alliance and accepted before the proposal runs out. A ceasefire only
+/// holds for a while, after which the war resumes. Every change of relation is written to the
+/// galaxy's public diplomacy log, and [`DiplomacyService::check_attack`] refuses hostile
+/// actions between allies and between alliances that agreed not to fight.
+use std::collections::HashMap;
+
+use crate::alliance::{Alliance, AllianceError, AllianceService};
+use crate::db::{Database, PersistenceError};
+use crate::models::{AllianceRelationRow, DiplomacyLogRow, DiplomacyProposalRow};
+use crate::notifications::{NotificationError, NotificationKind, NotificationService};
+
+/// Ticks a proposal stays open before it runs out
+pub const PROPOSAL_DURATION: usize = 2 * 24 * 3600;
+
+/// Ticks a ceasefire holds before the war resumes
+pub const CEASEFIRE_DURATION: usize = 3 * 24 * 3600;
+
+/// Relation between two alliances
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Relation {
+    /// No formal relation
+    Neutral,
+    War,
+    /// A pause in a war, which resumes once it runs out
+    Ceasefire,
+    NonAggressionPact,
+    Peace,
+}
+
+impl Relation {
+    /// Relations that have to be proposed and accepted
+    pub const PROPOSABLE: [Relation; 3] = [
+        Relation::Ceasefire,
+        Relation::NonAggressionPact,
+        Relation::Peace,
+    ];
+
+    /// Whether members of the alliances may attack each other
+    pub fn allows_attacks(self) -> bool {
+        matches!(self, Relation::Neutral | Relation::War)
+    }
+
+    /// Whether a relation can be proposed from this one
+    ///
+    /// Wars end with a ceasefire or peace, and neutral alliances can agree to a pact. A pact can
+    /// be turned into peace.
+    pub fn can_propose(self, relation: Relation) -> bool {
+        match relation {
+            Relation::Ceasefire => self == Relation::War,
+            Relation::NonAggressionPact => self == Relation::Neutral,
+            Relation::Peace => matches!(
+                self,
+                Relation::War | Relation::Ceasefire | Relation::NonAggressionPact
+            ),
+            Relation::Neutral | Relation::War => false,
+        }
+    }
+
+    /// War can be declared on a neutral alliance or by breaking a ceasefire
+    ///
+    /// Pacts and peace have to be cancelled first.
+    pub fn can_declare_war(self) -> bool {
+        matches!(self, Relation::Neutral | Relation::Ceasefire)
+    }
+
+    /// Pacts and peace can be cancelled by either side, leaving the alliances neutral
+    pub fn can_cancel(self) -> bool {
+        matches!(self, Relation::NonAggressionPact | Relation::Peace)
+    }
+
+    /// Name of the relation for players
+    pub fn describe(self) -> &'static str {
+        match self {
+            Relation::Neutral => "neutral",
+            Relation::War => "war",
+            Relation::Ceasefire => "ceasefire",
+            Relation::NonAggressionPact => "non-aggression pact",
+            Relation::Peace => "peace",
+        }
+    }
+}
+
+impl std::fmt::Display for Relation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Relation::Neutral => write!(f, "neutral"),
+            Relation::War => write!(f, "war"),
+            Relation::Ceasefire => write!(f, "ceasefire"),
+            Relation::NonAggressionPact => write!(f, "non_aggression_pact"),
+            Relation::Peace => write!(f, "peace"),
+        }
+    }
+}
+
+impl std::str::FromStr for Relation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "neutral" => Ok(Relation::Neutral),
+            "war" => Ok(Relation::War),
+            "ceasefire" => Ok(Relation::Ceasefire),
+            "non_aggression_pact" => Ok(Relation::NonAggressionPact),
+            "peace" => Ok(Relation::Peace),
+            _ => Err(format!("Unknown relation {}", s)),
+        }
+    }
+}
+
+/// Current relation of an alliance with another
+#[derive(Debug, Clone, PartialEq)]
+pub struct RelationStatus {
+    pub relation: Relation,
+    /// Tick the relation started
+    pub since: usize,
+    /// Tick a ceasefire runs out
+    pub expires_at: Option<usize>,
+}
+
+/// An open proposal from one alliance to another
+#[derive(Debug, Clone, PartialEq)]
+pub struct Proposal {
+    pub id: i64,
+    pub from_alliance: i64,
+    pub to_alliance: i64,
+    pub relation: Relation,
+    pub proposed_by: i64,
+    pub created_at: usize,
+    pub expires_at: usize,
+}
+
+impl From<DiplomacyProposalRow> for Proposal {
+    fn from(row: DiplomacyProposalRow) -> Self {
+        Proposal {
+            id: row.id,
+            from_alliance: row.from_alliance,
+            to_alliance: row.to_alliance,
+            relation: row.relation.parse().unwrap_or(Relation::Neutral),
+            proposed_by: row.proposed_by,
+            created_at: row.created_at as usize,
+            expires_at: row.expires_at as usize,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiplomacyError {
+    #[error("Database error: {0}")]
+    Database(#[from] PersistenceError),
+
+    #[error("{0}")]
+    Alliance(#[from] AllianceError),
+
+    #[error("{0}")]
+    Notification(#[from] NotificationError),
+
+    #[error("Only leaders and officers can conduct diplomacy")]
+    NotPermitted,
+
+    #[error("An alliance can't have relations with itself")]
+    SameAlliance,
+
+    #[error("Can't go from {} to {}", .from.describe(), .to.describe())]
+    InvalidTransition { from: Relation, to: Relation },
+
+    #[error("Already proposed {}", article(*.0))]
+    AlreadyProposed(Relation),
+
+    #[error("Proposal not found")]
+    ProposalNotFound,
+
+    #[error("Attacking an ally isn't allowed")]
+    AttackOnAlly,
+
+    #[error("Attacks aren't allowed after agreeing to {}", article(*.0))]
+    AttackRefused(Relation),
+}
+
+/// Service for relations between alliances
+pub struct DiplomacyService {
+    db: Database,
+}
+
+impl DiplomacyService {
+    /// Create a new diplomacy service
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// Bring the diplomacy of a galaxy up to a tick
+    ///
+    /// Proposals that ran out are dropped, and ceasefires that ran out return to war.
+    pub async fn update(&self, galaxy_name: &str, tick: usize) -> Result<(), DiplomacyError> {
+        self.db.delete_expired_proposals(galaxy_name, tick).await?;
+        for row in self.db.get_alliance_relations(galaxy_name).await? {
+            let status = status(&row);
+            let Some(expires_at) = status.expires_at.filter(|&expires_at| expires_at <= tick)
+            else {
+                continue;
+            };
+            if status.relation != Relation::Ceasefire {
+                continue;
+            }
+            let (a, b) = self.pair(row.alliance_a, row.alliance_b).await?;
+            self.change(
+                &a,
+                &b,
+                Relation::War,
+                expires_at,
+                "expired",
+                format!(
+                    "The ceasefire between {} and {} ran out, the war resumes",
+                    label(&a),
+                    label(&b)
+                ),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Get the relation between two alliances
+    pub async fn relation(
+        &self,
+        alliance_a: i64,
+        alliance_b: i64,
+        tick: usize,
+    ) -> Result<Relation, DiplomacyError> {
+        let alliance = self.alliance(alliance_a).await?;
+        self.update(&alliance.galaxy_name, tick).await?;
+        Ok(self.current(alliance_a, alliance_b).await?.relation)
+    }
+
+    /// Get the relations of an alliance with every other alliance it isn't neutral with
+    pub async fn relations(
+        &self,
+        alliance_id: i64,
+        tick: usize,
+    ) -> Result<HashMap<i64, RelationStatus>, DiplomacyError> {
+        let alliance = self.alliance(alliance_id).await?;
+        self.update(&alliance.galaxy_name, tick).await?;
+        Ok(self
+            .db
+            .get_alliance_relations(&alliance.galaxy_name)
+            .await?
+            .iter()
+            .filter_map(|row| {
+                let other = if row.alliance_a == alliance_id {
+                    row.alliance_b
+                } else if row.alliance_b == alliance_id {
+                    row.alliance_a
+                } else {
+                    return None;
+                };
+                Some((other, status(row)))
+            })
+            .collect())
+    }
+
+    /// Get every relation in a galaxy that isn't neutral, as pairs of alliance ids
+    pub async fn galaxy_relations(
+        &self,
+        galaxy_name: &str,
+        tick: usize,
+    ) -> Result<Vec<(i64, i64, RelationStatus)>, DiplomacyError> {
+        self.update(galaxy_name, tick).await?;
+        Ok(self
+            .db
+            .get_alliance_relations(galaxy_name)
+            .await?
+            .iter()
+            .map(|row| (row.alliance_a, row.alliance_b, status(row)))
+            .collect())
+    }
+
+    /// Get the open proposals an alliance made or received, oldest first
+    pub async fn proposals(
+        &self,
+        alliance_id: i64,
+        tick: usize,
+    ) -> Result<Vec<Proposal>, DiplomacyError> {
+        let alliance = self.alliance(alliance_id).await?;
+        self.update(&alliance.galaxy_name, tick).await?;
+        Ok(self
+            .db
+            .get_alliance_proposals(alliance_id)
+            .await?
+            .into_iter()
+            .map(Proposal::from)
+            .collect())
+    }
+
+    /// Propose a ceasefire, pact or peace to another alliance
+    pub async fn propose(
+        &self,
+        account_id: i64,
+        alliance_id: i64,
+        relation: Relation,
+        tick: usize,
+    ) -> Result<Proposal, DiplomacyError> {
+        let (own, other) = self.sides(account_id, alliance_id, tick).await?;
+        let current = self.current(own.id, other.id).await?.relation;
+        if !current.can_propose(relation) {
+            return Err(DiplomacyError::InvalidTransition {
+                from: current,
+                to: relation,
+            });
+        }
+        if self
+            .db
+            .get_alliance_proposals(own.id)
+            .await?
+            .iter()
+            .any(|proposal| {
+                proposal.from_alliance == own.id
+                    && proposal.to_alliance == other.id
+                    && proposal.relation == relation.to_string()
+            })
+        {
+            return Err(DiplomacyError::AlreadyProposed(relation));
+        }
+
+        let mut row = DiplomacyProposalRow {
+            id: 0,
+            galaxy_name: own.galaxy_name.clone(),
+            from_alliance: own.id,
+            to_alliance: other.id,
+            relation: relation.to_string(),
+            proposed_by: account_id,
+            created_at: tick as i64,
+            expires_at: (tick + PROPOSAL_DURATION) as i64,
+        };
+        row.id = self.db.create_diplomacy_proposal(&row).await?;
+        Ok(row.into())
+    }
+
+    /// Accept a proposal made to the alliance of an account
+    pub async fn accept(
+        &self,
+        account_id: i64,
+        proposal_id: i64,
+        tick: usize,
+    ) -> Result<Relation, DiplomacyError> {
+        let (proposal, own, other) = self.received(account_id, proposal_id, tick).await?;
+        let current = self.current(own.id, other.id).await?.relation;
+        if !current.can_propose(proposal.relation) {
+            return Err(DiplomacyError::InvalidTransition {
+                from: current,
+                to: proposal.relation,
+            });
+        }
+        let message = match proposal.relation {
+            Relation::Ceasefire => "agreed to a ceasefire",
+            Relation::NonAggressionPact => "signed a non-aggression pact",
+            _ => "made peace",
+        };
+        self.change(
+            &other,
+            &own,
+            proposal.relation,
+            tick,
+            "accepted",
+            format!("{} and {} {}", label(&other), label(&own), message),
+        )
+        .await?;
+        Ok(proposal.relation)
+    }
+
+    /// Reject a proposal made to the alliance of an account
+    ///
+    /// Rejections aren't logged publicly, the proposal is only dropped.
+    pub async fn reject(
+        &self,
+        account_id: i64,
+        proposal_id: i64,
+        tick: usize,
+    ) -> Result<(), DiplomacyError> {
+        let (proposal, _, _) = self.received(account_id, proposal_id, tick).await?;
+        self.db.delete_diplomacy_proposal(proposal.id).await?;
+        Ok(())
+    }
+
+    /// Declare war on another alliance
+    pub async fn declare_war(
+        &self,
+        account_id: i64,
+        alliance_id: i64,
+        tick: usize,
+    ) -> Result<(), DiplomacyError> {
+        let (own, other) = self.sides(account_id, alliance_id, tick).await?;
+        let current = self.current(own.id, other.id).await?.relation;
+        if !current.can_declare_war() {
+            return Err(DiplomacyError::InvalidTransition {
+                from: current,
+                to: Relation::War,
+            });
+        }
+        let message = if current == Relation::Ceasefire {
+            format!(
+                "{} broke the ceasefire with {}, the war resumes",
+                label(&own),
+                label(&other)
+            )
+        } else {
+            format!("{} declared war on {}", label(&own), label(&other))
+        };
+        self.change(&own, &other, Relation::War, tick, "declared_war", message)
+            .await
+    }
+
+    /// Cancel a pact or peace with another alliance, leaving them neutral
+    pub async fn cancel(
+        &self,
+        account_id: i64,
+        alliance_id: i64,
+        tick: usize,
+    ) -> Result<(), DiplomacyError> {
+        let (own, other) = self.sides(account_id, alliance_id, tick).await?;
+        let current = self.current(own.id, other.id).await?.relation;
+        if !current.can_cancel() {
+            return Err(DiplomacyError::InvalidTransition {
+                from: current,
+                to: Relation::Neutral,
+            });
+        }
+        let message = format!(
+            "{} cancelled its {} with {}",
+            label(&own),
+            current.describe(),
+            label(&other)
+        );
+        self.change(&own, &other, Relation::Neutral, tick, "cancelled", message)
+            .await
+    }
+
+    /// Get the latest entries of a galaxy's diplomacy log, newest first, optionally only those
+    /// involving an alliance
+    pub async fn log(
+        &self,
+        galaxy_name: &str,
+        alliance_id: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<DiplomacyLogRow>, DiplomacyError> {
+        Ok(self
+            .db
+            .get_diplomacy_log(galaxy_name, alliance_id, limit)
+            .await?)
+    }
+
+    /// Check if one account may attack another
+    ///
+    /// Members of the same alliance never fight, and neither do alliances in a ceasefire, pact
+    /// or peace. Accounts outside of alliances can always be attacked.
+    pub async fn check_attack(
+        &self,
+        attacker_id: i64,
+        defender_id: i64,
+        tick: usize,
+    ) -> Result<(), DiplomacyError> {
+        let alliances = AllianceService::new(self.db.clone());
+        if alliances.are_allies(attacker_id, defender_id).await? {
+            return Err(DiplomacyError::AttackOnAlly);
+        }
+        let attacker = alliances.alliance_of(attacker_id).await?;
+        let defender = alliances.alliance_of(defender_id).await?;
+        let (Some(attacker), Some(defender)) = (attacker, defender) else {
+            return Ok(());
+        };
+        let relation = self.relation(attacker.id, defender.id, tick).await?;
+        if !relation.allows_attacks() {
+            return Err(DiplomacyError::AttackRefused(relation));
+        }
+        Ok(())
+    }
+
+    /// The stored relation between two alliances, without bringing the galaxy up to date
+    async fn current(
+        &self,
+        alliance_a: i64,
+        alliance_b: i64,
+    ) -> Result<RelationStatus, DiplomacyError> {
+        Ok(
+            match self
+                .db
+                .get_alliance_relation(alliance_a, alliance_b)
+                .await?
+            {
+                Some(row) => status(&row),
+                None => RelationStatus {
+                    relation: Relation::Neutral,
+                    since: 0,
+                    expires_at: None,
+                },
+            },
+        )
+    }
+
+    /// Store a change of relation and log it publicly
+    async fn change(
+        &self,
+        a: &Alliance,
+        b: &Alliance,
+        relation: Relation,
+        tick: usize,
+        action: &str,
+        message: String,
+    ) -> Result<(), DiplomacyError> {
+        let stored = (relation != Relation::Neutral).then(|| relation.to_string());
+        let expires_at = (relation == Relation::Ceasefire).then_some(tick + CEASEFIRE_DURATION);
+        self.db
+            .set_alliance_relation(
+                &a.galaxy_name,
+                a.id,
+                b.id,
+                stored.as_deref(),
+                tick,
+                expires_at,
+            )
+            .await?;
+        self.db
+            .add_diplomacy_log(&DiplomacyLogRow {
+                id: 0,
+                galaxy_name: a.galaxy_name.clone(),
+                tick: tick as i64,
+                alliance_a: a.id,
+                alliance_b: b.id,
+                action: action.to_string(),
+                relation: relation.to_string(),
+                message: message.clone(),
+            })
+            .await?;
+
+        let notifications = NotificationService::new(self.db.clone());
+        for alliance in [a, b] {
+            for member in self.db.get_alliance_members(alliance.id).await? {
+                notifications
+                    .notify(member.id, NotificationKind::Diplomacy, tick, None, &message)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The alliance an account acts for and the other alliance, in the same galaxy
+    async fn sides(
+        &self,
+        account_id: i64,
+        alliance_id: i64,
+        tick: usize,
+    ) -> Result<(Alliance, Alliance), DiplomacyError> {
+        let own = self.diplomat(account_id).await?;
+        let other = self.alliance(alliance_id).await?;
+        if own.id == other.id {
+            return Err(DiplomacyError::SameAlliance);
+        }
+        if own.galaxy_name != other.galaxy_name {
+            return Err(AllianceError::AllianceNotFound.into());
+        }
+        self.update(&own.galaxy_name, tick).await?;
+        Ok((own, other))
+    }
+
+    /// A proposal made to the alliance of an account, with its own and the proposing alliance
+    async fn received(
+        &self,
+        account_id: i64,
+        proposal_id: i64,
+        tick: usize,
+    ) -> Result<(Proposal, Alliance, Alliance), DiplomacyError> {
+        let own = self.diplomat(account_id).await?;
+        self.update(&own.galaxy_name, tick).await?;
+        let proposal: Proposal = self
+            .db
+            .get_diplomacy_proposal(proposal_id)
+            .await?
+            .filter(|proposal| proposal.to_alliance == own.id)
+            .ok_or(DiplomacyError::ProposalNotFound)?
+            .into();
+        let other = self.alliance(proposal.from_alliance).await?;
+        Ok((proposal, own, other))
+    }
+
+    /// The alliance of an account that may conduct diplomacy for it
+    async fn diplomat(&self, account_id: i64) -> Result<Alliance, DiplomacyError> {
+        let alliance = AllianceService::new(self.db.clone())
+            .alliance_of(account_id)
+            .await?
+            .ok_or(AllianceError::NotInAlliance)?;
+        if !alliance
+            .role(account_id)
+            .is_some_and(|role| role.can_conduct_diplomacy())
+        {
+            return Err(DiplomacyError::NotPermitted);
+        }
+        Ok(alliance)
+    }
+
+    async fn alliance(&self, alliance_id: i64) -> Result<Alliance, DiplomacyError> {
+        Ok(AllianceService::new(self.db.clone())
+            .get(alliance_id)
+            .await?
+            .ok_or(AllianceError::AllianceNotFound)?)
+    }
+
+    async fn pair(
+        &self,
+        alliance_a: i64,
+        alliance_b: i64,
+    ) -> Result<(Alliance, Alliance), DiplomacyError> {
+        Ok((
+            self.alliance(alliance_a).await?,
+            self.alliance(alliance_b).await?,
+        ))
+    }
+}
+
+fn status(row: &AllianceRelationRow) -> RelationStatus {
+    RelationStatus {
+        relation: row.relation.parse().unwrap_or(Relation::Neutral),
+        since: row.since as usize,
+        expires_at: row.expires_at.map(|tick| tick as usize),
+    }
+}
+
+/// A relation as the object of a sentence, e.g. "a ceasefire"
+fn article(relation: Relation) -> String {
+    match relation {
+        Relation::Ceasefire | Relation::NonAggressionPact => format!("a {}", relation.describe()),
+        _ => relation.describe().to_string(),
+    }
+}
+
+fn label(alliance: &Alliance) -> String {
+    format!("[{}] {}", alliance.tag, alliance.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alliance::{Alliance, AllianceService};
+    use crate::test_utils::{self, join, CONFIG, START};
+    use crate::{AppState, Coords, UserGalaxyAccount};
+
+    #[test]
+    fn test_relation_transitions() {
+        use Relation::*;
+        assert!(Neutral.can_declare_war());
+        assert!(Ceasefire.can_declare_war());
+        assert!(!NonAggressionPact.can_declare_war());
+        assert!(!Peace.can_declare_war());
+
+        assert!(War.can_propose(Ceasefire));
+        assert!(War.can_propose(Peace));
+        assert!(!War.can_propose(NonAggressionPact));
+        assert!(Neutral.can_propose(NonAggressionPact));
+        assert!(!Neutral.can_propose(Ceasefire));
+        assert!(NonAggressionPact.can_propose(Peace));
+        assert!(!Peace.can_propose(War));
+
+        assert!(NonAggressionPact.can_cancel());
+        assert!(!War.can_cancel());
+
+        assert!(War.allows_attacks());
+        assert!(Neutral.allows_attacks());
+        assert!(!Ceasefire.allows_attacks());
+        assert!(!NonAggressionPact.allows_attacks());
+
+        for relation in [Neutral, War, Ceasefire, NonAggressionPact, Peace] {
+            assert_eq!(relation.to_string().parse::<Relation>(), Ok(relation));
+        }
+        assert!("truce".parse::<Relation>().is_err());
+    }
+
+    /// Ada leading the Star League, against the Red Fleet of Bo and Cy
+    struct Treaty {
+        app_state: AppState,
+        diplomacy: DiplomacyService,
+        star: Alliance,
+        red: Alliance,
+        ada: UserGalaxyAccount,
+        bo: UserGalaxyAccount,
+        cy: UserGalaxyAccount,
+        ada_home: Coords,
+        bo_home: Coords,
+    }
+
+    async fn setup() -> Treaty {
+        let (_, db, app_state) = test_utils::setup(CONFIG, &["treaty"]).await;
+        let (ada, ada_home) = join(&db, &app_state, "treaty", "Ada").await;
+        let (bo, bo_home) = join(&db, &app_state, "treaty", "Bo").await;
+        let (cy, _) = join(&db, &app_state, "treaty", "Cy").await;
+        let alliances = AllianceService::new(db.clone());
+        let star = alliances
+            .create(ada.id, "Star League", "STAR")
+            .await
+            .unwrap();
+        let red = alliances.create(bo.id, "Red Fleet", "RED").await.unwrap();
+        alliances.invite(bo.id, "Cy").await.unwrap();
+        alliances.accept(cy.id, red.id).await.unwrap();
+        Treaty {
+            app_state,
+            diplomacy: DiplomacyService::new(db),
+            star,
+            red,
+            ada,
+            bo,
+            cy,
+            ada_home,
+            bo_home,
+        }
+    }
+
+    /// Agree to a non-aggression pact at a tick
+    async fn pact(treaty: &Treaty, tick: usize) {
+        let proposal = treaty
+            .diplomacy
+            .propose(
+                treaty.ada.id,
+                treaty.red.id,
+                Relation::NonAggressionPact,
+                tick,
+            )
+            .await
+            .unwrap();
+        treaty
+            .diplomacy
+            .accept(treaty.bo.id, proposal.id, tick)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pact() {
+        let treaty = setup().await;
+        let Treaty {
+            app_state,
+            diplomacy,
+            star,
+            red,
+            ada,
+            bo,
+            cy,
+            ada_home,
+            bo_home,
+        } = &treaty;
+        let tick = START;
+
+        // Neutral alliances can fight, until they agree to a pact
+        assert_eq!(
+            diplomacy.relation(star.id, red.id, tick).await.unwrap(),
+            Relation::Neutral
+        );
+        app_state
+            .check_attack("treaty", *ada_home, *bo_home, tick)
+            .await
+            .unwrap();
+        let proposal = diplomacy
+            .propose(ada.id, red.id, Relation::NonAggressionPact, tick)
+            .await
+            .unwrap();
+        assert!(matches!(
+            diplomacy.accept(cy.id, proposal.id, tick).await,
+            Err(DiplomacyError::NotPermitted)
+        ));
+        assert!(matches!(
+            diplomacy.accept(ada.id, proposal.id, tick).await,
+            Err(DiplomacyError::ProposalNotFound)
+        ));
+        diplomacy
+            .accept(bo.id, proposal.id, tick + 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            diplomacy.relation(red.id, star.id, tick).await.unwrap(),
+            Relation::NonAggressionPact
+        );
+        assert!(diplomacy.proposals(star.id, tick).await.unwrap().is_empty());
+        assert!(app_state
+            .check_attack("treaty", *ada_home, *bo_home, tick)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_allies_never_attack() {
+        let treaty = setup().await;
+        assert!(matches!(
+            treaty
+                .diplomacy
+                .check_attack(treaty.bo.id, treaty.cy.id, START)
+                .await,
+            Err(DiplomacyError::AttackOnAlly)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_transitions() {
+        let treaty = setup().await;
+        let Treaty {
+            diplomacy,
+            star,
+            red,
+            ada,
+            bo,
+            ..
+        } = &treaty;
+
+        // There is no war to cease
+        assert!(matches!(
+            diplomacy
+                .propose(ada.id, red.id, Relation::Ceasefire, START)
+                .await,
+            Err(DiplomacyError::InvalidTransition { .. })
+        ));
+
+        // A pact has to be cancelled before going to war
+        pact(&treaty, START).await;
+        assert!(matches!(
+            diplomacy.declare_war(bo.id, star.id, START + 20).await,
+            Err(DiplomacyError::InvalidTransition { .. })
+        ));
+        diplomacy.cancel(bo.id, star.id, START + 20).await.unwrap();
+        diplomacy
+            .declare_war(bo.id, star.id, START + 30)
+            .await
+            .unwrap();
+        diplomacy
+            .check_attack(ada.id, treaty.cy.id, START + 30)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ceasefire_expires() {
+        let treaty = setup().await;
+        let Treaty {
+            diplomacy,
+            star,
+            red,
+            ada,
+            bo,
+            ..
+        } = &treaty;
+        diplomacy.declare_war(bo.id, star.id, START).await.unwrap();
+
+        // A ceasefire runs out and the war resumes
+        let proposal = diplomacy
+            .propose(ada.id, red.id, Relation::Ceasefire, START + 40)
+            .await
+            .unwrap();
+        diplomacy
+            .accept(bo.id, proposal.id, START + 50)
+            .await
+            .unwrap();
+        assert!(matches!(
+            diplomacy.check_attack(ada.id, bo.id, START + 60).await,
+            Err(DiplomacyError::AttackRefused(Relation::Ceasefire))
+        ));
+        let ended = START + 50 + CEASEFIRE_DURATION;
+        assert_eq!(
+            diplomacy.relation(star.id, red.id, ended).await.unwrap(),
+            Relation::War
+        );
+    }
+
+    #[tokio::test]
+    async fn test_log() {
+        let treaty = setup().await;
+        let Treaty {
+            diplomacy,
+            star,
+            red,
+            ada,
+            bo,
+            ..
+        } = &treaty;
+        pact(&treaty, START).await;
+        diplomacy.cancel(bo.id, star.id, START + 20).await.unwrap();
+        diplomacy
+            .declare_war(bo.id, star.id, START + 30)
+            .await
+            .unwrap();
+        let proposal = diplomacy
+            .propose(ada.id, red.id, Relation::Ceasefire, START + 40)
+            .await
+            .unwrap();
+        diplomacy
+            .accept(bo.id, proposal.id, START + 50)
+            .await
+            .unwrap();
+        diplomacy
+            .relation(star.id, red.id, START + 50 + CEASEFIRE_DURATION)
+            .await
+            .unwrap();
+
+        // Newest first
+        let log = diplomacy.log("treaty", None, 10).await.unwrap();
+        let actions: Vec<&str> = log.iter().map(|entry| entry.action.as_str()).collect();
+        assert_eq!(
+            actions,
+            [
+                "expired",
+                "accepted",
+                "declared_war",
+                "cancelled",
+                "accepted"
+            ]
+        );
+        assert_eq!(
+            log[3].message,
+            "[RED] Red Fleet cancelled its non-aggression pact with [STAR] Star League"
+        );
+    }
+}
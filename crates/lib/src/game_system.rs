@@ -1,7 +1,10 @@
 use core::panic;
 use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::config::{GalaxyConfig, StructureConfig, SystemConfig};
+use crate::scoring::{self, Scores};
 use crate::{Cost, Details, Resources, StructureInfo, SystemInfo, SystemProduction};
 use std::fmt;
 use std::str::FromStr;
@@ -20,6 +23,19 @@ pub struct System {
 
     /// List of structures in the system.
     structures: Vec<Structure>,
+
+    /// Builds completed since they were last taken, so the owner can be told
+    completed_builds: Vec<CompletedBuild>,
+}
+
+/// A structure upgrade that has finished
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompletedBuild {
+    pub structure: StructureType,
+    /// Level the structure reached
+    pub level: usize,
+    /// Tick the upgrade finished at
+    pub tick: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -28,21 +44,27 @@ struct Structure {
     level: usize,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
 pub struct Event {
+    /// Tick the event completes at
     pub completion: usize,
+    /// What happens when it completes
     pub action: EventCallback,
+    /// Structure the event is about, if any
     pub structure: Option<StructureType>,
 }
 
 pub type EventInfo = Event;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
 pub enum EventCallback {
     Build,
 }
 
-#[derive(Hash, Debug, Clone, Copy, PartialEq, Eq)]
+/// A kind of structure, named in lowercase like `asteroidmine` outside of Rust
+#[derive(Hash, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
 pub enum StructureType {
     Colony,
     AsteroidMine,
@@ -95,6 +117,7 @@ impl System {
             events: Vec::new(),
             resources,
             structures,
+            completed_builds: Vec::new(),
         }
     }
 
@@ -114,6 +137,7 @@ impl System {
             events,
             resources,
             structures,
+            completed_builds: Vec::new(),
         }
     }
 
@@ -214,6 +238,11 @@ impl System {
                 if let Some(structure) = event.structure {
                     let index = self.structure(structure).unwrap();
                     self.structures[index].level += 1;
+                    self.completed_builds.push(CompletedBuild {
+                        structure,
+                        level: self.structures[index].level,
+                        tick: event.completion,
+                    });
                 } else {
                     panic!("Structure event without StructureType");
                 }
@@ -255,6 +284,11 @@ impl System {
         }
     }
 
+    /// Take the builds completed since the last call, oldest first
+    pub fn take_completed_builds(&mut self) -> Vec<CompletedBuild> {
+        std::mem::take(&mut self.completed_builds)
+    }
+
     /// Register a new event
     /// The event will be sorted by the completion time
     pub fn register_event(&mut self, event: Event) {
@@ -300,34 +334,19 @@ impl System {
         let production = self.get_production(new_tick, galaxy_config);
         let storage = self.get_storage(new_tick, galaxy_config);
 
-        // Calculate how many complete production cycles occurred for each resource
-        let _tick_diff = new_tick - self.current_tick;
+        let produced = |rate: usize| produced(rate, self.current_tick, new_tick);
 
-        // Metal production: if production is X per hour, then every (3600/X) ticks we get 1 metal
         if production.metal > 0 {
-            let production_interval = 3600 / production.metal;
-            let start_cycle = self.current_tick / production_interval;
-            let end_cycle = new_tick / production_interval;
-            let cycles_completed = end_cycle - start_cycle;
-            self.resources.metal = (self.resources.metal + cycles_completed).min(storage.metal);
+            self.resources.metal =
+                (self.resources.metal + produced(production.metal)).min(storage.metal);
         }
-
-        // Water production
         if production.water > 0 {
-            let production_interval = 3600 / production.water;
-            let start_cycle = self.current_tick / production_interval;
-            let end_cycle = new_tick / production_interval;
-            let cycles_completed = end_cycle - start_cycle;
-            self.resources.water = (self.resources.water + cycles_completed).min(storage.water);
+            self.resources.water =
+                (self.resources.water + produced(production.water)).min(storage.water);
         }
-
-        // Crew production
         if production.crew > 0 {
-            let production_interval = 3600 / production.crew;
-            let start_cycle = self.current_tick / production_interval;
-            let end_cycle = new_tick / production_interval;
-            let cycles_completed = end_cycle - start_cycle;
-            self.resources.crew = (self.resources.crew + cycles_completed).min(storage.crew);
+            self.resources.crew =
+                (self.resources.crew + produced(production.crew)).min(storage.crew);
         }
 
         self.current_tick = new_tick;
@@ -335,14 +354,21 @@ impl System {
 
     /// Get the score of a system.
     ///
-    /// The score is the summation of every level of every structure in the system.
-    /// A structure with a level of 4 will contribute 1+2+3+4=10 to the score.
+    /// The score is the sum of every category of [`System::scores`].
     pub fn score(&mut self, tick: usize, galaxy_config: &GalaxyConfig) -> usize {
+        self.scores(tick, galaxy_config).total()
+    }
+
+    /// Get the score of a system per category.
+    ///
+    /// Structures are scored with the scoring model of the galaxy config, by default a
+    /// structure with a level of 4 will contribute 1+2+3+4=10 to the economy score.
+    pub fn scores(&mut self, tick: usize, galaxy_config: &GalaxyConfig) -> Scores {
         self.update_to_tick(tick, galaxy_config);
-        self.structures
-            .iter()
-            .map(|b| (1..=b.level).sum::<usize>())
-            .sum()
+        scoring::score(
+            galaxy_config,
+            self.structures.iter().map(|s| (s.name, s.level)),
+        )
     }
 
     /// Build a structure
@@ -360,7 +386,7 @@ impl System {
         if self.structure(structure).is_some() {
             // Verify if the structure can be built
             let cost = &System::get_structure_config(galaxy_config, structure)
-                .get_cost(self.structure_level(structure) + 1);
+                .get_cost(self.structure_level(structure) + 1, galaxy_config.speed());
             if self.resources >= cost.resources {
                 // Deduct the cost
                 self.resources = self.resources - cost.resources;
@@ -381,6 +407,56 @@ impl System {
         }
     }
 
+    /// Take resources from the system in a raid
+    ///
+    /// Raiders carry at most `capacity` resources in total and take at most `max_fraction` of
+    /// each resource, spread evenly over the resources. Returns what was taken.
+    pub fn raid(
+        &mut self,
+        tick: usize,
+        galaxy_config: &GalaxyConfig,
+        capacity: usize,
+        max_fraction: f64,
+    ) -> Resources {
+        self.update_to_tick(tick, galaxy_config);
+        let available = self.resources * max_fraction.clamp(0.0, 1.0);
+        let total = available.metal + available.crew + available.water;
+        let loot = if total > capacity {
+            // Round down so the raiders never carry more than their capacity
+            let ratio = capacity as f64 / total as f64;
+            let scale = |amount: usize| (amount as f64 * ratio) as usize;
+            Resources {
+                metal: scale(available.metal),
+                crew: scale(available.crew),
+                water: scale(available.water),
+            }
+        } else {
+            available
+        };
+        self.resources = self.resources - loot;
+        loot
+    }
+
+    /// Add resources to the system, up to its storage
+    pub fn add_resources(
+        &mut self,
+        tick: usize,
+        galaxy_config: &GalaxyConfig,
+        resources: Resources,
+    ) {
+        self.update_to_tick(tick, galaxy_config);
+        let storage = self.get_storage(tick, galaxy_config);
+        // Never drop resources that were already above the storage
+        let add = |current: usize, added: usize, storage: usize| {
+            (current + added).min(storage.max(current))
+        };
+        self.resources = Resources {
+            metal: add(self.resources.metal, resources.metal, storage.metal),
+            crew: add(self.resources.crew, resources.crew, storage.crew),
+            water: add(self.resources.water, resources.water, storage.water),
+        };
+    }
+
     /// Get the details of the system
     pub fn get_details(
         &mut self,
@@ -403,15 +479,17 @@ impl System {
                     builds.insert(
                         structure.name,
                         System::get_structure_config(galaxy_config, structure.name)
-                            .get_cost(structure.level + 1),
+                            .get_cost(structure.level + 1, galaxy_config.speed()),
                     );
                 }
                 details.builds = Some(builds);
             }
             Ok(Details::Structure(details))
         } else {
+            let scores = self.scores(tick, galaxy_config);
             let mut details = SystemInfo {
-                score: self.score(tick, galaxy_config),
+                score: scores.total(),
+                scores,
                 resources: self.resources,
                 structures: IndexMap::new(),
                 production: self.get_production(tick, galaxy_config),
@@ -425,6 +503,25 @@ impl System {
     }
 }
 
+/// Units made at a rate per hour between two ticks.
+///
+/// If production is X per hour, then every (3600/X) ticks we get 1 unit. Rates above one unit
+/// per tick, only reachable with a galaxy speed, spread X units evenly over each hour instead.
+pub(crate) fn produced(rate: usize, from: usize, to: usize) -> usize {
+    match 3600 / rate {
+        0 => to * rate / 3600 - from * rate / 3600,
+        interval => to / interval - from / interval,
+    }
+}
+
+/// Earliest tick by which a number of units have been made at a rate per hour since a tick
+pub(crate) fn produced_by(rate: usize, from: usize, amount: usize) -> usize {
+    match 3600 / rate {
+        0 => ((amount + from * rate / 3600) * 3600).div_ceil(rate),
+        interval => (amount + from / interval) * interval,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -769,4 +866,88 @@ mod tests {
         assert_eq!(restored_system.resources.water, 11); // 8 + 3
         assert_eq!(restored_system.resources.crew, 5); // 4 + 1
     }
+
+    #[test]
+    fn test_speed_scales_production() {
+        let mut galaxy_config = create_test_galaxy_config();
+        galaxy_config.speed = Some(2.0);
+        let system_config = create_test_system_config();
+        let mut system = System::new(0, &system_config, &galaxy_config);
+
+        // One hour at double speed produces two hours worth
+        system.update_to_tick(3600, &galaxy_config);
+        assert_eq!(system.resources.metal, 14); // 10 + 2 * 2
+        assert_eq!(system.resources.water, 11); // 5 + 3 * 2
+        assert_eq!(system.resources.crew, 5); // 3 + 1 * 2
+    }
+
+    #[test]
+    fn test_speed_scales_build_time() {
+        let mut galaxy_config = create_test_galaxy_config();
+        galaxy_config.speed = Some(4.0);
+        let system_config = create_test_system_config();
+        let mut system = System::new(0, &system_config, &galaxy_config);
+
+        system.resources = Resources {
+            metal: 100,
+            water: 100,
+            crew: 10,
+        };
+
+        // Asteroid mine level 2 takes 7200 ticks at normal speed, with an unchanged resource cost
+        let event = system
+            .build(0, &galaxy_config, StructureType::AsteroidMine)
+            .unwrap();
+        assert_eq!(event.completion, 1800);
+        assert_eq!(system.resources.metal, 90); // 100 - 10
+        assert_eq!(system.resources.crew, 8); // 10 - 2
+
+        system.update_to_tick(1800, &galaxy_config);
+        assert_eq!(system.structure_level(StructureType::AsteroidMine), 2);
+    }
+
+    #[test]
+    fn test_production_matches_intervals_at_normal_speed() {
+        // Every (3600/X) ticks makes 1 unit, as systems always produced without a galaxy speed
+        for rate in [1, 7, 13, 100, 999, 3600] {
+            let interval = 3600 / rate;
+            for (from, to) in [(0, 3600), (500, 7199), (1234, 100_000), (3599, 3600)] {
+                assert_eq!(
+                    produced(rate, from, to),
+                    to / interval - from / interval,
+                    "rate {} from {} to {}",
+                    rate,
+                    from,
+                    to
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_produced_by() {
+        for rate in [1, 7, 13, 100, 3600, 3601, 10_000] {
+            for from in [0, 1, 514, 5000] {
+                for amount in [1, 2, 10] {
+                    let tick = produced_by(rate, from, amount);
+                    assert!(produced(rate, from, tick) >= amount);
+                    assert!(produced(rate, from, tick - 1) < amount);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_very_high_speed() {
+        let mut galaxy_config = create_test_galaxy_config();
+        galaxy_config.speed = Some(3600.0);
+        let system_config = create_test_system_config();
+        let mut system = System::new(0, &system_config, &galaxy_config);
+
+        // Production is faster than one unit per tick, each tick is an hour
+        system.update_to_tick(1, &galaxy_config);
+        assert_eq!(system.resources.metal, 12); // 10 + 2
+        assert_eq!(system.resources.water, 8); // 5 + 3
+        assert_eq!(system.resources.crew, 4); // 3 + 1
+    }
 }
@@ -0,0 +1,264 @@
+/// Map layouts for placing systems in a new galaxy
+///
+/// Every layout places exactly `system_count` systems, or returns an error if that isn't possible.
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::TAU;
+
+use crate::config::{GalaxyConfig, LayoutConfig};
+use crate::Coords;
+
+/// Number of candidate positions tried per system before giving up
+const ATTEMPTS_PER_SYSTEM: usize = 100;
+
+/// Generate the coordinates of every system in a new galaxy
+pub fn generate(config: &GalaxyConfig, rng: &mut StdRng) -> Result<Vec<Coords>, String> {
+    let count = config.system_count;
+    let cells = (config.size.x + 1) * (config.size.y + 1);
+    if count > cells {
+        return Err(format!(
+            "Cannot place {} systems in a galaxy with only {} locations",
+            count, cells
+        ));
+    }
+
+    let center = (config.size.x as f64 / 2.0, config.size.y as f64 / 2.0);
+    let max_radius = center.0.min(center.1);
+
+    match &config.layout {
+        LayoutConfig::Uniform => Ok(uniform(config, rng)),
+        LayoutConfig::Poisson { min_distance } => place(config, rng, *min_distance, |rng| {
+            (
+                rng.gen_range(0.0..=config.size.x as f64),
+                rng.gen_range(0.0..=config.size.y as f64),
+            )
+        }),
+        LayoutConfig::Clustered { clusters, radius } => {
+            if *clusters == 0 {
+                return Err("Clustered layout needs at least one cluster".to_string());
+            }
+            let centers: Vec<(f64, f64)> = (0..*clusters)
+                .map(|_| {
+                    (
+                        rng.gen_range(0.0..=config.size.x as f64),
+                        rng.gen_range(0.0..=config.size.y as f64),
+                    )
+                })
+                .collect();
+            place(config, rng, 0.0, |rng| {
+                let (cx, cy) = centers[rng.gen_range(0..centers.len())];
+                point_in_disc(rng, (cx, cy), *radius)
+            })
+        }
+        LayoutConfig::Spiral {
+            arms,
+            turns,
+            spread,
+        } => {
+            if *arms == 0 {
+                return Err("Spiral layout needs at least one arm".to_string());
+            }
+            place(config, rng, 0.0, |rng| {
+                let arm = rng.gen_range(0..*arms);
+                // Bias towards the outside of the galaxy so the arms aren't crowded at the core
+                let t: f64 = rng.gen::<f64>().sqrt();
+                let angle = arm as f64 * TAU / *arms as f64 + t * turns * TAU;
+                let r = t * max_radius;
+                let on_arm = (center.0 + r * angle.cos(), center.1 + r * angle.sin());
+                point_in_disc(rng, on_arm, *spread)
+            })
+        }
+        LayoutConfig::Rings { rings, width } => {
+            if *rings == 0 {
+                return Err("Ring layout needs at least one ring".to_string());
+            }
+            place(config, rng, 0.0, |rng| {
+                // Pick a ring weighted by its circumference, so systems are spread evenly
+                let ring = ((rng.gen::<f64>().sqrt() * *rings as f64) as usize).min(rings - 1);
+                let r = max_radius * (ring + 1) as f64 / *rings as f64
+                    + rng.gen_range(-0.5..=0.5) * width;
+                let angle = rng.gen_range(0.0..TAU);
+                (center.0 + r * angle.cos(), center.1 + r * angle.sin())
+            })
+        }
+    }
+}
+
+/// Pick distinct locations uniformly across the whole galaxy
+fn uniform(config: &GalaxyConfig, rng: &mut StdRng) -> Vec<Coords> {
+    let width = config.size.x + 1;
+    let cells = width * (config.size.y + 1);
+    rand::seq::index::sample(rng, cells, config.system_count)
+        .into_iter()
+        .map(|index| (index % width, index / width).into())
+        .collect()
+}
+
+/// Uniformly random point within a disc
+fn point_in_disc(rng: &mut StdRng, center: (f64, f64), radius: f64) -> (f64, f64) {
+    let r = radius * rng.gen::<f64>().sqrt();
+    let angle = rng.gen_range(0.0..TAU);
+    (center.0 + r * angle.cos(), center.1 + r * angle.sin())
+}
+
+/// Place systems at candidates drawn from `sample`
+///
+/// Candidates outside the galaxy, on an occupied location, or closer than `min_distance` to
+/// another system are rejected.
+fn place<F>(
+    config: &GalaxyConfig,
+    rng: &mut StdRng,
+    min_distance: f64,
+    mut sample: F,
+) -> Result<Vec<Coords>, String>
+where
+    F: FnMut(&mut StdRng) -> (f64, f64),
+{
+    let count = config.system_count;
+    let mut placed = Vec::with_capacity(count);
+    let mut occupied = HashSet::new();
+
+    // Spatial hash for the minimum distance checks, with cells as large as the minimum distance
+    let cell_size = min_distance.max(1.0);
+    let mut grid: HashMap<(i64, i64), Vec<Coords>> = HashMap::new();
+    let cell_of = |c: Coords| {
+        (
+            (c.x as f64 / cell_size) as i64,
+            (c.y as f64 / cell_size) as i64,
+        )
+    };
+
+    let mut attempts = 0;
+    while placed.len() < count {
+        if attempts >= count * ATTEMPTS_PER_SYSTEM {
+            return Err(format!(
+                "Only placed {} of {} systems, the layout is too dense for the galaxy size",
+                placed.len(),
+                count
+            ));
+        }
+        attempts += 1;
+
+        let (x, y) = sample(rng);
+        let (x, y) = (x.round(), y.round());
+        if x < 0.0 || y < 0.0 || x > config.size.x as f64 || y > config.size.y as f64 {
+            continue;
+        }
+        let coords: Coords = (x as usize, y as usize).into();
+        if occupied.contains(&coords) {
+            continue;
+        }
+
+        if min_distance > 0.0 {
+            let (cx, cy) = cell_of(coords);
+            let too_close = (cx - 1..=cx + 1)
+                .flat_map(|i| (cy - 1..=cy + 1).map(move |j| (i, j)))
+                .filter_map(|cell| grid.get(&cell))
+                .flatten()
+                .any(|other| distance(coords, *other) < min_distance);
+            if too_close {
+                continue;
+            }
+            grid.entry((cx, cy)).or_default().push(coords);
+        }
+
+        occupied.insert(coords);
+        placed.push(coords);
+    }
+
+    Ok(placed)
+}
+
+/// Euclidean distance between two coordinates
+pub fn distance(a: Coords, b: Coords) -> f64 {
+    let dx = a.x as f64 - b.x as f64;
+    let dy = a.y as f64 - b.y as f64;
+    (dx * dx + dy * dy).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GalaxySize;
+    use rand::SeedableRng;
+
+    fn create_test_config(layout: LayoutConfig, system_count: usize) -> GalaxyConfig {
+        GalaxyConfig {
+            system_count,
+            size: GalaxySize { x: 100, y: 100 },
+            layout,
+            ..Default::default()
+        }
+    }
+
+    fn assert_valid(config: &GalaxyConfig, coords: &[Coords]) {
+        assert_eq!(coords.len(), config.system_count);
+        let unique: HashSet<_> = coords.iter().collect();
+        assert_eq!(unique.len(), coords.len(), "Systems must not overlap");
+        for c in coords {
+            assert!(c.x <= config.size.x && c.y <= config.size.y);
+        }
+    }
+
+    #[test]
+    fn test_all_layouts_place_every_system() {
+        let layouts = vec![
+            LayoutConfig::Uniform,
+            LayoutConfig::Poisson { min_distance: 4.0 },
+            LayoutConfig::Clustered {
+                clusters: 5,
+                radius: 12.0,
+            },
+            LayoutConfig::Spiral {
+                arms: 3,
+                turns: 1.0,
+                spread: 4.0,
+            },
+            LayoutConfig::Rings {
+                rings: 3,
+                width: 4.0,
+            },
+        ];
+        for layout in layouts {
+            let config = create_test_config(layout, 200);
+            let mut rng = StdRng::seed_from_u64(1);
+            let coords = generate(&config, &mut rng).unwrap();
+            assert_valid(&config, &coords);
+        }
+    }
+
+    #[test]
+    fn test_poisson_respects_min_distance() {
+        let config = create_test_config(LayoutConfig::Poisson { min_distance: 5.0 }, 150);
+        let mut rng = StdRng::seed_from_u64(2);
+        let coords = generate(&config, &mut rng).unwrap();
+        for (i, a) in coords.iter().enumerate() {
+            for b in coords.iter().skip(i + 1) {
+                assert!(distance(*a, *b) >= 5.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_full_galaxy_uniform() {
+        // Every single location is used
+        let mut config = create_test_config(LayoutConfig::Uniform, 121);
+        config.size = GalaxySize { x: 10, y: 10 };
+        let mut rng = StdRng::seed_from_u64(3);
+        let coords = generate(&config, &mut rng).unwrap();
+        assert_valid(&config, &coords);
+    }
+
+    #[test]
+    fn test_impossible_layouts_error() {
+        let mut config = create_test_config(LayoutConfig::Uniform, 122);
+        config.size = GalaxySize { x: 10, y: 10 };
+        let mut rng = StdRng::seed_from_u64(4);
+        assert!(generate(&config, &mut rng).is_err());
+
+        // Far too many systems for this spacing
+        let config = create_test_config(LayoutConfig::Poisson { min_distance: 30.0 }, 100);
+        assert!(generate(&config, &mut rng).is_err());
+    }
+}
@@ -0,0 +1,437 @@
+/// Leaderboards and score history
+///
+/// Scores are computed on the fly from the systems, so the standings are snapshotted
+/// periodically to keep a history. Leaderboards compare the live standings with the snapshot
+/// from a day before, and the snapshots are grouped per player for charts. Leaderboards can
+/// rank by the total score or by a single category, and alliances are ranked by the sum of
+/// their members.
+use std::collections::HashMap;
+
+use crate::alliance::Alliance;
+use crate::config::ScoreCategory;
+use crate::lifecycle::{self, Standing};
+use crate::models::ScoreHistoryRow;
+use crate::scoring::Scores;
+
+/// Ticks the leaderboard changes are measured over
+pub const DELTA_PERIOD: usize = 24 * 3600;
+
+/// A player on the leaderboard
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeaderboardEntry {
+    pub rank: usize,
+    pub account_id: i64,
+    pub account_name: String,
+    /// Score the leaderboard is ranked by
+    pub score: usize,
+    /// Score of every category
+    pub scores: Scores,
+    pub systems: usize,
+    /// Score gained since the earlier snapshot, None if the player wasn't in it
+    pub score_delta: Option<i64>,
+    /// Places gained since the earlier snapshot, positive when moving up
+    pub rank_delta: Option<i64>,
+}
+
+/// An alliance on the leaderboard
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllianceEntry {
+    pub rank: usize,
+    pub alliance_id: i64,
+    pub name: String,
+    pub tag: String,
+    pub members: usize,
+    /// Score the leaderboard is ranked by, summed over the members
+    pub score: usize,
+    /// Score of every category, summed over the members
+    pub scores: Scores,
+    pub systems: usize,
+}
+
+/// Score of a player in a snapshot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScorePoint {
+    pub tick: usize,
+    /// Rank by the total score
+    pub rank: usize,
+    pub score: usize,
+    pub scores: Scores,
+}
+
+/// Scores of a player over time
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreSeries {
+    pub account_id: i64,
+    pub account_name: String,
+    /// Every snapshot, oldest first
+    pub points: Vec<ScorePoint>,
+}
+
+/// Rank the players of a snapshot by a category, or by the total score for None
+pub fn snapshot_standings(
+    rows: &[ScoreHistoryRow],
+    category: Option<ScoreCategory>,
+) -> Vec<Standing> {
+    let mut standings: Vec<Standing> = rows.iter().map(ScoreHistoryRow::to_standing).collect();
+    // The stored ranks are by the total score
+    if let Some(category) = category {
+        for (standing, row) in standings.iter_mut().zip(rows) {
+            standing.score = row.scores().get(Some(category));
+        }
+        lifecycle::rank_standings(&mut standings);
+    }
+    standings
+}
+
+/// Build the leaderboard from the current standings and an earlier snapshot
+///
+/// `scores` has the score of every category per account, the standings can be ranked by any
+/// of them as long as the earlier snapshot is ranked the same way.
+pub fn leaderboard(
+    standings: &[Standing],
+    names: &HashMap<i64, String>,
+    scores: &HashMap<i64, Scores>,
+    previous: &[Standing],
+) -> Vec<LeaderboardEntry> {
+    let previous: HashMap<i64, &Standing> = previous
+        .iter()
+        .map(|standing| (standing.account_id, standing))
+        .collect();
+    standings
+        .iter()
+        .map(|standing| {
+            let before = previous.get(&standing.account_id);
+            LeaderboardEntry {
+                rank: standing.rank,
+                account_id: standing.account_id,
+                account_name: names.get(&standing.account_id).cloned().unwrap_or_default(),
+                score: standing.score,
+                scores: scores
+                    .get(&standing.account_id)
+                    .copied()
+                    .unwrap_or_default(),
+                systems: standing.systems,
+                score_delta: before.map(|b| standing.score as i64 - b.score as i64),
+                rank_delta: before.map(|b| b.rank as i64 - standing.rank as i64),
+            }
+        })
+        .collect()
+}
+
+/// Rank the alliances by the standings of their members
+///
+/// Ties are broken by system count, then by the oldest alliance.
+pub fn alliance_leaderboard(
+    alliances: &[Alliance],
+    standings: &[Standing],
+    scores: &HashMap<i64, Scores>,
+) -> Vec<AllianceEntry> {
+    let standings: HashMap<i64, &Standing> = standings
+        .iter()
+        .map(|standing| (standing.account_id, standing))
+        .collect();
+    let mut entries: Vec<AllianceEntry> = alliances
+        .iter()
+        .map(|alliance| {
+            let mut entry = AllianceEntry {
+                rank: 0,
+                alliance_id: alliance.id,
+                name: alliance.name.clone(),
+                tag: alliance.tag.clone(),
+                members: alliance.members.len(),
+                score: 0,
+                scores: Scores::default(),
+                systems: 0,
+            };
+            for member in &alliance.members {
+                if let Some(standing) = standings.get(&member.account_id) {
+                    entry.score += standing.score;
+                    entry.systems += standing.systems;
+                }
+                if let Some(member_scores) = scores.get(&member.account_id) {
+                    entry.scores = entry.scores + *member_scores;
+                }
+            }
+            entry
+        })
+        .collect();
+    entries.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(b.systems.cmp(&a.systems))
+            .then(a.alliance_id.cmp(&b.alliance_id))
+    });
+    for (i, entry) in entries.iter_mut().enumerate() {
+        entry.rank = i + 1;
+    }
+    entries
+}
+
+/// Group snapshot rows into one series per player, in order of first appearance
+pub fn score_series(rows: &[ScoreHistoryRow]) -> Vec<ScoreSeries> {
+    let mut series: Vec<ScoreSeries> = Vec::new();
+    let mut index: HashMap<i64, usize> = HashMap::new();
+    for row in rows {
+        let i = *index.entry(row.user_galaxy_account_id).or_insert_with(|| {
+            series.push(ScoreSeries {
+                account_id: row.user_galaxy_account_id,
+                account_name: row.account_name.clone(),
+                points: Vec::new(),
+            });
+            series.len() - 1
+        });
+        series[i].points.push(ScorePoint {
+            tick: row.tick as usize,
+            rank: row.rank as usize,
+            score: row.score as usize,
+            scores: row.scores(),
+        });
+    }
+    series
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ScoreCategory;
+    use crate::test_utils::{join, setup, CONFIG, DAY, HOUR, START};
+    use crate::{AppState, StructureType, UserService};
+
+    /// Mines count towards the military score
+    const SCORING: &str = r#"
+scoring:
+  structures:
+    asteroidmine:
+      category: military
+"#;
+
+    /// Tick, score and rank of every snapshot
+    fn points(series: &ScoreSeries) -> Vec<(usize, usize, usize)> {
+        series
+            .points
+            .iter()
+            .map(|point| (point.tick, point.score, point.rank))
+            .collect()
+    }
+
+    /// Ada, Bo and an AI player a day after a snapshot, with Bo having upgraded a mine an hour in
+    async fn setup_race() -> AppState {
+        let (clock, db, app_state) = setup(&format!("{}{}", CONFIG, SCORING), &["race"]).await;
+        join(&db, &app_state, "race", "Ada").await;
+        let (_, bo) = join(&db, &app_state, "race", "Bo").await;
+        UserService::new(db.clone())
+            .add_ai_player("race", "Bot", "economic", &app_state)
+            .await
+            .unwrap();
+
+        assert!(app_state
+            .snapshot_scores("race", app_state.tick())
+            .await
+            .unwrap());
+        clock.advance(HOUR);
+        app_state
+            .build_structure("race", app_state.tick(), bo, StructureType::AsteroidMine)
+            .await
+            .unwrap();
+        clock.advance(DAY - HOUR);
+        app_state
+    }
+
+    fn standing(rank: usize, account_id: i64, score: usize) -> Standing {
+        Standing {
+            rank,
+            account_id,
+            score,
+            systems: 1,
+        }
+    }
+
+    #[test]
+    fn test_leaderboard_deltas() {
+        let names: HashMap<i64, String> = [(1, "Ada".to_string()), (2, "Bo".to_string())]
+            .into_iter()
+            .collect();
+        let previous = vec![standing(1, 1, 50), standing(2, 2, 40)];
+        let current = vec![standing(1, 2, 90), standing(2, 1, 60), standing(3, 3, 0)];
+
+        let board = leaderboard(&current, &names, &HashMap::new(), &previous);
+        assert_eq!(board.len(), 3);
+        assert_eq!(board[0].account_name, "Bo");
+        assert_eq!(board[0].score_delta, Some(50));
+        assert_eq!(board[0].rank_delta, Some(1));
+        assert_eq!(board[1].score_delta, Some(10));
+        assert_eq!(board[1].rank_delta, Some(-1));
+        // Joined since the snapshot
+        assert_eq!(board[2].score_delta, None);
+        assert_eq!(board[2].rank_delta, None);
+    }
+
+    #[test]
+    fn test_alliance_leaderboard() {
+        use crate::alliance::{AllianceMember, AllianceRole};
+
+        let alliance = |id: i64, members: &[i64]| Alliance {
+            id,
+            galaxy_name: "test".to_string(),
+            name: format!("alliance{}", id),
+            tag: format!("A{}", id),
+            members: members
+                .iter()
+                .map(|&account_id| AllianceMember {
+                    account_id,
+                    account_name: format!("player{}", account_id),
+                    role: AllianceRole::Member,
+                })
+                .collect(),
+        };
+        let alliances = vec![alliance(1, &[1]), alliance(2, &[2, 3]), alliance(3, &[])];
+        let standings = vec![standing(1, 1, 50), standing(2, 2, 40), standing(3, 3, 30)];
+        let scores: HashMap<i64, Scores> = [(
+            2,
+            Scores {
+                economy: 30,
+                military: 10,
+                defense: 0,
+            },
+        )]
+        .into_iter()
+        .collect();
+
+        let board = alliance_leaderboard(&alliances, &standings, &scores);
+        assert_eq!(board.len(), 3);
+        assert_eq!((board[0].alliance_id, board[0].score), (2, 70));
+        assert_eq!(board[0].systems, 2);
+        assert_eq!(board[0].members, 2);
+        assert_eq!(board[0].scores.military, 10);
+        assert_eq!((board[1].alliance_id, board[1].score), (1, 50));
+        // Alliances without members are still ranked
+        assert_eq!((board[2].alliance_id, board[2].score), (3, 0));
+        assert_eq!(board[2].rank, 3);
+    }
+
+    fn row(tick: i64, account: i64, score: i64, rank: i64) -> ScoreHistoryRow {
+        ScoreHistoryRow {
+            galaxy_name: "test".to_string(),
+            tick,
+            user_galaxy_account_id: account,
+            account_name: format!("player{}", account),
+            rank,
+            score,
+            systems: 1,
+            economy: score,
+            military: 0,
+            defense: 0,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_standings() {
+        let mut rows = vec![row(0, 1, 30, 1), row(0, 2, 20, 2)];
+        rows[0].economy = 25;
+        rows[0].defense = 5;
+        rows[1].economy = 5;
+        rows[1].defense = 15;
+
+        let total = snapshot_standings(&rows, None);
+        assert_eq!(total[0].account_id, 1);
+        assert_eq!(total[0].score, 30);
+
+        let defense = snapshot_standings(&rows, Some(ScoreCategory::Defense));
+        assert_eq!(defense[0].account_id, 2);
+        assert_eq!(defense[0].rank, 1);
+        assert_eq!(defense[0].score, 15);
+        assert_eq!(defense[1].score, 5);
+    }
+
+    #[test]
+    fn test_score_series() {
+        let rows = vec![
+            row(0, 2, 10, 1),
+            row(0, 1, 5, 2),
+            row(3600, 1, 30, 1),
+            row(3600, 2, 20, 2),
+        ];
+
+        let series = score_series(&rows);
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].account_id, 2);
+        let points = |series: &ScoreSeries| -> Vec<(usize, usize, usize)> {
+            series
+                .points
+                .iter()
+                .map(|point| (point.tick, point.score, point.rank))
+                .collect()
+        };
+        assert_eq!(points(&series[0]), vec![(0, 10, 1), (3600, 20, 2)]);
+        assert_eq!(series[1].account_name, "player1");
+        assert_eq!(points(&series[1]), vec![(0, 5, 2), (3600, 30, 1)]);
+        assert_eq!(series[1].points[1].scores.economy, 30);
+    }
+
+    #[tokio::test]
+    async fn test_leaderboard() {
+        let app_state = setup_race().await;
+
+        // Ties went to the first to join, until Bo upgraded a mine
+        let board = app_state
+            .leaderboard("race", app_state.tick(), None)
+            .await
+            .unwrap();
+        // AI players aren't ranked
+        assert_eq!(board.len(), 2);
+        assert_eq!(board[0].account_name, "Bo");
+        assert_eq!(board[0].rank, 1);
+        assert_eq!(board[0].score, 4);
+        assert_eq!(board[0].score_delta, Some(2));
+        assert_eq!(board[0].rank_delta, Some(1));
+        assert_eq!(board[1].account_name, "Ada");
+        assert_eq!(board[1].score_delta, Some(0));
+        assert_eq!(board[1].rank_delta, Some(-1));
+    }
+
+    #[tokio::test]
+    async fn test_leaderboard_by_category() {
+        let app_state = setup_race().await;
+
+        // The mine counts towards the military score, the colony towards the economy
+        let military = app_state
+            .leaderboard("race", app_state.tick(), Some(ScoreCategory::Military))
+            .await
+            .unwrap();
+        assert_eq!(military[0].account_name, "Bo");
+        assert_eq!(military[0].score, 3);
+        assert_eq!(military[0].scores.economy, 1);
+        assert_eq!(military[0].score_delta, Some(2));
+        assert_eq!(military[0].rank_delta, Some(1));
+        let economy = app_state
+            .leaderboard("race", app_state.tick(), Some(ScoreCategory::Economy))
+            .await
+            .unwrap();
+        assert_eq!(economy[0].account_name, "Ada");
+        assert_eq!(economy[0].score, 1);
+        assert_eq!(economy[0].rank_delta, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_score_history() {
+        let app_state = setup_race().await;
+        app_state
+            .snapshot_scores("race", app_state.tick())
+            .await
+            .unwrap();
+
+        let history = app_state.score_history("race", None, 0).await.unwrap();
+        assert_eq!(history.len(), 2);
+        let bo_history = history.iter().find(|s| s.account_name == "Bo").unwrap();
+        assert_eq!(points(bo_history), vec![(START, 2, 2), (START + DAY, 4, 1)]);
+        assert_eq!(bo_history.points[1].scores.military, 3);
+
+        // Of one player since a tick
+        let only_bo = app_state
+            .score_history("race", Some(bo_history.account_id), START + HOUR)
+            .await
+            .unwrap();
+        assert_eq!(only_bo.len(), 1);
+        assert_eq!(points(&only_bo[0]), vec![(START + DAY, 4, 1)]);
+    }
+}
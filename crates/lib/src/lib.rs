@@ -1,26 +1,54 @@
 use game_system::EventInfo;
 use indexmap::IndexMap;
-use rand::Rng;
-use std::collections::HashMap;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
 
+pub mod ai;
 pub mod app;
 pub mod app_config;
+pub mod clock;
 pub mod config;
 mod game_system;
+pub mod layout;
+pub mod leaderboard;
+pub mod lifecycle;
+pub mod live;
+pub mod npc;
+pub mod planner;
+pub mod scoring;
+pub mod spawn;
 
 // Database and models modules
+pub mod alliance;
+pub mod api_tokens;
 pub mod auth;
+pub mod chat;
 pub mod db;
+pub mod diplomacy;
+pub mod mail;
+pub mod messages;
 pub mod models;
+pub mod notifications;
 pub mod persistence;
 pub mod user_service;
+pub mod webhooks;
+
+#[cfg(test)]
+mod test_utils;
 
 use crate::config::GalaxyConfig;
-use crate::game_system::System;
+use crate::lifecycle::{EndReason, GalaxyPhase, Standing};
+use crate::npc::{AttackReport, NpcState};
+use crate::scoring::Scores;
+use crate::spawn::SpawnLocation;
 
 pub use crate::app::AppState;
 pub use crate::app_config::AppConfig;
-pub use crate::game_system::{Event, EventCallback, StructureType};
+pub use crate::clock::{AcceleratedClock, Clock, ManualClock, RealClock};
+pub use crate::game_system::{CompletedBuild, Event, EventCallback, StructureType, System};
 
 // Re-export database types
 pub use crate::auth::*;
@@ -29,6 +57,8 @@ pub use crate::models::*;
 pub use crate::user_service::*;
 
 /// Return the current second since the Unix epoch
+///
+/// Game code should use the clock owned by `AppState` instead, so time can be controlled.
 pub fn tick() -> usize {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -52,17 +82,92 @@ pub struct Galaxy {
     /// Track which systems have changed and need database persistence
     dirty_systems: std::collections::HashSet<Coords>,
 
+    /// Player systems changed since the live updates were last taken
+    changed_systems: HashSet<Coords>,
+
     /// Flag indicating if the galaxy needs to be persisted
     needs_persist: bool,
+
+    /// Seed for all random generation in the galaxy
+    seed: u64,
+
+    /// Tick at which the galaxy ended, it is frozen from then on
+    ended_at: Option<usize>,
+
+    /// Final standings, set when the galaxy ends
+    standings: Vec<Standing>,
+
+    /// Why the galaxy ended, until it has been taken
+    unreported_end: Option<EndReason>,
+
+    /// Human account owning each of their systems, the systems ranked in the standings
+    owners: HashMap<Coords, i64>,
+
+    /// Every human account in the galaxy
+    accounts: Vec<i64>,
+
+    /// Alliance of every account in one
+    alliances: HashMap<i64, i64>,
+
+    /// Tick at which the galaxy was paused, if it is paused
+    paused_at: Option<usize>,
+
+    /// Total number of ticks the galaxy has spent paused, excluding the current pause
+    paused_offset: usize,
+
+    /// Systems run by NPCs
+    npcs: HashMap<Coords, NpcState>,
+
+    /// Systems owned by players, the targets of NPC raids
+    player_systems: HashSet<Coords>,
+
+    /// Most recent NPC raids, oldest first
+    attack_reports: Vec<AttackReport>,
+
+    /// Raids on player systems since the notices were last taken
+    unreported_raids: Vec<AttackReport>,
 }
 
+/// Something that happened to a player system, for its owner to be told about
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameNotice {
+    /// A structure upgrade finished
+    Build {
+        coords: Coords,
+        build: CompletedBuild,
+    },
+    /// An NPC system raided the system
+    Raid(AttackReport),
+}
+
+impl GameNotice {
+    /// The system the notice is about
+    pub fn coords(&self) -> Coords {
+        match self {
+            GameNotice::Build { coords, .. } => *coords,
+            GameNotice::Raid(report) => report.target,
+        }
+    }
+
+    /// Tick the notice happened at
+    pub fn tick(&self) -> usize {
+        match self {
+            GameNotice::Build { build, .. } => build.tick,
+            GameNotice::Raid(report) => report.tick,
+        }
+    }
+}
+
+/// Number of NPC raids kept in memory
+const MAX_ATTACK_REPORTS: usize = 100;
+
 /// Production of a system.
 ///
 /// Each value is the amount of resources produced per 3600 ticks (hour).
 pub type SystemProduction = Resources;
 
 /// Resources in a system.
-#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct Resources {
     pub metal: usize,
     pub crew: usize,
@@ -121,17 +226,21 @@ impl std::cmp::PartialOrd for Resources {
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
 pub struct SystemInfo {
     /// Computed score of the system
     pub score: usize,
 
+    /// Computed score of the system per category
+    pub scores: Scores,
+
     /// Resources in the system.
     pub resources: Resources,
 
     /// Production of the system.
     ///
     /// Given in units per hour (3600 ticks).
+    #[schema(value_type = Resources)]
     pub production: SystemProduction,
 
     /// Structure levels
@@ -140,11 +249,12 @@ pub struct SystemInfo {
     /// Events in flight
     ///
     /// Next resource, unit builds, incoming attacks, etc.
+    #[schema(value_type = Vec<Event>)]
     pub events: Vec<EventInfo>,
 }
 
 /// Struct to hold the cost for a build
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
 pub struct Cost {
     pub resources: Resources,
     pub ticks: usize,
@@ -180,11 +290,12 @@ impl std::ops::Mul<f64> for Cost {
 /// Info for a specific structure
 ///
 /// Lots of details are optional, as they don't all apply to all structures
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
 pub struct StructureInfo {
     /// Level of the structure.
     pub level: usize,
     /// Production of the structure, if any.
+    #[schema(value_type = Option<Resources>)]
     pub production: Option<SystemProduction>,
     /// Things that this structure can build, if any.
     pub builds: Option<IndexMap<StructureType, Cost>>,
@@ -203,32 +314,81 @@ pub enum Details {
 impl Galaxy {
     /// Create a new Galaxy
     ///
-    /// Uses a custom configuration struct to set up all the details
-    pub fn new(config: GalaxyConfig, initial_tick: usize) -> Self {
+    /// Uses a custom configuration struct to set up all the details.
+    ///
+    /// If the config has no seed a random one is picked and stored in the cached config, so
+    /// the same galaxy can be regenerated later.
+    pub fn new(mut config: GalaxyConfig, initial_tick: usize) -> Result<Self, String> {
+        config.check_speed()?;
+        let seed = *config.seed.get_or_insert_with(rand::random);
+        let mut rng = StdRng::seed_from_u64(seed);
         let mut systems = HashMap::new();
-        let mut rng = rand::thread_rng();
-        for _ in 0..config.system_count {
-            // Create a new island at a random location in the 2d space
-            let x: usize = rng.gen_range(0..=config.size.x);
-            let y: usize = rng.gen_range(0..=config.size.y);
-            if systems.contains_key(&(x, y).into()) {
-                // Already have a system here, try again
-                continue;
-            }
-            let system = System::new(initial_tick, &config.systems, &config);
-            systems.insert((x, y).into(), system);
+        // Systems don't exist before the galaxy starts
+        let system_tick = initial_tick.max(config.lifecycle.start.unwrap_or(0));
+        let generated = layout::generate(&config, &mut rng)?;
+        for coords in generated.iter() {
+            let system = System::new(system_tick, &config.systems, &config);
+            systems.insert(*coords, system);
         }
+        let npcs = npc::choose_systems(&config.npc, &generated, &mut rng)
+            .into_iter()
+            .map(|coords| {
+                let npc = NpcState::new(system_tick, &config.npc, config.speed(), &mut rng);
+                (coords, npc)
+            })
+            .collect();
+
+        let mut galaxy = Self::from_database(config, initial_tick, systems);
+        galaxy.npcs = npcs;
+        Ok(galaxy)
+    }
+
+    /// Create a galaxy from database data, without generating any systems
+    pub fn from_database(
+        mut config: GalaxyConfig,
+        tick: usize,
+        systems: HashMap<Coords, System>,
+    ) -> Self {
+        let seed = *config.seed.get_or_insert_with(rand::random);
         Self {
             config,
             systems,
-            tick: initial_tick,
+            tick,
 
             dirty_systems: std::collections::HashSet::new(),
+            changed_systems: HashSet::new(),
 
             needs_persist: false,
+            seed,
+            ended_at: None,
+            standings: Vec::new(),
+            unreported_end: None,
+            owners: HashMap::new(),
+            accounts: Vec::new(),
+            alliances: HashMap::new(),
+            paused_at: None,
+            paused_offset: 0,
+            npcs: HashMap::new(),
+            player_systems: HashSet::new(),
+            attack_reports: Vec::new(),
+            unreported_raids: Vec::new(),
         }
     }
 
+    /// Get the seed used for generating this galaxy
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Seeded RNG for generating new content after the galaxy is created.
+    ///
+    /// It is derived from the galaxy seed and a counter of the galaxy state (e.g. the system
+    /// count), so replaying the same sequence of generation steps gives the same result, even
+    /// across a reload.
+    fn generation_rng(&self, counter: usize) -> StdRng {
+        StdRng::seed_from_u64(self.seed.wrapping_add(counter as u64))
+    }
+
     /// Retrieve the details of a system, possibly scoped to a specific structure
     pub fn get_details(
         &mut self,
@@ -239,7 +399,11 @@ impl Galaxy {
         let _old_tick = self.tick;
         self.update_tick(tick)?;
 
-        let system = self.systems.get_mut(&coords).unwrap();
+        let tick = self.game_tick(tick);
+        let system = self
+            .systems
+            .get_mut(&coords)
+            .ok_or_else(|| format!("System ({}, {}) not found", coords.x, coords.y))?;
         let result = system.get_details(tick, &self.config, structure);
 
         // Mark dirty if tick changed (indicates event processing occurred)
@@ -261,14 +425,25 @@ impl Galaxy {
         let _old_tick = self.tick;
         self.update_tick(tick)?;
 
+        let tick = self.game_tick(tick);
         let mut stats = format!("System count: {}\n", self.config.system_count);
         for (coords, system) in self.systems.iter_mut() {
             stats.push_str(&format!(
-                "System at {:?} has score {} and metal {}\n",
+                "System at {:?} has score {} and metal {}",
                 coords,
                 system.score(tick, &self.config),
                 system.metal(tick, &self.config),
             ));
+            if let Some(npc) = self.npcs.get(coords) {
+                stats.push_str(&format!(" (NPC with {} defenders)", npc.defenders));
+            }
+            stats.push('\n');
+        }
+        for attack in self.attack_reports.iter() {
+            stats.push_str(&format!(
+                "NPC system at {:?} raided {:?} with {} raiders at tick {}\n",
+                attack.attacker, attack.target, attack.raiders, attack.tick
+            ));
         }
 
         // Mark all systems dirty if tick changed
@@ -300,7 +475,17 @@ impl Galaxy {
         structure: StructureType,
     ) -> Result<Event, String> {
         self.update_tick(tick)?;
-        let system = self.systems.get_mut(&coords).unwrap();
+        match self.phase(tick) {
+            GalaxyPhase::Pending => return Err("Galaxy has not started yet".to_string()),
+            GalaxyPhase::Paused => return Err("Galaxy is paused".to_string()),
+            GalaxyPhase::Ended => return Err("Galaxy has ended".to_string()),
+            GalaxyPhase::Running => {}
+        }
+        let tick = self.game_tick(tick);
+        let system = self
+            .systems
+            .get_mut(&coords)
+            .ok_or_else(|| format!("System ({}, {}) not found", coords.x, coords.y))?;
         let result = system.build(tick, &self.config, structure);
 
         // Mark for persistence on successful build
@@ -312,18 +497,434 @@ impl Galaxy {
         result
     }
 
+    /// The tick with all the time spent paused removed
+    fn unpaused_tick(&self, tick: usize) -> usize {
+        self.paused_at
+            .unwrap_or(tick)
+            .saturating_sub(self.paused_offset)
+    }
+
+    /// The tick seen by the systems
+    ///
+    /// Time only passes between the start and the end of the galaxy, and not while it is
+    /// paused, so nothing is produced or completed outside of that. The start and end of the
+    /// galaxy are pushed back by the time spent paused.
+    pub fn game_tick(&self, tick: usize) -> usize {
+        let tick = self
+            .unpaused_tick(tick)
+            .max(self.config.lifecycle.start.unwrap_or(0));
+        match self.ended_at.or(self.config.lifecycle.end) {
+            Some(end) => tick.min(end),
+            None => tick,
+        }
+    }
+
+    /// Get the phase of the galaxy at a given tick
+    pub fn phase(&self, tick: usize) -> GalaxyPhase {
+        let lifecycle = &self.config.lifecycle;
+        let tick = self.unpaused_tick(tick);
+        if self.ended_at.is_some() || lifecycle.end.is_some_and(|end| tick >= end) {
+            GalaxyPhase::Ended
+        } else if self.paused_at.is_some() {
+            GalaxyPhase::Paused
+        } else if lifecycle.start.is_some_and(|start| tick < start) {
+            GalaxyPhase::Pending
+        } else {
+            GalaxyPhase::Running
+        }
+    }
+
+    /// Check if new players can join at this tick
+    pub fn check_registration(&self, tick: usize) -> Result<(), String> {
+        if self.phase(tick) == GalaxyPhase::Ended {
+            return Err("Galaxy has ended".to_string());
+        }
+        self.config.lifecycle.check_registration(tick)
+    }
+
+    /// Set the human players ranked when the galaxy ends
+    ///
+    /// `owners` maps every system of a human player to its account, `accounts` lists every
+    /// human account and `alliances` maps the accounts in an alliance to its id. AI players
+    /// never rank or win, they only stand in for missing opponents.
+    pub fn set_players(
+        &mut self,
+        owners: HashMap<Coords, i64>,
+        accounts: Vec<i64>,
+        alliances: HashMap<i64, i64>,
+    ) {
+        self.owners = owners;
+        self.accounts = accounts;
+        self.alliances = alliances;
+    }
+
+    /// Take the reason the galaxy ended, only once, bringing it up to the tick first
+    pub fn take_end(&mut self, tick: usize) -> Result<Option<EndReason>, String> {
+        self.update_tick(tick)?;
+        Ok(self.unreported_end.take())
+    }
+
+    /// End the galaxy if the end tick or a victory condition has been reached
+    ///
+    /// The final standings are recorded, and the reason is kept until it is taken.
+    fn check_end(&mut self, tick: usize) {
+        let phase = self.phase(tick);
+        if self.ended_at.is_some() || phase == GalaxyPhase::Pending || phase == GalaxyPhase::Paused
+        {
+            return;
+        }
+        if phase == GalaxyPhase::Running && self.config.lifecycle.victory.is_empty() {
+            return;
+        }
+
+        let standings = self.current_standings(tick);
+        let reason = if phase == GalaxyPhase::Ended {
+            EndReason::Timeout
+        } else if let Some(condition) = lifecycle::check_victory(
+            &self.config.lifecycle,
+            &standings,
+            &self.alliances,
+            self.systems.len(),
+        ) {
+            EndReason::Victory(condition)
+        } else {
+            return;
+        };
+
+        self.finish(tick, standings);
+        self.unreported_end = Some(reason);
+    }
+
+    /// End the galaxy immediately, recording the final standings as they are now
+    pub fn freeze(&mut self, tick: usize) -> Result<(), String> {
+        self.update_tick(tick)?;
+        if self.ended_at.is_some() {
+            return Err("Galaxy has already ended".to_string());
+        }
+        let standings = self.current_standings(tick);
+        self.finish(tick, standings);
+        Ok(())
+    }
+
+    /// Rank the human players by the score of their systems at this tick
+    pub fn current_standings(&mut self, tick: usize) -> Vec<Standing> {
+        let scores = self
+            .system_scores(tick, self.owners.keys().copied().collect::<Vec<_>>())
+            .into_iter()
+            .map(|(coords, scores)| (coords, scores.total()))
+            .collect();
+        lifecycle::compute_standings(&scores, &self.owners, &self.accounts)
+    }
+
+    /// Score systems at this tick, per category
+    pub fn system_scores(
+        &mut self,
+        tick: usize,
+        coords: impl IntoIterator<Item = Coords>,
+    ) -> HashMap<Coords, Scores> {
+        let game_tick = self.game_tick(tick);
+        let mut scores = HashMap::new();
+        for coords in coords {
+            if let Some(system) = self.systems.get_mut(&coords) {
+                scores.insert(coords, system.scores(game_tick, &self.config));
+            }
+        }
+        scores
+    }
+
+    /// Freeze the galaxy at this tick with the given final standings
+    fn finish(&mut self, tick: usize, standings: Vec<Standing>) {
+        self.ended_at = Some(self.game_tick(tick));
+        self.paused_at = None;
+        self.standings = standings;
+        // Owned systems were updated to the end tick
+        let owned: Vec<Coords> = self.owners.keys().copied().collect();
+        for coords in owned {
+            if self.systems.contains_key(&coords) {
+                self.mark_system_dirty(coords);
+            }
+        }
+        self.needs_persist = true;
+    }
+
+    /// Pause the galaxy, no time passes for the systems until it is resumed
+    pub fn pause(&mut self, tick: usize) -> Result<(), String> {
+        self.update_tick(tick)?;
+        match self.phase(tick) {
+            GalaxyPhase::Paused => Err("Galaxy is already paused".to_string()),
+            GalaxyPhase::Ended => Err("Galaxy has ended".to_string()),
+            _ => {
+                self.paused_at = Some(tick);
+                Ok(())
+            }
+        }
+    }
+
+    /// Resume a paused galaxy
+    pub fn resume(&mut self, tick: usize) -> Result<(), String> {
+        self.update_tick(tick)?;
+        match self.paused_at.take() {
+            Some(paused_at) => {
+                self.paused_offset += tick - paused_at;
+                Ok(())
+            }
+            None => Err("Galaxy is not paused".to_string()),
+        }
+    }
+
+    /// Tick at which the galaxy was paused, if it is paused
+    pub fn paused_at(&self) -> Option<usize> {
+        self.paused_at
+    }
+
+    /// Total ticks spent paused before the current pause
+    pub fn paused_offset(&self) -> usize {
+        self.paused_offset
+    }
+
+    /// Restore the pause state of the galaxy (used when loading from database)
+    pub fn restore_pause(&mut self, paused_at: Option<usize>, paused_offset: usize) {
+        self.paused_at = paused_at;
+        self.paused_offset = paused_offset;
+    }
+
+    /// Tick at which the galaxy ended, if it has
+    pub fn ended_at(&self) -> Option<usize> {
+        self.ended_at
+    }
+
+    /// Final standings of the galaxy, empty until it has ended
+    pub fn standings(&self) -> &[Standing] {
+        &self.standings
+    }
+
+    /// Restore the end of the galaxy (used when loading from database)
+    pub fn restore_end(&mut self, ended_at: usize, standings: Vec<Standing>) {
+        self.ended_at = Some(ended_at);
+        self.standings = standings;
+    }
+
+    /// Systems run by NPCs
+    pub fn npcs(&self) -> &HashMap<Coords, NpcState> {
+        &self.npcs
+    }
+
+    /// Restore the NPC systems (used when loading from database)
+    pub fn restore_npcs(&mut self, npcs: HashMap<Coords, NpcState>) {
+        self.npcs = npcs;
+    }
+
+    /// Set the systems owned by players (used when loading from database)
+    pub fn set_player_systems(&mut self, coords: impl IntoIterator<Item = Coords>) {
+        self.player_systems = coords.into_iter().collect();
+    }
+
+    /// Most recent NPC raids, oldest first
+    pub fn recent_attacks(&self) -> &[AttackReport] {
+        &self.attack_reports
+    }
+
+    /// Raids on player systems that haven't been taken as notices yet, oldest first
+    pub fn unreported_raids(&self) -> &[AttackReport] {
+        &self.unreported_raids
+    }
+
+    /// Restore the recent and unreported raids (used when loading from database)
+    pub fn restore_attacks(&mut self, recent: Vec<AttackReport>, unreported: Vec<AttackReport>) {
+        self.attack_reports = recent;
+        self.unreported_raids = unreported;
+    }
+
+    /// Take what happened to player systems since the last call, oldest first
+    ///
+    /// Player systems are brought up to the tick first, so every build due by then is
+    /// reported. Notices about other systems are dropped.
+    pub fn take_notices(&mut self, tick: usize) -> Result<Vec<GameNotice>, String> {
+        self.update_tick(tick)?;
+        let game_tick = self.game_tick(tick);
+        let mut notices = Vec::new();
+        let mut updated = Vec::new();
+        for (coords, system) in self.systems.iter_mut() {
+            if !self.player_systems.contains(coords) {
+                system.take_completed_builds();
+                continue;
+            }
+            system.update_to_tick(game_tick, &self.config);
+            let builds = system.take_completed_builds();
+            if !builds.is_empty() {
+                updated.push(*coords);
+            }
+            notices.extend(builds.into_iter().map(|build| GameNotice::Build {
+                coords: *coords,
+                build,
+            }));
+        }
+        for coords in updated {
+            self.mark_system_dirty(coords);
+        }
+        // The raids are saved as reported along with their targets
+        for raid in &self.unreported_raids {
+            self.dirty_systems.insert(raid.target);
+            self.needs_persist = true;
+        }
+        notices.extend(self.unreported_raids.drain(..).map(GameNotice::Raid));
+        notices.sort_by_key(|notice| notice.tick());
+        Ok(notices)
+    }
+
+    /// Take the player systems that changed since the last call, for the live updates
+    ///
+    /// Player systems with a build due by the tick are brought up to it first, so finished
+    /// builds show up without waiting for someone to look at the system. With `all`, every
+    /// player system is returned, changed or not.
+    pub fn take_changes(&mut self, tick: usize, all: bool) -> Result<Vec<Coords>, String> {
+        self.update_tick(tick)?;
+        let game_tick = self.game_tick(tick);
+        for coords in &self.player_systems {
+            let Some(system) = self.systems.get_mut(coords) else {
+                continue;
+            };
+            if system.event_to_process(game_tick) {
+                system.update_to_tick(game_tick, &self.config);
+                self.dirty_systems.insert(*coords);
+                self.changed_systems.insert(*coords);
+                self.needs_persist = true;
+            }
+        }
+
+        let mut changed: Vec<_> = if all {
+            self.changed_systems.clear();
+            self.player_systems
+                .iter()
+                .filter(|coords| self.systems.contains_key(coords))
+                .copied()
+                .collect()
+        } else {
+            self.changed_systems.drain().collect()
+        };
+        changed.sort_by_key(|coords| (coords.x, coords.y));
+        Ok(changed)
+    }
+
     /// Update the current tick, and verify we are not going back in time
-    fn update_tick(&mut self, tick: usize) -> Result<(), String> {
+    ///
+    /// The galaxy ends here as soon as it reaches its end tick or a victory condition.
+    pub fn update_tick(&mut self, tick: usize) -> Result<(), String> {
         if tick < self.tick {
             return Err("Tick is out of order".to_string());
         }
         self.tick = tick;
+        self.process_npcs(self.game_tick(tick));
+        self.check_end(tick);
         Ok(())
     }
 
+    /// Take every NPC action due up to a game tick
+    ///
+    /// Actions are taken in tick order across all the NPC systems, so a raid sees its target
+    /// as it was at the time of the raid.
+    fn process_npcs(&mut self, tick: usize) {
+        while let Some((next, coords)) = self
+            .npcs
+            .iter()
+            .map(|(coords, npc)| (npc.next_action(), *coords))
+            .filter(|(next, _)| *next <= tick)
+            .min_by_key(|(next, coords)| (*next, coords.x, coords.y))
+        {
+            self.npc_act(coords, next);
+        }
+    }
+
+    /// Take the due actions of an NPC system
+    fn npc_act(&mut self, coords: Coords, tick: usize) {
+        let speed = self.config.speed();
+        let Some(mut npc) = self.npcs.get(&coords).cloned() else {
+            return;
+        };
+        npc.gather_defenders(tick, &self.config.npc, speed);
+
+        if npc.next_build <= tick {
+            self.npc_build(coords, tick);
+            npc.next_build = tick + self.config.npc.build_delay(speed);
+        }
+        if npc.next_attack.is_some_and(|next| next <= tick) {
+            self.npc_attack(coords, tick, npc.raiders());
+            // Seeded by position and time so a reloaded galaxy raids at the same ticks
+            let mut rng = self.generation_rng(tick ^ (coords.x << 16 | coords.y));
+            npc.next_attack = self
+                .config
+                .npc
+                .attack_delay(speed, &mut rng)
+                .map(|delay| tick + delay);
+        }
+
+        self.npcs.insert(coords, npc);
+        self.mark_system_dirty(coords);
+    }
+
+    /// Upgrade the lowest level structure an NPC system can afford
+    fn npc_build(&mut self, coords: Coords, tick: usize) {
+        let Some(system) = self.systems.get_mut(&coords) else {
+            return;
+        };
+        let mut structures = system.get_structures();
+        structures.sort_by_key(|(_, level)| *level);
+        for (structure, _) in structures {
+            if system.build(tick, &self.config, structure).is_ok() {
+                break;
+            }
+        }
+    }
+
+    /// Raid the closest player system in range of an NPC system
+    fn npc_attack(&mut self, coords: Coords, tick: usize, raiders: usize) {
+        if raiders == 0 {
+            return;
+        }
+        let Some(target) = npc::find_target(&self.config.npc, coords, &self.player_systems) else {
+            return;
+        };
+        let Some(system) = self.systems.get_mut(&target) else {
+            return;
+        };
+        let loot = system.raid(
+            tick,
+            &self.config,
+            raiders * self.config.npc.loot_per_raider,
+            self.config.npc.max_loot_fraction,
+        );
+        if let Some(system) = self.systems.get_mut(&coords) {
+            system.add_resources(tick, &self.config, loot);
+        }
+        self.mark_system_dirty(target);
+
+        log::info!(
+            "NPC system {:?} raided {:?} with {} raiders and took {:?}",
+            coords,
+            target,
+            raiders,
+            loot
+        );
+        let report = AttackReport {
+            tick,
+            attacker: coords,
+            target,
+            raiders,
+            loot,
+        };
+        if self.attack_reports.len() >= MAX_ATTACK_REPORTS {
+            self.attack_reports.remove(0);
+        }
+        self.attack_reports.push(report.clone());
+        self.unreported_raids.push(report);
+    }
+
     /// Change tracking methods (only available with db feature)
     pub fn mark_system_dirty(&mut self, coords: Coords) {
         self.dirty_systems.insert(coords);
+        if self.player_systems.contains(&coords) {
+            self.changed_systems.insert(coords);
+        }
         self.needs_persist = true;
     }
 
@@ -359,26 +960,30 @@ impl Galaxy {
         self.tick
     }
 
-    /// Create a new system for a user at a random available location
+    /// Create or assign a system for a new user, following the galaxy's spawn policy
+    ///
+    /// Returns the coordinates of the user's system, or None if the policy can't find a
+    /// location.
     pub fn create_user_system(&mut self, tick: usize) -> Option<Coords> {
-        let mut rng = rand::thread_rng();
-        let max_attempts = 1000;
+        let tick = self.game_tick(tick);
+        let mut rng = self.generation_rng(self.systems.len() + self.player_systems.len());
 
-        for _ in 0..max_attempts {
-            let x: usize = rng.gen_range(0..=self.config.size.x);
-            let y: usize = rng.gen_range(0..=self.config.size.y);
-            let coords = (x, y).into();
-
-            if !self.systems.contains_key(&coords) {
-                // Found an empty location, create a new system
+        // NPC systems can't be adopted, so they count as taken
+        let mut taken: Vec<Coords> = self.player_systems.iter().copied().collect();
+        if self.config.spawn == config::SpawnConfig::Adopt {
+            taken.extend(self.npcs.keys());
+        }
+        let coords = match spawn::choose(&self.config, &self.systems, &taken, &mut rng)? {
+            SpawnLocation::New(coords) => {
                 let system = System::new(tick, &self.config.systems, &self.config);
                 self.systems.insert(coords, system);
-                self.mark_system_dirty(coords);
-                return Some(coords);
+                coords
             }
-        }
-
-        None // No available location found
+            SpawnLocation::Existing(coords) => coords,
+        };
+        self.player_systems.insert(coords);
+        self.mark_system_dirty(coords);
+        Some(coords)
     }
 
     /// Get all systems owned by a specific user (via coordinates)
@@ -412,7 +1017,7 @@ impl Galaxy {
 }
 
 /// Coords for systems
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
 pub struct Coords {
     pub x: usize,
     pub y: usize,
@@ -426,3 +1031,319 @@ impl From<(usize, usize)> for Coords {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GalaxySize;
+
+    fn create_test_config(seed: Option<u64>) -> GalaxyConfig {
+        GalaxyConfig {
+            seed,
+            system_count: 20,
+            size: GalaxySize { x: 50, y: 50 },
+            ..Default::default()
+        }
+    }
+
+    fn sorted_coords(galaxy: &Galaxy) -> Vec<(usize, usize)> {
+        let mut coords: Vec<_> = galaxy.systems().keys().map(|c| (c.x, c.y)).collect();
+        coords.sort();
+        coords
+    }
+
+    #[test]
+    fn test_same_seed_same_layout() {
+        let galaxy1 = Galaxy::new(create_test_config(Some(42)), 0).unwrap();
+        let galaxy2 = Galaxy::new(create_test_config(Some(42)), 0).unwrap();
+        assert_eq!(sorted_coords(&galaxy1), sorted_coords(&galaxy2));
+
+        let galaxy3 = Galaxy::new(create_test_config(Some(43)), 0).unwrap();
+        assert_ne!(sorted_coords(&galaxy1), sorted_coords(&galaxy3));
+    }
+
+    #[test]
+    fn test_random_seed_is_recorded() {
+        let galaxy = Galaxy::new(create_test_config(None), 0).unwrap();
+        assert_eq!(galaxy.get_config().seed, Some(galaxy.seed()));
+
+        // Regenerating from the stored config gives the same galaxy
+        let regenerated = Galaxy::new(galaxy.get_config().clone(), 0).unwrap();
+        assert_eq!(sorted_coords(&galaxy), sorted_coords(&regenerated));
+    }
+
+    #[test]
+    fn test_invalid_speed() {
+        for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let mut config = create_test_config(Some(1));
+            config.speed = Some(speed);
+            assert!(Galaxy::new(config, 0).is_err(), "speed {}", speed);
+        }
+        let mut config = create_test_config(Some(1));
+        config.speed = Some(0.5);
+        assert!(Galaxy::new(config, 0).is_ok());
+    }
+
+    #[test]
+    fn test_user_system_placement_is_seeded() {
+        let mut galaxy1 = Galaxy::new(create_test_config(Some(7)), 0).unwrap();
+        let mut galaxy2 = Galaxy::new(create_test_config(Some(7)), 0).unwrap();
+
+        for _ in 0..5 {
+            assert_eq!(
+                galaxy1.create_user_system(100),
+                galaxy2.create_user_system(100)
+            );
+        }
+    }
+
+    #[test]
+    fn test_galaxy_is_frozen_after_end() {
+        let mut config = create_test_config(Some(1));
+        config.systems.structures.insert(
+            "asteroidmine".to_string(),
+            config::StructureConfig {
+                starting_level: 1,
+                production: Some(config::ProductionConfig {
+                    metal: 3600,
+                    ..Default::default()
+                }),
+                storage: Some(config::StorageConfig {
+                    metal: 100000,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        config.lifecycle.start = Some(100);
+        config.lifecycle.end = Some(200);
+        let mut galaxy = Galaxy::new(config, 0).unwrap();
+        let coords = *galaxy.systems().keys().next().unwrap();
+        galaxy.set_players([(coords, 1)].into_iter().collect(), vec![1], HashMap::new());
+        let metal = |galaxy: &mut Galaxy, tick| match galaxy.get_details(tick, coords, None) {
+            Ok(Details::System(info)) => info.resources.metal,
+            _ => panic!("Expected system details"),
+        };
+
+        // Production only happens between the start and the end
+        assert_eq!(galaxy.phase(50), GalaxyPhase::Pending);
+        assert_eq!(metal(&mut galaxy, 50), 0);
+        assert_eq!(galaxy.phase(150), GalaxyPhase::Running);
+        assert_eq!(metal(&mut galaxy, 150), 50);
+        assert_eq!(galaxy.phase(250), GalaxyPhase::Ended);
+        assert_eq!(metal(&mut galaxy, 250), 100);
+        assert_eq!(metal(&mut galaxy, 10000), 100);
+
+        // The galaxy ended as soon as it was brought past the end tick
+        assert_eq!(galaxy.ended_at(), Some(200));
+        assert_eq!(galaxy.take_end(10000), Ok(Some(EndReason::Timeout)));
+        assert_eq!(galaxy.take_end(10000), Ok(None));
+        assert_eq!(galaxy.standings()[0].account_id, 1);
+        assert!(galaxy
+            .build(10001, coords, StructureType::AsteroidMine)
+            .is_err());
+    }
+
+    #[test]
+    fn test_victory_ends_galaxy() {
+        let mut config = create_test_config(Some(2));
+        config.lifecycle.victory = vec![config::VictoryCondition::Control { percent: 10.0 }];
+        let mut galaxy = Galaxy::new(config, 0).unwrap();
+        let coords: Vec<Coords> = galaxy.systems().keys().cloned().collect();
+        let mut owners: HashMap<Coords, i64> =
+            [(coords[0], 1), (coords[1], 2)].into_iter().collect();
+
+        // 1 of 20 systems each isn't enough
+        galaxy.set_players(owners.clone(), vec![1, 2], HashMap::new());
+        assert_eq!(galaxy.take_end(10), Ok(None));
+        assert_eq!(galaxy.phase(10), GalaxyPhase::Running);
+
+        // Any build past the victory ends the galaxy before it is made
+        owners.insert(coords[2], 2);
+        galaxy.set_players(owners, vec![1, 2], HashMap::new());
+        assert!(galaxy
+            .build(20, coords[2], StructureType::AsteroidMine)
+            .is_err());
+        assert_eq!(galaxy.phase(20), GalaxyPhase::Ended);
+        assert_eq!(
+            galaxy.take_end(20),
+            Ok(Some(EndReason::Victory(
+                config::VictoryCondition::Control { percent: 10.0 }
+            )))
+        );
+        assert_eq!(galaxy.standings()[0].account_id, 2);
+    }
+
+    #[test]
+    fn test_no_production_while_paused() {
+        let mut config = create_test_config(Some(3));
+        config.systems.structures.insert(
+            "asteroidmine".to_string(),
+            config::StructureConfig {
+                starting_level: 1,
+                production: Some(config::ProductionConfig {
+                    metal: 3600,
+                    ..Default::default()
+                }),
+                storage: Some(config::StorageConfig {
+                    metal: 100000,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        let mut galaxy = Galaxy::new(config, 0).unwrap();
+        let coords = *galaxy.systems().keys().next().unwrap();
+        let metal = |galaxy: &mut Galaxy, tick| match galaxy.get_details(tick, coords, None) {
+            Ok(Details::System(info)) => info.resources.metal,
+            _ => panic!("Expected system details"),
+        };
+
+        assert_eq!(metal(&mut galaxy, 100), 100);
+        galaxy.pause(100).unwrap();
+        assert_eq!(galaxy.phase(200), GalaxyPhase::Paused);
+        assert_eq!(metal(&mut galaxy, 1000), 100);
+        galaxy.resume(1000).unwrap();
+        assert_eq!(metal(&mut galaxy, 1100), 200);
+        assert_eq!(galaxy.phase(1100), GalaxyPhase::Running);
+    }
+
+    #[test]
+    fn test_npc_systems_build_and_raid() {
+        let mut config = create_test_config(Some(4));
+        config.systems.structures.insert(
+            "asteroidmine".to_string(),
+            config::StructureConfig {
+                starting_level: 1,
+                production: Some(config::ProductionConfig {
+                    metal: 3600,
+                    ..Default::default()
+                }),
+                storage: Some(config::StorageConfig {
+                    metal: 100000,
+                    ..Default::default()
+                }),
+                cost: Some(config::CostConfig {
+                    time: 60,
+                    metal: 10,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        // Every resource must be above the cost for a build
+        config.systems.resources = [("crew".to_string(), 10), ("water".to_string(), 10)]
+            .into_iter()
+            .collect();
+        config.npc = config::NpcConfig {
+            fraction: 0.5,
+            build_interval: 600,
+            defenders_per_hour: 10,
+            attack_interval: 3600,
+            attack_range: 1000.0,
+            loot_per_raider: 100,
+            max_loot_fraction: 0.5,
+            ..Default::default()
+        };
+        let mut galaxy = Galaxy::new(config, 0).unwrap();
+        assert_eq!(galaxy.npcs().len(), 10);
+        let player = galaxy.create_user_system(0).unwrap();
+        assert!(!galaxy.npcs().contains_key(&player));
+        galaxy
+            .build(600, player, StructureType::AsteroidMine)
+            .unwrap();
+
+        let metal = match galaxy.get_details(36000, player, None) {
+            Ok(Details::System(info)) => info.resources.metal,
+            _ => panic!("Expected system details"),
+        };
+        // Ten hours of production, minus what the raiders took
+        assert!(metal < 36000);
+
+        let attacks = galaxy.recent_attacks();
+        assert!(!attacks.is_empty());
+        assert!(attacks.iter().all(|attack| attack.target == player));
+        assert!(attacks.iter().all(|attack| attack.loot.metal > 0));
+        for (coords, npc) in galaxy.npcs() {
+            assert!(npc.defenders > 0);
+            let structures = galaxy.systems()[coords].get_structures();
+            assert!(structures.iter().any(|(_, level)| *level > 1));
+        }
+
+        // The player is told about the raids and its build, and nobody about the NPC builds
+        let raids = galaxy.recent_attacks().len();
+        let notices = galaxy.take_notices(36000).unwrap();
+        assert_eq!(notices.len(), raids + 1);
+        assert!(notices.iter().all(|notice| notice.coords() == player));
+        assert_eq!(
+            notices[0],
+            GameNotice::Build {
+                coords: player,
+                build: CompletedBuild {
+                    structure: StructureType::AsteroidMine,
+                    level: 2,
+                    tick: 660,
+                },
+            }
+        );
+        assert!(galaxy.take_notices(36000).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_npc_systems_cannot_be_adopted() {
+        let mut config = create_test_config(Some(5));
+        config.spawn = config::SpawnConfig::Adopt;
+        config.npc.fraction = 0.95;
+        let mut galaxy = Galaxy::new(config, 0).unwrap();
+        assert_eq!(galaxy.npcs().len(), 19);
+
+        let coords = galaxy.create_user_system(0).unwrap();
+        assert!(!galaxy.npcs().contains_key(&coords));
+        assert_eq!(galaxy.create_user_system(0), None);
+    }
+
+    #[test]
+    fn test_take_changes() {
+        let mut galaxy = Galaxy::new(create_test_config(Some(6)), 0).unwrap();
+        let player = galaxy.create_user_system(0).unwrap();
+        assert_eq!(galaxy.take_changes(0, false).unwrap(), vec![player]);
+        assert!(galaxy.take_changes(10, false).unwrap().is_empty());
+
+        // Only player systems are live
+        let other = *galaxy.systems().keys().find(|c| **c != player).unwrap();
+        galaxy.mark_system_dirty(other);
+        galaxy.mark_system_dirty(player);
+        assert_eq!(galaxy.take_changes(20, false).unwrap(), vec![player]);
+        assert_eq!(galaxy.take_changes(30, true).unwrap(), vec![player]);
+        assert!(galaxy.take_changes(40, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unknown_system() {
+        let mut galaxy = Galaxy::new(create_test_config(Some(1)), 0).unwrap();
+        let coords = Coords { x: 1000, y: 1000 };
+        assert_eq!(
+            galaxy.get_details(0, coords, None).unwrap_err(),
+            "System (1000, 1000) not found"
+        );
+        assert!(galaxy.build(0, coords, StructureType::Colony).is_err());
+    }
+
+    #[test]
+    fn test_system_info_json() {
+        let info = SystemInfo {
+            structures: [(StructureType::AsteroidMine, 2)].into_iter().collect(),
+            events: vec![Event {
+                completion: 10,
+                action: EventCallback::Build,
+                structure: Some(StructureType::Colony),
+            }],
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["structures"]["asteroidmine"], 2);
+        assert_eq!(json["events"][0]["action"], "build");
+        assert_eq!(json["events"][0]["structure"], "colony");
+        assert_eq!(json["resources"]["metal"], 0);
+    }
+}
@@ -0,0 +1,268 @@
+/// Galaxy lifecycle, covering the start and end of a game and the final standings
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::config::{LifecycleConfig, VictoryCondition};
+use crate::Coords;
+
+/// Phase of a galaxy at a given tick
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GalaxyPhase {
+    /// The start tick hasn't been reached yet
+    Pending,
+    /// The game is being played
+    Running,
+    /// An operator has paused the galaxy, no time passes for the systems
+    Paused,
+    /// The galaxy is frozen and the final standings are known
+    Ended,
+}
+
+impl std::fmt::Display for GalaxyPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GalaxyPhase::Pending => write!(f, "Not started"),
+            GalaxyPhase::Running => write!(f, "Running"),
+            GalaxyPhase::Paused => write!(f, "Paused"),
+            GalaxyPhase::Ended => write!(f, "Ended"),
+        }
+    }
+}
+
+/// Why a galaxy ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum EndReason {
+    /// The configured end tick was reached
+    Timeout,
+    /// A player or alliance reached a victory condition
+    Victory(VictoryCondition),
+}
+
+/// Position of a single player in the galaxy
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standing {
+    /// 1 for the winner
+    pub rank: usize,
+    /// The user_galaxy_accounts id of the player
+    pub account_id: i64,
+    /// Total score of all the player's systems
+    pub score: usize,
+    /// Number of systems owned by the player
+    pub systems: usize,
+}
+
+impl LifecycleConfig {
+    /// Check if players can join the galaxy at this tick
+    pub fn check_registration(&self, tick: usize) -> Result<(), String> {
+        if let Some(start) = self.registration_start {
+            if tick < start {
+                return Err(format!(
+                    "Registration opens in {} seconds",
+                    start.saturating_sub(tick)
+                ));
+            }
+        }
+        if let Some(end) = self.registration_end {
+            if tick > end {
+                return Err("Registration is closed".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rank every player by the total score of the systems they own
+///
+/// `scores` has the score of every system, `owners` maps owned systems to their account, and
+/// `accounts` lists every account in the galaxy so players without systems are still ranked.
+pub fn compute_standings(
+    scores: &HashMap<Coords, usize>,
+    owners: &HashMap<Coords, i64>,
+    accounts: &[i64],
+) -> Vec<Standing> {
+    let mut totals: HashMap<i64, (usize, usize)> =
+        accounts.iter().map(|&account| (account, (0, 0))).collect();
+    for (coords, account) in owners {
+        let total = totals.entry(*account).or_default();
+        total.0 += scores.get(coords).copied().unwrap_or(0);
+        total.1 += 1;
+    }
+
+    let mut standings: Vec<Standing> = totals
+        .into_iter()
+        .map(|(account_id, (score, systems))| Standing {
+            rank: 0,
+            account_id,
+            score,
+            systems,
+        })
+        .collect();
+    rank_standings(&mut standings);
+    standings
+}
+
+/// Sort standings by score and number them from 1
+pub fn rank_standings(standings: &mut [Standing]) {
+    // Ties are broken by system count, then by who joined first
+    standings.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(b.systems.cmp(&a.systems))
+            .then(a.account_id.cmp(&b.account_id))
+    });
+    for (i, standing) in standings.iter_mut().enumerate() {
+        standing.rank = i + 1;
+    }
+}
+
+/// Find the first victory condition that has been reached, if any
+///
+/// `alliances` maps the accounts in an alliance to its id, every other player counts as an
+/// alliance of their own.
+pub fn check_victory(
+    config: &LifecycleConfig,
+    standings: &[Standing],
+    alliances: &HashMap<i64, i64>,
+    system_count: usize,
+) -> Option<VictoryCondition> {
+    // Players outside an alliance are told apart from alliances with the same id
+    let team = |standing: &Standing| match alliances.get(&standing.account_id) {
+        Some(alliance) => (true, *alliance),
+        None => (false, standing.account_id),
+    };
+    config
+        .victory
+        .iter()
+        .find(|condition| match condition {
+            VictoryCondition::Score { score } => standings.iter().any(|s| s.score >= *score),
+            VictoryCondition::Control { percent } => {
+                system_count > 0
+                    && standings
+                        .iter()
+                        .any(|s| s.systems as f64 * 100.0 / system_count as f64 >= *percent)
+            }
+            VictoryCondition::LastAllianceStanding => {
+                // Someone must have been eliminated, a galaxy with one alliance hasn't been won
+                let teams: HashSet<(bool, i64)> = standings.iter().map(team).collect();
+                let alive: HashSet<(bool, i64)> = standings
+                    .iter()
+                    .filter(|s| s.systems > 0)
+                    .map(team)
+                    .collect();
+                teams.len() > 1 && alive.len() == 1
+            }
+        })
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_standings() -> Vec<Standing> {
+        let scores: HashMap<Coords, usize> = [
+            ((0, 0).into(), 10),
+            ((1, 1).into(), 5),
+            ((2, 2).into(), 20),
+            ((3, 3).into(), 7),
+        ]
+        .into_iter()
+        .collect();
+        let owners: HashMap<Coords, i64> =
+            [((0, 0).into(), 1), ((1, 1).into(), 1), ((2, 2).into(), 2)]
+                .into_iter()
+                .collect();
+        compute_standings(&scores, &owners, &[1, 2, 3])
+    }
+
+    #[test]
+    fn test_standings_order() {
+        let standings = create_test_standings();
+        assert_eq!(standings.len(), 3);
+        assert_eq!((standings[0].account_id, standings[0].score), (2, 20));
+        assert_eq!((standings[1].account_id, standings[1].score), (1, 15));
+        assert_eq!(standings[1].systems, 2);
+        // Players without systems are still ranked, unowned systems are ignored
+        assert_eq!((standings[2].account_id, standings[2].score), (3, 0));
+        assert_eq!(
+            standings.iter().map(|s| s.rank).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_victory_conditions() {
+        let standings = create_test_standings();
+        let solo = HashMap::new();
+        let config = |victory| LifecycleConfig {
+            victory: vec![victory],
+            ..Default::default()
+        };
+
+        let score = VictoryCondition::Score { score: 20 };
+        assert_eq!(
+            check_victory(&config(score.clone()), &standings, &solo, 4),
+            Some(score)
+        );
+        let score = VictoryCondition::Score { score: 21 };
+        assert_eq!(check_victory(&config(score), &standings, &solo, 4), None);
+
+        // Player 1 owns 2 out of 4 systems
+        let control = VictoryCondition::Control { percent: 50.0 };
+        assert_eq!(
+            check_victory(&config(control.clone()), &standings, &solo, 4),
+            Some(control)
+        );
+        let control = VictoryCondition::Control { percent: 60.0 };
+        assert_eq!(check_victory(&config(control), &standings, &solo, 4), None);
+
+        // Two players still own systems
+        let last = config(VictoryCondition::LastAllianceStanding);
+        assert_eq!(check_victory(&last, &standings, &solo, 4), None);
+        let survivors: Vec<Standing> = standings
+            .iter()
+            .cloned()
+            .map(|mut s| {
+                if s.account_id == 1 {
+                    s.systems = 0;
+                }
+                s
+            })
+            .collect();
+        assert_eq!(
+            check_victory(&last, &survivors, &solo, 4),
+            Some(VictoryCondition::LastAllianceStanding)
+        );
+        // A lone player hasn't won anything
+        assert_eq!(check_victory(&last, &survivors[..1], &solo, 4), None);
+
+        // Players 1 and 2 are allies, player 3 never had a system
+        let allied: HashMap<i64, i64> = [(1, 7), (2, 7)].into_iter().collect();
+        assert_eq!(
+            check_victory(&last, &standings, &allied, 4),
+            Some(VictoryCondition::LastAllianceStanding)
+        );
+        // An alliance on its own hasn't won anything either
+        assert_eq!(check_victory(&last, &standings[..2], &allied, 4), None);
+        // Alliance ids don't clash with players outside an alliance
+        let allied: HashMap<i64, i64> = [(1, 2)].into_iter().collect();
+        assert_eq!(check_victory(&last, &standings, &allied, 4), None);
+    }
+
+    #[test]
+    fn test_registration_window() {
+        let config = LifecycleConfig {
+            registration_start: Some(100),
+            registration_end: Some(200),
+            ..Default::default()
+        };
+        assert!(config.check_registration(99).is_err());
+        assert!(config.check_registration(100).is_ok());
+        assert!(config.check_registration(200).is_ok());
+        assert!(config.check_registration(201).is_err());
+        assert!(LifecycleConfig::default().check_registration(0).is_ok());
+    }
+}
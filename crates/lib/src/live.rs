@@ -0,0 +1,290 @@
+/// Live updates of player systems
+///
+/// Galaxies record the player systems that change, when a build starts or finishes, a raid
+/// lands or anything else marks them dirty. [`AppState::publish_live_updates`] regularly sends
+/// their new state through the [`LiveHub`], along with the finished builds and raids players
+/// are notified of, so connected players see their systems move without reloading.
+///
+/// [`AppState::publish_live_updates`]: crate::app::AppState::publish_live_updates
+use serde::Serialize;
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+use crate::{Coords, GameNotice, Resources, StructureType, SystemInfo};
+
+/// Updates kept for subscribers that fall behind
+const HUB_CAPACITY: usize = 1024;
+
+/// Something that happened to a player system
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LiveUpdate {
+    /// Galaxy of the system
+    pub galaxy: String,
+    /// The system
+    pub coords: Coords,
+    /// Game tick it happened at
+    pub tick: usize,
+    /// What happened
+    pub event: LiveEvent,
+}
+
+/// What happened to a system, tagged by its `type`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    /// The system changed, or is refreshed with what it produced since, and is sent whole
+    System { system: SystemInfo },
+    /// A structure upgrade finished
+    BuildComplete {
+        structure: StructureType,
+        /// Level the structure reached
+        level: usize,
+    },
+    /// An NPC system raided the system
+    Raid {
+        attacker: Coords,
+        raiders: usize,
+        /// Resources taken from the system
+        loot: Resources,
+    },
+}
+
+impl LiveEvent {
+    /// Name of the kind of event, the same as its `type`
+    pub fn name(&self) -> &'static str {
+        match self {
+            LiveEvent::System { .. } => "system",
+            LiveEvent::BuildComplete { .. } => "build_complete",
+            LiveEvent::Raid { .. } => "raid",
+        }
+    }
+}
+
+impl LiveUpdate {
+    /// The update telling the owner of a system about a notice
+    pub fn from_notice(galaxy: &str, notice: &GameNotice) -> Self {
+        let event = match notice {
+            GameNotice::Build { build, .. } => LiveEvent::BuildComplete {
+                structure: build.structure,
+                level: build.level,
+            },
+            GameNotice::Raid(report) => LiveEvent::Raid {
+                attacker: report.attacker,
+                raiders: report.raiders,
+                loot: report.loot,
+            },
+        };
+        Self {
+            galaxy: galaxy.to_string(),
+            coords: notice.coords(),
+            tick: notice.tick(),
+            event,
+        }
+    }
+}
+
+/// Broadcasts live updates of every galaxy to everyone following them
+///
+/// Subscribers get the updates of every system and pick the ones of the systems they own.
+#[derive(Debug, Clone)]
+pub struct LiveHub {
+    sender: broadcast::Sender<LiveUpdate>,
+}
+
+impl LiveHub {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(HUB_CAPACITY);
+        Self { sender }
+    }
+
+    /// Receive every update published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveUpdate> {
+        self.sender.subscribe()
+    }
+
+    /// Whether anyone is following the updates, they're not worth computing otherwise
+    pub fn has_subscribers(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    pub(crate) fn publish(&self, update: LiveUpdate) {
+        // Sending only fails when nobody is listening
+        let _ = self.sender.send(update);
+    }
+}
+
+impl Default for LiveHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{self, join, CONFIG, START};
+    use crate::CompletedBuild;
+    use crate::{AppState, ManualClock};
+    use std::sync::Arc;
+    use tokio::sync::broadcast::Receiver;
+
+    #[test]
+    fn test_update_json() {
+        let notice = GameNotice::Build {
+            coords: Coords { x: 3, y: 4 },
+            build: CompletedBuild {
+                structure: StructureType::AsteroidMine,
+                level: 2,
+                tick: 600,
+            },
+        };
+        let update = LiveUpdate::from_notice("andromeda", &notice);
+        assert_eq!(update.event.name(), "build_complete");
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            serde_json::json!({
+                "galaxy": "andromeda",
+                "coords": {"x": 3, "y": 4},
+                "tick": 600,
+                "event": {"type": "build_complete", "structure": "asteroidmine", "level": 2},
+            })
+        );
+    }
+
+    fn received(receiver: &mut Receiver<LiveUpdate>) -> Vec<LiveUpdate> {
+        std::iter::from_fn(|| receiver.try_recv().ok()).collect()
+    }
+
+    /// Ada and Bo in a galaxy, with the changes of their joining already published
+    async fn setup() -> (Arc<ManualClock>, AppState, Coords, Coords) {
+        let (clock, db, app_state) = test_utils::setup(CONFIG, &["live"]).await;
+        let (_, ada) = join(&db, &app_state, "live", "Ada").await;
+        let (_, bo) = join(&db, &app_state, "live", "Bo").await;
+        app_state
+            .publish_live_updates("live", app_state.tick(), false)
+            .await
+            .unwrap();
+        (clock, app_state, ada, bo)
+    }
+
+    #[tokio::test]
+    async fn test_no_subscribers() {
+        let (_clock, app_state, ada, _bo) = setup().await;
+
+        // Nothing is computed without subscribers
+        app_state
+            .build_structure("live", START, ada, StructureType::AsteroidMine)
+            .await
+            .unwrap();
+        assert_eq!(
+            app_state
+                .publish_live_updates("live", app_state.tick(), false)
+                .await
+                .unwrap(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_build_started() {
+        let (_clock, app_state, ada, _bo) = setup().await;
+        let mut receiver = app_state.live_hub().subscribe();
+        assert_eq!(
+            app_state
+                .publish_live_updates("live", app_state.tick(), false)
+                .await
+                .unwrap(),
+            0
+        );
+
+        app_state
+            .build_structure("live", START, ada, StructureType::AsteroidMine)
+            .await
+            .unwrap();
+        app_state
+            .publish_live_updates("live", app_state.tick(), false)
+            .await
+            .unwrap();
+        let updates = received(&mut receiver);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(
+            (updates[0].galaxy.as_str(), updates[0].coords),
+            ("live", ada)
+        );
+        match &updates[0].event {
+            LiveEvent::System { system } => {
+                assert_eq!(system.events.len(), 1);
+                assert_eq!(system.resources.metal, 950);
+            }
+            event => panic!("Expected a system update, got {:?}", event),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_finished() {
+        let (clock, app_state, ada, _bo) = setup().await;
+        app_state
+            .build_structure("live", START, ada, StructureType::AsteroidMine)
+            .await
+            .unwrap();
+        app_state
+            .publish_live_updates("live", app_state.tick(), false)
+            .await
+            .unwrap();
+        let mut receiver = app_state.live_hub().subscribe();
+
+        // The finished build is sent straight away, then again once it's notified
+        clock.advance(600);
+        app_state
+            .publish_live_updates("live", app_state.tick(), false)
+            .await
+            .unwrap();
+        let updates = received(&mut receiver);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].tick, START + 600);
+        match &updates[0].event {
+            LiveEvent::System { system } => {
+                assert!(system.events.is_empty());
+                assert_eq!(system.structures[&StructureType::AsteroidMine], 2);
+            }
+            event => panic!("Expected a system update, got {:?}", event),
+        }
+        app_state
+            .collect_notifications("live", app_state.tick())
+            .await
+            .unwrap();
+        let updates = received(&mut receiver);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].coords, ada);
+        assert!(matches!(
+            updates[0].event,
+            LiveEvent::BuildComplete {
+                structure: StructureType::AsteroidMine,
+                level: 2
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_refresh() {
+        let (_clock, app_state, ada, bo) = setup().await;
+        let mut receiver = app_state.live_hub().subscribe();
+
+        // Refreshing sends every player system, changed or not
+        assert_eq!(
+            app_state
+                .publish_live_updates("live", app_state.tick(), true)
+                .await
+                .unwrap(),
+            2
+        );
+        let mut coords: Vec<_> = received(&mut receiver)
+            .iter()
+            .map(|update| update.coords)
+            .collect();
+        coords.sort_by_key(|coords| (coords.x, coords.y));
+        let mut expected = vec![ada, bo];
+        expected.sort_by_key(|coords| (coords.x, coords.y));
+        assert_eq!(coords, expected);
+    }
+}
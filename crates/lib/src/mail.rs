@@ -0,0 +1,657 @@
+/// Email to users
+///
+/// Email goes through a [`Mailer`], chosen in the `mail` section of the application config: an
+/// SMTP server, or a maildir on disk for development. Messages are rendered from templates.
+/// Transactional ones like address verification are always sent, while game alerts are only
+/// mailed for the notification kinds a user opted in to.
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::{Message, SmtpTransport, Transport};
+
+use crate::app_config::{MailBackend, MailSettings, SmtpSettings};
+use crate::db::{Database, PersistenceError};
+use crate::notifications::NotificationKind;
+
+/// Alerts mailed in one go
+const ALERT_BATCH: usize = 50;
+
+/// How long the SMTP server has to answer
+const SMTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An email to send
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MailError {
+    #[error("Database error: {0}")]
+    Database(#[from] PersistenceError),
+
+    #[error("Invalid email address {0}")]
+    InvalidAddress(String),
+
+    #[error("Failed to build email: {0}")]
+    Message(String),
+
+    #[error("Failed to send email: {0}")]
+    Delivery(String),
+
+    #[error("Failed to write email: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Email isn't set up on this server")]
+    Disabled,
+}
+
+/// Sends email somewhere
+///
+/// Sending blocks until the email was handed over, so async code should send from a blocking
+/// task.
+pub trait Mailer: Send + Sync + std::fmt::Debug {
+    /// Send an email
+    fn send(&self, email: &Email) -> Result<(), MailError>;
+}
+
+/// Build the message of an email from a sender
+fn message(from: &Mailbox, email: &Email) -> Result<Message, MailError> {
+    let to = email
+        .to
+        .parse::<Mailbox>()
+        .map_err(|_| MailError::InvalidAddress(email.to.clone()))?;
+    Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(&email.subject)
+        .body(email.body.clone())
+        .map_err(|e| MailError::Message(e.to_string()))
+}
+
+fn mailbox(address: &str) -> Result<Mailbox, MailError> {
+    address
+        .parse()
+        .map_err(|_| MailError::InvalidAddress(address.to_string()))
+}
+
+/// Sends email through an SMTP server
+#[derive(Debug)]
+pub struct SmtpMailer {
+    from: Mailbox,
+    transport: SmtpTransport,
+}
+
+impl SmtpMailer {
+    /// Create a mailer for an SMTP server, sending from an address
+    pub fn new(settings: &SmtpSettings, from: &str) -> Result<Self, MailError> {
+        let mut builder = SmtpTransport::builder_dangerous(&settings.host)
+            .port(settings.port)
+            .timeout(Some(SMTP_TIMEOUT));
+        if settings.starttls {
+            let parameters = TlsParameters::new(settings.host.clone())
+                .map_err(|e| MailError::Delivery(e.to_string()))?;
+            builder = builder.tls(Tls::Required(parameters));
+        }
+        if let (Some(username), Some(password)) = (&settings.username, &settings.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        Ok(Self {
+            from: mailbox(from)?,
+            transport: builder.build(),
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        self.transport
+            .send(&message(&self.from, email)?)
+            .map_err(|e| MailError::Delivery(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Writes every email to a maildir instead of sending it, for development
+///
+/// Mail clients can open the directory, or the files in its `new` directory can be read as
+/// they are.
+#[derive(Debug)]
+pub struct MaildirMailer {
+    from: Mailbox,
+    dir: PathBuf,
+}
+
+impl MaildirMailer {
+    /// Create a mailer for a maildir, creating its directories if they don't exist
+    pub fn new(dir: impl Into<PathBuf>, from: &str) -> Result<Self, MailError> {
+        let dir = dir.into();
+        for subdir in ["tmp", "new", "cur"] {
+            std::fs::create_dir_all(dir.join(subdir))?;
+        }
+        Ok(Self {
+            from: mailbox(from)?,
+            dir,
+        })
+    }
+}
+
+impl Mailer for MaildirMailer {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        let message = message(&self.from, email)?;
+        // Written to tmp first so readers never see half an email
+        let name = format!(
+            "{}.{:016x}.galactic-war",
+            chrono::Utc::now().timestamp(),
+            rand::random::<u64>()
+        );
+        let tmp = self.dir.join("tmp").join(&name);
+        std::fs::write(&tmp, message.formatted())?;
+        std::fs::rename(&tmp, self.dir.join("new").join(&name))?;
+        Ok(())
+    }
+}
+
+/// Create the mailer of the mail settings, None if email is disabled
+pub fn mailer(settings: &MailSettings) -> Result<Option<Arc<dyn Mailer>>, MailError> {
+    Ok(match settings.backend {
+        MailBackend::None => None,
+        MailBackend::Maildir => Some(Arc::new(MaildirMailer::new(
+            &settings.maildir,
+            &settings.from,
+        )?)),
+        MailBackend::Smtp => Some(Arc::new(SmtpMailer::new(&settings.smtp, &settings.from)?)),
+    })
+}
+
+/// The emails the server sends
+#[derive(Debug, Clone)]
+pub enum MailTemplate<'a> {
+    /// Confirm the address of a user
+    Verification { username: &'a str, link: &'a str },
+    /// Let a user who forgot their password choose a new one
+    PasswordReset { username: &'a str, link: &'a str },
+    /// Tell a user about a notification of one of their galaxy accounts
+    GameAlert {
+        username: &'a str,
+        galaxy: &'a str,
+        account: &'a str,
+        kind: NotificationKind,
+        message: &'a str,
+    },
+}
+
+impl MailTemplate<'_> {
+    /// Render the subject and body of the email, linking to a server at a base URL
+    pub fn render(&self, base_url: &str) -> (String, String) {
+        let base_url = base_url.trim_end_matches('/');
+        match self {
+            MailTemplate::Verification { username, link } => (
+                "Confirm your Galactic War email address".to_string(),
+                format!(
+                    "Hello {},\n\nPlease confirm this is your email address by opening this link:\n\n{}\n\nIf you didn't register at Galactic War, you can ignore this email.\n",
+                    username, link
+                ),
+            ),
+            MailTemplate::PasswordReset { username, link } => (
+                "Reset your Galactic War password".to_string(),
+                format!(
+                    "Hello {},\n\nChoose a new password by opening this link:\n\n{}\n\nIf you didn't ask for this, you can ignore this email and keep your password.\n",
+                    username, link
+                ),
+            ),
+            MailTemplate::GameAlert {
+                username,
+                galaxy,
+                account,
+                kind,
+                message,
+            } => (
+                format!("[Galactic War] {} in {}", alert_title(*kind), galaxy),
+                format!(
+                    "Hello {},\n\n{}\n\nThis happened to {} in galaxy {}:\n{}/galaxy/{}/dashboard\n\nYou get these emails because you chose to on your dashboard, where you can turn them off:\n{}/dashboard\n",
+                    username, message, account, galaxy, base_url, galaxy, base_url
+                ),
+            ),
+        }
+    }
+}
+
+/// Subject of an alert of a notification kind
+fn alert_title(kind: NotificationKind) -> &'static str {
+    match kind {
+        NotificationKind::Build => "Build finished",
+        NotificationKind::IncomingAttack => "Incoming attack",
+        NotificationKind::ScoutDetected => "Scouts detected",
+        NotificationKind::BattleReport => "Battle report",
+        NotificationKind::Diplomacy => "Diplomacy changed",
+    }
+}
+
+/// Service for email
+pub struct MailService {
+    db: Database,
+    mailer: Option<Arc<dyn Mailer>>,
+    base_url: String,
+}
+
+impl MailService {
+    /// Create a new mail service, sending through a mailer if email is enabled
+    pub fn new(db: Database, mailer: Option<Arc<dyn Mailer>>, base_url: &str) -> Self {
+        Self {
+            db,
+            mailer,
+            base_url: base_url.to_string(),
+        }
+    }
+
+    /// Whether email is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.mailer.is_some()
+    }
+
+    /// Get the notification kinds a user gets by email
+    pub async fn alerts(&self, user_id: i64) -> Result<Vec<NotificationKind>, MailError> {
+        Ok(self
+            .db
+            .get_mail_alerts(user_id)
+            .await?
+            .iter()
+            .filter_map(|kind| kind.parse().ok())
+            .collect())
+    }
+
+    /// Choose the notification kinds a user gets by email, from now on
+    pub async fn set_alerts(
+        &self,
+        user_id: i64,
+        kinds: &[NotificationKind],
+    ) -> Result<(), MailError> {
+        let kinds = kinds
+            .iter()
+            .map(|kind| kind.to_string())
+            .collect::<Vec<_>>();
+        Ok(self.db.set_mail_alerts(user_id, &kinds).await?)
+    }
+
+    /// Send an email rendered from a template to an address
+    pub async fn send(&self, to: &str, template: &MailTemplate<'_>) -> Result<(), MailError> {
+        let mailer = self.mailer.clone().ok_or(MailError::Disabled)?;
+        let (subject, body) = template.render(&self.base_url);
+        let email = Email {
+            to: to.to_string(),
+            subject,
+            body,
+        };
+        tokio::task::spawn_blocking(move || mailer.send(&email))
+            .await
+            .map_err(|e| MailError::Delivery(e.to_string()))?
+    }
+
+    /// Mail the unread notifications users opted in to, returning how many were sent
+    ///
+    /// Stops at the first email the mailer fails to send, so it's retried on the next run.
+    /// Alerts that can never be mailed, like those to an invalid address, are skipped for good
+    /// so they don't hold back the ones after them.
+    pub async fn send_alerts(&self) -> Result<usize, MailError> {
+        if !self.is_enabled() {
+            return Ok(0);
+        }
+        let mut sent = 0;
+        for alert in self.db.get_pending_mail_alerts(ALERT_BATCH).await? {
+            let result = match alert.kind.parse() {
+                Ok(kind) => {
+                    self.send(
+                        &alert.email,
+                        &MailTemplate::GameAlert {
+                            username: &alert.username,
+                            galaxy: &alert.galaxy_name,
+                            account: &alert.account_name,
+                            kind,
+                            message: &alert.message,
+                        },
+                    )
+                    .await
+                }
+                Err(e) => Err(MailError::Message(e)),
+            };
+            match result {
+                Ok(()) => sent += 1,
+                Err(e @ (MailError::InvalidAddress(_) | MailError::Message(_))) => {
+                    log::warn!(
+                        "Skipping mail alert of notification {}: {}",
+                        alert.notification_id,
+                        e
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+            self.db
+                .set_notification_mailed(alert.notification_id)
+                .await?;
+        }
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_config::SmtpSettings;
+    use crate::notifications::NotificationService;
+    use crate::test_utils::{self, join, CONFIG, START};
+    use crate::{AppState, UserGalaxyAccount, UserService};
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_game_alert() {
+        let (subject, body) = MailTemplate::GameAlert {
+            username: "ada",
+            galaxy: "andromeda",
+            account: "Ada",
+            kind: NotificationKind::BattleReport,
+            message: "System (1, 2) was raided",
+        }
+        .render("https://example.com/");
+        assert_eq!(subject, "[Galactic War] Battle report in andromeda");
+        assert!(body.starts_with("Hello ada,\n\nSystem (1, 2) was raided\n"));
+        assert!(body.contains("https://example.com/galaxy/andromeda/dashboard\n"));
+        assert!(body.contains("https://example.com/dashboard\n"));
+    }
+
+    #[test]
+    fn test_maildir() {
+        let dir =
+            std::env::temp_dir().join(format!("galactic-war-maildir-{}", rand::random::<u64>()));
+        let mailer = MaildirMailer::new(&dir, "Galactic War <war@example.com>").unwrap();
+        assert!(matches!(
+            mailer.send(&Email {
+                to: "not an address".to_string(),
+                subject: "Hi".to_string(),
+                body: "Hello".to_string(),
+            }),
+            Err(MailError::InvalidAddress(_))
+        ));
+        mailer
+            .send(&Email {
+                to: "ada@example.com".to_string(),
+                subject: "Hi".to_string(),
+                body: "Hello".to_string(),
+            })
+            .unwrap();
+
+        let files = std::fs::read_dir(dir.join("new"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(files.len(), 1);
+        assert_eq!(std::fs::read_dir(dir.join("tmp")).unwrap().count(), 0);
+        let email = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(email.contains("To: ada@example.com"));
+        assert!(email.contains("Subject: Hi"));
+        assert!(email.ends_with("Hello"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Accept SMTP sessions on a local port, passing on the data of every email
+    async fn smtp_sink() -> (u16, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, received) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+                stream
+                    .get_mut()
+                    .write_all(b"220 localhost ESMTP sink\r\n")
+                    .await
+                    .unwrap();
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    if stream.read_line(&mut line).await.unwrap() == 0 {
+                        break;
+                    }
+                    let command = line.to_uppercase();
+                    let reply: &[u8] = if command.starts_with("DATA") {
+                        stream
+                            .get_mut()
+                            .write_all(b"354 Go ahead\r\n")
+                            .await
+                            .unwrap();
+                        let mut data = String::new();
+                        loop {
+                            line.clear();
+                            stream.read_line(&mut line).await.unwrap();
+                            if line == ".\r\n" {
+                                break;
+                            }
+                            data.push_str(&line);
+                        }
+                        sender.send(data).unwrap();
+                        b"250 Queued\r\n"
+                    } else if command.starts_with("QUIT") {
+                        stream.get_mut().write_all(b"221 Bye\r\n").await.unwrap();
+                        break;
+                    } else {
+                        b"250 OK\r\n"
+                    };
+                    stream.get_mut().write_all(reply).await.unwrap();
+                }
+            }
+        });
+
+        (port, received)
+    }
+
+    /// Ada in a galaxy, with a mail service sending to an SMTP sink
+    async fn setup() -> (
+        Database,
+        AppState,
+        MailService,
+        mpsc::UnboundedReceiver<String>,
+        UserGalaxyAccount,
+    ) {
+        let (_, db, app_state) = test_utils::setup(CONFIG, &["mail"]).await;
+        let (ada, _) = join(&db, &app_state, "mail", "ada").await;
+        let (port, received) = smtp_sink().await;
+        let mailer = SmtpMailer::new(
+            &SmtpSettings {
+                host: "127.0.0.1".to_string(),
+                port,
+                ..SmtpSettings::default()
+            },
+            "Galactic War <war@example.com>",
+        )
+        .unwrap();
+        let mail = MailService::new(
+            db.clone(),
+            Some(Arc::new(mailer)),
+            "https://war.example.com",
+        );
+        (db, app_state, mail, received, ada)
+    }
+
+    #[tokio::test]
+    async fn test_disabled() {
+        let (_, db, app_state) = test_utils::setup(CONFIG, &["mail"]).await;
+        let (ada, _) = join(&db, &app_state, "mail", "ada").await;
+        NotificationService::new(db.clone())
+            .notify(
+                ada.id,
+                NotificationKind::BattleReport,
+                START,
+                None,
+                "Raided",
+            )
+            .await
+            .unwrap();
+
+        // Without a mailer nothing is sent
+        let disabled = app_state.mail().unwrap();
+        assert!(!disabled.is_enabled());
+        assert!(matches!(
+            disabled
+                .send(
+                    "ada@example.com",
+                    &MailTemplate::Verification {
+                        username: "ada",
+                        link: "https://war.example.com/verify"
+                    }
+                )
+                .await,
+            Err(MailError::Disabled)
+        ));
+        assert_eq!(disabled.send_alerts().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_transactional_email() {
+        let (_db, _app_state, mail, mut received, _ada) = setup().await;
+
+        // Transactional email is always sent
+        mail.send(
+            "ada@example.com",
+            &MailTemplate::PasswordReset {
+                username: "ada",
+                link: "https://war.example.com/reset",
+            },
+        )
+        .await
+        .unwrap();
+        let email = received.recv().await.unwrap();
+        assert!(email.contains("To: ada@example.com"));
+        assert!(email.contains("Subject: Reset your Galactic War password"));
+        assert!(email.contains("https://war.example.com/reset"));
+    }
+
+    #[tokio::test]
+    async fn test_alerts_opted_in() {
+        let (db, _app_state, mail, mut received, ada) = setup().await;
+        let notifications = NotificationService::new(db);
+
+        // Alerts are only mailed for the kinds the user opted in to
+        notifications
+            .notify(
+                ada.id,
+                NotificationKind::BattleReport,
+                START,
+                None,
+                "Raided",
+            )
+            .await
+            .unwrap();
+        assert_eq!(mail.send_alerts().await.unwrap(), 0);
+        notifications.read_all(ada.id).await.unwrap();
+
+        mail.set_alerts(ada.user_id, &[NotificationKind::BattleReport])
+            .await
+            .unwrap();
+        assert_eq!(
+            mail.alerts(ada.user_id).await.unwrap(),
+            vec![NotificationKind::BattleReport]
+        );
+        notifications
+            .notify(ada.id, NotificationKind::Build, START, None, "Built")
+            .await
+            .unwrap();
+        notifications
+            .notify(
+                ada.id,
+                NotificationKind::BattleReport,
+                START,
+                None,
+                "Raided again",
+            )
+            .await
+            .unwrap();
+        assert_eq!(mail.send_alerts().await.unwrap(), 1);
+        let email = received.recv().await.unwrap();
+        assert!(email.contains("Subject: [Galactic War] Battle report in mail"));
+        assert!(email.contains("Raided again"));
+        assert!(email.contains("https://war.example.com/galaxy/mail/dashboard"));
+
+        mail.set_alerts(ada.user_id, &[]).await.unwrap();
+        assert!(mail.alerts(ada.user_id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_alerts_mailed_once() {
+        let (db, _app_state, mail, mut received, ada) = setup().await;
+        let notifications = NotificationService::new(db);
+        mail.set_alerts(ada.user_id, &[NotificationKind::BattleReport])
+            .await
+            .unwrap();
+
+        // Every notification is mailed once
+        notifications
+            .notify(
+                ada.id,
+                NotificationKind::BattleReport,
+                START,
+                None,
+                "Raided",
+            )
+            .await
+            .unwrap();
+        assert_eq!(mail.send_alerts().await.unwrap(), 1);
+        received.recv().await.unwrap();
+        assert_eq!(mail.send_alerts().await.unwrap(), 0);
+
+        // And not at all once it was read
+        notifications
+            .notify(ada.id, NotificationKind::BattleReport, START, None, "Seen")
+            .await
+            .unwrap();
+        notifications.read_all(ada.id).await.unwrap();
+        assert_eq!(mail.send_alerts().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_undeliverable_alerts_are_skipped() {
+        let (db, app_state, mail, mut received, ada) = setup().await;
+        let user_id = db
+            .create_user("bo", "not an address", "hash")
+            .await
+            .unwrap();
+        let (bo, _) = UserService::new(db.clone())
+            .join_galaxy(user_id, "mail", "bo", &app_state)
+            .await
+            .unwrap();
+        let notifications = NotificationService::new(db);
+        for account in [&bo, &ada] {
+            mail.set_alerts(account.user_id, &[NotificationKind::BattleReport])
+                .await
+                .unwrap();
+        }
+
+        // The older alert to an invalid address doesn't hold back the next one
+        notifications
+            .notify(bo.id, NotificationKind::BattleReport, START, None, "Lost")
+            .await
+            .unwrap();
+        notifications
+            .notify(ada.id, NotificationKind::BattleReport, START, None, "Won")
+            .await
+            .unwrap();
+        assert_eq!(mail.send_alerts().await.unwrap(), 1);
+        let email = received.recv().await.unwrap();
+        assert!(email.contains("To: ada@example.com"));
+        assert!(email.contains("Won"));
+
+        // Nor is it tried again
+        assert_eq!(mail.send_alerts().await.unwrap(), 0);
+        assert!(received.try_recv().is_err());
+    }
+}
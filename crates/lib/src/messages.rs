@@ -0,0 +1,354 @@
+/// Private messages between galaxy accounts
+///
+/// Every galaxy account has a mailbox of the messages it received and sent. Messages stay
+/// within a galaxy and are addressed by the recipient's account name. Both sides keep their own
+/// copy until they delete it, and an account can block others from messaging it.
+use crate::db::{Database, PersistenceError};
+use crate::models::{MessageRow, UserGalaxyAccountRow};
+use crate::webhooks::{WebhookError, WebhookEvent, WebhookService};
+
+/// Longest message subject
+pub const MAX_SUBJECT_LENGTH: usize = 100;
+
+/// Longest message body
+pub const MAX_BODY_LENGTH: usize = 5000;
+
+/// Messages shown per mailbox folder
+pub const MAILBOX_SIZE: usize = 100;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MessageError {
+    #[error("Database error: {0}")]
+    Database(#[from] PersistenceError),
+
+    #[error("Galaxy account not found")]
+    AccountNotFound,
+
+    #[error("No account named {0} in this galaxy")]
+    UnknownAccount(String),
+
+    #[error("Invalid message: {0}")]
+    InvalidMessage(String),
+
+    #[error("Message not found")]
+    MessageNotFound,
+
+    #[error("{0} isn't accepting your messages")]
+    Blocked(String),
+
+    #[error("You can't message or block yourself")]
+    OwnAccount,
+
+    #[error("{0} is already blocked")]
+    AlreadyBlocked(String),
+
+    #[error("{0} isn't blocked")]
+    NotBlocked(String),
+
+    #[error("Webhook error: {0}")]
+    Webhook(#[from] WebhookError),
+}
+
+/// Service for private messages
+pub struct MessageService {
+    db: Database,
+}
+
+impl MessageService {
+    /// Create a new message service
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// Send a message to the account with a name in the sender's galaxy
+    ///
+    /// The recipient's webhooks are told who sent it and its subject, but not its body.
+    pub async fn send(
+        &self,
+        account_id: i64,
+        recipient_name: &str,
+        subject: &str,
+        body: &str,
+    ) -> Result<MessageRow, MessageError> {
+        let account = self.account(account_id).await?;
+        let recipient = self
+            .named(&account.galaxy_name, recipient_name.trim())
+            .await?;
+        if recipient.id == account.id {
+            return Err(MessageError::OwnAccount);
+        }
+        let (subject, body) = (subject.trim(), body.trim());
+        if subject.is_empty() || subject.chars().count() > MAX_SUBJECT_LENGTH {
+            return Err(MessageError::InvalidMessage(format!(
+                "the subject must be 1 to {} characters",
+                MAX_SUBJECT_LENGTH
+            )));
+        }
+        if body.is_empty() || body.chars().count() > MAX_BODY_LENGTH {
+            return Err(MessageError::InvalidMessage(format!(
+                "the message must be 1 to {} characters",
+                MAX_BODY_LENGTH
+            )));
+        }
+        if self.db.is_account_blocked(recipient.id, account.id).await? {
+            return Err(MessageError::Blocked(recipient.account_name));
+        }
+
+        let message_id = self
+            .db
+            .send_message(
+                &account.galaxy_name,
+                account.id,
+                recipient.id,
+                subject,
+                body,
+            )
+            .await?;
+        let message = self
+            .db
+            .get_message(message_id)
+            .await?
+            .ok_or(MessageError::MessageNotFound)?;
+
+        let data = serde_json::json!({
+            "galaxy": message.galaxy_name,
+            "account": message.recipient_name,
+            "message_id": message.id,
+            "from": message.sender_name,
+            "subject": message.subject,
+        });
+        WebhookService::new(self.db.clone())
+            .enqueue(recipient.user_id, WebhookEvent::MessageReceived, data)
+            .await?;
+        Ok(message)
+    }
+
+    /// Get the latest messages an account received, newest first
+    pub async fn inbox(&self, account_id: i64) -> Result<Vec<MessageRow>, MessageError> {
+        Ok(self.db.get_inbox(account_id, MAILBOX_SIZE).await?)
+    }
+
+    /// Get the latest messages an account sent, newest first
+    pub async fn outbox(&self, account_id: i64) -> Result<Vec<MessageRow>, MessageError> {
+        Ok(self.db.get_outbox(account_id, MAILBOX_SIZE).await?)
+    }
+
+    /// Count the unread messages in an account's inbox
+    pub async fn unread_count(&self, account_id: i64) -> Result<usize, MessageError> {
+        Ok(self.db.count_unread_messages(account_id).await? as usize)
+    }
+
+    /// Open a message in an account's mailbox
+    ///
+    /// Opening a received message marks it as read.
+    pub async fn read(&self, account_id: i64, message_id: i64) -> Result<MessageRow, MessageError> {
+        let mut message = self.message(account_id, message_id).await?;
+        if message.recipient_id == account_id && !message.is_read() {
+            self.db.set_message_read(message.id, true).await?;
+            message = self.message(account_id, message_id).await?;
+        }
+        Ok(message)
+    }
+
+    /// Mark a received message as unread again
+    pub async fn mark_unread(&self, account_id: i64, message_id: i64) -> Result<(), MessageError> {
+        let message = self.message(account_id, message_id).await?;
+        if message.recipient_id != account_id {
+            return Err(MessageError::MessageNotFound);
+        }
+        self.db.set_message_read(message.id, false).await?;
+        Ok(())
+    }
+
+    /// Delete a message from an account's mailbox
+    ///
+    /// The other side keeps its copy.
+    pub async fn delete(&self, account_id: i64, message_id: i64) -> Result<(), MessageError> {
+        if !self.db.delete_message(message_id, account_id).await? {
+            return Err(MessageError::MessageNotFound);
+        }
+        Ok(())
+    }
+
+    /// Stop an account from messaging another, by its name in the galaxy
+    pub async fn block(&self, account_id: i64, account_name: &str) -> Result<(), MessageError> {
+        let account = self.account(account_id).await?;
+        let blocked = self
+            .named(&account.galaxy_name, account_name.trim())
+            .await?;
+        if blocked.id == account.id {
+            return Err(MessageError::OwnAccount);
+        }
+        if !self.db.block_account(account.id, blocked.id).await? {
+            return Err(MessageError::AlreadyBlocked(blocked.account_name));
+        }
+        Ok(())
+    }
+
+    /// Let a blocked account message another again
+    pub async fn unblock(&self, account_id: i64, account_name: &str) -> Result<(), MessageError> {
+        let account = self.account(account_id).await?;
+        let blocked = self
+            .named(&account.galaxy_name, account_name.trim())
+            .await?;
+        if !self.db.unblock_account(account.id, blocked.id).await? {
+            return Err(MessageError::NotBlocked(blocked.account_name));
+        }
+        Ok(())
+    }
+
+    /// Get the accounts an account has blocked
+    pub async fn blocked(
+        &self,
+        account_id: i64,
+    ) -> Result<Vec<UserGalaxyAccountRow>, MessageError> {
+        Ok(self.db.get_blocked_accounts(account_id).await?)
+    }
+
+    /// A message that is still in an account's mailbox
+    async fn message(&self, account_id: i64, message_id: i64) -> Result<MessageRow, MessageError> {
+        self.db
+            .get_account_message(message_id, account_id)
+            .await?
+            .ok_or(MessageError::MessageNotFound)
+    }
+
+    async fn account(&self, account_id: i64) -> Result<UserGalaxyAccountRow, MessageError> {
+        self.db
+            .get_galaxy_account(account_id)
+            .await?
+            .ok_or(MessageError::AccountNotFound)
+    }
+
+    async fn named(
+        &self,
+        galaxy_name: &str,
+        account_name: &str,
+    ) -> Result<UserGalaxyAccountRow, MessageError> {
+        self.db
+            .get_galaxy_account_by_name(galaxy_name, account_name)
+            .await?
+            .ok_or_else(|| MessageError::UnknownAccount(account_name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{self, join, CONFIG};
+    use crate::{AppState, UserGalaxyAccount};
+
+    /// Ada and Bo in one galaxy, Cy in another
+    async fn setup() -> (
+        Database,
+        AppState,
+        MessageService,
+        UserGalaxyAccount,
+        UserGalaxyAccount,
+    ) {
+        let (_, db, app_state) = test_utils::setup(CONFIG, &["mail", "elsewhere"]).await;
+        let (ada, _) = join(&db, &app_state, "mail", "Ada").await;
+        let (bo, _) = join(&db, &app_state, "mail", "Bo").await;
+        join(&db, &app_state, "elsewhere", "Cy").await;
+        (db.clone(), app_state, MessageService::new(db), ada, bo)
+    }
+
+    #[tokio::test]
+    async fn test_send() {
+        let (_db, _app_state, messages, ada, bo) = setup().await;
+
+        // Messages are addressed by name within the galaxy
+        let sent = messages
+            .send(ada.id, "Bo", "Trade", "Metal for water?")
+            .await
+            .unwrap();
+        assert_eq!(sent.recipient_name, "Bo");
+        assert_eq!(messages.outbox(ada.id).await.unwrap()[0].id, sent.id);
+        assert_eq!(messages.inbox(bo.id).await.unwrap()[0].id, sent.id);
+        assert!(matches!(
+            messages.send(ada.id, "Cy", "Hello", "Anyone there?").await,
+            Err(MessageError::UnknownAccount(_))
+        ));
+        assert!(matches!(
+            messages.send(ada.id, "Bo", " ", "No subject").await,
+            Err(MessageError::InvalidMessage(_))
+        ));
+        assert!(matches!(
+            messages.send(ada.id, "Ada", "Me", "Myself").await,
+            Err(MessageError::OwnAccount)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_and_unread() {
+        let (_db, _app_state, messages, ada, bo) = setup().await;
+        let sent = messages
+            .send(ada.id, "Bo", "Trade", "Metal for water?")
+            .await
+            .unwrap();
+
+        // Reading marks the message read, and it can be marked unread again
+        assert_eq!(messages.unread_count(bo.id).await.unwrap(), 1);
+        let read = messages.read(bo.id, sent.id).await.unwrap();
+        assert!(read.is_read());
+        assert_eq!(messages.unread_count(bo.id).await.unwrap(), 0);
+        messages.mark_unread(bo.id, sent.id).await.unwrap();
+        assert_eq!(messages.unread_count(bo.id).await.unwrap(), 1);
+
+        // Only by its recipient
+        assert!(matches!(
+            messages.mark_unread(ada.id, sent.id).await,
+            Err(MessageError::MessageNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_block() {
+        let (_db, _app_state, messages, ada, bo) = setup().await;
+
+        // Blocked players can't send messages, but can still get them
+        messages.block(bo.id, "Ada").await.unwrap();
+        assert!(matches!(
+            messages.send(ada.id, "Bo", "Please", "Reconsider").await,
+            Err(MessageError::Blocked(_))
+        ));
+        messages
+            .send(bo.id, "Ada", "Sorry", "Not now")
+            .await
+            .unwrap();
+        assert_eq!(
+            messages.blocked(bo.id).await.unwrap()[0].account_name,
+            "Ada"
+        );
+
+        messages.unblock(bo.id, "Ada").await.unwrap();
+        assert!(matches!(
+            messages.unblock(bo.id, "Ada").await,
+            Err(MessageError::NotBlocked(_))
+        ));
+        messages
+            .send(ada.id, "Bo", "Thanks", "Talk soon")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_delete() {
+        let (db, _app_state, messages, ada, bo) = setup().await;
+        let sent = messages
+            .send(ada.id, "Bo", "Trade", "Metal for water?")
+            .await
+            .unwrap();
+
+        // Each side deletes its own copy, and the message is gone once both did
+        messages.delete(bo.id, sent.id).await.unwrap();
+        assert!(messages.inbox(bo.id).await.unwrap().is_empty());
+        assert!(matches!(
+            messages.read(bo.id, sent.id).await,
+            Err(MessageError::MessageNotFound)
+        ));
+        assert_eq!(messages.read(ada.id, sent.id).await.unwrap().id, sent.id);
+        messages.delete(ada.id, sent.id).await.unwrap();
+        assert!(db.get_message(sent.id).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,21 @@
+use chrono::{DateTime, Utc};
+
+/// Database row representing an alliance in a galaxy
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct AllianceRow {
+    pub id: i64,
+    pub galaxy_name: String,
+    pub name: String,
+    pub tag: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Database row representing an invite to join an alliance
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct AllianceInviteRow {
+    pub alliance_id: i64,
+    pub user_galaxy_account_id: i64,
+    /// Account that sent the invite
+    pub invited_by: i64,
+    pub created_at: DateTime<Utc>,
+}
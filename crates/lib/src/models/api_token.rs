@@ -0,0 +1,15 @@
+use chrono::{DateTime, Utc};
+
+/// Database row representing a personal API token of a user
+///
+/// The token itself isn't stored, only its hash.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct ApiTokenRow {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    /// read_only, play or admin
+    pub scope: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+
+/// Database row representing a chat channel
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct ChatChannelRow {
+    pub id: i64,
+    pub galaxy_name: String,
+    /// galaxy, alliance or group
+    pub kind: String,
+    /// Alliance of an alliance channel
+    pub alliance_id: Option<i64>,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Database row representing a message posted in a chat channel
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct ChatMessageRow {
+    pub id: i64,
+    pub channel_id: i64,
+    pub user_galaxy_account_id: i64,
+    pub account_name: String,
+    pub body: String,
+    pub sent_at: DateTime<Utc>,
+}
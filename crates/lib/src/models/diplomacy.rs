@@ -0,0 +1,39 @@
+/// Database row representing the relation between two alliances
+///
+/// Each pair is stored once, with `alliance_a` the lower id.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct AllianceRelationRow {
+    pub galaxy_name: String,
+    pub alliance_a: i64,
+    pub alliance_b: i64,
+    pub relation: String,
+    pub since: i64,
+    pub expires_at: Option<i64>,
+}
+
+/// Database row representing a pending diplomatic proposal
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct DiplomacyProposalRow {
+    pub id: i64,
+    pub galaxy_name: String,
+    pub from_alliance: i64,
+    pub to_alliance: i64,
+    pub relation: String,
+    /// Account that made the proposal
+    pub proposed_by: i64,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+/// Database row representing an entry of a galaxy's public diplomacy log
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct DiplomacyLogRow {
+    pub id: i64,
+    pub galaxy_name: String,
+    pub tick: i64,
+    pub alliance_a: i64,
+    pub alliance_b: i64,
+    pub action: String,
+    pub relation: String,
+    pub message: String,
+}
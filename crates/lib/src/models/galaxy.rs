@@ -6,6 +6,10 @@ pub struct GalaxyRow {
     pub name: String,
     pub config_file: String,
     pub tick: i64,
+    pub seed: Option<i64>,
+    pub ended_at: Option<i64>,
+    pub paused_at: Option<i64>,
+    pub paused_offset: i64,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -17,6 +21,10 @@ impl GalaxyRow {
             name,
             config_file,
             tick: tick as i64,
+            seed: None,
+            ended_at: None,
+            paused_at: None,
+            paused_offset: 0,
             created_at: now,
             updated_at: now,
         }
@@ -25,4 +33,72 @@ impl GalaxyRow {
     pub fn tick_as_usize(&self) -> usize {
         self.tick as usize
     }
+
+    pub fn seed_as_u64(&self) -> Option<u64> {
+        self.seed.map(|seed| seed as u64)
+    }
+
+    pub fn ended_at_as_usize(&self) -> Option<usize> {
+        self.ended_at.map(|tick| tick as usize)
+    }
+
+    pub fn paused_at_as_usize(&self) -> Option<usize> {
+        self.paused_at.map(|tick| tick as usize)
+    }
+}
+
+/// Database row representing a player's final position in an ended galaxy
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct GalaxyStandingRow {
+    pub galaxy_name: String,
+    pub rank: i64,
+    pub user_galaxy_account_id: i64,
+    pub account_name: String,
+    pub score: i64,
+    pub systems: i64,
+}
+
+impl GalaxyStandingRow {
+    pub fn to_standing(&self) -> crate::lifecycle::Standing {
+        crate::lifecycle::Standing {
+            rank: self.rank as usize,
+            account_id: self.user_galaxy_account_id,
+            score: self.score as usize,
+            systems: self.systems as usize,
+        }
+    }
+}
+
+/// Database row representing a player's score in a snapshot
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct ScoreHistoryRow {
+    pub galaxy_name: String,
+    pub tick: i64,
+    pub user_galaxy_account_id: i64,
+    pub account_name: String,
+    pub rank: i64,
+    pub score: i64,
+    pub systems: i64,
+    pub economy: i64,
+    pub military: i64,
+    pub defense: i64,
+}
+
+impl ScoreHistoryRow {
+    pub fn to_standing(&self) -> crate::lifecycle::Standing {
+        crate::lifecycle::Standing {
+            rank: self.rank as usize,
+            account_id: self.user_galaxy_account_id,
+            score: self.score as usize,
+            systems: self.systems as usize,
+        }
+    }
+
+    pub fn scores(&self) -> crate::scoring::Scores {
+        crate::scoring::Scores {
+            economy: self.economy as usize,
+            military: self.military as usize,
+            defense: self.defense as usize,
+        }
+    }
 }
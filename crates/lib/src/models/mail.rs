@@ -0,0 +1,12 @@
+/// A notification to mail to the user of its account
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct MailAlertRow {
+    pub notification_id: i64,
+    /// Notification kind
+    pub kind: String,
+    pub message: String,
+    pub galaxy_name: String,
+    pub account_name: String,
+    pub username: String,
+    pub email: String,
+}
@@ -0,0 +1,23 @@
+use chrono::{DateTime, Utc};
+
+/// Database row representing a private message between two galaxy accounts
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct MessageRow {
+    pub id: i64,
+    pub galaxy_name: String,
+    pub sender_id: i64,
+    pub sender_name: String,
+    pub recipient_id: i64,
+    pub recipient_name: String,
+    pub subject: String,
+    pub body: String,
+    pub sent_at: DateTime<Utc>,
+    /// When the recipient read the message, None while it's unread
+    pub read_at: Option<DateTime<Utc>>,
+}
+
+impl MessageRow {
+    pub fn is_read(&self) -> bool {
+        self.read_at.is_some()
+    }
+}
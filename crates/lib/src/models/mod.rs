@@ -1,10 +1,26 @@
+pub mod alliance;
+pub mod api_token;
+pub mod chat;
+pub mod diplomacy;
 pub mod events;
 pub mod galaxy;
+pub mod mail;
+pub mod message;
+pub mod notification;
 pub mod system;
 pub mod user;
+pub mod webhook;
 
 // Re-export commonly used types
+pub use alliance::*;
+pub use api_token::*;
+pub use chat::*;
+pub use diplomacy::*;
 pub use events::*;
 pub use galaxy::*;
+pub use mail::*;
+pub use message::*;
+pub use notification::*;
 pub use system::*;
 pub use user::*;
+pub use webhook::*;
@@ -0,0 +1,24 @@
+use chrono::{DateTime, Utc};
+
+/// Database row representing a notification for a galaxy account
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct NotificationRow {
+    pub id: i64,
+    pub user_galaxy_account_id: i64,
+    /// build, incoming_attack, scout_detected, battle_report or diplomacy
+    pub kind: String,
+    pub tick: i64,
+    /// System the notification is about, if any
+    pub system_x: Option<i64>,
+    pub system_y: Option<i64>,
+    pub message: String,
+    pub created_at: DateTime<Utc>,
+    /// When the account read the notification, None while it's unread
+    pub read_at: Option<DateTime<Utc>>,
+}
+
+impl NotificationRow {
+    pub fn is_read(&self) -> bool {
+        self.read_at.is_some()
+    }
+}
@@ -81,3 +81,61 @@ impl StructureRow {
         self.level as usize
     }
 }
+
+/// Database row representing an NPC raid on a player system
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttackReportRow {
+    pub tick: i64,
+    pub attacker_x: i64,
+    pub attacker_y: i64,
+    pub target_x: i64,
+    pub target_y: i64,
+    pub raiders: i64,
+    pub loot_metal: i64,
+    pub loot_crew: i64,
+    pub loot_water: i64,
+    pub unreported: bool,
+}
+
+impl AttackReportRow {
+    pub fn to_attack_report(&self) -> crate::npc::AttackReport {
+        crate::npc::AttackReport {
+            tick: self.tick as usize,
+            attacker: (self.attacker_x as usize, self.attacker_y as usize).into(),
+            target: (self.target_x as usize, self.target_y as usize).into(),
+            raiders: self.raiders as usize,
+            loot: crate::Resources {
+                metal: self.loot_metal as usize,
+                crew: self.loot_crew as usize,
+                water: self.loot_water as usize,
+            },
+        }
+    }
+}
+
+/// Database row representing the state of an NPC system
+#[derive(Debug, Clone, PartialEq)]
+pub struct NpcSystemRow {
+    pub system_id: i64,
+    pub x: i64,
+    pub y: i64,
+    pub defenders: i64,
+    pub updated_tick: i64,
+    pub next_build: i64,
+    pub next_attack: Option<i64>,
+}
+
+impl NpcSystemRow {
+    pub fn coords(&self) -> crate::Coords {
+        (self.x as usize, self.y as usize).into()
+    }
+
+    pub fn to_npc_state(&self) -> crate::npc::NpcState {
+        crate::npc::NpcState {
+            defenders: self.defenders as usize,
+            updated: self.updated_tick as usize,
+            next_build: self.next_build as usize,
+            next_attack: self.next_attack.map(|tick| tick as usize),
+        }
+    }
+}
@@ -34,6 +34,12 @@ pub struct UserGalaxyAccountRow {
     pub account_name: String,
     pub joined_at: DateTime<Utc>,
     pub last_active: DateTime<Utc>,
+    /// Strategy playing the account, None for human players
+    pub ai_strategy: Option<String>,
+    /// Alliance the account is a member of
+    pub alliance_id: Option<i64>,
+    /// Role of the account in its alliance
+    pub alliance_role: Option<String>,
 }
 
 impl UserGalaxyAccountRow {
@@ -46,9 +52,16 @@ impl UserGalaxyAccountRow {
             account_name,
             joined_at: now,
             last_active: now,
+            ai_strategy: None,
+            alliance_id: None,
+            alliance_role: None,
         }
     }
 
+    pub fn is_ai(&self) -> bool {
+        self.ai_strategy.is_some()
+    }
+
     pub fn update_last_active(&mut self) {
         self.last_active = Utc::now();
     }
@@ -105,6 +118,18 @@ pub struct UserGalaxyAccount {
     pub account_name: String,
     pub joined_at: DateTime<Utc>,
     pub last_active: DateTime<Utc>,
+    /// Strategy playing the account, None for human players
+    pub ai_strategy: Option<String>,
+    /// Alliance the account is a member of
+    pub alliance_id: Option<i64>,
+    /// Role of the account in its alliance
+    pub alliance_role: Option<String>,
+}
+
+impl UserGalaxyAccount {
+    pub fn is_ai(&self) -> bool {
+        self.ai_strategy.is_some()
+    }
 }
 
 impl From<UserGalaxyAccountRow> for UserGalaxyAccount {
@@ -116,6 +141,9 @@ impl From<UserGalaxyAccountRow> for UserGalaxyAccount {
             account_name: row.account_name,
             joined_at: row.joined_at,
             last_active: row.last_active,
+            ai_strategy: row.ai_strategy,
+            alliance_id: row.alliance_id,
+            alliance_role: row.alliance_role,
         }
     }
 }
@@ -0,0 +1,34 @@
+use chrono::{DateTime, Utc};
+
+/// Database row representing a webhook of a user
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct WebhookRow {
+    pub id: i64,
+    pub user_id: i64,
+    pub url: String,
+    /// Key of the HMAC signature of every payload
+    pub secret: String,
+    /// Comma separated events the webhook receives
+    pub events: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Database row representing a payload sent, or to be sent, to a webhook
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct WebhookDeliveryRow {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub event: String,
+    pub payload: String,
+    /// pending, delivered or failed
+    pub status: String,
+    pub attempts: i64,
+    /// Tick of the next attempt while pending
+    pub next_attempt_at: i64,
+    /// HTTP status of the last attempt, None if it got no response
+    pub response_status: Option<i64>,
+    /// Why the last attempt failed
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,371 @@
+/// Notifications of galaxy accounts
+///
+/// Accounts are told when one of their builds finishes, when their systems are attacked or
+/// scouted, and when their alliance's diplomacy changes. Notifications are kept with their read
+/// state until newer ones push them out.
+use crate::db::{Database, PersistenceError};
+use crate::models::NotificationRow;
+use crate::webhooks::{WebhookError, WebhookEvent, WebhookService};
+use crate::{Coords, GameNotice};
+
+/// Notifications kept per account, older ones are deleted
+pub const KEPT_NOTIFICATIONS: usize = 200;
+
+/// Notifications listed at once
+pub const LIST_LENGTH: usize = 50;
+
+/// What a notification is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NotificationKind {
+    /// A structure upgrade finished
+    Build,
+    /// An attack is on its way to one of the account's systems
+    IncomingAttack,
+    /// One of the account's systems was scouted
+    ScoutDetected,
+    /// One of the account's systems was attacked
+    BattleReport,
+    /// The relation of the account's alliance with another changed
+    Diplomacy,
+}
+
+impl NotificationKind {
+    /// The webhook event sent along with a notification of this kind, if any
+    pub fn webhook_event(&self) -> Option<WebhookEvent> {
+        match self {
+            NotificationKind::Build => Some(WebhookEvent::BuildComplete),
+            NotificationKind::IncomingAttack | NotificationKind::BattleReport => {
+                Some(WebhookEvent::UnderAttack)
+            }
+            NotificationKind::ScoutDetected | NotificationKind::Diplomacy => None,
+        }
+    }
+}
+
+impl std::fmt::Display for NotificationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotificationKind::Build => write!(f, "build"),
+            NotificationKind::IncomingAttack => write!(f, "incoming_attack"),
+            NotificationKind::ScoutDetected => write!(f, "scout_detected"),
+            NotificationKind::BattleReport => write!(f, "battle_report"),
+            NotificationKind::Diplomacy => write!(f, "diplomacy"),
+        }
+    }
+}
+
+impl std::str::FromStr for NotificationKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "build" => Ok(NotificationKind::Build),
+            "incoming_attack" => Ok(NotificationKind::IncomingAttack),
+            "scout_detected" => Ok(NotificationKind::ScoutDetected),
+            "battle_report" => Ok(NotificationKind::BattleReport),
+            "diplomacy" => Ok(NotificationKind::Diplomacy),
+            _ => Err(format!("Unknown notification kind {}", s)),
+        }
+    }
+}
+
+/// Describe a game notice to the owner of its system
+pub fn describe(notice: &GameNotice) -> (NotificationKind, String) {
+    match notice {
+        GameNotice::Build { coords, build } => (
+            NotificationKind::Build,
+            format!(
+                "{} reached level {} in system ({}, {})",
+                build.structure, build.level, coords.x, coords.y
+            ),
+        ),
+        GameNotice::Raid(report) => (
+            NotificationKind::BattleReport,
+            format!(
+                "System ({}, {}) was raided by {} raiders from ({}, {}), who took {} metal, {} crew and {} water",
+                report.target.x,
+                report.target.y,
+                report.raiders,
+                report.attacker.x,
+                report.attacker.y,
+                report.loot.metal,
+                report.loot.crew,
+                report.loot.water
+            ),
+        ),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum NotificationError {
+    #[error("Database error: {0}")]
+    Database(#[from] PersistenceError),
+
+    #[error("Notification not found")]
+    NotificationNotFound,
+
+    #[error("Webhook error: {0}")]
+    Webhook(#[from] WebhookError),
+}
+
+/// Service for notifications
+pub struct NotificationService {
+    db: Database,
+}
+
+impl NotificationService {
+    /// Create a new notification service
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// Notify an account of something that happened at a tick, optionally in one of its systems
+    ///
+    /// The webhooks of the account's user that receive the kind's event are sent it too.
+    pub async fn notify(
+        &self,
+        account_id: i64,
+        kind: NotificationKind,
+        tick: usize,
+        coords: Option<Coords>,
+        message: &str,
+    ) -> Result<i64, NotificationError> {
+        let id = self
+            .db
+            .add_notification(&NotificationRow {
+                id: 0,
+                user_galaxy_account_id: account_id,
+                kind: kind.to_string(),
+                tick: tick as i64,
+                system_x: coords.map(|coords| coords.x as i64),
+                system_y: coords.map(|coords| coords.y as i64),
+                message: message.to_string(),
+                created_at: chrono::Utc::now(),
+                read_at: None,
+            })
+            .await?;
+        self.db
+            .delete_old_notifications(account_id, KEPT_NOTIFICATIONS)
+            .await?;
+
+        if let Some(event) = kind.webhook_event() {
+            if let Some(account) = self.db.get_galaxy_account(account_id).await? {
+                let data = serde_json::json!({
+                    "galaxy": account.galaxy_name,
+                    "account": account.account_name,
+                    "kind": kind.to_string(),
+                    "tick": tick,
+                    "system": coords.map(|coords| serde_json::json!({"x": coords.x, "y": coords.y})),
+                    "message": message,
+                });
+                WebhookService::new(self.db.clone())
+                    .enqueue(account.user_id, event, data)
+                    .await?;
+            }
+        }
+        Ok(id)
+    }
+
+    /// Notify the owner of a system of a game notice about it
+    pub async fn notify_notice(
+        &self,
+        account_id: i64,
+        notice: &GameNotice,
+    ) -> Result<i64, NotificationError> {
+        let (kind, message) = describe(notice);
+        self.notify(
+            account_id,
+            kind,
+            notice.tick(),
+            Some(notice.coords()),
+            &message,
+        )
+        .await
+    }
+
+    /// Get the latest notifications of an account, newest first
+    pub async fn list(
+        &self,
+        account_id: i64,
+        unread_only: bool,
+    ) -> Result<Vec<NotificationRow>, NotificationError> {
+        Ok(self
+            .db
+            .get_notifications(account_id, unread_only, LIST_LENGTH)
+            .await?)
+    }
+
+    /// Count the unread notifications of an account
+    pub async fn unread_count(&self, account_id: i64) -> Result<usize, NotificationError> {
+        Ok(self.db.count_unread_notifications(account_id).await? as usize)
+    }
+
+    /// Mark a notification of an account as read
+    pub async fn read(
+        &self,
+        account_id: i64,
+        notification_id: i64,
+    ) -> Result<(), NotificationError> {
+        if !self
+            .db
+            .set_notification_read(notification_id, account_id)
+            .await?
+        {
+            return Err(NotificationError::NotificationNotFound);
+        }
+        Ok(())
+    }
+
+    /// Mark every notification of an account as read, returning how many were unread
+    pub async fn read_all(&self, account_id: i64) -> Result<usize, NotificationError> {
+        Ok(self.db.set_all_notifications_read(account_id).await? as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alliance::AllianceService;
+    use crate::diplomacy::DiplomacyService;
+    use crate::test_utils::{self, join, CONFIG, START};
+    use crate::{AppState, Coords, ManualClock, StructureType, UserGalaxyAccount};
+    use std::sync::Arc;
+
+    /// Ada and Bo in a galaxy
+    struct News {
+        clock: Arc<ManualClock>,
+        db: Database,
+        app_state: AppState,
+        notifications: NotificationService,
+        ada: UserGalaxyAccount,
+        ada_home: Coords,
+        bo: UserGalaxyAccount,
+    }
+
+    async fn setup() -> News {
+        let (clock, db, app_state) = test_utils::setup(CONFIG, &["news"]).await;
+        let (ada, ada_home) = join(&db, &app_state, "news", "Ada").await;
+        let (bo, _) = join(&db, &app_state, "news", "Bo").await;
+        News {
+            clock,
+            notifications: NotificationService::new(db.clone()),
+            db,
+            app_state,
+            ada,
+            ada_home,
+            bo,
+        }
+    }
+
+    /// Have Ada's mine upgrade finish and collect the notification
+    async fn finish_build(news: &News) {
+        news.app_state
+            .build_structure("news", START, news.ada_home, StructureType::AsteroidMine)
+            .await
+            .unwrap();
+        news.clock.advance(600);
+        news.app_state
+            .collect_notifications("news", news.app_state.tick())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_build_finished() {
+        let News {
+            clock,
+            app_state,
+            notifications,
+            ada,
+            ada_home,
+            bo,
+            ..
+        } = setup().await;
+
+        // A build is only reported once it has finished, to the owner of the system
+        app_state
+            .build_structure("news", START, ada_home, StructureType::AsteroidMine)
+            .await
+            .unwrap();
+        clock.advance(300);
+        assert_eq!(
+            app_state
+                .collect_notifications("news", app_state.tick())
+                .await
+                .unwrap(),
+            0
+        );
+        clock.advance(600);
+        assert_eq!(
+            app_state
+                .collect_notifications("news", app_state.tick())
+                .await
+                .unwrap(),
+            1
+        );
+        let list = notifications.list(ada.id, false).await.unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].kind, NotificationKind::Build.to_string());
+        assert_eq!(list[0].tick as usize, START + 600);
+        assert_eq!(
+            (list[0].system_x, list[0].system_y),
+            (Some(ada_home.x as i64), Some(ada_home.y as i64))
+        );
+        assert!(notifications.list(bo.id, false).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_read() {
+        let news = setup().await;
+        finish_build(&news).await;
+        let News {
+            notifications,
+            ada,
+            bo,
+            ..
+        } = news;
+        let list = notifications.list(ada.id, false).await.unwrap();
+
+        // Reading marks it read for that account only
+        assert_eq!(notifications.unread_count(ada.id).await.unwrap(), 1);
+        assert!(matches!(
+            notifications.read(bo.id, list[0].id).await,
+            Err(NotificationError::NotificationNotFound)
+        ));
+        notifications.read(ada.id, list[0].id).await.unwrap();
+        assert_eq!(notifications.unread_count(ada.id).await.unwrap(), 0);
+        assert!(notifications.list(ada.id, true).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_declaration_of_war() {
+        let News {
+            db,
+            app_state,
+            notifications,
+            ada,
+            bo,
+            ..
+        } = setup().await;
+
+        // Every member of both alliances hears about a declaration of war
+        let alliances = AllianceService::new(db.clone());
+        let star = alliances
+            .create(ada.id, "Star League", "STAR")
+            .await
+            .unwrap();
+        let red = alliances.create(bo.id, "Red Fleet", "RED").await.unwrap();
+        DiplomacyService::new(db.clone())
+            .declare_war(bo.id, star.id, app_state.tick())
+            .await
+            .unwrap();
+        for account in [ada.id, bo.id] {
+            let unread = notifications.list(account, true).await.unwrap();
+            assert_eq!(unread.len(), 1);
+            assert_eq!(unread[0].kind, NotificationKind::Diplomacy.to_string());
+            assert!(unread[0].message.contains(&red.name));
+        }
+        assert_eq!(notifications.read_all(bo.id).await.unwrap(), 1);
+        assert_eq!(notifications.unread_count(bo.id).await.unwrap(), 0);
+    }
+}
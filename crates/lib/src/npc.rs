@@ -0,0 +1,187 @@
+/// NPC barbarian systems
+///
+/// A share of the generated systems is run by the galaxy itself. Every NPC system upgrades a
+/// structure on a schedule, gathers defenders over time and raids the closest player system in
+/// range. Their actions are taken in tick order as the galaxy moves forward.
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashSet;
+
+use crate::config::NpcConfig;
+use crate::layout::distance;
+use crate::{Coords, Resources};
+
+/// State of an NPC system
+#[derive(Debug, Clone, PartialEq)]
+pub struct NpcState {
+    /// Defenders stationed in the system
+    pub defenders: usize,
+    /// Tick the defenders were last counted at
+    pub updated: usize,
+    /// Tick of the next build
+    pub next_build: usize,
+    /// Tick of the next raid, None when raids are disabled
+    pub next_attack: Option<usize>,
+}
+
+/// A raid by an NPC system on a player system
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttackReport {
+    pub tick: usize,
+    pub attacker: Coords,
+    pub target: Coords,
+    pub raiders: usize,
+    pub loot: Resources,
+}
+
+impl NpcConfig {
+    /// Ticks between two builds at the given galaxy speed
+    pub fn build_delay(&self, speed: f64) -> usize {
+        ((self.build_interval as f64 / speed) as usize).max(1)
+    }
+
+    /// Ticks until the next raid at the given galaxy speed, or None if raids are disabled
+    ///
+    /// Raids are spread between half and one and a half times the interval so NPC systems
+    /// don't all strike together.
+    pub fn attack_delay(&self, speed: f64, rng: &mut StdRng) -> Option<usize> {
+        if self.attack_interval == 0 {
+            return None;
+        }
+        let interval = self.attack_interval as f64 / speed;
+        Some((rng.gen_range(interval / 2.0..=interval * 1.5) as usize).max(1))
+    }
+}
+
+impl NpcState {
+    /// Create the state of a new NPC system
+    pub fn new(tick: usize, config: &NpcConfig, speed: f64, rng: &mut StdRng) -> Self {
+        Self {
+            defenders: 0,
+            updated: tick,
+            next_build: tick + config.build_delay(speed),
+            next_attack: config.attack_delay(speed, rng).map(|delay| tick + delay),
+        }
+    }
+
+    /// Tick of the next action of the system
+    pub fn next_action(&self) -> usize {
+        self.next_build.min(self.next_attack.unwrap_or(usize::MAX))
+    }
+
+    /// Gather the defenders trained up to a tick
+    pub fn gather_defenders(&mut self, tick: usize, config: &NpcConfig, speed: f64) {
+        if tick <= self.updated {
+            return;
+        }
+        // Same as production, count whole defenders since tick 0 so fractions carry over
+        let rate = (config.defenders_per_hour as f64 * speed) as usize;
+        let trained = tick * rate / 3600 - self.updated * rate / 3600;
+        self.defenders = (self.defenders + trained).min(config.max_defenders);
+        self.updated = tick;
+    }
+
+    /// Number of defenders sent on a raid, half of them stay home
+    pub fn raiders(&self) -> usize {
+        self.defenders / 2
+    }
+}
+
+/// Choose which of the generated systems are run by NPCs
+pub fn choose_systems(config: &NpcConfig, coords: &[Coords], rng: &mut StdRng) -> Vec<Coords> {
+    let count = (config.fraction.clamp(0.0, 1.0) * coords.len() as f64).round() as usize;
+    if count == 0 {
+        return Vec::new();
+    }
+    // Sort so the choice only depends on the RNG, not the input ordering
+    let mut coords = coords.to_vec();
+    coords.sort_by_key(|c| (c.x, c.y));
+    coords.choose_multiple(rng, count).cloned().collect()
+}
+
+/// Find the closest player system within range of an NPC system
+pub fn find_target(
+    config: &NpcConfig,
+    attacker: Coords,
+    players: &HashSet<Coords>,
+) -> Option<Coords> {
+    players
+        .iter()
+        .map(|target| (distance(attacker, *target), *target))
+        .filter(|(distance, _)| *distance <= config.attack_range)
+        // Ties are broken by coordinates so the choice doesn't depend on the HashSet ordering
+        .min_by(|a, b| {
+            a.0.total_cmp(&b.0)
+                .then((a.1.x, a.1.y).cmp(&(b.1.x, b.1.y)))
+        })
+        .map(|(_, target)| target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn create_test_coords() -> Vec<Coords> {
+        (0..20).map(|i| (i, i * 2).into()).collect()
+    }
+
+    #[test]
+    fn test_choose_systems() {
+        let config = NpcConfig {
+            fraction: 0.25,
+            ..Default::default()
+        };
+        let coords = create_test_coords();
+        let chosen = choose_systems(&config, &coords, &mut StdRng::seed_from_u64(1));
+        assert_eq!(chosen.len(), 5);
+        assert!(chosen.iter().all(|c| coords.contains(c)));
+
+        // The input order doesn't matter
+        let mut reversed = coords.clone();
+        reversed.reverse();
+        assert_eq!(
+            choose_systems(&config, &reversed, &mut StdRng::seed_from_u64(1)),
+            chosen
+        );
+
+        let none = NpcConfig::default();
+        assert!(choose_systems(&none, &coords, &mut StdRng::seed_from_u64(1)).is_empty());
+    }
+
+    #[test]
+    fn test_gather_defenders() {
+        let config = NpcConfig {
+            defenders_per_hour: 2,
+            max_defenders: 5,
+            ..Default::default()
+        };
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut npc = NpcState::new(0, &config, 1.0, &mut rng);
+        npc.gather_defenders(1800, &config, 1.0);
+        assert_eq!(npc.defenders, 1);
+        npc.gather_defenders(3600, &config, 1.0);
+        assert_eq!(npc.defenders, 2);
+        assert_eq!(npc.raiders(), 1);
+        npc.gather_defenders(36000, &config, 1.0);
+        assert_eq!(npc.defenders, 5);
+    }
+
+    #[test]
+    fn test_find_target() {
+        let config = NpcConfig {
+            attack_range: 5.0,
+            ..Default::default()
+        };
+        let players: HashSet<Coords> = [(3, 4).into(), (10, 10).into(), (0, 5).into()]
+            .into_iter()
+            .collect();
+        // (0, 5) and (3, 4) are both 5 away, the lowest coordinates win
+        assert_eq!(
+            find_target(&config, (0, 0).into(), &players),
+            Some((0, 5).into())
+        );
+        assert_eq!(find_target(&config, (20, 0).into(), &players), None);
+    }
+}
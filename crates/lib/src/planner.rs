@@ -0,0 +1,587 @@
+/// Build-order planner
+///
+/// Searches for the order of builds that reaches a goal soonest. Plans are made on a copy of
+/// the system's state using the same costs, production and storage as the system itself, so a
+/// plan followed step by step plays out exactly as predicted.
+///
+/// The search is a beam search: every step the most promising partial plans are extended with
+/// one more build, and each of them is scored by finishing it with a simple rule. For a
+/// structure level that rule is upgrading the target until it's reached, for a score it's
+/// upgrading whatever finishes first.
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::config::{GalaxyConfig, StructureConfig};
+use crate::game_system::{produced, produced_by};
+use crate::scoring;
+use crate::{Resources, StructureType, System};
+
+/// Number of partial plans kept at each step of the search
+const BEAM_WIDTH: usize = 16;
+
+/// Number of builds searched before the rest of the plan follows the simple rule
+const MAX_STEPS: usize = 200;
+
+/// Ticks waited at most for the resources to line up when they are just short of a cost
+const MAX_SETTLE: usize = 3600;
+
+/// Highest structure level a plan can aim for
+pub const MAX_LEVEL: usize = 100;
+
+/// Furthest ahead a score goal can be, in hours
+pub const MAX_HOURS: usize = 7 * 24;
+
+/// What a plan tries to achieve
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Goal {
+    /// Reach a level of a structure as soon as possible
+    Level {
+        structure: StructureType,
+        level: usize,
+    },
+    /// Have the highest score possible at a tick
+    Score { deadline: usize },
+}
+
+/// A build in a plan
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanStep {
+    pub structure: StructureType,
+    /// Level the structure is upgraded to
+    pub level: usize,
+    /// Tick the build can start, once the resources are available
+    pub start: usize,
+    /// Tick the build completes
+    pub completion: usize,
+}
+
+/// A build order for a system
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildPlan {
+    pub goal: Goal,
+    pub steps: Vec<PlanStep>,
+    /// Tick the goal is reached, or the deadline for a score goal
+    pub finish: usize,
+    /// Score of the system at the finish
+    pub score: usize,
+}
+
+/// State of a system while planning
+#[derive(Debug, Clone)]
+struct State {
+    tick: usize,
+    resources: Resources,
+    /// Completed level of each structure, in the order of `Planner::structures`
+    levels: Vec<usize>,
+    /// Index of the structure being built and its completion
+    building: Option<(usize, usize)>,
+    /// Production and storage of the completed levels
+    production: Resources,
+    storage: Resources,
+    steps: Vec<PlanStep>,
+}
+
+impl State {
+    /// Level of a structure once the current build completes
+    fn level(&self, index: usize) -> usize {
+        match self.building {
+            Some((building, _)) if building == index => self.levels[index] + 1,
+            _ => self.levels[index],
+        }
+    }
+
+    /// Levels once the current build completes
+    fn final_levels(&self) -> Vec<usize> {
+        (0..self.levels.len()).map(|i| self.level(i)).collect()
+    }
+
+    /// Tick the current build completes
+    fn completion(&self) -> usize {
+        self.building
+            .map(|(_, completion)| completion)
+            .unwrap_or(self.tick)
+    }
+
+    /// Same production rules as `System::update_to_tick`
+    fn produce_until(&mut self, tick: usize) {
+        if tick <= self.tick {
+            return;
+        }
+        let produced = |rate: usize| produced(rate, self.tick, tick);
+        let add = |amount: usize, rate: usize, storage: usize| {
+            if rate > 0 {
+                (amount + produced(rate)).min(storage)
+            } else {
+                amount
+            }
+        };
+        self.resources = Resources {
+            metal: add(
+                self.resources.metal,
+                self.production.metal,
+                self.storage.metal,
+            ),
+            crew: add(self.resources.crew, self.production.crew, self.storage.crew),
+            water: add(
+                self.resources.water,
+                self.production.water,
+                self.storage.water,
+            ),
+        };
+        self.tick = tick;
+    }
+}
+
+/// Metal, crew and water, in that order
+fn amounts(resources: Resources) -> [usize; 3] {
+    [resources.metal, resources.crew, resources.water]
+}
+
+/// A partial plan and how good it is once finished by the simple rule, lower is better
+#[derive(Debug, Clone)]
+struct Candidate {
+    state: State,
+    rank: (usize, usize),
+}
+
+/// Plans builds for one system
+struct Planner<'a> {
+    config: &'a GalaxyConfig,
+    /// Structures of the system and their config
+    structures: Vec<(StructureType, &'a StructureConfig)>,
+    goal: Goal,
+}
+
+impl<'a> Planner<'a> {
+    /// Score of a state once its current build completes
+    fn score(&self, state: &State) -> usize {
+        let levels = self
+            .structures
+            .iter()
+            .enumerate()
+            .map(|(i, (structure, _))| (*structure, state.level(i)));
+        scoring::score(self.config, levels).total()
+    }
+
+    fn new(system: &System, config: &'a GalaxyConfig, goal: Goal) -> Self {
+        Self {
+            config,
+            structures: system
+                .get_structures()
+                .into_iter()
+                .filter_map(|(structure, _)| {
+                    let name = structure.to_string().to_lowercase();
+                    Some((structure, config.systems.structures.get(&name)?))
+                })
+                .collect(),
+            goal,
+        }
+    }
+
+    fn start(&self, system: &System, tick: usize) -> State {
+        let mut system = system.clone();
+        let tick = tick.max(system.get_current_tick());
+        system.update_to_tick(tick, self.config);
+        let levels: HashMap<StructureType, usize> = system.get_structures().into_iter().collect();
+        let building = system.get_events().iter().find_map(|event| {
+            let index = self.index(event.structure?)?;
+            Some((index, event.completion))
+        });
+        let mut state = State {
+            tick,
+            resources: system.get_resources(),
+            levels: self
+                .structures
+                .iter()
+                .map(|(structure, _)| levels[structure])
+                .collect(),
+            building,
+            production: Resources::default(),
+            storage: Resources::default(),
+            steps: Vec::new(),
+        };
+        self.refresh(&mut state);
+        state
+    }
+
+    fn index(&self, structure: StructureType) -> Option<usize> {
+        self.structures.iter().position(|(s, _)| *s == structure)
+    }
+
+    /// Work out the production and storage of the completed levels
+    fn refresh(&self, state: &mut State) {
+        let speed = self.config.speed();
+        let mut production = Resources::default();
+        let mut storage = Resources::default();
+        for ((_, config), level) in self.structures.iter().zip(&state.levels) {
+            production = production + config.get_production(*level, speed);
+            storage = storage + config.get_storage(*level);
+        }
+        state.production = production;
+        state.storage = storage;
+    }
+
+    /// Produce resources up to a tick, completing the current build first like systems do
+    fn advance(&self, state: &mut State, tick: usize) {
+        if let Some((index, completion)) = state.building {
+            if completion <= tick {
+                state.levels[index] += 1;
+                state.building = None;
+                self.refresh(state);
+            }
+        }
+        state.produce_until(tick);
+    }
+
+    /// Start the next level of a structure as soon as possible
+    ///
+    /// Returns None if it can never be paid for with the current production and storage.
+    fn build(&self, state: &State, index: usize, keep_steps: bool) -> Option<State> {
+        let mut state = State {
+            steps: if keep_steps {
+                state.steps.clone()
+            } else {
+                Vec::new()
+            },
+            ..state.clone()
+        };
+        let completion = state.completion();
+        self.advance(&mut state, completion);
+        let (structure, config) = self.structures[index];
+        let cost = config.get_cost(state.levels[index] + 1, self.config.speed());
+        let need = amounts(cost.resources);
+        let rate = amounts(state.production);
+        let cap = amounts(state.storage);
+
+        // Earliest tick each resource covers the cost, inverting the production formula
+        let mut start = state.tick;
+        for (i, have) in amounts(state.resources).into_iter().enumerate() {
+            if have >= need[i] {
+                continue;
+            }
+            if rate[i] == 0 || cap[i] < need[i] {
+                return None;
+            }
+            start = start.max(produced_by(rate[i], state.tick, need[i] - have));
+        }
+        self.advance(&mut state, start);
+
+        // Resources only compare when they are all above or all equal to the cost, so wait for
+        // the ones that are exactly at the cost to grow past it
+        let limit = state.tick + MAX_SETTLE;
+        while !matches!(
+            state.resources.partial_cmp(&cost.resources),
+            Some(Ordering::Greater | Ordering::Equal)
+        ) {
+            let stuck = amounts(state.resources)
+                .into_iter()
+                .enumerate()
+                .any(|(i, have)| have == need[i] && (rate[i] == 0 || cap[i] <= need[i]));
+            if stuck || state.tick >= limit {
+                return None;
+            }
+            let next = state.tick + 1;
+            self.advance(&mut state, next);
+        }
+
+        let completion = state.tick + cost.ticks;
+        state.resources = state.resources - cost.resources;
+        state.building = Some((index, completion));
+        if keep_steps {
+            state.steps.push(PlanStep {
+                structure,
+                level: state.levels[index] + 1,
+                start: state.tick,
+                completion,
+            });
+        }
+        Some(state)
+    }
+
+    /// Finish a partial plan with the simple rule for the goal
+    fn finish(&self, state: &State, keep_steps: bool) -> Option<(State, (usize, usize))> {
+        let mut finished = state.clone();
+        if !keep_steps {
+            finished.steps = Vec::new();
+        }
+        match self.goal {
+            Goal::Level { structure, level } => {
+                let index = self.index(structure)?;
+                while finished.level(index) < level {
+                    finished = self.build(&finished, index, keep_steps)?;
+                }
+                let rank = (finished.completion(), 0);
+                Some((finished, rank))
+            }
+            Goal::Score { deadline } => {
+                while let Some(next) = (0..self.structures.len())
+                    .filter_map(|index| self.build(&finished, index, keep_steps))
+                    .filter(|next| next.completion() <= deadline)
+                    .min_by_key(|next| next.completion())
+                {
+                    finished = next;
+                }
+                // Highest score first, then the plan that gets there earliest
+                let rank = (usize::MAX - self.score(&finished), finished.completion());
+                Some((finished, rank))
+            }
+        }
+    }
+
+    fn candidate(&self, state: State) -> Option<Candidate> {
+        let (_, rank) = self.finish(&state, false)?;
+        Some(Candidate { state, rank })
+    }
+
+    fn search(&self, start: State) -> Result<BuildPlan, String> {
+        let mut best = self
+            .candidate(start.clone())
+            .ok_or("The goal can't be reached")?;
+        let mut beam = vec![start];
+        for _ in 0..MAX_STEPS {
+            // A build finishing after the best plan reaches the goal can't beat it
+            let limit = match self.goal {
+                Goal::Level { .. } => best.rank.0.saturating_sub(1),
+                Goal::Score { deadline } => deadline,
+            };
+            let mut candidates: HashMap<Vec<usize>, Candidate> = HashMap::new();
+            for state in &beam {
+                for index in 0..self.structures.len() {
+                    let Some(next) = self
+                        .build(state, index, true)
+                        .filter(|next| next.completion() <= limit)
+                    else {
+                        continue;
+                    };
+                    // Plans ending with the same levels only differ by timing, keep the best one
+                    let key = next.final_levels();
+                    let Some(candidate) = self.candidate(next) else {
+                        continue;
+                    };
+                    match candidates.get(&key) {
+                        Some(existing) if existing.rank <= candidate.rank => {}
+                        _ => {
+                            candidates.insert(key, candidate);
+                        }
+                    }
+                }
+            }
+            if candidates.is_empty() {
+                break;
+            }
+
+            let mut candidates: Vec<Candidate> = candidates.into_values().collect();
+            candidates.sort_by_key(|candidate| (candidate.rank, candidate.state.completion()));
+            candidates.truncate(BEAM_WIDTH);
+            if candidates[0].rank < best.rank {
+                best = candidates[0].clone();
+            }
+            beam = candidates
+                .into_iter()
+                .map(|candidate| candidate.state)
+                .collect();
+        }
+
+        let (finished, _) = self
+            .finish(&best.state, true)
+            .ok_or("The goal can't be reached")?;
+        Ok(BuildPlan {
+            goal: self.goal,
+            finish: match self.goal {
+                Goal::Level { .. } => finished.completion(),
+                Goal::Score { deadline } => deadline,
+            },
+            score: self.score(&finished),
+            steps: finished.steps,
+        })
+    }
+}
+
+/// Search for the build order that reaches a goal soonest
+///
+/// The plan starts from the state of the system at `tick`, after any build in progress.
+pub fn plan(
+    system: &System,
+    tick: usize,
+    config: &GalaxyConfig,
+    goal: Goal,
+) -> Result<BuildPlan, String> {
+    let planner = Planner::new(system, config, goal);
+    let start = planner.start(system, tick);
+    match goal {
+        Goal::Level { structure, .. } if planner.index(structure).is_none() => {
+            return Err("Structure not found".to_string())
+        }
+        Goal::Level { level, .. } if level > MAX_LEVEL => {
+            return Err(format!("Levels above {} can't be planned", MAX_LEVEL))
+        }
+        Goal::Score { deadline } if deadline < start.tick => {
+            return Err("The deadline has already passed".to_string())
+        }
+        Goal::Score { deadline } if deadline - start.tick > MAX_HOURS * 3600 => {
+            return Err(format!(
+                "Scores more than {} hours ahead can't be planned",
+                MAX_HOURS
+            ))
+        }
+        _ => {}
+    }
+    planner.search(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+system_count: 1
+size:
+  x: 1
+  y: 1
+systems:
+  resources:
+    metal: 100
+    crew: 100
+    water: 100
+  structures:
+    colony:
+      starting_level: 1
+      multiplier: 1.0
+      production:
+        metal: 100
+        crew: 100
+        water: 100
+      storage:
+        metal: 100000
+        crew: 100000
+        water: 100000
+      cost:
+        time: 600
+        metal: 1000
+        crew: 100
+        water: 100
+    asteroidmine:
+      starting_level: 1
+      production:
+        multiplier: 2.0
+        metal: 100
+      cost:
+        multiplier: 1.5
+        time: 600
+        metal: 150
+        crew: 50
+        water: 50
+"#;
+
+    fn create_test_config() -> GalaxyConfig {
+        serde_yaml::from_str(CONFIG).unwrap()
+    }
+
+    /// Follow the simple rule for a goal from the start
+    fn simple_rule(system: &System, config: &GalaxyConfig, goal: Goal) -> (State, usize) {
+        let planner = Planner::new(system, config, goal);
+        let start = planner.start(system, 0);
+        let finished = planner.finish(&start, false).unwrap().0;
+        let score = planner.score(&finished);
+        (finished, score)
+    }
+
+    /// Follow a plan on a real system, checking every build happens as predicted
+    fn replay(plan: &BuildPlan, config: &GalaxyConfig) -> System {
+        let mut system = System::new(0, &config.systems, config);
+        for step in &plan.steps {
+            if step.start > 0 {
+                let mut early = system.clone();
+                assert!(early.build(step.start - 1, config, step.structure).is_err());
+            }
+            let event = system.build(step.start, config, step.structure).unwrap();
+            assert_eq!(event.completion, step.completion);
+        }
+        system
+    }
+
+    #[test]
+    fn test_plan_structure_level() {
+        let config = create_test_config();
+        let system = System::new(0, &config.systems, &config);
+        let goal = Goal::Level {
+            structure: StructureType::Colony,
+            level: 4,
+        };
+        let plan = plan(&system, 0, &config, goal).unwrap();
+
+        let last = plan.steps.last().unwrap();
+        assert_eq!(last.structure, StructureType::Colony);
+        assert_eq!(last.level, 4);
+        assert_eq!(plan.finish, last.completion);
+        // Investing in the mine first beats saving up for the colony
+        assert!(plan
+            .steps
+            .iter()
+            .any(|step| step.structure == StructureType::AsteroidMine));
+        let (naive, _) = simple_rule(&system, &config, goal);
+        assert!(plan.finish < naive.completion());
+
+        let mut system = replay(&plan, &config);
+        assert_eq!(system.score(plan.finish, &config), plan.score);
+    }
+
+    #[test]
+    fn test_plan_score() {
+        let config = create_test_config();
+        let system = System::new(0, &config.systems, &config);
+        let deadline = 24 * 3600;
+        let plan = plan(&system, 0, &config, Goal::Score { deadline }).unwrap();
+
+        assert_eq!(plan.finish, deadline);
+        assert!(plan.steps.iter().all(|step| step.completion <= deadline));
+        let (_, greedy) = simple_rule(&system, &config, plan.goal);
+        assert!(plan.score >= greedy);
+
+        let mut system = replay(&plan, &config);
+        assert_eq!(system.score(deadline, &config), plan.score);
+    }
+
+    #[test]
+    fn test_plan_waits_for_current_build() {
+        let config = create_test_config();
+        let mut system = System::new(0, &config.systems, &config);
+        system
+            .build(3600, &config, StructureType::AsteroidMine)
+            .unwrap();
+        let goal = Goal::Level {
+            structure: StructureType::AsteroidMine,
+            level: 2,
+        };
+        // The build in progress already reaches the goal, level 2 takes 1.5 times as long
+        let plan = plan(&system, 3600, &config, goal).unwrap();
+        assert!(plan.steps.is_empty());
+        assert_eq!(plan.finish, 4500);
+    }
+
+    #[test]
+    fn test_plan_errors() {
+        let config = create_test_config();
+        let system = System::new(0, &config.systems, &config);
+        let level = |structure, level| Goal::Level { structure, level };
+
+        assert!(plan(&system, 0, &config, level(StructureType::Hatchery, 2)).is_err());
+        assert!(plan(&system, 100, &config, Goal::Score { deadline: 50 }).is_err());
+        assert!(plan(
+            &system,
+            0,
+            &config,
+            level(StructureType::Colony, MAX_LEVEL + 1)
+        )
+        .is_err());
+        let deadline = MAX_HOURS * 3600 + 1;
+        assert!(plan(&system, 0, &config, Goal::Score { deadline }).is_err());
+
+        // The colony costs more than the storage can ever hold
+        let mut small = config.clone();
+        let colony = small.systems.structures.get_mut("colony").unwrap();
+        colony.storage.as_mut().unwrap().metal = 500;
+        let system = System::new(0, &small.systems, &small);
+        assert!(plan(&system, 0, &small, level(StructureType::Colony, 2)).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+/// Scoring of systems
+///
+/// The galaxy config decides what each structure level is worth and which category it counts
+/// towards, see [`ScoringConfig`](crate::config::ScoringConfig).
+use crate::config::{GalaxyConfig, LevelScoring, ScoreCategory, StructureScoring};
+use crate::StructureType;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Score of a system or player, split by category
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Scores {
+    pub economy: usize,
+    pub military: usize,
+    pub defense: usize,
+}
+
+impl Scores {
+    /// Sum of every category
+    pub fn total(&self) -> usize {
+        self.economy + self.military + self.defense
+    }
+
+    /// Score in a category, or the total for None
+    pub fn get(&self, category: Option<ScoreCategory>) -> usize {
+        match category {
+            Some(ScoreCategory::Economy) => self.economy,
+            Some(ScoreCategory::Military) => self.military,
+            Some(ScoreCategory::Defense) => self.defense,
+            None => self.total(),
+        }
+    }
+}
+
+impl std::ops::Add for Scores {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            economy: self.economy + other.economy,
+            military: self.military + other.military,
+            defense: self.defense + other.defense,
+        }
+    }
+}
+
+/// Score the structure levels of a system
+pub fn score(
+    config: &GalaxyConfig,
+    levels: impl IntoIterator<Item = (StructureType, usize)>,
+) -> Scores {
+    let scoring = &config.scoring;
+    let default = StructureScoring::default();
+    // Points are summed unrounded so fractional weights don't lose points per structure
+    let mut points = [0.0; 3];
+    for (structure, level) in levels {
+        let name = structure.to_string().to_lowercase();
+        let structure_scoring = scoring.structures.get(&name).unwrap_or(&default);
+
+        let mut structure_points = match scoring.levels {
+            LevelScoring::Triangular => (1..=level).sum::<usize>(),
+            LevelScoring::Linear => level,
+        } as f64;
+        if scoring.resources_spent > 0.0 {
+            if let Some(structure_config) = config.systems.structures.get(&name) {
+                // Starting levels are free, only the upgrades were paid for
+                let spent: usize = (structure_config.starting_level + 1..=level)
+                    .map(|level| {
+                        let cost = structure_config.get_cost(level, 1.0).resources;
+                        cost.metal + cost.crew + cost.water
+                    })
+                    .sum();
+                structure_points += spent as f64 * scoring.resources_spent;
+            }
+        }
+
+        let category = match structure_scoring.category {
+            ScoreCategory::Economy => 0,
+            ScoreCategory::Military => 1,
+            ScoreCategory::Defense => 2,
+        };
+        points[category] += structure_points * structure_scoring.weight;
+    }
+    Scores {
+        economy: points[0].round() as usize,
+        military: points[1].round() as usize,
+        defense: points[2].round() as usize,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+system_count: 1
+size:
+  x: 1
+  y: 1
+systems:
+  resources: {}
+  structures:
+    colony:
+      starting_level: 1
+      cost:
+        multiplier: 2.0
+        metal: 10
+        crew: 10
+        water: 10
+    hatchery:
+      cost:
+        metal: 5
+    storagedepot:
+      cost:
+        water: 5
+"#;
+
+    fn create_test_config() -> GalaxyConfig {
+        serde_yaml::from_str(CONFIG).unwrap()
+    }
+
+    fn levels() -> Vec<(StructureType, usize)> {
+        vec![
+            (StructureType::Colony, 3),
+            (StructureType::Hatchery, 2),
+            (StructureType::StorageDepot, 1),
+        ]
+    }
+
+    #[test]
+    fn test_default_scoring() {
+        // 1+2+3, 1+2 and 1, all economy
+        let scores = score(&create_test_config(), levels());
+        assert_eq!(
+            scores,
+            Scores {
+                economy: 10,
+                military: 0,
+                defense: 0
+            }
+        );
+        assert_eq!(scores.total(), 10);
+        assert_eq!(scores.get(Some(ScoreCategory::Military)), 0);
+    }
+
+    #[test]
+    fn test_configured_scoring() {
+        let mut config = create_test_config();
+        config.scoring = serde_yaml::from_str(
+            r#"
+levels: linear
+resources_spent: 0.1
+structures:
+  hatchery:
+    weight: 2.5
+    category: military
+  storagedepot:
+    category: defense
+"#,
+        )
+        .unwrap();
+
+        let scores = score(&config, levels());
+        // Colony: 3 levels, levels 2 and 3 cost 60 and 120
+        assert_eq!(scores.economy, 3 + 18);
+        // Hatchery: (2 levels + 10 spent * 0.1) * 2.5
+        assert_eq!(scores.military, 8);
+        // Storage depot: 1 level + 5 spent * 0.1, rounded
+        assert_eq!(scores.defense, 2);
+        assert_eq!(scores.total(), 31);
+    }
+}
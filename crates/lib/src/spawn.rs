@@ -0,0 +1,208 @@
+/// Placement policies for the systems of players joining a galaxy
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashMap;
+
+use crate::config::{GalaxyConfig, SpawnConfig};
+use crate::layout::distance;
+use crate::{Coords, System};
+
+/// Number of random locations tried before deciding the galaxy is full
+const MAX_ATTEMPTS: usize = 1000;
+
+/// Where a new player will start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnLocation {
+    /// Create a new system at these coordinates
+    New(Coords),
+    /// Take over the existing unowned system at these coordinates
+    Existing(Coords),
+}
+
+/// Choose the starting location for a new player, using the galaxy's spawn policy
+///
+/// `owned` lists the coordinates of every system that already belongs to a player.
+pub fn choose(
+    config: &GalaxyConfig,
+    systems: &HashMap<Coords, System>,
+    owned: &[Coords],
+    rng: &mut StdRng,
+) -> Option<SpawnLocation> {
+    let is_empty = |coords: &Coords| !systems.contains_key(coords);
+    match &config.spawn {
+        SpawnConfig::Random => random_location(config, rng, is_empty).map(SpawnLocation::New),
+        SpawnConfig::MinDistance { distance } => random_location(config, rng, |coords| {
+            is_empty(coords) && nearest(*coords, owned) >= *distance
+        })
+        .map(SpawnLocation::New),
+        SpawnConfig::Sparse { candidates } => {
+            // Compare against owned systems, or every system if nobody has joined yet
+            let neighbours: Vec<Coords> = if owned.is_empty() {
+                systems.keys().cloned().collect()
+            } else {
+                owned.to_vec()
+            };
+            (0..(*candidates).max(1))
+                .filter_map(|_| random_location(config, rng, is_empty))
+                .max_by(|a, b| nearest(*a, &neighbours).total_cmp(&nearest(*b, &neighbours)))
+                .map(SpawnLocation::New)
+        }
+        SpawnConfig::Waves { wave_size, depth } => {
+            // Each wave of players starts one band further from the edge than the last
+            let wave = owned.len() / (*wave_size).max(1);
+            let max_depth = config.size.x.min(config.size.y) as f64 / 2.0;
+            let inner = (wave as f64 * depth).min((max_depth - depth).max(0.0));
+            let outer = inner + depth;
+            random_location(config, rng, |coords| {
+                let edge = edge_distance(config, *coords);
+                is_empty(coords) && edge >= inner && edge < outer
+            })
+            .map(SpawnLocation::New)
+        }
+        SpawnConfig::Adopt => {
+            let mut unowned: Vec<Coords> = systems
+                .keys()
+                .filter(|coords| !owned.contains(coords))
+                .cloned()
+                .collect();
+            // Sort so the choice only depends on the RNG, not the HashMap ordering
+            unowned.sort_by_key(|c| (c.x, c.y));
+            unowned.choose(rng).cloned().map(SpawnLocation::Existing)
+        }
+    }
+}
+
+/// Pick a random location in the galaxy that passes the filter
+fn random_location<F>(config: &GalaxyConfig, rng: &mut StdRng, filter: F) -> Option<Coords>
+where
+    F: Fn(&Coords) -> bool,
+{
+    for _ in 0..MAX_ATTEMPTS {
+        let x: usize = rng.gen_range(0..=config.size.x);
+        let y: usize = rng.gen_range(0..=config.size.y);
+        let coords = (x, y).into();
+        if filter(&coords) {
+            return Some(coords);
+        }
+    }
+    None
+}
+
+/// Distance to the nearest of the given systems, or infinity if there are none
+fn nearest(coords: Coords, others: &[Coords]) -> f64 {
+    others
+        .iter()
+        .map(|other| distance(coords, *other))
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// Distance from the coordinates to the closest edge of the galaxy
+fn edge_distance(config: &GalaxyConfig, coords: Coords) -> f64 {
+    coords
+        .x
+        .min(coords.y)
+        .min(config.size.x - coords.x)
+        .min(config.size.y - coords.y) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GalaxySize;
+    use rand::SeedableRng;
+
+    fn create_test_config(spawn: SpawnConfig) -> GalaxyConfig {
+        GalaxyConfig {
+            size: GalaxySize { x: 100, y: 100 },
+            spawn,
+            ..Default::default()
+        }
+    }
+
+    fn create_test_systems(coords: &[Coords]) -> HashMap<Coords, System> {
+        coords.iter().map(|c| (*c, System::default())).collect()
+    }
+
+    #[test]
+    fn test_min_distance_policy() {
+        let config = create_test_config(SpawnConfig::MinDistance { distance: 30.0 });
+        let owned: Vec<Coords> = vec![(50, 50).into()];
+        let systems = create_test_systems(&owned);
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..20 {
+            match choose(&config, &systems, &owned, &mut rng) {
+                Some(SpawnLocation::New(coords)) => {
+                    assert!(distance(coords, owned[0]) >= 30.0)
+                }
+                other => panic!("Unexpected spawn {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_min_distance_policy_full() {
+        // The whole galaxy is within range of the owned system
+        let config = create_test_config(SpawnConfig::MinDistance { distance: 200.0 });
+        let owned: Vec<Coords> = vec![(50, 50).into()];
+        let systems = create_test_systems(&owned);
+        let mut rng = StdRng::seed_from_u64(2);
+        assert_eq!(choose(&config, &systems, &owned, &mut rng), None);
+    }
+
+    #[test]
+    fn test_sparse_policy_avoids_players() {
+        let config = create_test_config(SpawnConfig::Sparse { candidates: 50 });
+        let owned: Vec<Coords> = vec![(0, 0).into(), (0, 100).into(), (100, 0).into()];
+        let systems = create_test_systems(&owned);
+        let mut rng = StdRng::seed_from_u64(3);
+        match choose(&config, &systems, &owned, &mut rng) {
+            Some(SpawnLocation::New(coords)) => {
+                // Best of 50 candidates should be well away from the three occupied corners
+                assert!(nearest(coords, &owned) > 40.0, "{:?}", coords)
+            }
+            other => panic!("Unexpected spawn {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_waves_policy_moves_inward() {
+        let config = create_test_config(SpawnConfig::Waves {
+            wave_size: 2,
+            depth: 10.0,
+        });
+        let mut rng = StdRng::seed_from_u64(4);
+
+        // First wave spawns on the outer edge
+        let Some(SpawnLocation::New(first)) = choose(&config, &HashMap::new(), &[], &mut rng)
+        else {
+            panic!("Expected a new system");
+        };
+        assert!(edge_distance(&config, first) < 10.0);
+
+        // Third player is in the second wave
+        let owned: Vec<Coords> = vec![(0, 0).into(), (1, 1).into()];
+        let systems = create_test_systems(&owned);
+        let Some(SpawnLocation::New(third)) = choose(&config, &systems, &owned, &mut rng) else {
+            panic!("Expected a new system");
+        };
+        let edge = edge_distance(&config, third);
+        assert!((10.0..20.0).contains(&edge));
+    }
+
+    #[test]
+    fn test_adopt_policy() {
+        let config = create_test_config(SpawnConfig::Adopt);
+        let owned: Vec<Coords> = vec![(1, 1).into()];
+        let systems = create_test_systems(&[(1, 1).into(), (5, 5).into()]);
+        let mut rng = StdRng::seed_from_u64(5);
+        assert_eq!(
+            choose(&config, &systems, &owned, &mut rng),
+            Some(SpawnLocation::Existing((5, 5).into()))
+        );
+
+        // Nothing left to adopt
+        let owned: Vec<Coords> = vec![(1, 1).into(), (5, 5).into()];
+        assert_eq!(choose(&config, &systems, &owned, &mut rng), None);
+    }
+}
@@ -0,0 +1,87 @@
+//! Setup shared by tests that play through the AppState API
+
+use std::sync::Arc;
+
+use crate::config::GalaxyConfig;
+use crate::{AppState, Coords, Database, ManualClock, UserGalaxyAccount, UserService};
+
+/// Tick the clock starts at
+pub const START: usize = 1_700_000_000;
+
+pub const HOUR: usize = 3600;
+
+pub const DAY: usize = 24 * HOUR;
+
+/// A small galaxy where players start with plenty of resources and storage, and a mine that
+/// takes 10 minutes to upgrade
+pub const CONFIG: &str = r#"
+seed: 1
+system_count: 10
+size:
+  x: 20
+  y: 20
+systems:
+  resources:
+    metal: 1000
+    crew: 1000
+    water: 1000
+  structures:
+    colony:
+      starting_level: 1
+      storage:
+        metal: 1000000
+        crew: 1000000
+        water: 1000000
+    asteroidmine:
+      starting_level: 1
+      production:
+        metal: 100
+      cost:
+        time: 600
+        metal: 50
+        crew: 50
+        water: 50
+"#;
+
+/// Create an app state on a test database, with a manual clock at [`START`] and galaxies of a
+/// config
+pub async fn setup(config: &str, galaxies: &[&str]) -> (Arc<ManualClock>, Database, AppState) {
+    setup_with(&serde_yaml::from_str(config).unwrap(), galaxies).await
+}
+
+/// Like [`setup`], for a config the test has changed after parsing it
+pub async fn setup_with(
+    config: &GalaxyConfig,
+    galaxies: &[&str],
+) -> (Arc<ManualClock>, Database, AppState) {
+    let clock = Arc::new(ManualClock::new(START));
+    let db = Database::new_test().await.unwrap();
+    let app_state = AppState::new_with_database(db.clone(), clock.clone())
+        .await
+        .unwrap();
+    for galaxy in galaxies {
+        app_state
+            .create_galaxy(galaxy, config, app_state.tick())
+            .await
+            .unwrap();
+    }
+    (clock, db, app_state)
+}
+
+/// Register a user named after an account, and join a galaxy with it
+pub async fn join(
+    db: &Database,
+    app_state: &AppState,
+    galaxy: &str,
+    name: &str,
+) -> (UserGalaxyAccount, Coords) {
+    let username = name.to_lowercase();
+    let user_id = db
+        .create_user(&username, &format!("{}@example.com", username), "hash")
+        .await
+        .unwrap();
+    UserService::new(db.clone())
+        .join_galaxy(user_id, galaxy, name, app_state)
+        .await
+        .unwrap()
+}
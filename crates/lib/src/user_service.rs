@@ -25,8 +25,17 @@ pub enum UserServiceError {
 
     #[error("User does not have an account in this galaxy")]
     UserNotInGalaxy,
+
+    #[error("Cannot join this galaxy: {0}")]
+    RegistrationClosed(String),
+
+    #[error("Unknown AI strategy: {0}")]
+    UnknownStrategy(String),
 }
 
+/// Password hash of the users behind AI accounts, it isn't a valid hash so they can't log in
+const AI_PASSWORD_HASH: &str = "!";
+
 /// Service for managing users within galaxies
 pub struct UserService {
     auth: AuthService,
@@ -52,6 +61,19 @@ impl UserService {
         galaxy_name: &str,
         account_name: &str,
         app_state: &crate::app::AppState,
+    ) -> Result<(UserGalaxyAccount, Coords), UserServiceError> {
+        self.join(user_id, galaxy_name, account_name, None, app_state)
+            .await
+    }
+
+    /// Create a galaxy account and assign it a system, played by an AI strategy if given
+    async fn join(
+        &self,
+        user_id: i64,
+        galaxy_name: &str,
+        account_name: &str,
+        ai_strategy: Option<&str>,
+        app_state: &crate::app::AppState,
     ) -> Result<(UserGalaxyAccount, Coords), UserServiceError> {
         // Check if user already has an account in this galaxy
         if (self
@@ -72,14 +94,21 @@ impl UserService {
             return Err(UserServiceError::AccountNameTaken);
         }
 
-        // Create galaxy account
+        // Check the galaxy is open for new players
+        let current_tick = app_state.tick();
+        app_state
+            .check_registration(galaxy_name, current_tick)
+            .await
+            .map_err(UserServiceError::RegistrationClosed)?;
+
+        // Create galaxy account, with its strategy so an AI is never a human player
         let account_id = self
             .db
-            .create_user_galaxy_account(user_id, galaxy_name, account_name)
+            .create_user_galaxy_account(user_id, galaxy_name, account_name, ai_strategy)
             .await?;
 
-        // Create a new system for the user using AppState
-        let current_tick = crate::tick();
+        // Create a new system for the user using AppState, placed by the galaxy's spawn policy.
+        // The galaxy knows which systems players own, so concurrent joins never get the same one.
         let (coords, system_info) = app_state
             .create_user_system_in_galaxy(galaxy_name, current_tick)
             .await
@@ -91,7 +120,11 @@ impl UserService {
                 }
             })?;
 
-        // Save the system to database with user ownership
+        // Save the system to database with user ownership, at the tick the system sees
+        let game_tick = app_state
+            .game_tick(galaxy_name, current_tick)
+            .await
+            .map_err(|e| UserServiceError::Database(crate::db::PersistenceError::Migration(e)))?;
         let system_id = self
             .db
             .save_system(
@@ -99,7 +132,7 @@ impl UserService {
                 coords.x,
                 coords.y,
                 &system_info.resources,
-                current_tick,
+                game_tick,
                 Some(account_id),
             )
             .await?;
@@ -114,9 +147,56 @@ impl UserService {
             .await?
             .expect("Account should exist after creation");
 
+        // The galaxy ranks its players itself when it ends
+        app_state
+            .refresh_players(galaxy_name)
+            .await
+            .map_err(|e| UserServiceError::Database(crate::db::PersistenceError::Migration(e)))?;
+
         Ok((UserGalaxyAccount::from(account), coords))
     }
 
+    /// Add an AI player to a galaxy, played by the named strategy
+    ///
+    /// Galaxy accounts belong to users, so every AI account gets a user of its own that can't
+    /// log in.
+    pub async fn add_ai_player(
+        &self,
+        galaxy_name: &str,
+        account_name: &str,
+        strategy: &str,
+        app_state: &crate::app::AppState,
+    ) -> Result<(UserGalaxyAccount, Coords), UserServiceError> {
+        if crate::ai::strategy(strategy).is_none() {
+            return Err(UserServiceError::UnknownStrategy(strategy.to_string()));
+        }
+
+        // Reuse the user of an earlier attempt, but never one a human registered
+        let username = format!("ai:{}:{}", galaxy_name, account_name);
+        let user_id = match self.db.get_user_by_username(&username).await? {
+            Some(user) if user.password_hash == AI_PASSWORD_HASH => user.id,
+            Some(_) => return Err(UserServiceError::AccountNameTaken),
+            None => {
+                self.db
+                    .create_user(
+                        &username,
+                        &format!("{}@ai.invalid", username),
+                        AI_PASSWORD_HASH,
+                    )
+                    .await?
+            }
+        };
+
+        self.join(
+            user_id,
+            galaxy_name,
+            account_name,
+            Some(strategy),
+            app_state,
+        )
+        .await
+    }
+
     /// Get user's galaxy account
     pub async fn get_user_galaxy_account(
         &self,
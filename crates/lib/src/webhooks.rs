@@ -0,0 +1,980 @@
+/// Outgoing webhooks of users
+///
+/// A user registers URLs to be told about events in any of their galaxies: finished builds,
+/// attacks on their systems and received messages. Every event is queued as a JSON payload per
+/// matching webhook and POSTed by the server, signed with the webhook's secret so the receiver
+/// can check where it came from. Failed deliveries are retried with a growing delay, and the
+/// latest deliveries of every webhook are kept as a log.
+///
+/// Webhooks can only reach public addresses, so the server can't be used to probe the network
+/// it runs in. Their host is resolved when they are registered and again on every delivery, and
+/// the delivery connects to the addresses that were checked.
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use ureq::config::Config;
+use ureq::http::Uri;
+use ureq::unversioned::resolver::{ResolvedSocketAddrs, Resolver};
+use ureq::unversioned::transport::{DefaultConnector, NextTimeout};
+
+use crate::db::{Database, PersistenceError};
+use crate::models::{WebhookDeliveryRow, WebhookRow};
+
+/// Webhooks a user can register
+pub const MAX_WEBHOOKS: usize = 5;
+
+/// Longest webhook URL
+pub const MAX_URL_LENGTH: usize = 500;
+
+/// Attempts at a delivery before giving up on it
+pub const MAX_ATTEMPTS: i64 = 5;
+
+/// Seconds before the first retry of a delivery, doubled for every further one
+pub const RETRY_DELAY: usize = 30;
+
+/// Deliveries kept per webhook, older ones are deleted
+pub const KEPT_DELIVERIES: usize = 100;
+
+/// Deliveries attempted in one go
+const DELIVERY_BATCH: usize = 50;
+
+/// How long a receiver has to answer
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Header naming the event of a payload
+pub const EVENT_HEADER: &str = "X-Galactic-War-Event";
+
+/// Header with the id of a delivery, the same across its retries
+pub const DELIVERY_HEADER: &str = "X-Galactic-War-Delivery";
+
+/// Header with the signature of a payload, `sha256=` followed by the hex HMAC of the body
+pub const SIGNATURE_HEADER: &str = "X-Galactic-War-Signature";
+
+/// Something a webhook can be told about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WebhookEvent {
+    /// A structure upgrade in one of the user's systems finished
+    BuildComplete,
+    /// One of the user's systems is being or was attacked
+    UnderAttack,
+    /// One of the user's galaxy accounts received a private message
+    MessageReceived,
+}
+
+impl WebhookEvent {
+    /// Every event, in the order they are listed
+    pub const ALL: [WebhookEvent; 3] = [
+        WebhookEvent::BuildComplete,
+        WebhookEvent::UnderAttack,
+        WebhookEvent::MessageReceived,
+    ];
+}
+
+impl std::fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookEvent::BuildComplete => write!(f, "build_complete"),
+            WebhookEvent::UnderAttack => write!(f, "under_attack"),
+            WebhookEvent::MessageReceived => write!(f, "message_received"),
+        }
+    }
+}
+
+impl std::str::FromStr for WebhookEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "build_complete" => Ok(WebhookEvent::BuildComplete),
+            "under_attack" => Ok(WebhookEvent::UnderAttack),
+            "message_received" => Ok(WebhookEvent::MessageReceived),
+            _ => Err(format!("Unknown webhook event {}", s)),
+        }
+    }
+}
+
+/// Where a delivery stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// Waiting for its first attempt or a retry
+    Pending,
+    /// The receiver accepted it
+    Delivered,
+    /// Every attempt failed
+    Failed,
+}
+
+impl std::fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeliveryStatus::Pending => write!(f, "pending"),
+            DeliveryStatus::Delivered => write!(f, "delivered"),
+            DeliveryStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl std::str::FromStr for DeliveryStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "delivered" => Ok(DeliveryStatus::Delivered),
+            "failed" => Ok(DeliveryStatus::Failed),
+            _ => Err(format!("Unknown delivery status {}", s)),
+        }
+    }
+}
+
+/// The events a webhook receives
+pub fn webhook_events(webhook: &WebhookRow) -> Vec<WebhookEvent> {
+    webhook
+        .events
+        .split(',')
+        .filter_map(|event| event.parse().ok())
+        .collect()
+}
+
+/// Sign a payload with a webhook secret, as the hex HMAC-SHA256 of the body
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Seconds to wait before retrying a delivery that failed a number of times
+pub fn retry_delay(attempts: i64) -> usize {
+    RETRY_DELAY << (attempts.clamp(1, MAX_ATTEMPTS) - 1)
+}
+
+/// Whether webhooks may reach an address, which is not loopback, private, shared (carrier-grade
+/// NAT), link-local or unspecified
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || first == 0
+                // Shared 100.64.0.0/10
+                || (first == 100 && second & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            // NAT64 64:ff9b::/96 reaches the IPv4 address in its last 32 bits
+            None if ip.segments()[..6] == [0x64, 0xff9b, 0, 0, 0, 0] => {
+                let [.., a, b, c, d] = ip.octets();
+                is_public(IpAddr::V4([a, b, c, d].into()))
+            }
+            None => {
+                let prefix = ip.segments()[0];
+                // Unique local fc00::/7 and link-local fe80::/10
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || prefix & 0xfe00 == 0xfc00
+                    || prefix & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// The `host:port` a webhook URL connects to
+fn authority(url: &str) -> Option<String> {
+    let uri = url.parse::<Uri>().ok()?;
+    let port = match (uri.port_u16(), uri.scheme_str()?) {
+        (Some(port), _) => port,
+        (None, "http") => 80,
+        (None, "https") => 443,
+        _ => return None,
+    };
+    Some(format!("{}:{}", uri.host()?, port))
+}
+
+/// Whether a host resolved to addresses that are all public
+fn all_public(addresses: impl Iterator<Item = SocketAddr>) -> bool {
+    let mut any = false;
+    for address in addresses {
+        if !is_public(address.ip()) {
+            return false;
+        }
+        any = true;
+    }
+    any
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("Database error: {0}")]
+    Database(#[from] PersistenceError),
+
+    #[error("Webhook URLs must start with http:// or https:// and be at most {MAX_URL_LENGTH} characters")]
+    InvalidUrl,
+
+    #[error("Webhook URLs must resolve to a public address")]
+    PrivateAddress,
+
+    #[error("Choose at least one event")]
+    NoEvents,
+
+    #[error("You can't have more than {MAX_WEBHOOKS} webhooks")]
+    TooManyWebhooks,
+
+    #[error("Webhook not found")]
+    WebhookNotFound,
+}
+
+/// Service for webhooks
+pub struct WebhookService {
+    db: Database,
+    private_addresses: bool,
+}
+
+impl WebhookService {
+    /// Create a new webhook service
+    pub fn new(db: Database) -> Self {
+        Self {
+            db,
+            private_addresses: false,
+        }
+    }
+
+    /// Let webhooks reach private and loopback addresses, for receivers on a local network
+    pub fn with_private_addresses(mut self) -> Self {
+        self.private_addresses = true;
+        self
+    }
+
+    /// Register a webhook of a user for some events, with a new random secret
+    pub async fn register(
+        &self,
+        user_id: i64,
+        url: &str,
+        events: &[WebhookEvent],
+    ) -> Result<WebhookRow, WebhookError> {
+        let url = url.trim();
+        let valid = url.len() <= MAX_URL_LENGTH
+            && !url.contains(char::is_whitespace)
+            && ["http://", "https://"].iter().any(|scheme| {
+                url.strip_prefix(scheme)
+                    .is_some_and(|rest| !rest.is_empty() && !rest.starts_with('/'))
+            });
+        if !valid {
+            return Err(WebhookError::InvalidUrl);
+        }
+        let authority = authority(url).ok_or(WebhookError::InvalidUrl)?;
+        if !self.private_addresses {
+            let public = tokio::net::lookup_host(authority)
+                .await
+                .is_ok_and(all_public);
+            if !public {
+                return Err(WebhookError::PrivateAddress);
+            }
+        }
+        let events = WebhookEvent::ALL
+            .iter()
+            .filter(|event| events.contains(event))
+            .map(|event| event.to_string())
+            .collect::<Vec<_>>();
+        if events.is_empty() {
+            return Err(WebhookError::NoEvents);
+        }
+        if self.db.get_webhooks(user_id).await?.len() >= MAX_WEBHOOKS {
+            return Err(WebhookError::TooManyWebhooks);
+        }
+
+        let secret = hex::encode(rand::random::<[u8; 32]>());
+        let webhook_id = self
+            .db
+            .create_webhook(user_id, url, &secret, &events.join(","))
+            .await?;
+        self.db
+            .get_webhook(webhook_id)
+            .await?
+            .ok_or(WebhookError::WebhookNotFound)
+    }
+
+    /// Get the webhooks of a user
+    pub async fn webhooks(&self, user_id: i64) -> Result<Vec<WebhookRow>, WebhookError> {
+        Ok(self.db.get_webhooks(user_id).await?)
+    }
+
+    /// Delete a webhook of a user, with its delivery log
+    pub async fn delete(&self, user_id: i64, webhook_id: i64) -> Result<(), WebhookError> {
+        if !self.db.delete_webhook(webhook_id, user_id).await? {
+            return Err(WebhookError::WebhookNotFound);
+        }
+        Ok(())
+    }
+
+    /// Get the latest deliveries of a webhook of a user, newest first
+    pub async fn deliveries(
+        &self,
+        user_id: i64,
+        webhook_id: i64,
+    ) -> Result<Vec<WebhookDeliveryRow>, WebhookError> {
+        match self.db.get_webhook(webhook_id).await? {
+            Some(webhook) if webhook.user_id == user_id => Ok(self
+                .db
+                .get_webhook_deliveries(webhook_id, KEPT_DELIVERIES)
+                .await?),
+            _ => Err(WebhookError::WebhookNotFound),
+        }
+    }
+
+    /// Queue an event for every webhook of a user that receives it
+    ///
+    /// The payload holds the event, when it was queued and the given data. Returns how many
+    /// deliveries were queued.
+    pub async fn enqueue(
+        &self,
+        user_id: i64,
+        event: WebhookEvent,
+        data: serde_json::Value,
+    ) -> Result<usize, WebhookError> {
+        let webhooks = self
+            .db
+            .get_webhooks(user_id)
+            .await?
+            .into_iter()
+            .filter(|webhook| webhook_events(webhook).contains(&event))
+            .collect::<Vec<_>>();
+        if webhooks.is_empty() {
+            return Ok(0);
+        }
+
+        let payload = serde_json::json!({
+            "event": event.to_string(),
+            "created_at": chrono::Utc::now().to_rfc3339(),
+            "data": data,
+        })
+        .to_string();
+        for webhook in &webhooks {
+            self.db
+                .add_webhook_delivery(webhook.id, &event.to_string(), &payload)
+                .await?;
+            self.db
+                .delete_old_webhook_deliveries(webhook.id, KEPT_DELIVERIES)
+                .await?;
+        }
+        Ok(webhooks.len())
+    }
+
+    /// Attempt the deliveries that are due at a tick, returning how many were delivered
+    ///
+    /// A delivery is delivered when its receiver answers with a 2xx status. Otherwise it is
+    /// retried after [`retry_delay`], until it failed [`MAX_ATTEMPTS`] times.
+    pub async fn deliver_due(&self, tick: usize) -> Result<usize, WebhookError> {
+        let mut delivered = 0;
+        for mut delivery in self
+            .db
+            .get_due_webhook_deliveries(tick, DELIVERY_BATCH)
+            .await?
+        {
+            let Some(webhook) = self.db.get_webhook(delivery.webhook_id).await? else {
+                continue;
+            };
+            let request = Request {
+                url: webhook.url,
+                event: delivery.event.clone(),
+                delivery_id: delivery.id,
+                signature: sign(&webhook.secret, &delivery.payload),
+                body: delivery.payload.clone(),
+                private_addresses: self.private_addresses,
+            };
+            let response = tokio::task::spawn_blocking(move || request.send())
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()));
+
+            delivery.attempts += 1;
+            let error = match response {
+                Ok(status) => {
+                    delivery.response_status = Some(status as i64);
+                    if (200..300).contains(&status) {
+                        None
+                    } else {
+                        Some(format!("Receiver answered with HTTP status {}", status))
+                    }
+                }
+                Err(error) => {
+                    delivery.response_status = None;
+                    Some(error)
+                }
+            };
+            delivery.status = match error {
+                None => {
+                    delivered += 1;
+                    DeliveryStatus::Delivered
+                }
+                Some(_) if delivery.attempts >= MAX_ATTEMPTS => DeliveryStatus::Failed,
+                Some(_) => {
+                    delivery.next_attempt_at = (tick + retry_delay(delivery.attempts)) as i64;
+                    DeliveryStatus::Pending
+                }
+            }
+            .to_string();
+            delivery.error = error;
+            self.db.update_webhook_delivery(&delivery).await?;
+        }
+        Ok(delivered)
+    }
+}
+
+/// A signed POST of a payload to a webhook
+struct Request {
+    url: String,
+    event: String,
+    delivery_id: i64,
+    signature: String,
+    body: String,
+    private_addresses: bool,
+}
+
+impl Request {
+    /// Send the request, returning the status the receiver answered with
+    fn send(self) -> Result<u16, String> {
+        // The host may resolve elsewhere since the webhook was registered
+        let addresses: Vec<SocketAddr> = authority(&self.url)
+            .and_then(|authority| authority.to_socket_addrs().ok())
+            .map(|addresses| addresses.collect())
+            .unwrap_or_default();
+        if !self.private_addresses && !all_public(addresses.iter().copied()) {
+            return Err(WebhookError::PrivateAddress.to_string());
+        }
+
+        let response = agent(addresses)
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .header("User-Agent", "galactic-war-webhooks")
+            .header(EVENT_HEADER, &self.event)
+            .header(DELIVERY_HEADER, self.delivery_id.to_string())
+            .header(SIGNATURE_HEADER, format!("sha256={}", self.signature))
+            .send(&self.body)
+            .map_err(|e| e.to_string())?;
+        Ok(response.status().as_u16())
+    }
+}
+
+/// An agent connecting to addresses that were already checked rather than resolving the host
+/// again, and not following redirects, as they could lead anywhere
+fn agent(addresses: Vec<SocketAddr>) -> ureq::Agent {
+    let config = ureq::Agent::config_builder()
+        .timeout_global(Some(DELIVERY_TIMEOUT))
+        .http_status_as_error(false)
+        .max_redirects(0)
+        .build();
+    ureq::Agent::with_parts(config, DefaultConnector::new(), Resolved(addresses))
+}
+
+/// Resolves every host to addresses that were already looked up
+#[derive(Debug)]
+struct Resolved(Vec<SocketAddr>);
+
+impl Resolver for Resolved {
+    fn resolve(
+        &self,
+        _uri: &Uri,
+        _config: &Config,
+        _timeout: NextTimeout,
+    ) -> Result<ResolvedSocketAddrs, ureq::Error> {
+        let mut addresses = self.empty();
+        for address in &self.0 {
+            if addresses.try_push(*address).is_err() {
+                break;
+            }
+        }
+        if addresses.is_empty() {
+            return Err(ureq::Error::HostNotFound);
+        }
+        Ok(addresses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::MessageService;
+    use crate::test_utils::{self, join, CONFIG, START};
+    use crate::{AppState, Coords, ManualClock, StructureType, UserGalaxyAccount};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_ne!(sign("other", "body"), sign("secret", "body"));
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), 30);
+        assert_eq!(retry_delay(2), 60);
+        assert_eq!(retry_delay(4), 240);
+        assert_eq!(retry_delay(100), retry_delay(MAX_ATTEMPTS));
+    }
+
+    #[test]
+    fn test_is_public() {
+        for ip in [
+            "93.184.215.14",
+            "8.8.8.8",
+            "100.128.0.1",
+            "2606:4700::1111",
+            "64:ff9b::808:808",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "100.64.0.1",
+            "100.127.255.254",
+            "64:ff9b::7f00:1",
+            "64:ff9b::a00:1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn test_authority() {
+        assert_eq!(
+            authority("http://example.com/hook").as_deref(),
+            Some("example.com:80")
+        );
+        assert_eq!(
+            authority("https://user@example.com:8443").as_deref(),
+            Some("example.com:8443")
+        );
+        assert_eq!(
+            authority("https://[::1]/hook").as_deref(),
+            Some("[::1]:443")
+        );
+        assert_eq!(authority("ftp://example.com"), None);
+    }
+
+    #[test]
+    fn test_events() {
+        for event in WebhookEvent::ALL {
+            assert_eq!(event.to_string().parse::<WebhookEvent>(), Ok(event));
+        }
+        assert!("raid".parse::<WebhookEvent>().is_err());
+    }
+
+    /// A request the receiver got, with lowercase header names
+    struct Received {
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    /// Listen for webhook requests on a local port, answering them with the given statuses in turn
+    /// and then with 200
+    async fn receiver(statuses: Vec<u16>) -> (String, mpsc::UnboundedReceiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, received) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let mut statuses = statuses.into_iter();
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+                let mut headers = HashMap::new();
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                loop {
+                    line.clear();
+                    stream.read_line(&mut line).await.unwrap();
+                    match line.trim_end().split_once(':') {
+                        Some((name, value)) => {
+                            headers.insert(name.to_lowercase(), value.trim().to_string());
+                        }
+                        None => break,
+                    }
+                }
+                let length = headers["content-length"].parse().unwrap();
+                let mut body = vec![0; length];
+                stream.read_exact(&mut body).await.unwrap();
+
+                let status = statuses.next().unwrap_or(200);
+                stream
+                    .get_mut()
+                    .write_all(
+                        format!(
+                            "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                            status
+                        )
+                        .as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+                let body = String::from_utf8(body).unwrap();
+                sender.send(Received { headers, body }).unwrap();
+            }
+        });
+
+        (url, received)
+    }
+
+    /// Ada and Bo in a galaxy, with webhooks that may reach the local receiver
+    struct Hooks {
+        clock: Arc<ManualClock>,
+        db: Database,
+        app_state: AppState,
+        webhooks: WebhookService,
+        ada: UserGalaxyAccount,
+        ada_home: Coords,
+        bo: UserGalaxyAccount,
+    }
+
+    async fn setup() -> Hooks {
+        let (clock, db, app_state) = test_utils::setup(CONFIG, &["hooks"]).await;
+        let (ada, ada_home) = join(&db, &app_state, "hooks", "Ada").await;
+        let (bo, _) = join(&db, &app_state, "hooks", "Bo").await;
+        Hooks {
+            clock,
+            webhooks: WebhookService::new(db.clone()).with_private_addresses(),
+            db,
+            app_state,
+            ada,
+            ada_home,
+            bo,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register() {
+        let Hooks {
+            webhooks, ada, bo, ..
+        } = setup().await;
+
+        // Webhooks need an HTTP URL and at least one event
+        assert!(matches!(
+            webhooks
+                .register(
+                    ada.user_id,
+                    "ftp://example.com",
+                    &[WebhookEvent::UnderAttack]
+                )
+                .await,
+            Err(WebhookError::InvalidUrl)
+        ));
+        assert!(matches!(
+            webhooks
+                .register(ada.user_id, "https://example.com/hook", &[])
+                .await,
+            Err(WebhookError::NoEvents)
+        ));
+
+        let webhook = webhooks
+            .register(
+                ada.user_id,
+                "https://example.com/hook",
+                &[WebhookEvent::MessageReceived, WebhookEvent::BuildComplete],
+            )
+            .await
+            .unwrap();
+        assert_eq!(webhook.events, "build_complete,message_received");
+        for _ in 1..MAX_WEBHOOKS {
+            webhooks
+                .register(ada.user_id, "https://example.com/hook", &WebhookEvent::ALL)
+                .await
+                .unwrap();
+        }
+        assert!(matches!(
+            webhooks
+                .register(ada.user_id, "https://example.com/hook", &WebhookEvent::ALL)
+                .await,
+            Err(WebhookError::TooManyWebhooks)
+        ));
+
+        // Only the owner can delete a webhook
+        assert!(matches!(
+            webhooks.delete(bo.user_id, webhook.id).await,
+            Err(WebhookError::WebhookNotFound)
+        ));
+        webhooks.delete(ada.user_id, webhook.id).await.unwrap();
+        assert_eq!(
+            webhooks.webhooks(ada.user_id).await.unwrap().len(),
+            MAX_WEBHOOKS - 1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let Hooks {
+            clock,
+            db,
+            app_state,
+            webhooks,
+            ada,
+            bo,
+            ..
+        } = setup().await;
+        let (url, mut received) = receiver(vec![500]).await;
+        let webhook = webhooks
+            .register(ada.user_id, &url, &[WebhookEvent::MessageReceived])
+            .await
+            .unwrap();
+
+        // A received message is queued, and retried after the receiver failed it
+        MessageService::new(db)
+            .send(bo.id, "Ada", "Truce?", "Let's talk")
+            .await
+            .unwrap();
+        assert_eq!(webhooks.deliver_due(app_state.tick()).await.unwrap(), 0);
+        let first = received.recv().await.unwrap();
+        let log = webhooks.deliveries(ada.user_id, webhook.id).await.unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].status, DeliveryStatus::Pending.to_string());
+        assert_eq!((log[0].attempts, log[0].response_status), (1, Some(500)));
+        assert_eq!(log[0].next_attempt_at as usize, START + retry_delay(1));
+
+        assert_eq!(webhooks.deliver_due(app_state.tick()).await.unwrap(), 0);
+        clock.advance(retry_delay(1));
+        assert_eq!(webhooks.deliver_due(app_state.tick()).await.unwrap(), 1);
+        let retry = received.recv().await.unwrap();
+        assert_eq!(retry.body, first.body);
+        let log = webhooks.deliveries(ada.user_id, webhook.id).await.unwrap();
+        assert_eq!(log[0].status, DeliveryStatus::Delivered.to_string());
+        assert!(log[0].delivered_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_signed_payload() {
+        let Hooks {
+            db,
+            app_state,
+            webhooks,
+            ada,
+            bo,
+            ..
+        } = setup().await;
+        let (url, mut received) = receiver(vec![]).await;
+        let webhook = webhooks
+            .register(ada.user_id, &url, &[WebhookEvent::MessageReceived])
+            .await
+            .unwrap();
+        MessageService::new(db)
+            .send(bo.id, "Ada", "Truce?", "Let's talk")
+            .await
+            .unwrap();
+        assert_eq!(webhooks.deliver_due(app_state.tick()).await.unwrap(), 1);
+
+        // The payload leaves out the message body, and is signed with the webhook's secret
+        let request = received.recv().await.unwrap();
+        let log = webhooks.deliveries(ada.user_id, webhook.id).await.unwrap();
+        assert_eq!(
+            request.headers[&EVENT_HEADER.to_lowercase()],
+            "message_received"
+        );
+        assert_eq!(
+            request.headers[&DELIVERY_HEADER.to_lowercase()],
+            log[0].id.to_string()
+        );
+        assert_eq!(
+            request.headers[&SIGNATURE_HEADER.to_lowercase()],
+            format!("sha256={}", sign(&webhook.secret, &request.body))
+        );
+        let payload: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(payload["event"], "message_received");
+        assert_eq!(payload["data"]["from"], "Bo");
+        assert_eq!(payload["data"]["subject"], "Truce?");
+        assert!(payload["data"].get("body").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_build_complete() {
+        let Hooks {
+            clock,
+            app_state,
+            webhooks,
+            ada,
+            ada_home,
+            ..
+        } = setup().await;
+        let (url, mut received) = receiver(vec![]).await;
+        webhooks
+            .register(ada.user_id, &url, &[WebhookEvent::BuildComplete])
+            .await
+            .unwrap();
+
+        // Finished builds are sent along with their notification
+        app_state
+            .build_structure(
+                "hooks",
+                app_state.tick(),
+                ada_home,
+                StructureType::AsteroidMine,
+            )
+            .await
+            .unwrap();
+        clock.advance(600);
+        app_state
+            .collect_notifications("hooks", app_state.tick())
+            .await
+            .unwrap();
+        assert_eq!(webhooks.deliver_due(app_state.tick()).await.unwrap(), 1);
+        let build = received.recv().await.unwrap();
+        let payload: serde_json::Value = serde_json::from_str(&build.body).unwrap();
+        assert_eq!(payload["event"], "build_complete");
+        assert_eq!(payload["data"]["account"], "Ada");
+        assert_eq!(payload["data"]["system"]["x"], ada_home.x);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable() {
+        let Hooks {
+            clock,
+            db,
+            app_state,
+            webhooks,
+            ada,
+            bo,
+            ..
+        } = setup().await;
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_url = format!("http://{}/hook", closed.local_addr().unwrap());
+        drop(closed);
+        let unreachable = webhooks
+            .register(bo.user_id, &closed_url, &[WebhookEvent::MessageReceived])
+            .await
+            .unwrap();
+        MessageService::new(db)
+            .send(ada.id, "Bo", "No", "Never")
+            .await
+            .unwrap();
+
+        // A receiver that can't be reached fails the delivery after the last attempt
+        for attempt in 1..=MAX_ATTEMPTS {
+            assert_eq!(webhooks.deliver_due(app_state.tick()).await.unwrap(), 0);
+            let log = webhooks
+                .deliveries(bo.user_id, unreachable.id)
+                .await
+                .unwrap();
+            assert_eq!(log[0].attempts, attempt);
+            assert!(log[0].error.is_some());
+            clock.advance(retry_delay(attempt));
+        }
+        let log = webhooks
+            .deliveries(bo.user_id, unreachable.id)
+            .await
+            .unwrap();
+        assert_eq!(log[0].status, DeliveryStatus::Failed.to_string());
+        assert_eq!(webhooks.deliver_due(app_state.tick()).await.unwrap(), 0);
+        assert_eq!(
+            webhooks
+                .deliveries(bo.user_id, unreachable.id)
+                .await
+                .unwrap()[0]
+                .attempts,
+            MAX_ATTEMPTS
+        );
+    }
+
+    #[tokio::test]
+    async fn test_public_addresses_only() {
+        let Hooks {
+            db,
+            app_state,
+            webhooks: local,
+            ada,
+            bo,
+            ..
+        } = setup().await;
+        let webhooks = WebhookService::new(db.clone());
+
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://10.0.0.1/hook",
+            "http://[::1]/hook",
+            "http://localhost/hook",
+        ] {
+            assert!(
+                matches!(
+                    webhooks
+                        .register(ada.user_id, url, &[WebhookEvent::MessageReceived])
+                        .await,
+                    Err(WebhookError::PrivateAddress)
+                ),
+                "{}",
+                url
+            );
+        }
+        webhooks
+            .register(
+                ada.user_id,
+                "https://93.184.215.14/hook",
+                &[WebhookEvent::BuildComplete],
+            )
+            .await
+            .unwrap();
+
+        // A webhook that was let in is still checked when it is delivered
+        let (url, mut received) = receiver(vec![]).await;
+        let webhook = local
+            .register(ada.user_id, &url, &[WebhookEvent::MessageReceived])
+            .await
+            .unwrap();
+        MessageService::new(db)
+            .send(bo.id, "Ada", "Hi", "Hello")
+            .await
+            .unwrap();
+        assert_eq!(webhooks.deliver_due(app_state.tick()).await.unwrap(), 0);
+        let log = webhooks.deliveries(ada.user_id, webhook.id).await.unwrap();
+        assert_eq!(log[0].attempts, 1);
+        assert_eq!(
+            log[0].error.as_deref(),
+            Some(WebhookError::PrivateAddress.to_string().as_str())
+        );
+        assert!(received.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connect_to_checked_addresses() {
+        let (url, mut received) = receiver(vec![]).await;
+        let address = authority(&url).unwrap().parse().unwrap();
+
+        // Whatever the host resolves to by now, only the checked address is used
+        let status = tokio::task::spawn_blocking(move || {
+            agent(vec![address])
+                .post("http://rebound.invalid/hook")
+                .header("Content-Type", "application/json")
+                .send("{}")
+                .map(|response| response.status().as_u16())
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(received.recv().await.unwrap().body, "{}");
+        assert!(agent(vec![])
+            .post("http://rebound.invalid/hook")
+            .send("{}")
+            .is_err());
+    }
+}
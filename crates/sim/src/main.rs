@@ -0,0 +1,125 @@
+//! Headless balance simulator
+//!
+//! Plays a single system from a galaxy config with a build plan on a simulated clock, and
+//! reports its score, resources and structure levels over time. No database or web server is
+//! involved, so a change to a config can be checked in seconds.
+use galactic_war::ai;
+use galactic_war::config::GalaxyConfig;
+use galactic_war::StructureType;
+use std::str::FromStr;
+
+mod sim;
+
+use crate::sim::{Greedy, Plan, Simulation};
+
+const USAGE: &str = "Usage: galactic-war-sim <config.yaml> [options]
+
+Options:
+  --strategy NAME   greedy, economic, raider or turtle (default: greedy)
+  --script FILE     build the structures listed in FILE in order, one per line
+  --hours N         simulated time in hours (default: 72)
+  --step TICKS      ticks between build decisions (default: 60)
+  --interval TICKS  ticks between samples (default: 3600)
+  --format FORMAT   csv or json (default: csv)";
+
+/// Command line options
+struct Options {
+    config: String,
+    strategy: String,
+    script: Option<String>,
+    hours: usize,
+    step: usize,
+    interval: usize,
+    format: String,
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = run(options) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        config: String::new(),
+        strategy: "greedy".to_string(),
+        script: None,
+        hours: 72,
+        step: 60,
+        interval: 3600,
+        format: "csv".to_string(),
+    };
+    let number = |value: Option<String>, name: &str| {
+        value
+            .and_then(|value| value.parse::<usize>().ok())
+            .ok_or_else(|| format!("{} needs a number", name))
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--strategy" => options.strategy = args.next().ok_or("--strategy needs a name")?,
+            "--script" => options.script = Some(args.next().ok_or("--script needs a file")?),
+            "--hours" => options.hours = number(args.next(), "--hours")?,
+            "--step" => options.step = number(args.next(), "--step")?,
+            "--interval" => options.interval = number(args.next(), "--interval")?,
+            "--format" => options.format = args.next().ok_or("--format needs csv or json")?,
+            "-h" | "--help" => return Err("Simulate a galaxy config".to_string()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ if options.config.is_empty() => options.config = arg,
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+    if options.config.is_empty() {
+        return Err("Missing the galaxy config".to_string());
+    }
+    Ok(options)
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let config = std::fs::read_to_string(&options.config)
+        .map_err(|e| format!("Failed to read {}: {}", options.config, e))?;
+    let config: GalaxyConfig = serde_yaml::from_str(&config)
+        .map_err(|e| format!("Failed to parse {}: {}", options.config, e))?;
+
+    let plan = match &options.script {
+        Some(path) => Plan::Script(read_script(path)?),
+        None if options.strategy == "greedy" => Plan::Strategy(Box::new(Greedy)),
+        None => Plan::Strategy(
+            ai::strategy(&options.strategy)
+                .ok_or_else(|| format!("Unknown strategy {}", options.strategy))?,
+        ),
+    };
+
+    let mut simulation = Simulation::new(&config, plan);
+    let samples = simulation.run(options.hours * 3600, options.step, options.interval);
+    match options.format.as_str() {
+        "csv" => print!("{}", sim::to_csv(&samples)),
+        "json" => println!(
+            "{}",
+            serde_json::to_string_pretty(&samples).map_err(|e| e.to_string())?
+        ),
+        format => return Err(format!("Unknown format {}", format)),
+    }
+    Ok(())
+}
+
+/// Read a build script, one structure per line, ignoring blank lines and # comments
+fn read_script(path: &str) -> Result<Vec<StructureType>, String> {
+    let script =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    script
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            StructureType::from_str(line).map_err(|_| format!("Unknown structure {}", line))
+        })
+        .collect()
+}
@@ -0,0 +1,282 @@
+/// Simulate a single system following a build plan
+///
+/// The system runs on a manual clock, so days of play take milliseconds and the results only
+/// depend on the config and the plan.
+use galactic_war::ai::{Strategy, SystemView};
+use galactic_war::config::GalaxyConfig;
+use galactic_war::{Clock, Details, ManualClock, StructureType, System};
+use indexmap::IndexMap;
+use serde::Serialize;
+
+/// How the simulated player decides what to build
+#[derive(Debug)]
+pub enum Plan {
+    /// Follow a strategy, building the first structure it wants that can be afforded
+    Strategy(Box<dyn Strategy>),
+    /// Build the listed structures in order, waiting until each one can be afforded
+    Script(Vec<StructureType>),
+}
+
+/// Upgrades whatever is cheapest, regardless of what it does
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Greedy;
+
+impl Strategy for Greedy {
+    fn name(&self) -> &'static str {
+        "greedy"
+    }
+
+    fn priorities(&self, view: &SystemView) -> Vec<StructureType> {
+        let structures: Vec<StructureType> = view.costs.keys().cloned().collect();
+        view.cheapest(&structures)
+    }
+}
+
+/// State of the system at a point in time
+#[derive(Debug, Clone, Serialize)]
+pub struct Sample {
+    pub tick: usize,
+    pub score: usize,
+    pub metal: usize,
+    pub crew: usize,
+    pub water: usize,
+    /// Number of builds started so far
+    pub builds: usize,
+    /// Level of every structure, in config order
+    pub structures: IndexMap<String, usize>,
+}
+
+/// A system being played by a plan
+pub struct Simulation<'a> {
+    config: &'a GalaxyConfig,
+    system: System,
+    clock: ManualClock,
+    plan: Plan,
+    /// Next entry of a script plan
+    script_position: usize,
+    builds: usize,
+}
+
+impl<'a> Simulation<'a> {
+    /// Create a new system at tick 0
+    pub fn new(config: &'a GalaxyConfig, plan: Plan) -> Self {
+        Self {
+            config,
+            system: System::new(0, &config.systems, config),
+            clock: ManualClock::new(0),
+            plan,
+            script_position: 0,
+            builds: 0,
+        }
+    }
+
+    /// Run for `duration` ticks
+    ///
+    /// A build decision is made every `step` ticks and a sample is recorded every `interval`
+    /// ticks, including the first and last tick.
+    pub fn run(&mut self, duration: usize, step: usize, interval: usize) -> Vec<Sample> {
+        let step = step.max(1);
+        let interval = interval.max(1);
+        let mut samples = vec![self.sample()];
+        let mut next_sample = interval;
+        while self.clock.now() < duration {
+            self.decide();
+            self.clock.advance(step.min(duration - self.clock.now()));
+            while next_sample <= self.clock.now() {
+                samples.push(self.sample_at(next_sample));
+                next_sample += interval;
+            }
+        }
+        if samples.last().is_some_and(|sample| sample.tick < duration) {
+            samples.push(self.sample());
+        }
+        samples
+    }
+
+    /// Start a build if the plan wants one and nothing is being built
+    fn decide(&mut self) {
+        let tick = self.clock.now();
+        let Some(view) = self.view(tick) else {
+            return;
+        };
+        if view
+            .info
+            .events
+            .iter()
+            .any(|event| event.structure.is_some())
+        {
+            return;
+        }
+        let choice = match &self.plan {
+            Plan::Strategy(strategy) => strategy
+                .priorities(&view)
+                .into_iter()
+                .find(|structure| view.can_afford(*structure)),
+            Plan::Script(script) => script
+                .get(self.script_position)
+                .filter(|structure| view.can_afford(**structure))
+                .cloned(),
+        };
+        if let Some(structure) = choice {
+            if self.system.build(tick, self.config, structure).is_ok() {
+                self.builds += 1;
+                self.script_position += 1;
+            }
+        }
+    }
+
+    /// What a player would see of the system at a tick
+    fn view(&mut self, tick: usize) -> Option<SystemView> {
+        let Ok(Details::System(info)) = self.system.get_details(tick, self.config, None) else {
+            return None;
+        };
+        let costs = match self
+            .system
+            .get_details(tick, self.config, Some(StructureType::Colony))
+        {
+            Ok(Details::Structure(colony)) => colony.builds.unwrap_or_default(),
+            _ => IndexMap::new(),
+        };
+        Some(SystemView {
+            coords: (0, 0).into(),
+            info,
+            costs,
+        })
+    }
+
+    fn sample(&mut self) -> Sample {
+        self.sample_at(self.clock.now())
+    }
+
+    /// Record the state of the system at a tick, which must not be before the last update
+    fn sample_at(&mut self, tick: usize) -> Sample {
+        let resources = self.system.resources(tick, self.config);
+        Sample {
+            tick,
+            score: self.system.score(tick, self.config),
+            metal: resources.metal,
+            crew: resources.crew,
+            water: resources.water,
+            builds: self.builds,
+            structures: self
+                .system
+                .get_structures()
+                .into_iter()
+                .map(|(structure, level)| (structure.to_string().to_lowercase(), level))
+                .collect(),
+        }
+    }
+}
+
+/// Write samples as CSV, one column per structure level
+pub fn to_csv(samples: &[Sample]) -> String {
+    let mut csv = String::from("tick,score,metal,crew,water,builds");
+    if let Some(first) = samples.first() {
+        for structure in first.structures.keys() {
+            csv.push(',');
+            csv.push_str(structure);
+        }
+    }
+    csv.push('\n');
+    for sample in samples {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}",
+            sample.tick, sample.score, sample.metal, sample.crew, sample.water, sample.builds
+        ));
+        for level in sample.structures.values() {
+            csv.push_str(&format!(",{}", level));
+        }
+        csv.push('\n');
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+system_count: 1
+size:
+  x: 1
+  y: 1
+systems:
+  resources:
+    metal: 100
+    crew: 100
+    water: 100
+  structures:
+    colony:
+      starting_level: 1
+      production:
+        metal: 100
+        crew: 100
+        water: 100
+      storage:
+        metal: 100000
+        crew: 100000
+        water: 100000
+      cost:
+        time: 600
+        metal: 90
+        crew: 90
+        water: 90
+    hatchery:
+      starting_level: 1
+      production:
+        crew: 100
+      cost:
+        time: 600
+        metal: 20
+        crew: 20
+        water: 20
+"#;
+
+    fn create_test_config() -> GalaxyConfig {
+        serde_yaml::from_str(CONFIG).unwrap()
+    }
+
+    #[test]
+    fn test_greedy_builds_cheapest() {
+        let config = create_test_config();
+        let mut simulation = Simulation::new(&config, Plan::Strategy(Box::new(Greedy)));
+        let samples = simulation.run(3600, 60, 1800);
+
+        assert_eq!(
+            samples.iter().map(|s| s.tick).collect::<Vec<_>>(),
+            vec![0, 1800, 3600]
+        );
+        assert_eq!(samples[0].structures["hatchery"], 1);
+        let last = samples.last().unwrap();
+        assert!(last.builds > 0);
+        assert!(last.structures["hatchery"] > last.structures["colony"]);
+        assert!(last.score > samples[0].score);
+    }
+
+    #[test]
+    fn test_script_is_followed_in_order() {
+        let config = create_test_config();
+        let script = vec![StructureType::Colony, StructureType::Hatchery];
+        let mut simulation = Simulation::new(&config, Plan::Script(script));
+        let samples = simulation.run(7200, 60, 7200);
+
+        let last = samples.last().unwrap();
+        assert_eq!(last.builds, 2);
+        assert_eq!(last.structures["colony"], 2);
+        assert_eq!(last.structures["hatchery"], 2);
+    }
+
+    #[test]
+    fn test_csv_output() {
+        let config = create_test_config();
+        let mut simulation = Simulation::new(&config, Plan::Script(Vec::new()));
+        let csv = to_csv(&simulation.run(3600, 60, 3600));
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "tick,score,metal,crew,water,builds,colony,hatchery"
+        );
+        assert_eq!(lines[1], "0,2,100,100,100,0,1,1");
+        assert_eq!(lines[2], "3600,2,200,300,200,0,1,1");
+    }
+}